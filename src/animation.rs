@@ -0,0 +1,281 @@
+use std::path::{Path, PathBuf};
+
+use eframe::egui_wgpu::RenderState;
+use egui_snarl::{NodeId, Snarl};
+use thiserror::Error;
+
+use crate::node::Node;
+use crate::node::message::SelfNodeMut;
+use crate::node::render::RenderNode;
+use crate::node::render::raytracer::RaytracerRenderNode;
+use crate::node::viewer::NodeViewer;
+use crate::raytracer::still::{StillRender, StillRenderError};
+use crate::raytracer::{RenderParamsValidationError, SamplingParams};
+
+#[derive(Error, Debug)]
+pub enum AnimationError {
+    #[error("the render node was removed")]
+    RenderRemoved,
+    #[error("the render has no camera or scene")]
+    NothingToRender,
+    #[error("invalid parameters: {0}")]
+    InvalidParams(#[from] RenderParamsValidationError),
+    #[error("failed to render frame: {0}")]
+    Render(#[from] StillRenderError),
+    #[error("failed to write frame: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// Frame range and quality of an exported animation.
+#[derive(Clone, Debug)]
+pub struct AnimationSettings {
+    pub start_frame: u32,
+    pub end_frame: u32,
+    pub fps: f64,
+    pub samples_per_frame: u32,
+    pub width: u32,
+    pub height: u32,
+    pub output_dir: String,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            start_frame: 1,
+            end_frame: 48,
+            fps: 24.0,
+            samples_per_frame: 64,
+            width: 640,
+            height: 360,
+            output_dir: "frames".to_owned(),
+        }
+    }
+}
+
+impl AnimationSettings {
+    /// Time in seconds `frame` is rendered at, frame zero is at the start of the clock.
+    pub fn frame_time(&self, frame: u32) -> f64 {
+        f64::from(frame) / self.fps
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        Path::new(&self.output_dir).join(format!("frame_{frame:04}.png"))
+    }
+}
+
+struct AnimationExport {
+    render_id: NodeId,
+    render_state: RenderState,
+    /// Whether the viewport draws bloom, frames are rendered with the same post processing.
+    bloom: bool,
+    frame: u32,
+    /// Updates left until the frame is rendered. Pins take their remote values when shown,
+    /// so a value can need several updates to travel along a chain of nodes.
+    settle_updates: u32,
+    /// The frame being rendered on the GPU, it takes a frame of samples per update of the app.
+    still: Option<StillRender>,
+}
+
+/// Renders a frame range of a raytracer render offscreen on the GPU, without holding up the updates of the app.
+#[derive(Default)]
+pub struct AnimationWindow {
+    settings: AnimationSettings,
+    export: Option<AnimationExport>,
+    status: Option<Result<String, String>>,
+}
+
+impl AnimationWindow {
+    const SETTLE_UPDATES: u32 = 3;
+
+    /// Time of the frame being exported, it stands in for the clock while the export runs.
+    pub fn time(&self) -> Option<f64> {
+        self.export
+            .as_ref()
+            .map(|export| self.settings.frame_time(export.frame))
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, open: &mut bool, viewer: &NodeViewer, snarl: &Snarl<Node>) {
+        egui::Window::new("Render animation")
+            .open(open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(self.export.is_none(), |ui| self.settings_view(ui));
+
+                match &self.export {
+                    None => {
+                        if ui.button("Render").clicked() {
+                            self.start(viewer, snarl);
+                        }
+                    },
+                    Some(export) => {
+                        let settings = &self.settings;
+                        let done = export.frame - settings.start_frame;
+                        let total = settings.end_frame - settings.start_frame + 1;
+                        let text = format!("Frame {} of {total}", done + 1);
+                        ui.add(egui::ProgressBar::new(done as f32 / total as f32).text(text));
+                        if ui.button("Cancel").clicked() {
+                            self.export = None;
+                            self.status = Some(Err(format!("Cancelled after {done} frames")));
+                        }
+                    },
+                }
+
+                match &self.status {
+                    None => {},
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    },
+                    Some(Err(err)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, err);
+                    },
+                }
+            });
+    }
+
+    fn settings_view(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.settings;
+        egui::Grid::new("animation_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Frames");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut settings.start_frame).range(0..=settings.end_frame));
+                ui.label("to");
+                ui.add(egui::DragValue::new(&mut settings.end_frame).range(settings.start_frame..=u32::MAX));
+            });
+            ui.end_row();
+
+            ui.label("Frame rate");
+            ui.add(
+                egui::DragValue::new(&mut settings.fps)
+                    .range(1.0..=240.0)
+                    .suffix(" fps"),
+            );
+            ui.end_row();
+
+            ui.label("Samples per frame");
+            ui.add(egui::DragValue::new(&mut settings.samples_per_frame).range(SamplingParams::MAX_SAMPLES_RANGE));
+            ui.end_row();
+
+            ui.label("Resolution");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut settings.width).range(1..=8192).suffix(" px"));
+                ui.label("×");
+                ui.add(egui::DragValue::new(&mut settings.height).range(1..=8192).suffix(" px"));
+            });
+            ui.end_row();
+
+            ui.label("Output directory");
+            ui.text_edit_singleline(&mut settings.output_dir);
+            ui.end_row();
+        });
+    }
+
+    /// Renders the first raytracer render connected to an output.
+    fn start(&mut self, viewer: &NodeViewer, snarl: &Snarl<Node>) {
+        let render_id = viewer
+            .renders()
+            .iter()
+            .copied()
+            .find(|&id| matches!(snarl.get_node(id), Some(Node::Render(RenderNode::Raytracer(_)))));
+        let Some(render_id) = render_id else {
+            self.status = Some(Err("No raytracer render is connected to an output".to_owned()));
+            return;
+        };
+        if let Err(err) = std::fs::create_dir_all(&self.settings.output_dir) {
+            self.status = Some(Err(format!("Failed to create output directory: {err}")));
+            return;
+        }
+
+        self.export = Some(AnimationExport {
+            render_id,
+            render_state: viewer.render_state().clone(),
+            bloom: viewer.bloom(),
+            frame: self.settings.start_frame,
+            settle_updates: Self::SETTLE_UPDATES,
+            still: None,
+        });
+        self.status = None;
+    }
+
+    /// Writes the frame being exported once its values settled and moves on to the next one.
+    /// Called after the graph was shown, so the pins have taken the values of the frame's time.
+    pub fn advance(&mut self, ctx: &egui::Context, snarl: &mut Snarl<Node>) {
+        let Some(export) = &mut self.export else {
+            return;
+        };
+        ctx.request_repaint();
+
+        if export.settle_updates > 0 {
+            export.settle_updates -= 1;
+            return;
+        }
+
+        let frame = export.frame;
+        match render_frame(&self.settings, export, snarl) {
+            Ok(false) => {},
+            Ok(true) if frame < self.settings.end_frame => {
+                export.frame += 1;
+                export.settle_updates = Self::SETTLE_UPDATES;
+            },
+            Ok(true) => {
+                let count = self.settings.end_frame - self.settings.start_frame + 1;
+                self.export = None;
+                self.status = Some(Ok(format!("Wrote {count} frames to {}", self.settings.output_dir)));
+            },
+            Err(err) => {
+                self.export = None;
+                self.status = Some(Err(format!("Frame {frame}: {err}")));
+            },
+        }
+    }
+}
+
+/// Advances the frame of `export` by a step and returns `true` once it was written. The scene is taken from the
+/// graph when the frame starts, after any textures of it have loaded, and the render keeps going from there.
+fn render_frame(
+    settings: &AnimationSettings,
+    export: &mut AnimationExport,
+    snarl: &mut Snarl<Node>,
+) -> Result<bool, AnimationError> {
+    let (render_id, device) = (export.render_id, &export.render_state.device);
+    let Some(still) = &mut export.still else {
+        if !matches!(snarl.get_node(render_id), Some(Node::Render(RenderNode::Raytracer(_)))) {
+            return Err(AnimationError::RenderRemoved);
+        }
+        if RaytracerRenderNode::is_loading_textures(SelfNodeMut::new(render_id, snarl)) {
+            return Ok(false);
+        }
+
+        let self_node = SelfNodeMut::new(render_id, snarl);
+        let (render_params, scene) = RaytracerRenderNode::still(self_node, settings.samples_per_frame, export.bloom)
+            .ok_or(AnimationError::NothingToRender)?;
+        let size = (settings.width, settings.height);
+        export.still = Some(StillRender::new(device, &scene, &render_params, size)?);
+        return Ok(false);
+    };
+
+    let Some(image) = still.step(device, &export.render_state.queue)? else {
+        return Ok(false);
+    };
+    export.still = None;
+    image.save(settings.frame_path(export.frame))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_paths_and_times() {
+        let settings = AnimationSettings {
+            fps: 25.0,
+            output_dir: "out".to_owned(),
+            ..Default::default()
+        };
+
+        assert_eq!(settings.frame_path(1), Path::new("out").join("frame_0001.png"));
+        assert_eq!(settings.frame_path(12345), Path::new("out").join("frame_12345.png"));
+        assert_eq!(settings.frame_time(50), 2.0);
+    }
+}
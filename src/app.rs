@@ -1,11 +1,27 @@
 use eframe::{App, CreationContext};
-use egui::{Id, Key, LayerId, Order, Sense, UiBuilder};
-use egui_snarl::Snarl;
+use egui::{Id, LayerId, Order, Sense, UiBuilder, pos2};
 use egui_snarl::ui::{NodeLayout, PinPlacement, SnarlStyle, SnarlWidget};
+use egui_snarl::{InPinId, OutPinId, Snarl};
 use serde::{Deserialize, Serialize};
 
-use crate::node::Node;
+use crate::animation::AnimationWindow;
+use crate::command_palette::{Command, CommandPalette};
+use crate::history::History;
+use crate::import::ImportWindow;
+use crate::node::camera::CameraNode;
+use crate::node::collection::CollectionNode;
+use crate::node::material::{
+    CheckerboardNode, DielectricNode, EmissiveNode, InputMaterial, LambertianNode, MaterialNode, MetalNode,
+};
+use crate::node::primitive::{PrimitiveNode, SphereNode};
+use crate::node::render::raytracer::RaytracerRenderNode;
+use crate::node::scene::SceneNode;
 use crate::node::viewer::NodeViewer;
+use crate::node::{Node, OutputNode, RenderNode};
+use crate::presets::{Presets, PresetsView};
+use crate::raytracer::scene::{Material, Scene, Sphere, TextureId};
+use crate::shortcuts::{Action, KeyBindings, ShortcutsWindow};
+use crate::types::{Color, NodePin, UpAxis, Vector3f32};
 
 #[derive(Debug, Copy, Clone, Deserialize, Serialize, egui_probe::EguiProbe)]
 pub enum EditMode {
@@ -23,7 +39,7 @@ impl EditMode {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, egui_probe::EguiProbe)]
+#[derive(Debug, Clone, Deserialize, Serialize, egui_probe::EguiProbe)]
 pub struct AppSettings {
     pub visible_settings: bool,
     pub edit_mode: EditMode,
@@ -31,6 +47,38 @@ pub struct AppSettings {
     pub viewing_nodes_opacity: f32,
     pub show_nodes: bool,
     pub animation_time: f32,
+    #[serde(default)]
+    pub visible_shortcuts: bool,
+    /// Show the values of number and vector outputs on their wires.
+    #[serde(default)]
+    pub show_wire_labels: bool,
+    /// Show samples, elapsed time and speed of the raytracer over the render.
+    #[serde(default)]
+    pub show_render_stats: bool,
+    /// Show how long the parts of the latest raytracer frame took, measured with the `frame-timings` feature.
+    #[serde(default)]
+    pub show_frame_timings: bool,
+    /// Show a panel with the contents of every scene and the problems found in them.
+    #[serde(default)]
+    pub show_scene_stats: bool,
+    /// Let bright highlights of raytracer renders glow, off for physically accurate output.
+    #[serde(default)]
+    pub bloom: bool,
+    /// Fills the viewport while no render is connected to an output.
+    #[serde(default = "AppSettings::default_viewport_clear_color")]
+    pub viewport_clear_color: egui::Color32,
+    /// Ask for attention when the renders converge, the window title says so either way.
+    #[serde(default = "AppSettings::default_notify_render_complete")]
+    pub notify_render_complete: bool,
+    /// Show the current mode and the keys switching it in the corner of the viewport.
+    #[serde(default = "AppSettings::default_show_mode_indicator")]
+    pub show_mode_indicator: bool,
+    /// Z-up lines scenes made in tools that put z up the right way, cameras and the sky turn along.
+    #[serde(default)]
+    pub up_axis: UpAxis,
+    #[serde(default)]
+    #[egui_probe(skip)]
+    pub key_bindings: KeyBindings,
 }
 
 impl Default for AppSettings {
@@ -42,15 +90,52 @@ impl Default for AppSettings {
             viewing_nodes_opacity: 0.5,
             show_nodes: true,
             animation_time: 0.2,
+            visible_shortcuts: false,
+            show_wire_labels: false,
+            show_render_stats: false,
+            show_frame_timings: false,
+            show_scene_stats: false,
+            bloom: false,
+            viewport_clear_color: Self::default_viewport_clear_color(),
+            notify_render_complete: Self::default_notify_render_complete(),
+            show_mode_indicator: Self::default_show_mode_indicator(),
+            up_axis: UpAxis::default(),
+            key_bindings: KeyBindings::default(),
         }
     }
 }
 
+impl AppSettings {
+    fn default_viewport_clear_color() -> egui::Color32 {
+        egui::Color32::from_gray(16)
+    }
+
+    fn default_notify_render_complete() -> bool {
+        true
+    }
+
+    fn default_show_mode_indicator() -> bool {
+        true
+    }
+}
+
 pub struct NodedApp {
     snarl: Snarl<Node>,
     style: SnarlStyle,
     settings: AppSettings,
+    presets: Presets,
+    presets_view: PresetsView,
     viewer: NodeViewer,
+    shortcuts_window: ShortcutsWindow,
+    command_palette: CommandPalette,
+    import_window: ImportWindow,
+    visible_import: bool,
+    animation_window: AnimationWindow,
+    visible_animation: bool,
+    /// The renders converged and the window title was changed to say so.
+    render_complete: bool,
+    history: History,
+    commands: Vec<Command<NodedApp>>,
 }
 
 impl NodedApp {
@@ -59,12 +144,15 @@ impl NodedApp {
 
         cx.egui_ctx.style_mut(|style| style.animation_time *= 10.0);
 
-        let snarl = cx.storage.map_or_else(Snarl::new, |storage| {
-            storage
-                .get_string("snarl")
-                .and_then(|snarl| serde_json::from_str(&snarl).ok())
-                .unwrap_or_default()
-        });
+        let screen_rect = cx.egui_ctx.input(|i| i.screen_rect());
+        let max_viewport_resolution = (screen_rect.width() * screen_rect.height() / 10.0) as u32;
+        println!("Max resolution: {max_viewport_resolution}");
+
+        // The first launch shows a render right away, a graph that fails to load is not replaced.
+        let mut snarl = match cx.storage.and_then(|storage| storage.get_string("snarl")) {
+            Some(snarl) => serde_json::from_str(&snarl).unwrap_or_default(),
+            None => default_graph(max_viewport_resolution),
+        };
         // let snarl = Snarl::new();
 
         let style = cx.storage.map_or_else(default_style, |storage| {
@@ -82,26 +170,224 @@ impl NodedApp {
                 .unwrap_or_default()
         });
 
-        let screen_rect = cx.egui_ctx.input(|i| i.screen_rect());
-        let max_viewport_resolution = (screen_rect.width() * screen_rect.height() / 10.0) as u32;
-        println!("Max resolution: {max_viewport_resolution}");
+        let presets = cx.storage.map_or_else(Presets::default, |storage| {
+            storage
+                .get_string("presets")
+                .and_then(|presets| serde_json::from_str(&presets).ok())
+                .unwrap_or_default()
+        });
 
-        let viewer = NodeViewer::new(
+        let graph_view = cx.storage.and_then(|storage| {
+            storage
+                .get_string("graph_view")
+                .and_then(|graph_view| serde_json::from_str(&graph_view).ok())
+        });
+
+        let mut viewer = NodeViewer::new(
             cx.wgpu_render_state.clone().expect("WGPU must be enabled"),
             max_viewport_resolution,
-            &snarl,
+            &mut snarl,
         );
+        if let Some(graph_view) = graph_view {
+            viewer.restore_graph_view(graph_view);
+        }
         Self {
             snarl,
             style,
             settings,
+            presets,
+            presets_view: PresetsView::default(),
             viewer,
+            shortcuts_window: ShortcutsWindow::default(),
+            command_palette: CommandPalette::default(),
+            import_window: ImportWindow::default(),
+            visible_import: false,
+            animation_window: AnimationWindow::default(),
+            visible_animation: false,
+            render_complete: false,
+            history: History::default(),
+            commands: Self::commands(),
         }
     }
+
+    fn commands() -> Vec<Command<NodedApp>> {
+        vec![
+            Command::new("Save", |app, _ctx, frame| {
+                if let Some(storage) = frame.storage_mut() {
+                    app.save(storage);
+                    storage.flush();
+                }
+            }),
+            Command::new("Open...", |app, _ctx, _frame| app.open()),
+            Command::new("Save as...", |app, _ctx, _frame| app.save_as()),
+            Command::new("Import spheres...", |app, _ctx, _frame| app.visible_import = true),
+            Command::new("Render animation...", |app, _ctx, _frame| app.visible_animation = true),
+            Command::new("Undo", |app, _ctx, _frame| app.undo()),
+            Command::new("Redo", |app, _ctx, _frame| app.redo()),
+            Command::new("Clear all nodes", |app, _ctx, _frame| app.clear()),
+            Command::new("Toggle settings panel", |app, _ctx, _frame| {
+                app.settings.visible_settings = !app.settings.visible_settings;
+            }),
+            Command::new("Toggle nodes", |app, _ctx, _frame| {
+                app.settings.show_nodes = !app.settings.show_nodes;
+            }),
+            Command::new("Switch edit mode", |app, _ctx, _frame| {
+                app.settings.edit_mode.switch();
+            }),
+            Command::new("Pause/resume rendering", |app, _ctx, _frame| {
+                app.viewer.toggle_render_pause(&mut app.snarl);
+            }),
+            Command::new("Restart rendering", |app, _ctx, _frame| {
+                app.viewer.restart_renders(&mut app.snarl);
+            }),
+            Command::new("Show shortcuts", |app, _ctx, _frame| app.settings.visible_shortcuts = true),
+            Command::new("Quit", |_app, ctx, _frame| ctx.send_viewport_cmd(egui::ViewportCommand::Close)),
+        ]
+    }
+
+    /// Asks for a `.json` file and writes the node graph to it.
+    fn save_as(&self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Node graph", &["json"])
+            .set_file_name("scene.json")
+            .save_file()
+        else {
+            return;
+        };
+
+        let result = serde_json::to_string_pretty(&self.snarl)
+            .map_err(|err| err.to_string())
+            .and_then(|snarl| std::fs::write(&path, snarl).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            eprintln!("Failed to save node graph to `{}`: {err}", path.display());
+        }
+    }
+
+    /// Asks for a `.json` file and replaces the node graph with the one stored in it.
+    /// Texture paths are kept as they are, even if the files do not exist.
+    fn open(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("Node graph", &["json"]).pick_file() else {
+            return;
+        };
+
+        let result = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| serde_json::from_str(&text).map_err(|err| err.to_string()));
+        match result {
+            Ok(snarl) => {
+                self.viewer.record_snapshot(&self.snarl);
+                self.viewer.replace_snarl(&mut self.snarl, snarl);
+            },
+            Err(err) => eprintln!("Failed to open node graph `{}`: {err}", path.display()),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.viewer.record_snapshot(&self.snarl);
+        self.viewer.replace_snarl(&mut self.snarl, Snarl::default());
+    }
+
+    fn undo(&mut self) {
+        let current = serde_json::to_string(&self.snarl).unwrap();
+        if let Some(snapshot) = self.history.undo(current) {
+            self.restore(&snapshot);
+        }
+    }
+
+    fn redo(&mut self) {
+        let current = serde_json::to_string(&self.snarl).unwrap();
+        if let Some(snapshot) = self.history.redo(current) {
+            self.restore(&snapshot);
+        }
+    }
+
+    fn restore(&mut self, snapshot: &str) {
+        match serde_json::from_str(snapshot) {
+            Ok(snarl) => self.viewer.replace_snarl(&mut self.snarl, snarl),
+            Err(err) => eprintln!("Failed to restore node graph: {err}"),
+        }
+    }
+
+    /// Changes the window title when the renders converge and back once they start over.
+    fn update_render_complete(&mut self, ctx: &egui::Context) {
+        let render_complete = self.viewer.is_render_complete();
+        if render_complete == self.render_complete {
+            return;
+        }
+        self.render_complete = render_complete;
+
+        let title = if render_complete {
+            "noded — render complete"
+        } else {
+            "noded"
+        };
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.to_owned()));
+        if render_complete && self.settings.notify_render_complete {
+            ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                egui::UserAttentionType::Informational,
+            ));
+        }
+    }
+
+    /// Global shortcuts must not fire while a text field has keyboard focus
+    /// or while a shortcut is being rebound.
+    fn shortcuts_enabled(&self, ctx: &egui::Context) -> bool {
+        !ctx.wants_keyboard_input() && !self.shortcuts_window.is_rebinding()
+    }
+
+    /// Draws the current mode with the keys for switching it and hiding the nodes in the bottom left corner.
+    fn draw_mode_indicator(&self, painter: &egui::Painter, rect: egui::Rect) {
+        const MARGIN: f32 = 8.0;
+
+        let bindings = &self.settings.key_bindings;
+        let mode = match self.settings.edit_mode {
+            EditMode::Editing => "EDIT",
+            EditMode::View => "VIEW",
+        };
+        let text = format!(
+            "{mode}  {} switch mode · {} {} nodes",
+            bindings.key(Action::SwitchEditMode).name(),
+            bindings.key(Action::ToggleNodes).name(),
+            if self.settings.show_nodes { "hide" } else { "show" },
+        );
+
+        let galley = painter.layout_no_wrap(text, egui::FontId::monospace(12.0), egui::Color32::WHITE);
+        let corner = rect.left_bottom() + egui::vec2(MARGIN, -MARGIN) * 2.0;
+        let pos = corner - egui::vec2(0.0, galley.size().y);
+        let background = egui::Rect::from_min_size(pos, galley.size()).expand(MARGIN / 2.0);
+        painter.rect_filled(background, 4.0, egui::Color32::from_black_alpha(160));
+        painter.galley(pos, galley, egui::Color32::WHITE);
+    }
 }
 
 impl App for NodedApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn raw_input_hook(&mut self, ctx: &egui::Context, raw_input: &mut egui::RawInput) {
+        // With no widget focused egui moves the focus to the first widget on Tab, which would keep the shortcuts
+        // disabled until the focus is cleared. The mode switch is handled here, before egui sees the key.
+        if !self.shortcuts_enabled(ctx) {
+            return;
+        }
+
+        let action = Action::SwitchEditMode;
+        let key = self.settings.key_bindings.key(action);
+        let is_switch = |event: &egui::Event| match event {
+            egui::Event::Key {
+                key: pressed_key,
+                pressed: true,
+                modifiers,
+                ..
+            } => *pressed_key == key && modifiers.matches_logically(action.modifiers()),
+            _ => false,
+        };
+
+        let before = raw_input.events.len();
+        raw_input.events.retain(|event| !is_switch(event));
+        if raw_input.events.len() != before {
+            self.settings.edit_mode.switch();
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         // ctx.set_zoom_factor(1.0);
         // ctx.set_transform_layer(egui::LayerId::background(), egui::emath::TSTransform::from_scaling(1.0));
 
@@ -110,47 +396,156 @@ impl App for NodedApp {
 
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    if ui.button("Open...").clicked() {
+                        self.open();
+                        ui.close_menu();
+                    }
+                    if ui.button("Save As...").clicked() {
+                        self.save_as();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("Import spheres...").clicked() {
+                        self.visible_import = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Render animation...").clicked() {
+                        self.visible_animation = true;
+                        ui.close_menu();
+                    }
                     if ui.button("Quit").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
                 });
+                ui.menu_button("Edit", |ui| {
+                    if ui.button("Undo").clicked() {
+                        self.undo();
+                        ui.close_menu();
+                    }
+                    if ui.button("Redo").clicked() {
+                        self.redo();
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Help", |ui| {
+                    if ui.button("Shortcuts").clicked() {
+                        self.settings.visible_shortcuts = true;
+                        ui.close_menu();
+                    }
+                });
                 ui.add_space(16.0);
 
                 egui::widgets::global_theme_preference_switch(ui);
 
                 if ui.button("Clear All").clicked() {
-                    self.snarl = Snarl::default();
+                    self.clear();
                 }
             });
         });
 
-        if ctx.input(|i| i.key_pressed(Key::N)) {
+        // The palette is toggled even while a text field has focus, since its own search field takes it.
+        if !self.shortcuts_window.is_rebinding()
+            && ctx.input(|i| self.settings.key_bindings.pressed(Action::ToggleCommandPalette, i))
+        {
+            self.command_palette.toggle();
+        }
+
+        if let Some(idx) = self.command_palette.show(ctx, &self.commands) {
+            let run = self.commands[idx].run;
+            run(self, ctx, frame);
+        }
+
+        // Expressions using time are re-evaluated when their consumers are shown,
+        // which in turn restarts accumulation of the render. An animation export holds the time of its frame.
+        let time = self.animation_window.time().unwrap_or_else(|| ctx.input(|i| i.time));
+        if self.viewer.set_time(&mut self.snarl, time) {
+            ctx.request_repaint();
+        }
+        self.viewer.set_up_axis(&mut self.snarl, self.settings.up_axis);
+
+        let shortcuts_enabled = self.shortcuts_enabled(ctx);
+        let bindings = &self.settings.key_bindings;
+
+        if shortcuts_enabled && ctx.input(|i| bindings.pressed(Action::ToggleSettings, i)) {
             self.settings.visible_settings = !self.settings.visible_settings;
         }
 
+        if shortcuts_enabled && ctx.input(|i| bindings.pressed(Action::ToggleShortcuts, i)) {
+            self.settings.visible_shortcuts = !self.settings.visible_shortcuts;
+        }
+
+        let [undo, redo, copy, paste, delete] = [
+            Action::Undo,
+            Action::Redo,
+            Action::Copy,
+            Action::Paste,
+            Action::DeleteSelection,
+        ]
+        .map(|action| shortcuts_enabled && ctx.input(|i| bindings.pressed(action, i)));
+        if undo {
+            self.undo();
+        }
+        if redo {
+            self.redo();
+        }
+        if copy {
+            let selected = egui_snarl::ui::get_selected_nodes(snarl_id(), ctx);
+            self.viewer.copy_nodes(&selected, &self.snarl);
+        }
+        if paste {
+            self.viewer.paste_nodes(&mut self.snarl);
+        }
+        if delete {
+            let selected = egui_snarl::ui::get_selected_nodes(snarl_id(), ctx);
+            self.viewer.remove_nodes(&selected, &mut self.snarl);
+        }
+        if shortcuts_enabled && ctx.input(|i| bindings.pressed(Action::ToggleRenderPause, i)) {
+            self.viewer.toggle_render_pause(&mut self.snarl);
+        }
+        if shortcuts_enabled && ctx.input(|i| bindings.pressed(Action::RestartRender, i)) {
+            self.viewer.restart_renders(&mut self.snarl);
+        }
+
+        self.shortcuts_window
+            .show(ctx, &mut self.settings.visible_shortcuts, &mut self.settings.key_bindings);
+
+        self.import_window
+            .show(ctx, &mut self.visible_import, &mut self.viewer, &mut self.snarl);
+
+        self.animation_window
+            .show(ctx, &mut self.visible_animation, &self.viewer, &self.snarl);
+
         if self.settings.visible_settings {
             egui::SidePanel::left("style").show(ctx, |ui| {
                 // use egui_scale::EguiScale;
                 // ui.style_mut().scale(2.0);
 
                 egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.presets_view
+                        .show(ui, &mut self.presets, &mut self.settings, &mut self.style);
                     egui_probe::Probe::new(&mut self.style).show(ui);
                     egui_probe::Probe::new(&mut self.settings).show(ui);
                 });
             });
         }
 
+        if self.settings.show_scene_stats {
+            egui::SidePanel::right("scene_stats").show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    SceneNode::stats_view(ui, &self.snarl);
+                });
+            });
+        }
+
         ctx.style_mut(|style| style.animation_time = self.settings.animation_time);
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.input(|i| {
-                if i.key_pressed(Key::Tab) {
-                    self.settings.edit_mode.switch();
-                }
-                if i.key_pressed(Key::H) {
+            if shortcuts_enabled {
+                let bindings = &self.settings.key_bindings;
+                if ui.input(|i| bindings.pressed(Action::ToggleNodes, i)) {
                     self.settings.show_nodes = !self.settings.show_nodes;
                 }
-            });
+            }
 
             let last_panel_rect = ui.min_rect();
 
@@ -161,6 +556,10 @@ impl App for NodedApp {
                     .max_rect(last_panel_rect)
                     .sense(Sense::empty()),
             );
+            self.viewer.set_render_stats(self.settings.show_render_stats);
+            self.viewer.set_frame_timings(self.settings.show_frame_timings);
+            self.viewer.set_bloom(self.settings.bloom);
+            self.viewer.set_viewport_clear_color(self.settings.viewport_clear_color);
             self.viewer
                 .draw(&last_panel_rect, render_area_ui.painter(), &mut self.snarl);
 
@@ -181,11 +580,22 @@ impl App for NodedApp {
                 };
                 editing_area_ui.set_opacity(opacity);
 
-                SnarlWidget::new().id(Id::new("noded")).style(self.style).show(
+                self.viewer.set_wire_labels(self.settings.show_wire_labels);
+
+                SnarlWidget::new().id(snarl_id()).style(self.style).show(
                     &mut self.snarl,
                     &mut self.viewer,
                     &mut editing_area_ui,
                 );
+
+                let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+                if !dropped_files.is_empty() {
+                    let pos = ctx
+                        .input(|i| i.pointer.latest_pos())
+                        .unwrap_or(last_panel_rect.center());
+                    self.viewer
+                        .insert_dropped_textures(&dropped_files, pos, &mut self.snarl);
+                }
             }
 
             if let EditMode::View = self.settings.edit_mode {
@@ -199,9 +609,29 @@ impl App for NodedApp {
                 let overlay_response =
                     render_area_ui.interact(last_panel_rect, Id::new("overlay_blocker"), Sense::click_and_drag());
 
-                self.viewer.after_show(ui, &overlay_response, &mut self.snarl);
+                self.viewer.after_show(
+                    ui,
+                    &overlay_response,
+                    &mut self.snarl,
+                    &self.settings.key_bindings,
+                    shortcuts_enabled,
+                );
+            }
+
+            if self.settings.show_mode_indicator {
+                let painter = ui
+                    .ctx()
+                    .layer_painter(LayerId::new(Order::Foreground, Id::new("mode_indicator")));
+                self.draw_mode_indicator(&painter, last_panel_rect);
             }
         });
+
+        self.animation_window.advance(ctx, &mut self.snarl);
+        self.update_render_complete(ctx);
+
+        if let Some(snapshot) = self.viewer.take_snapshot() {
+            self.history.push(snapshot);
+        }
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
@@ -213,9 +643,19 @@ impl App for NodedApp {
 
         let settings = serde_json::to_string(&self.settings).unwrap();
         storage.set_string("settings", settings);
+
+        let presets = serde_json::to_string(&self.presets).unwrap();
+        storage.set_string("presets", presets);
+
+        let graph_view = serde_json::to_string(&self.viewer.graph_view()).unwrap();
+        storage.set_string("graph_view", graph_view);
     }
 }
 
+fn snarl_id() -> Id {
+    Id::new("noded")
+}
+
 const fn default_style() -> SnarlStyle {
     SnarlStyle {
         node_layout: Some(NodeLayout::FlippedSandwich),
@@ -246,3 +686,130 @@ const fn default_style() -> SnarlStyle {
         ..SnarlStyle::new()
     }
 }
+
+/// Spheres of [`Scene::test`] in a collection, seen by the default camera through a raytracer render on an output.
+fn default_graph(max_viewport_resolution: u32) -> Snarl<Node> {
+    const ROW_HEIGHT: f32 = 120.0;
+
+    let mut snarl = Snarl::new();
+    let scene = Scene::test();
+    let output = |node| OutPinId { node, output: 0 };
+    let input = |node, input| InPinId { node, input };
+
+    let mut collection = CollectionNode::default();
+    let mut sphere_ids = Vec::new();
+    for (idx, sphere) in scene.spheres.iter().enumerate() {
+        let pos = pos2(-900.0, idx as f32 * ROW_HEIGHT);
+        let node = Node::Primitive(PrimitiveNode::Sphere(sphere_node(&scene, sphere)));
+        let sphere_id = snarl.insert_node(pos, node);
+        collection.insert(idx, sphere_id);
+        sphere_ids.push(sphere_id);
+    }
+    let collection_id = snarl.insert_node(pos2(-600.0, 0.0), Node::Collection(collection));
+    for (idx, sphere_id) in sphere_ids.into_iter().enumerate() {
+        snarl.connect(output(sphere_id), input(collection_id, idx));
+    }
+
+    let scene_id = snarl.insert_node(pos2(-300.0, 0.0), Node::Scene(SceneNode::default()));
+    let camera_id = snarl.insert_node(pos2(-300.0, -300.0), Node::Camera(CameraNode::default()));
+    let render = RaytracerRenderNode::new(max_viewport_resolution);
+    let render_id = snarl.insert_node(pos2(0.0, 0.0), Node::Render(RenderNode::Raytracer(render)));
+    let output_id = snarl.insert_node(pos2(300.0, 0.0), Node::Output(OutputNode::default()));
+
+    snarl.connect(output(collection_id), input(scene_id, 0));
+    snarl.connect(output(camera_id), input(render_id, 3));
+    snarl.connect(output(scene_id), input(render_id, 4));
+    snarl.connect(output(render_id), input(output_id, 0));
+    snarl
+}
+
+/// Node with the material of `sphere`, image textures become a gray of their brightness.
+fn sphere_node(scene: &Scene, sphere: &Sphere) -> SphereNode {
+    let linear = |texture: TextureId| {
+        let data = &scene.textures[texture];
+        match data.key {
+            None => data.texture.as_slice()[0],
+            Some(_) => [0.5 * data.scale; 3],
+        }
+    };
+    let color = |texture: TextureId| {
+        let [r, g, b] = linear(texture);
+        Color::from(egui::Rgba::from_rgb(r, g, b))
+    };
+
+    let material = match scene.materials[sphere.material_idx() as usize] {
+        Material::Metal { albedo, fuzz, .. } => {
+            let mut node = MetalNode::default();
+            node.albedo = NodePin::new(color(albedo));
+            node.fuzz = NodePin::new(fuzz.into());
+            MaterialNode::Metal(node)
+        },
+        Material::Dielectric { refraction_index, .. } => {
+            let mut node = DielectricNode::default();
+            node.ior = NodePin::new(refraction_index.into());
+            MaterialNode::Dielectric(node)
+        },
+        Material::Checkerboard {
+            even,
+            odd,
+            scale,
+            space,
+        } => {
+            let mut node = CheckerboardNode::default();
+            node.even = NodePin::new(color(even));
+            node.odd = NodePin::new(color(odd));
+            node.scale = NodePin::new(scale.into());
+            node.space = space;
+            MaterialNode::Checkerboard(node)
+        },
+        Material::Emissive { emit, two_sided } => {
+            let mut node = EmissiveNode::default();
+            node.emit = NodePin::new(Vector3f32::from(linear(emit)).cast());
+            node.two_sided = two_sided;
+            MaterialNode::Emissive(node)
+        },
+        Material::Lambertian { albedo, .. } => {
+            let mut node = LambertianNode::default();
+            node.albedo = NodePin::new(color(albedo));
+            MaterialNode::Lambertian(node)
+        },
+        Material::Isotropic { .. } | Material::Subsurface { .. } | Material::Mix { .. } => {
+            MaterialNode::Lambertian(LambertianNode::default())
+        },
+    };
+
+    let (center, radius) = sphere.bounding_sphere();
+    let mut node = SphereNode::default();
+    node.center = NodePin::new(center.cast());
+    node.radius = NodePin::new(radius.into());
+    node.material = NodePin::new(InputMaterial::Internal(material));
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_graph_renders_the_test_scene() {
+        let snarl = default_graph(512);
+        let remote = |node, input| {
+            let remotes = snarl.in_pin(InPinId { node, input }).remotes;
+            assert_eq!(remotes.len(), 1, "input {input} of {}", snarl[node].name());
+            remotes[0].node
+        };
+
+        let (output_id, _) = snarl
+            .node_ids()
+            .find(|(_, node)| matches!(node, Node::Output(_)))
+            .expect("an output node");
+        let render_id = remote(output_id, 0);
+        assert!(matches!(snarl[render_id], Node::Render(RenderNode::Raytracer(_))));
+        assert!(matches!(snarl[remote(render_id, 3)], Node::Camera(_)));
+
+        let scene_id = remote(render_id, 4);
+        assert!(matches!(snarl[scene_id], Node::Scene(_)));
+        let collection = snarl[remote(scene_id, 0)].as_collection_node_ref();
+        assert_eq!(collection.to_node_ids().len(), Scene::test().spheres.len());
+    }
+}
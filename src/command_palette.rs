@@ -0,0 +1,99 @@
+use egui::{Align2, Key, TextEdit};
+
+pub type CommandFn<T> = fn(&mut T, &egui::Context, &mut eframe::Frame);
+
+/// Named action that can be searched and executed from the [`CommandPalette`].
+pub struct Command<T> {
+    pub name: &'static str,
+    pub run: CommandFn<T>,
+}
+
+impl<T> Command<T> {
+    pub const fn new(name: &'static str, run: CommandFn<T>) -> Self {
+        Self { name, run }
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        self.name.to_lowercase().contains(&query.to_lowercase())
+    }
+}
+
+/// Overlay with a search field listing every command that matches the typed text.
+#[derive(Debug, Default)]
+pub struct CommandPalette {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    /// Returns the index of the command chosen this frame.
+    pub fn show<T>(&mut self, ctx: &egui::Context, commands: &[Command<T>]) -> Option<usize> {
+        if !self.open {
+            return None;
+        }
+
+        let matching = commands
+            .iter()
+            .enumerate()
+            .filter(|(_, command)| command.matches(&self.query))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+
+        let (up, down, enter, escape) = ctx.input(|i| {
+            (
+                i.key_pressed(Key::ArrowUp),
+                i.key_pressed(Key::ArrowDown),
+                i.key_pressed(Key::Enter),
+                i.key_pressed(Key::Escape),
+            )
+        });
+
+        if down {
+            self.selected = (self.selected + 1).min(matching.len().saturating_sub(1));
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(matching.len().saturating_sub(1));
+
+        let mut chosen = if enter { matching.get(self.selected).copied() } else { None };
+
+        egui::Window::new("Command palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_TOP, [0.0, 32.0])
+            .show(ctx, |ui| {
+                let response = ui.add(TextEdit::singleline(&mut self.query).hint_text("Type a command..."));
+                if response.changed() {
+                    self.selected = 0;
+                }
+                response.request_focus();
+
+                ui.separator();
+
+                if matching.is_empty() {
+                    ui.weak("No matching commands");
+                }
+
+                for (position, &idx) in matching.iter().enumerate() {
+                    if ui.selectable_label(position == self.selected, commands[idx].name).clicked() {
+                        chosen = Some(idx);
+                    }
+                }
+            });
+
+        if chosen.is_some() || escape {
+            self.toggle();
+        }
+
+        chosen
+    }
+}
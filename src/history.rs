@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+/// Undo and redo stacks of serialized node graphs.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: VecDeque<String>,
+    redo: Vec<String>,
+}
+
+impl History {
+    /// Oldest snapshots are dropped once the undo stack grows past this.
+    pub const MAX_ENTRIES: usize = 50;
+
+    /// Records the graph as it was before an edit. Any undone edits can no longer be redone.
+    pub fn push(&mut self, snapshot: String) {
+        if self.undo.back() == Some(&snapshot) {
+            return;
+        }
+
+        if self.undo.len() == Self::MAX_ENTRIES {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(snapshot);
+        self.redo.clear();
+    }
+
+    /// Returns the graph to restore, `current` becomes redoable.
+    pub fn undo(&mut self, current: String) -> Option<String> {
+        let snapshot = self.undo.pop_back()?;
+        self.redo.push(current);
+        Some(snapshot)
+    }
+
+    /// Returns the graph to restore, `current` becomes undoable again.
+    pub fn redo(&mut self, current: String) -> Option<String> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push_back(current);
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = History::default();
+        history.push("a".to_owned());
+        history.push("b".to_owned());
+
+        assert_eq!(history.undo("c".to_owned()).as_deref(), Some("b"));
+        assert_eq!(history.undo("b".to_owned()).as_deref(), Some("a"));
+        assert_eq!(history.undo("a".to_owned()), None);
+        assert_eq!(history.redo("a".to_owned()).as_deref(), Some("b"));
+
+        history.push("x".to_owned());
+        assert_eq!(history.redo("y".to_owned()), None);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = History::default();
+        for idx in 0..History::MAX_ENTRIES + 10 {
+            history.push(idx.to_string());
+        }
+
+        let mut count = 0;
+        while history.undo(String::new()).is_some() {
+            count += 1;
+        }
+        assert_eq!(count, History::MAX_ENTRIES);
+    }
+}
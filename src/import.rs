@@ -0,0 +1,333 @@
+use std::path::Path;
+
+use egui::{Pos2, pos2};
+use egui_snarl::ui::SnarlViewer;
+use egui_snarl::{InPinId, OutPinId, Snarl};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::node::Node;
+use crate::node::collection::CollectionNode;
+use crate::node::material::{DielectricNode, EmissiveNode, InputMaterial, LambertianNode, MaterialNode, MetalNode};
+use crate::node::primitive::{PrimitiveNode, SphereNode};
+use crate::node::viewer::NodeViewer;
+use crate::types::{Color, NodePin, Vector3};
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("failed to read file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("expected a JSON array of spheres: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported file extension, expected `.csv` or `.json`")]
+    UnsupportedFormat,
+}
+
+/// Malformed entry that was skipped during the import.
+#[derive(Error, Debug, PartialEq)]
+#[error("row {row}: {message}")]
+pub struct RowError {
+    /// One-based line number for CSV, one-based array index for JSON.
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ImportedMaterial {
+    Lambertian { albedo: [f64; 3] },
+    Metal { albedo: [f64; 3], fuzz: f64 },
+    Dielectric { ior: f64 },
+    Emissive { emit: [f64; 3] },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ImportedSphere {
+    pub center: [f64; 3],
+    pub radius: f64,
+    pub material: ImportedMaterial,
+}
+
+#[derive(Debug, Default)]
+pub struct SphereImport {
+    pub spheres: Vec<ImportedSphere>,
+    pub errors: Vec<RowError>,
+}
+
+impl SphereImport {
+    /// Reads spheres from a `.csv` or `.json` file, depending on its extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ImportError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Ok(Self::from_csv(&text)),
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::from_json(&text),
+            _ => Err(ImportError::UnsupportedFormat),
+        }
+    }
+
+    /// Every line is `x, y, z, radius, material, parameters...` with parameters depending on the material:
+    /// `lambertian r g b`, `metal r g b fuzz`, `dielectric ior` or `emissive r g b`.
+    /// Empty lines, lines starting with `#` and a header line are skipped.
+    pub fn from_csv(text: &str) -> Self {
+        let mut import = Self::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            if idx == 0 && fields[0].parse::<f64>().is_err() {
+                continue;
+            }
+
+            match parse_csv_row(&fields) {
+                Ok(sphere) => import.spheres.push(sphere),
+                Err(message) => import.errors.push(RowError { row: idx + 1, message }),
+            }
+        }
+
+        import
+    }
+
+    /// Expects an array of `{ "center": [x, y, z], "radius": r, "material": { "type": "metal", ... } }`.
+    pub fn from_json(text: &str) -> Result<Self, ImportError> {
+        let rows = serde_json::from_str::<Vec<serde_json::Value>>(text)?;
+        let mut import = Self::default();
+
+        for (idx, row) in rows.into_iter().enumerate() {
+            match serde_json::from_value::<ImportedSphere>(row) {
+                Ok(sphere) => match validate(&sphere) {
+                    Ok(()) => import.spheres.push(sphere),
+                    Err(message) => import.errors.push(RowError { row: idx + 1, message }),
+                },
+                Err(err) => import.errors.push(RowError {
+                    row: idx + 1,
+                    message: err.to_string(),
+                }),
+            }
+        }
+
+        Ok(import)
+    }
+
+    /// Adds a sphere node for every imported sphere, all connected to a new collection node.
+    pub fn insert_nodes(&self, viewer: &mut NodeViewer, snarl: &mut Snarl<Node>, origin: Pos2) {
+        const ROW_HEIGHT: f32 = 120.0;
+
+        viewer.record_snapshot(snarl);
+        let collection_id = snarl.insert_node(origin, Node::Collection(CollectionNode::default()));
+
+        for (idx, sphere) in self.spheres.iter().enumerate() {
+            let pos = origin + egui::vec2(-300.0, idx as f32 * ROW_HEIGHT);
+            let sphere_id = snarl.insert_node(pos, Node::Primitive(PrimitiveNode::Sphere(sphere.to_node())));
+
+            let from = snarl.out_pin(OutPinId {
+                node: sphere_id,
+                output: 0,
+            });
+            let to = snarl.in_pin(InPinId {
+                node: collection_id,
+                input: idx,
+            });
+            viewer.connect(&from, &to, snarl);
+        }
+    }
+}
+
+impl ImportedSphere {
+    fn to_node(&self) -> SphereNode {
+        let material = match self.material {
+            ImportedMaterial::Lambertian { albedo } => {
+                let mut node = LambertianNode::default();
+                node.albedo = NodePin::new(to_color(albedo));
+                MaterialNode::Lambertian(node)
+            },
+            ImportedMaterial::Metal { albedo, fuzz } => {
+                let mut node = MetalNode::default();
+                node.albedo = NodePin::new(to_color(albedo));
+                node.fuzz = NodePin::new(fuzz);
+                MaterialNode::Metal(node)
+            },
+            ImportedMaterial::Dielectric { ior } => {
+                let mut node = DielectricNode::default();
+                node.ior = NodePin::new(ior);
+                MaterialNode::Dielectric(node)
+            },
+            ImportedMaterial::Emissive { emit } => {
+                let mut node = EmissiveNode::default();
+                node.emit = NodePin::new(Vector3::from(emit));
+                MaterialNode::Emissive(node)
+            },
+        };
+
+        let mut node = SphereNode::default();
+        node.center = NodePin::new(Vector3::from(self.center));
+        node.radius = NodePin::new(self.radius);
+        node.material = NodePin::new(InputMaterial::Internal(material));
+        node
+    }
+}
+
+fn parse_csv_row(fields: &[&str]) -> Result<ImportedSphere, String> {
+    let number = |idx: usize, name: &str| -> Result<f64, String> {
+        let field = fields.get(idx).ok_or_else(|| format!("missing {name}"))?;
+        field.parse::<f64>().map_err(|_| format!("{name} `{field}` is not a number"))
+    };
+    let color = |first: usize| -> Result<[f64; 3], String> {
+        Ok([number(first, "red")?, number(first + 1, "green")?, number(first + 2, "blue")?])
+    };
+
+    let center = [number(0, "x")?, number(1, "y")?, number(2, "z")?];
+    let radius = number(3, "radius")?;
+    let material_name = fields.get(4).ok_or("missing material")?;
+
+    let (material, field_count) = match material_name.to_lowercase().as_str() {
+        "lambertian" => (ImportedMaterial::Lambertian { albedo: color(5)? }, 8),
+        "metal" => (
+            ImportedMaterial::Metal {
+                albedo: color(5)?,
+                fuzz: number(8, "fuzz")?,
+            },
+            9,
+        ),
+        "dielectric" => (ImportedMaterial::Dielectric { ior: number(5, "ior")? }, 6),
+        "emissive" => (ImportedMaterial::Emissive { emit: color(5)? }, 8),
+        name => return Err(format!("unknown material `{name}`")),
+    };
+
+    if fields.len() > field_count {
+        return Err(format!("expected {field_count} fields, got {}", fields.len()));
+    }
+
+    let sphere = ImportedSphere {
+        center,
+        radius,
+        material,
+    };
+    validate(&sphere)?;
+    Ok(sphere)
+}
+
+fn validate(sphere: &ImportedSphere) -> Result<(), String> {
+    if !sphere.center.iter().all(|value| value.is_finite()) {
+        return Err("center must be finite".to_owned());
+    }
+
+    if !(sphere.radius.is_finite() && sphere.radius > 0.0) {
+        return Err(format!("radius must be positive, got {}", sphere.radius));
+    }
+
+    Ok(())
+}
+
+fn to_color(rgb: [f64; 3]) -> Color {
+    let channel = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color::from_rgb(channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))
+}
+
+/// Window asking for a file path and listing the rows that could not be imported.
+#[derive(Debug, Default)]
+pub struct ImportWindow {
+    path: String,
+    status: Option<Result<(usize, Vec<RowError>), String>>,
+}
+
+impl ImportWindow {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        open: &mut bool,
+        viewer: &mut NodeViewer,
+        snarl: &mut Snarl<Node>,
+    ) {
+        egui::Window::new("Import spheres")
+            .open(open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Path");
+                    ui.text_edit_singleline(&mut self.path);
+                    if ui.button("Import").clicked() {
+                        self.status = Some(match SphereImport::from_file(&self.path) {
+                            Ok(import) => {
+                                import.insert_nodes(viewer, snarl, pos2(0.0, 0.0));
+                                Ok((import.spheres.len(), import.errors))
+                            },
+                            Err(err) => Err(err.to_string()),
+                        });
+                    }
+                });
+
+                match &self.status {
+                    None => {
+                        ui.weak("CSV rows: x, y, z, radius, material, parameters...");
+                    },
+                    Some(Ok((count, errors))) => {
+                        ui.label(format!("Imported {count} spheres, skipped {} rows", errors.len()));
+                        for error in errors {
+                            ui.colored_label(ui.visuals().warn_fg_color, error.to_string());
+                        }
+                    },
+                    Some(Err(err)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, err);
+                    },
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_import() {
+        let text = "x,y,z,radius,material\n\
+                    0, 1, 2, 0.5, lambertian, 0.1, 0.2, 0.3\n\
+                    # comment\n\
+                    1, 1, 1, 1, metal, 1, 1, 1, 0.2\n\
+                    2, 0, 0, 1, dielectric, 1.5\n\
+                    3, 0, 0, 1, emissive, 4, 4, 4\n";
+
+        let import = SphereImport::from_csv(text);
+        assert!(import.errors.is_empty(), "{:?}", import.errors);
+        assert_eq!(import.spheres.len(), 4);
+        assert_eq!(import.spheres[0].center, [0.0, 1.0, 2.0]);
+        assert_eq!(import.spheres[2].material, ImportedMaterial::Dielectric { ior: 1.5 });
+    }
+
+    #[test]
+    fn test_csv_reports_malformed_rows() {
+        let text = "0, 0, 0, 1, lambertian, 1, 1, 1\n\
+                    0, 0, zero, 1, lambertian, 1, 1, 1\n\
+                    0, 0, 0, -1, lambertian, 1, 1, 1\n\
+                    0, 0, 0, 1, plastic\n\
+                    0, 0, 0, 1, metal, 1, 1, 1\n";
+
+        let import = SphereImport::from_csv(text);
+        assert_eq!(import.spheres.len(), 1);
+        assert_eq!(
+            import.errors.iter().map(|error| error.row).collect::<Vec<_>>(),
+            [2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_json_import() {
+        let text = r#"[
+            { "center": [0, 0, 0], "radius": 1, "material": { "type": "metal", "albedo": [1, 1, 1], "fuzz": 0 } },
+            { "center": [0, 0], "radius": 1, "material": { "type": "dielectric", "ior": 1.5 } },
+            { "center": [0, 0, 0], "radius": 2, "material": { "type": "emissive", "emit": [5, 5, 5] } }
+        ]"#;
+
+        let import = SphereImport::from_json(text).unwrap();
+        assert_eq!(import.spheres.len(), 2);
+        assert_eq!(import.errors.len(), 1);
+        assert_eq!(import.errors[0].row, 2);
+    }
+}
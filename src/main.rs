@@ -5,9 +5,15 @@ use eframe::wgpu;
 
 use self::app::NodedApp;
 
+mod animation;
 mod app;
+mod command_palette;
+mod history;
+mod import;
 mod node;
+mod presets;
 mod raytracer;
+mod shortcuts;
 mod types;
 mod widget;
 
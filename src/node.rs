@@ -2,26 +2,40 @@ use bitflags::bitflags;
 use eframe::wgpu::naga::FastIndexSet;
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, NodeId, Snarl};
-use serde::{Deserialize, Serialize};
+use egui_snarl::{InPin, NodeId, OutPin, Snarl};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use self::background::BackgroundNode;
 use self::camera::CameraNode;
 use self::collection::CollectionNode;
-use self::expression::ExpressionNode;
-use self::material::{CheckerboardNode, DielectricNode, EmissiveNode, LambertianNode, MaterialNode, MetalNode};
+use self::color::{ColorMixNode, HsvColorNode};
+use self::curve::CurveNode;
+use self::expression::{EvalError, ExpressionNode};
+use self::light::{DirectionalLightNode, LightNode, PointLightNode, SpotLightNode};
+use self::material::{
+    CheckerboardNode, DielectricNode, EmissiveNode, IsotropicNode, LambertianNode, MaterialNode, MetalNode,
+    MixMaterialNode, SubsurfaceNode,
+};
 use self::message::{CommonNodeMessage, CommonNodeResponse, InputMessage, MessageHandling, SelfNodeMut};
-use self::primitive::{PrimitiveNode, SphereNode};
+use self::primitive::{CsgNode, InstanceNode, PrimitiveNode, SphereNode, TransformNode};
 use self::render::RenderNode;
-use self::render::raytracer::RaytracerRenderNode;
+use self::render::raytracer::{LockedResolution, RaytracerRenderNode};
 use self::render::triangle::TriangleRenderNode;
 use self::scene::SceneNode;
-use self::texture::TextureNode;
-use self::viewer::{NodeConfig, empty_input_view};
+use self::texture::{NoiseTextureNode, TextureNode};
+use self::vecmath::{VectorMathNode, VectorMathOp};
+use self::viewer::{
+    NodeConfig, empty_input_view, format_float, format_vector, number_input_remote_value, number_input_view,
+};
 use crate::types::{Color, Vector3};
 
+pub mod background;
 pub mod camera;
 pub mod collection;
+pub mod color;
+pub mod curve;
 pub mod expression;
+pub mod light;
 pub mod material;
 pub mod message;
 pub mod primitive;
@@ -29,6 +43,7 @@ pub mod render;
 pub mod scene;
 pub mod subscribtion;
 pub mod texture;
+pub mod vecmath;
 pub mod viewer;
 
 bitflags! {
@@ -39,17 +54,29 @@ bitflags! {
         const MATERIAL_LAMBERT = Self::MATERIAL_DIELECTRIC.bits() << 1;
         const MATERIAL_EMISSIVE = Self::MATERIAL_LAMBERT.bits() << 1;
         const MATERIAL_CHECKERBOARD = Self::MATERIAL_EMISSIVE.bits() << 1;
-        const MATERIALS = Self::MATERIAL_METAL.bits() | Self::MATERIAL_DIELECTRIC.bits() | Self::MATERIAL_LAMBERT.bits() | Self::MATERIAL_EMISSIVE.bits() | Self::MATERIAL_CHECKERBOARD.bits();
+        const MATERIAL_ISOTROPIC = Self::MATERIAL_CHECKERBOARD.bits() << 1;
+        const MATERIAL_SUBSURFACE = Self::MATERIAL_ISOTROPIC.bits() << 1;
+        const MATERIAL_MIX = Self::MATERIAL_SUBSURFACE.bits() << 1;
+        const MATERIALS = Self::MATERIAL_METAL.bits() | Self::MATERIAL_DIELECTRIC.bits() | Self::MATERIAL_LAMBERT.bits() | Self::MATERIAL_EMISSIVE.bits() | Self::MATERIAL_CHECKERBOARD.bits() | Self::MATERIAL_ISOTROPIC.bits() | Self::MATERIAL_SUBSURFACE.bits() | Self::MATERIAL_MIX.bits();
 
-        const TEXTURE = Self::MATERIAL_CHECKERBOARD.bits() << 1;
+        const TEXTURE = Self::MATERIAL_MIX.bits() << 1;
 
         const PRIMITIVE_SPHERE = Self::TEXTURE.bits() << 1;
-        const PRIMITIVES = Self::PRIMITIVE_SPHERE.bits();
+        const PRIMITIVE_CSG = Self::PRIMITIVE_SPHERE.bits() << 1;
+        const PRIMITIVE_TRANSFORM = Self::PRIMITIVE_CSG.bits() << 1;
+        const PRIMITIVE_INSTANCE = Self::PRIMITIVE_TRANSFORM.bits() << 1;
+        const PRIMITIVES = Self::PRIMITIVE_SPHERE.bits() | Self::PRIMITIVE_CSG.bits() | Self::PRIMITIVE_TRANSFORM.bits() | Self::PRIMITIVE_INSTANCE.bits();
 
-        const COLLECTION = Self::PRIMITIVE_SPHERE.bits() << 1;
+        const LIGHT_POINT = Self::PRIMITIVE_INSTANCE.bits() << 1;
+        const LIGHT_DIRECTIONAL = Self::LIGHT_POINT.bits() << 1;
+        const LIGHT_SPOT = Self::LIGHT_DIRECTIONAL.bits() << 1;
+        const LIGHTS = Self::LIGHT_POINT.bits() | Self::LIGHT_DIRECTIONAL.bits() | Self::LIGHT_SPOT.bits();
+
+        const COLLECTION = Self::LIGHT_SPOT.bits() << 1;
         const CAMERA = Self::COLLECTION.bits() << 1;
+        const BACKGROUND = Self::CAMERA.bits() << 1;
 
-        const SCENE = Self::CAMERA.bits() << 1;
+        const SCENE = Self::BACKGROUND.bits() << 1;
 
         const RENDER_TRIANGLE = Self::SCENE.bits() << 1;
         const RENDER_RAYTRACER = Self::RENDER_TRIANGLE.bits() << 1;
@@ -64,9 +91,12 @@ bitflags! {
 
         const EXPRESSION = Self::VECTOR.bits() << 1;
 
+        const INTEGER = Self::EXPRESSION.bits() << 1;
+        const BOOL = Self::INTEGER.bits() << 1;
+
         const ALL = u64::MAX;
-        const TYPICAL_VECTOR_INPUT = NodeFlags::VECTOR.bits() | NodeFlags::COLOR.bits() | NodeFlags::NUMBER.bits() | NodeFlags::EXPRESSION.bits();
-        const TYPICAL_NUMBER_INPUT = NodeFlags::NUMBER.bits() | NodeFlags::EXPRESSION.bits();
+        const TYPICAL_VECTOR_INPUT = NodeFlags::VECTOR.bits() | NodeFlags::COLOR.bits() | NodeFlags::NUMBER.bits() | NodeFlags::INTEGER.bits() | NodeFlags::EXPRESSION.bits();
+        const TYPICAL_NUMBER_INPUT = NodeFlags::NUMBER.bits() | NodeFlags::INTEGER.bits() | NodeFlags::BOOL.bits() | NodeFlags::EXPRESSION.bits();
     }
 }
 
@@ -74,23 +104,38 @@ bitflags! {
 pub enum Node {
     Material(MaterialNode),
     Texture(TextureNode),
+    NoiseTexture(NoiseTextureNode),
     Primitive(PrimitiveNode),
+    Light(LightNode),
     Collection(CollectionNode),
     Camera(CameraNode),
+    Background(BackgroundNode),
     Scene(SceneNode),
     Render(RenderNode),
-    Output(OutputNode),
+    Output(#[serde(deserialize_with = "OutputNode::deserialize_or_unit")] OutputNode),
     Number(f64),
+    Integer(i64),
+    Bool(bool),
     String(String),
     Color(Color),
     Vector(Vector3),
     Expression(ExpressionNode),
+    Curve(CurveNode),
+    VectorMath(VectorMathNode),
+    ColorMix(ColorMixNode),
+    HsvColor(HsvColorNode),
 }
 
 impl Node {
     const NUMBER_NAME: &str = "Number";
     const NUMBER_OUTPUTS: [u64; 1] = [NodeFlags::NUMBER.bits()];
 
+    const INTEGER_NAME: &str = "Integer";
+    const INTEGER_OUTPUTS: [u64; 1] = [NodeFlags::INTEGER.bits()];
+
+    const BOOL_NAME: &str = "Bool";
+    const BOOL_OUTPUTS: [u64; 1] = [NodeFlags::BOOL.bits()];
+
     const STRING_NAME: &str = "String";
     const STRING_OUTPUTS: [u64; 1] = [NodeFlags::STRING.bits()];
 
@@ -133,18 +178,78 @@ impl Node {
                 CheckerboardNode::INPUTS.as_slice(),
                 CheckerboardNode::OUTPUTS.as_slice(),
             ),
+            (
+                IsotropicNode::NAME,
+                |_| Node::Material(MaterialNode::Isotropic(IsotropicNode::default())),
+                IsotropicNode::INPUTS.as_slice(),
+                IsotropicNode::OUTPUTS.as_slice(),
+            ),
+            (
+                SubsurfaceNode::NAME,
+                |_| Node::Material(MaterialNode::Subsurface(SubsurfaceNode::default())),
+                SubsurfaceNode::INPUTS.as_slice(),
+                SubsurfaceNode::OUTPUTS.as_slice(),
+            ),
+            (
+                MixMaterialNode::NAME,
+                |_| Node::Material(MaterialNode::Mix(MixMaterialNode::default())),
+                MixMaterialNode::INPUTS.as_slice(),
+                MixMaterialNode::OUTPUTS.as_slice(),
+            ),
             (
                 TextureNode::NAME,
                 |_| Node::Texture(TextureNode::default()),
                 TextureNode::INPUTS.as_slice(),
                 TextureNode::OUTPUTS.as_slice(),
             ),
+            (
+                NoiseTextureNode::NAME,
+                |_| Node::NoiseTexture(NoiseTextureNode::default()),
+                NoiseTextureNode::INPUTS.as_slice(),
+                NoiseTextureNode::OUTPUTS.as_slice(),
+            ),
             (
                 SphereNode::NAME,
                 |_| Node::Primitive(PrimitiveNode::Sphere(SphereNode::default())),
                 SphereNode::INPUTS.as_slice(),
                 SphereNode::OUTPUTS.as_slice(),
             ),
+            (
+                CsgNode::NAME,
+                |_| Node::Primitive(PrimitiveNode::Csg(CsgNode::default())),
+                CsgNode::INPUTS.as_slice(),
+                CsgNode::OUTPUTS.as_slice(),
+            ),
+            (
+                TransformNode::NAME,
+                |_| Node::Primitive(PrimitiveNode::Transform(TransformNode::default())),
+                TransformNode::INPUTS.as_slice(),
+                TransformNode::OUTPUTS.as_slice(),
+            ),
+            (
+                InstanceNode::NAME,
+                |_| Node::Primitive(PrimitiveNode::Instance(InstanceNode::default())),
+                InstanceNode::INPUTS.as_slice(),
+                InstanceNode::OUTPUTS.as_slice(),
+            ),
+            (
+                PointLightNode::NAME,
+                |_| Node::Light(LightNode::Point(PointLightNode::default())),
+                PointLightNode::INPUTS.as_slice(),
+                PointLightNode::OUTPUTS.as_slice(),
+            ),
+            (
+                DirectionalLightNode::NAME,
+                |_| Node::Light(LightNode::Directional(DirectionalLightNode::default())),
+                DirectionalLightNode::INPUTS.as_slice(),
+                DirectionalLightNode::OUTPUTS.as_slice(),
+            ),
+            (
+                SpotLightNode::NAME,
+                |_| Node::Light(LightNode::Spot(SpotLightNode::default())),
+                SpotLightNode::INPUTS.as_slice(),
+                SpotLightNode::OUTPUTS.as_slice(),
+            ),
             (
                 CollectionNode::NAME,
                 |_| Node::Collection(CollectionNode::default()),
@@ -157,6 +262,12 @@ impl Node {
                 CameraNode::INPUTS.as_slice(),
                 CameraNode::OUTPUTS.as_slice(),
             ),
+            (
+                BackgroundNode::NAME,
+                |_| Node::Background(BackgroundNode::default()),
+                BackgroundNode::INPUTS.as_slice(),
+                BackgroundNode::OUTPUTS.as_slice(),
+            ),
             (
                 SceneNode::NAME,
                 |_| Node::Scene(SceneNode::default()),
@@ -186,6 +297,8 @@ impl Node {
                 OutputNode::OUTPUTS.as_slice(),
             ),
             (Self::NUMBER_NAME, |_| Node::Number(0.0), &[], &Self::NUMBER_OUTPUTS),
+            (Self::INTEGER_NAME, |_| Node::Integer(0), &[], &Self::INTEGER_OUTPUTS),
+            (Self::BOOL_NAME, |_| Node::Bool(false), &[], &Self::BOOL_OUTPUTS),
             (
                 Self::STRING_NAME,
                 |_| Node::String(String::new()),
@@ -198,6 +311,18 @@ impl Node {
                 &[],
                 &Self::COLOR_OUTPUTS,
             ),
+            (
+                ColorMixNode::NAME,
+                |_| Node::ColorMix(ColorMixNode::default()),
+                ColorMixNode::INPUTS.as_slice(),
+                ColorMixNode::OUTPUTS.as_slice(),
+            ),
+            (
+                HsvColorNode::NAME,
+                |_| Node::HsvColor(HsvColorNode::default()),
+                HsvColorNode::INPUTS.as_slice(),
+                HsvColorNode::OUTPUTS.as_slice(),
+            ),
             (
                 Self::VECTOR_NAME,
                 |_| Node::Vector(Vector3::default()),
@@ -210,6 +335,48 @@ impl Node {
                 ExpressionNode::INPUTS.as_slice(),
                 ExpressionNode::OUTPUTS.as_slice(),
             ),
+            (
+                CurveNode::NAME,
+                |_| Node::Curve(CurveNode::default()),
+                CurveNode::INPUTS.as_slice(),
+                CurveNode::OUTPUTS.as_slice(),
+            ),
+            (
+                VectorMathOp::Add.name(),
+                |_| Node::VectorMath(VectorMathNode::new(VectorMathOp::Add)),
+                VectorMathOp::Add.inputs(),
+                VectorMathOp::Add.outputs(),
+            ),
+            (
+                VectorMathOp::Scale.name(),
+                |_| Node::VectorMath(VectorMathNode::new(VectorMathOp::Scale)),
+                VectorMathOp::Scale.inputs(),
+                VectorMathOp::Scale.outputs(),
+            ),
+            (
+                VectorMathOp::Dot.name(),
+                |_| Node::VectorMath(VectorMathNode::new(VectorMathOp::Dot)),
+                VectorMathOp::Dot.inputs(),
+                VectorMathOp::Dot.outputs(),
+            ),
+            (
+                VectorMathOp::Cross.name(),
+                |_| Node::VectorMath(VectorMathNode::new(VectorMathOp::Cross)),
+                VectorMathOp::Cross.inputs(),
+                VectorMathOp::Cross.outputs(),
+            ),
+            (
+                VectorMathOp::Normalize.name(),
+                |_| Node::VectorMath(VectorMathNode::new(VectorMathOp::Normalize)),
+                VectorMathOp::Normalize.inputs(),
+                VectorMathOp::Normalize.outputs(),
+            ),
+            (
+                VectorMathOp::Length.name(),
+                |_| Node::VectorMath(VectorMathNode::new(VectorMathOp::Length)),
+                VectorMathOp::Length.inputs(),
+                VectorMathOp::Length.outputs(),
+            ),
         ]
     }
 
@@ -220,19 +387,34 @@ impl Node {
             Self::Material(MaterialNode::Lambertian(_)) => LambertianNode::NAME,
             Self::Material(MaterialNode::Emissive(_)) => EmissiveNode::NAME,
             Self::Material(MaterialNode::Checkerboard(_)) => CheckerboardNode::NAME,
+            Self::Material(MaterialNode::Isotropic(_)) => IsotropicNode::NAME,
+            Self::Material(MaterialNode::Subsurface(_)) => SubsurfaceNode::NAME,
+            Self::Material(MaterialNode::Mix(_)) => MixMaterialNode::NAME,
             Self::Texture(_) => TextureNode::NAME,
+            Self::NoiseTexture(_) => NoiseTextureNode::NAME,
             Self::Primitive(PrimitiveNode::Sphere(_)) => SphereNode::NAME,
+            Self::Primitive(PrimitiveNode::Csg(_)) => CsgNode::NAME,
+            Self::Primitive(PrimitiveNode::Transform(_)) => TransformNode::NAME,
+            Self::Primitive(PrimitiveNode::Instance(_)) => InstanceNode::NAME,
+            Self::Light(light) => light.name(),
             Self::Collection(_) => CollectionNode::NAME,
             Self::Camera(_) => CameraNode::NAME,
+            Self::Background(_) => BackgroundNode::NAME,
             Self::Scene(_) => SceneNode::NAME,
             Self::Render(RenderNode::Triangle(_)) => TriangleRenderNode::NAME,
             Self::Render(RenderNode::Raytracer(_)) => RaytracerRenderNode::NAME,
             Self::Output(_) => OutputNode::NAME,
             Self::Number(_) => Self::NUMBER_NAME,
+            Self::Integer(_) => Self::INTEGER_NAME,
+            Self::Bool(_) => Self::BOOL_NAME,
             Self::String(_) => Self::STRING_NAME,
             Self::Color(_) => Self::COLOR_NAME,
             Self::Vector(_) => Self::VECTOR_NAME,
             Self::Expression(_) => ExpressionNode::NAME,
+            Self::Curve(_) => CurveNode::NAME,
+            Self::VectorMath(vector_math) => vector_math.name(),
+            Self::ColorMix(_) => ColorMixNode::NAME,
+            Self::HsvColor(_) => HsvColorNode::NAME,
         }
     }
 
@@ -240,17 +422,26 @@ impl Node {
         match self {
             Self::Material(material) => material.inputs(),
             Self::Texture(texture) => texture.inputs(),
+            Self::NoiseTexture(noise_texture) => noise_texture.inputs(),
             Self::Primitive(primitive) => primitive.inputs(),
+            Self::Light(light) => light.inputs(),
             Self::Collection(collection) => collection.inputs(),
             Self::Camera(camera) => camera.inputs(),
+            Self::Background(background) => background.inputs(),
             Self::Scene(scene) => scene.inputs(),
             Self::Render(render) => render.inputs(),
             Self::Output(output) => output.inputs(),
             Self::Number(_) => &[],
+            Self::Integer(_) => &[],
+            Self::Bool(_) => &[],
             Self::String(_) => &[],
             Self::Color(_) => &[],
             Self::Vector(_) => &[],
             Self::Expression(expression) => expression.inputs(),
+            Self::Curve(curve) => curve.inputs(),
+            Self::VectorMath(vector_math) => vector_math.inputs(),
+            Self::ColorMix(color_mix) => color_mix.inputs(),
+            Self::HsvColor(hsv_color) => hsv_color.inputs(),
         }
     }
 
@@ -258,17 +449,35 @@ impl Node {
         match self {
             Self::Material(material) => material.outputs(),
             Self::Texture(texture) => texture.outputs(),
+            Self::NoiseTexture(noise_texture) => noise_texture.outputs(),
             Self::Primitive(primitive) => primitive.outputs(),
+            Self::Light(light) => light.outputs(),
             Self::Collection(collection) => collection.outputs(),
             Self::Camera(camera) => camera.outputs(),
+            Self::Background(background) => background.outputs(),
             Self::Scene(scene) => scene.outputs(),
             Self::Render(render) => render.outputs(),
             Self::Output(output) => output.outputs(),
             Self::Number(_) => &Self::NUMBER_OUTPUTS,
+            Self::Integer(_) => &Self::INTEGER_OUTPUTS,
+            Self::Bool(_) => &Self::BOOL_OUTPUTS,
             Self::String(_) => &Self::STRING_OUTPUTS,
             Self::Color(_) => &Self::COLOR_OUTPUTS,
             Self::Vector(_) => &Self::VECTOR_OUTPUTS,
             Self::Expression(expression) => expression.outputs(),
+            Self::Curve(curve) => curve.outputs(),
+            Self::VectorMath(vector_math) => vector_math.outputs(),
+            Self::ColorMix(color_mix) => color_mix.outputs(),
+            Self::HsvColor(hsv_color) => hsv_color.outputs(),
+        }
+    }
+
+    /// Whether `output` of `from` can be wired into `input` of this node, see [`can_connect`].
+    /// Pins that don't exist take nothing.
+    pub fn can_accept(&self, input: usize, from: &Node, output: usize) -> bool {
+        match (from.outputs().get(output), self.inputs().get(input)) {
+            (Some(&out_flags), Some(&in_flags)) => can_connect(out_flags, in_flags),
+            _ => false,
         }
     }
 
@@ -287,25 +496,150 @@ impl Node {
         match self_node.node_ref() {
             Self::Material(_) => MaterialNode::handle_msg(self_node, msg),
             Self::Texture(_) => TextureNode::handle_msg(self_node, msg),
+            Self::NoiseTexture(_) => NoiseTextureNode::handle_msg(self_node, msg),
             Self::Primitive(_) => PrimitiveNode::handle_msg(self_node, msg),
+            Self::Light(_) => LightNode::handle_msg(self_node, msg),
             Self::Collection(_) => CollectionNode::handle_msg(self_node, msg),
             Self::Camera(_) => CameraNode::handle_msg(self_node, msg),
+            Self::Background(_) => BackgroundNode::handle_msg(self_node, msg),
             Self::Scene(_) => SceneNode::handle_msg(self_node, msg),
             Self::Render(_) => RenderNode::handle_msg(self_node, msg),
             Self::Output(_) => OutputNode::handle_msg(self_node, msg),
             Self::Expression(_) => ExpressionNode::handle_msg(self_node, msg),
+            Self::VectorMath(_) => VectorMathNode::handle_msg(self_node, msg),
+            Self::ColorMix(_) => ColorMixNode::handle_msg(self_node, msg),
+            Self::HsvColor(_) => HsvColorNode::handle_msg(self_node, msg),
             _ => None,
         }
     }
 
-    fn number_out(&self) -> f64 {
+    /// Copy of the node for pasting. Wires are not copied, so references to
+    /// connected nodes are dropped along with event subscriptions.
+    pub fn detached(&self) -> Self {
+        // Round-tripping drops the `#[serde(skip)]` state like subscriptions.
+        let mut node = serde_json::to_value(self)
+            .and_then(serde_json::from_value)
+            .unwrap_or_else(|_| self.clone());
+
+        match &mut node {
+            Self::Material(MaterialNode::Metal(metal)) => {
+                metal.texture.reset();
+                metal.normal_texture.reset();
+                metal.roughness_texture.reset();
+                metal.height_texture.reset();
+            },
+            Self::Material(MaterialNode::Dielectric(dielectric)) => dielectric.roughness_texture.reset(),
+            Self::Material(MaterialNode::Lambertian(lambertian)) => {
+                lambertian.texture.reset();
+                lambertian.normal_texture.reset();
+                lambertian.height_texture.reset();
+            },
+            Self::Material(MaterialNode::Emissive(emissive)) => emissive.texture.reset(),
+            Self::Material(MaterialNode::Mix(mix)) => mix.detach(),
+            Self::Primitive(PrimitiveNode::Sphere(sphere)) => sphere.material.reset(),
+            Self::Primitive(PrimitiveNode::Csg(csg)) => csg.detach(),
+            Self::Primitive(PrimitiveNode::Transform(transform)) => transform.detach(),
+            Self::Primitive(PrimitiveNode::Instance(instance)) => instance.detach(),
+            Self::Collection(collection) => collection.detach(),
+            Self::Scene(scene) => *scene = SceneNode::default(),
+            Self::Render(RenderNode::Raytracer(render)) => render.detach(),
+            _ => (),
+        }
+
+        node
+    }
+
+    /// Disabled nodes stay wired up but are left out when a scene is built.
+    pub fn is_disabled(&self) -> bool {
+        match self {
+            Self::Primitive(primitive) => primitive.is_disabled(),
+            Self::Light(light) => light.is_disabled(),
+            Self::Material(material) => material.is_disabled(),
+            _ => false,
+        }
+    }
+
+    /// `None` for nodes that can't be disabled.
+    pub fn disabled_mut(&mut self) -> Option<&mut bool> {
         match self {
-            Self::Number(value) => *value,
+            Self::Primitive(primitive) => Some(primitive.disabled_mut()),
+            Self::Light(light) => Some(light.disabled_mut()),
+            Self::Material(material) => Some(material.disabled_mut()),
+            _ => None,
+        }
+    }
+
+    /// Collapsed nodes hide their input widgets and show only the title and the pins. Value nodes are nothing
+    /// but the widget of their output, so they have nothing to collapse.
+    pub fn collapsed_mut(&mut self) -> Option<&mut bool> {
+        match self {
+            Self::Primitive(primitive) => Some(primitive.collapsed_mut()),
+            Self::Light(light) => Some(light.collapsed_mut()),
+            Self::Material(material) => Some(material.collapsed_mut()),
+            Self::Camera(camera) => Some(&mut camera.collapsed),
+            Self::Render(RenderNode::Raytracer(render)) => Some(&mut render.collapsed),
+            Self::Render(RenderNode::Triangle(render)) => Some(&mut render.collapsed),
+            Self::Texture(texture) => Some(&mut texture.collapsed),
+            Self::NoiseTexture(noise) => Some(&mut noise.collapsed),
+            Self::Collection(collection) => Some(&mut collection.collapsed),
+            Self::Background(background) => Some(&mut background.collapsed),
+            Self::Scene(scene) => Some(&mut scene.collapsed),
+            Self::Output(output) => Some(&mut output.collapsed),
+            Self::Expression(expression) => Some(&mut expression.collapsed),
+            Self::Curve(curve) => Some(&mut curve.collapsed),
+            Self::VectorMath(vector_math) => Some(&mut vector_math.collapsed),
+            Self::ColorMix(color_mix) => Some(&mut color_mix.collapsed),
+            Self::HsvColor(hsv_color) => Some(&mut hsv_color.collapsed),
+            Self::Number(_) | Self::Integer(_) | Self::Bool(_) | Self::String(_) | Self::Color(_) | Self::Vector(_) => {
+                None
+            },
+        }
+    }
+
+    fn number_out(&self) -> Result<f64, EvalError> {
+        match self {
+            Self::Number(value) => Ok(*value),
+            Self::Integer(value) => Ok(*value as f64),
+            Self::Bool(value) => Ok(f64::from(u8::from(*value))),
             Self::Expression(expr_node) => expr_node.eval(),
+            Self::Curve(curve) => Ok(curve.number_out()),
+            Self::VectorMath(vector_math) => Ok(vector_math.number_out()),
+            _ => unreachable!(),
+        }
+    }
+
+    fn vector_out(&self) -> Result<Vector3, EvalError> {
+        match self {
+            Self::Number(value) => Ok(Vector3::new(*value, *value, *value)),
+            Self::Integer(value) => Ok(Vector3::repeat(*value as f64)),
+            Self::Vector(vector) => Ok(*vector),
+            Self::Expression(expr_node) => expr_node.eval_vector(),
+            Self::Curve(curve) => Ok(Vector3::repeat(curve.number_out())),
+            Self::VectorMath(vector_math) => Ok(vector_math.vector_out()),
             _ => unreachable!(),
         }
     }
 
+    /// Short text describing the value a node outputs, shown on its wires.
+    fn value_label(&self) -> Option<String> {
+        match self {
+            Self::Expression(expr_node) if expr_node.is_vector() => {
+                Some(self.vector_out().map_or_else(|_| "err".to_owned(), format_vector))
+            },
+            Self::Number(_) | Self::Expression(_) | Self::Curve(_) => {
+                Some(self.number_out().map_or_else(|_| "err".to_owned(), format_float))
+            },
+            Self::Integer(value) => Some(value.to_string()),
+            Self::Bool(value) => Some(value.to_string()),
+            Self::Vector(_) => Some(self.vector_out().map_or_else(|_| "err".to_owned(), format_vector)),
+            Self::VectorMath(vector_math) if vector_math.op.outputs_number() => {
+                Some(format_float(vector_math.number_out()))
+            },
+            Self::VectorMath(vector_math) => Some(format_vector(vector_math.vector_out())),
+            _ => None,
+        }
+    }
+
     fn string_out(&self) -> &str {
         match self {
             Self::String(value) => value,
@@ -341,6 +675,13 @@ impl Node {
         }
     }
 
+    fn as_noise_texture_node_mut(&mut self) -> &mut NoiseTextureNode {
+        match self {
+            Self::NoiseTexture(noise_texture_node) => noise_texture_node,
+            node => panic!("Node `{}` is not a `{}`", node.name(), NoiseTextureNode::NAME),
+        }
+    }
+
     fn primitive_node_ref(&self) -> Option<&PrimitiveNode> {
         match self {
             Self::Primitive(primitive_node) => Some(primitive_node),
@@ -360,6 +701,20 @@ impl Node {
         }
     }
 
+    fn as_light_node_ref(&self) -> &LightNode {
+        match self {
+            Self::Light(light_node) => light_node,
+            node => panic!("Node `{}` is not a `{}`", node.name(), LightNode::NAME),
+        }
+    }
+
+    fn as_light_node_mut(&mut self) -> &mut LightNode {
+        match self {
+            Self::Light(light_node) => light_node,
+            node => panic!("Node `{}` is not a `{}`", node.name(), LightNode::NAME),
+        }
+    }
+
     fn collection_node_ref(&self) -> Option<&CollectionNode> {
         match self {
             Self::Collection(collection_node) => Some(collection_node),
@@ -407,6 +762,20 @@ impl Node {
         }
     }
 
+    fn background_node_ref(&self) -> Option<&BackgroundNode> {
+        match self {
+            Self::Background(background_node) => Some(background_node),
+            _ => None,
+        }
+    }
+
+    fn as_background_node_mut(&mut self) -> &mut BackgroundNode {
+        match self {
+            Self::Background(background_node) => background_node,
+            node => panic!("Node `{}` is not a `{}`", node.name(), BackgroundNode::NAME),
+        }
+    }
+
     fn as_scene_node_ref(&self) -> &SceneNode {
         match self {
             Self::Scene(scene_node) => scene_node,
@@ -428,6 +797,13 @@ impl Node {
         }
     }
 
+    fn render_node_mut(&mut self) -> Option<&mut RenderNode> {
+        match self {
+            Self::Render(render_node) => Some(render_node),
+            _ => None,
+        }
+    }
+
     fn as_render_node_ref(&self) -> &RenderNode {
         self.render_node_ref()
             .unwrap_or_else(|| panic!("Node `{}` is not a `{}`", self.name(), RenderNode::NAME))
@@ -447,12 +823,46 @@ impl Node {
         }
     }
 
+    fn as_output_node_mut(&mut self) -> &mut OutputNode {
+        match self {
+            Self::Output(output_node) => output_node,
+            node => panic!("Node `{}` is not an `{}`", node.name(), OutputNode::NAME),
+        }
+    }
+
     fn as_expression_node_mut(&mut self) -> &mut ExpressionNode {
         match self {
             Self::Expression(expr_node) => expr_node,
             node => panic!("Node `{}` is not an `{}`", node.name(), ExpressionNode::NAME),
         }
     }
+
+    fn as_vector_math_node_mut(&mut self) -> &mut VectorMathNode {
+        match self {
+            Self::VectorMath(vector_math) => vector_math,
+            node => panic!("Node `{}` is not a vector math node", node.name()),
+        }
+    }
+
+    fn as_color_mix_node_mut(&mut self) -> &mut ColorMixNode {
+        match self {
+            Self::ColorMix(color_mix) => color_mix,
+            node => panic!("Node `{}` is not a `{}`", node.name(), ColorMixNode::NAME),
+        }
+    }
+
+    fn as_hsv_color_node_mut(&mut self) -> &mut HsvColorNode {
+        match self {
+            Self::HsvColor(hsv_color) => hsv_color,
+            node => panic!("Node `{}` is not a `{}`", node.name(), HsvColorNode::NAME),
+        }
+    }
+}
+
+/// Whether an output with `out_flags` can be wired into an input with `in_flags`. Inputs list every type they take
+/// and outputs the types they can be read as, so the two have to share at least one flag.
+pub fn can_connect(out_flags: u64, in_flags: u64) -> bool {
+    out_flags & in_flags != 0
 }
 
 pub fn collect_for_node(
@@ -475,12 +885,35 @@ pub fn collect_for_node(
     }
 }
 
-#[derive(Clone, Default, Serialize, Deserialize)]
-pub struct OutputNode;
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OutputNode {
+    /// Renders connected to the output use `width` x `height` pixels instead of their tile of the window.
+    pub fixed_resolution: bool,
+    pub width: NodePin<u32>,
+    pub height: NodePin<u32>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl Default for OutputNode {
+    fn default() -> Self {
+        let resolution = LockedResolution::default();
+        Self {
+            fixed_resolution: false,
+            width: NodePin::new(resolution.width),
+            height: NodePin::new(resolution.height),
+            collapsed: false,
+        }
+    }
+}
 
 impl OutputNode {
     pub const NAME: &str = "Output";
-    pub const INPUTS: [u64; 1] = [NodeFlags::RENDERS.bits()];
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::RENDERS.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 0] = [];
 
     pub fn inputs(&self) -> &[u64] {
@@ -490,13 +923,165 @@ impl OutputNode {
     pub fn outputs(&self) -> &[u64] {
         &Self::OUTPUTS
     }
+
+    pub fn resolution(&self) -> LockedResolution {
+        LockedResolution {
+            enabled: self.fixed_resolution,
+            width: self.width.get(),
+            height: self.height.get(),
+        }
+    }
+
+    /// Graphs saved before the output had any settings store it as `null`.
+    fn deserialize_or_unit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<Self>::deserialize(deserializer)?.unwrap_or_default())
+    }
 }
 
 impl MessageHandling for OutputNode {
-    fn handle_input_show(_self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
         Some(match pin.id.input {
             0 => empty_input_view(ui, "Output"),
+            1 => {
+                const LABEL: &str = "Width";
+
+                let remote_value =
+                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let node = self_node.as_output_node_mut();
+                ui.add_enabled_ui(node.fixed_resolution, |ui| {
+                    number_input_view(ui, LABEL, &mut node.width, remote_value)
+                })
+                .inner
+            },
+            2 => {
+                const LABEL: &str = "Height";
+
+                let remote_value =
+                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let node = self_node.as_output_node_mut();
+                ui.add_enabled_ui(node.fixed_resolution, |ui| {
+                    number_input_view(ui, LABEL, &mut node.height, remote_value)
+                })
+                .inner
+            },
             _ => unreachable!(),
         })
     }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_output_node_mut();
+        match to.id.input {
+            0 => (),
+            1 => node.width.reset(),
+            2 => node.height.reset(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn test_old_output_node_follows_the_window() {
+        let node: Node = serde_json::from_str(r#"{"Output":null}"#).unwrap();
+
+        let output = node.output_node_ref().unwrap();
+        assert!(!output.resolution().enabled);
+    }
+
+    #[test]
+    fn test_integer_and_bool_cast_to_numbers() {
+        assert_eq!(Node::Integer(-3).number_out(), Ok(-3.0));
+        assert_eq!(Node::Bool(true).number_out(), Ok(1.0));
+        assert_eq!(Node::Bool(false).number_out(), Ok(0.0));
+    }
+
+    #[test]
+    fn test_detached_materials_drop_roughness_maps() {
+        let mut metal = MetalNode::default();
+        metal.roughness_texture.set(Some(NodeId(1)));
+        let metal = Node::Material(MaterialNode::Metal(metal)).detached();
+        assert!(matches!(metal, Node::Material(MaterialNode::Metal(m)) if m.roughness_texture.get().is_none()));
+
+        let mut dielectric = DielectricNode::default();
+        dielectric.roughness_texture.set(Some(NodeId(1)));
+        let glass = Node::Material(MaterialNode::Dielectric(dielectric)).detached();
+        assert!(matches!(glass, Node::Material(MaterialNode::Dielectric(d)) if d.roughness_texture.get().is_none()));
+    }
+
+    #[test]
+    fn test_can_connect_needs_a_shared_flag() {
+        let number_input = NodeFlags::TYPICAL_NUMBER_INPUT.bits();
+        assert!(can_connect(NodeFlags::NUMBER.bits(), number_input));
+        assert!(can_connect(ExpressionNode::OUTPUTS[0], number_input));
+        assert!(!can_connect(NodeFlags::VECTOR.bits(), number_input));
+        assert!(!can_connect(0, NodeFlags::ALL.bits()));
+    }
+
+    #[test]
+    fn test_connection_matrix() {
+        let fabrics: Vec<_> = Node::fabrics().into_iter().collect();
+        let mut connects = BTreeSet::new();
+        let mut fed_inputs = BTreeSet::new();
+        let mut used_outputs = BTreeSet::new();
+        for (from, _, _, outputs) in &fabrics {
+            for (to, _, inputs, _) in &fabrics {
+                for (output, &out_flags) in outputs.iter().enumerate() {
+                    for (input, &in_flags) in inputs.iter().enumerate() {
+                        if can_connect(out_flags, in_flags) {
+                            connects.insert((*from, *to));
+                            fed_inputs.insert((*to, input));
+                            used_outputs.insert((*from, output));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (name, _, inputs, outputs) in &fabrics {
+            for input in 0..inputs.len() {
+                assert!(
+                    fed_inputs.contains(&(*name, input)),
+                    "nothing feeds input {input} of `{name}`"
+                );
+            }
+            for output in 0..outputs.len() {
+                assert!(
+                    used_outputs.contains(&(*name, output)),
+                    "output {output} of `{name}` feeds nothing"
+                );
+            }
+        }
+
+        let expected = [
+            (Node::NUMBER_NAME, SphereNode::NAME, true),
+            (Node::INTEGER_NAME, TextureNode::NAME, true),
+            (Node::VECTOR_NAME, TextureNode::NAME, false),
+            (Node::NUMBER_NAME, SceneNode::NAME, false),
+            (Node::COLOR_NAME, MetalNode::NAME, true),
+            (ExpressionNode::NAME, TextureNode::NAME, true),
+            (CurveNode::NAME, SphereNode::NAME, true),
+            (TextureNode::NAME, MetalNode::NAME, true),
+            (MetalNode::NAME, SphereNode::NAME, true),
+            (MetalNode::NAME, RaytracerRenderNode::NAME, false),
+            (SphereNode::NAME, SceneNode::NAME, true),
+            (SphereNode::NAME, CollectionNode::NAME, true),
+            (PointLightNode::NAME, SceneNode::NAME, true),
+            (CollectionNode::NAME, SceneNode::NAME, true),
+            (SceneNode::NAME, SphereNode::NAME, false),
+            (SceneNode::NAME, RaytracerRenderNode::NAME, true),
+            (CameraNode::NAME, RaytracerRenderNode::NAME, true),
+            (CameraNode::NAME, SphereNode::NAME, false),
+            (BackgroundNode::NAME, RaytracerRenderNode::NAME, true),
+            (RaytracerRenderNode::NAME, OutputNode::NAME, true),
+            (TriangleRenderNode::NAME, OutputNode::NAME, true),
+        ];
+        for (from, to, expected) in expected {
+            assert_eq!(connects.contains(&(from, to)), expected, "`{from}` into `{to}`");
+        }
+    }
 }
@@ -0,0 +1,161 @@
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, NodeId, OutPin, Snarl};
+use serde::{Deserialize, Serialize};
+
+use super::message::{MessageHandling, SelfNodeMut};
+use super::viewer::{color_input_remote_value, color_input_view};
+use super::{Node, NodeFlags};
+use crate::raytracer::Background;
+use crate::types::{Color, NodePin, linear_rgb};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundMode {
+    #[default]
+    Solid,
+    Gradient,
+}
+
+impl BackgroundMode {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Solid => "Solid",
+            Self::Gradient => "Gradient",
+        }
+    }
+}
+
+/// Replaces the sky behind the scene for the camera, the scene is still lit by the sky.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BackgroundNode {
+    pub mode: BackgroundMode,
+    /// The solid color, or the top of the gradient.
+    pub top: NodePin<Color>,
+    /// Only used by the gradient.
+    pub bottom: NodePin<Color>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl Default for BackgroundNode {
+    fn default() -> Self {
+        Self {
+            mode: BackgroundMode::default(),
+            top: NodePin::new(Color::from_rgb(128, 178, 255)),
+            bottom: NodePin::new(Color::WHITE),
+            collapsed: false,
+        }
+    }
+}
+
+impl BackgroundNode {
+    pub const NAME: &str = "Background";
+    pub const INPUTS: [u64; 2] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::BACKGROUND.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    pub fn background(&self) -> Background {
+        let rgb = |color: Color| -> [f32; 3] { linear_rgb(color).into() };
+        match self.mode {
+            BackgroundMode::Solid => Background::Solid {
+                color: rgb(self.top.get()),
+            },
+            BackgroundMode::Gradient => Background::Gradient {
+                top: rgb(self.top.get()),
+                bottom: rgb(self.bottom.get()),
+            },
+        }
+    }
+}
+
+impl MessageHandling for BackgroundNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                let label = match self_node.as_background_node_mut().mode {
+                    BackgroundMode::Solid => "Color",
+                    BackgroundMode::Gradient => "Top",
+                };
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, label);
+                let node = self_node.as_background_node_mut();
+                ui.vertical(|ui| {
+                    mode_view(ui, pin.id.node, &mut node.mode);
+                    color_input_view(ui, label, &mut node.top, remote_value)
+                })
+                .inner
+            },
+            1 => {
+                const LABEL: &str = "Bottom";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_background_node_mut();
+                color_input_view(ui, LABEL, &mut node.bottom, remote_value)
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_background_node_mut();
+        match to.id.input {
+            0 => node.top.reset(),
+            1 => node.bottom.reset(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub fn background_node_by_id(background_id: NodeId, snarl: &Snarl<Node>) -> Option<&BackgroundNode> {
+    snarl.get_node(background_id).and_then(Node::background_node_ref)
+}
+
+fn mode_view(ui: &mut Ui, node_id: NodeId, mode: &mut BackgroundMode) {
+    ui.horizontal(|ui| {
+        ui.label("Mode");
+        egui::ComboBox::from_id_salt(("background_mode", node_id))
+            .selected_text(mode.name())
+            .show_ui(ui, |ui| {
+                for value in [BackgroundMode::Solid, BackgroundMode::Gradient] {
+                    ui.selectable_value(mode, value, value.name());
+                }
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solid_background_ignores_bottom() {
+        let node = BackgroundNode {
+            top: NodePin::new(Color::WHITE),
+            bottom: NodePin::new(Color::BLACK),
+            ..Default::default()
+        };
+        assert_eq!(node.background(), Background::Solid { color: [1.0; 3] });
+
+        let gradient = BackgroundNode {
+            mode: BackgroundMode::Gradient,
+            ..node
+        };
+        assert_eq!(
+            gradient.background(),
+            Background::Gradient {
+                top: [1.0; 3],
+                bottom: [0.0; 3]
+            }
+        );
+    }
+}
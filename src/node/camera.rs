@@ -1,26 +1,76 @@
-use egui::{InputState, Key, Pos2, Ui, Vec2};
+use egui::{InputState, Pos2, Ui, Vec2};
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, NodeId, OutPin, Snarl};
 use serde::{Deserialize, Serialize};
 
 use super::message::{MessageHandling, SelfNodeMut};
 use crate::node::viewer::{
-    as_number_input_view, number_input_remote_value, number_input_view, vector_input_remote_value, vector_input_view,
+    angle_input_view, angle_input_view_in_range, number_input_remote_value, number_input_view_in_range,
+    number_input_view_with_speed, vector_input_remote_value, vector_input_view,
 };
 use crate::node::{Node, NodeFlags};
-use crate::types::{Angle, Matrix3, NodePin, Point3, Vector3};
+use crate::raytracer::scene::Scene;
+use crate::raytracer::{Camera, CameraProjection, cpu};
+use crate::shortcuts::{Action, KeyBindings};
+use crate::types::{Angle, Matrix3, NodePin, Point3, UpAxis, Vector3, from_vector3_to_vector3f32};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CameraNode {
     pub position: NodePin<Point3>,
     pub yaw: NodePin<Angle>,
     pub pitch: NodePin<Angle>,
-    /// vfov angle must be between 0..=90 degrees.
+    /// vfov angle must be between 0.1..=90 degrees.
     pub vfov: NodePin<Angle>,
     /// Aperture must be between 0..=1.
     pub aperture: NodePin<f64>,
     /// Focus distance must be a positive number.
     pub focus_distance: NodePin<f64>,
+    /// The focus distance follows the first surface the center of the view sees, see [`Self::auto_focus_distance`].
+    #[serde(default)]
+    pub auto_focus: bool,
+    #[serde(default)]
+    pub projection: CameraProjection,
+    /// Point the camera looks at while the target pin is connected.
+    #[serde(default)]
+    pub target: NodePin<Point3>,
+    /// Up direction given with y up like yaw and pitch, it does not have to be normalized or perpendicular to the
+    /// view direction.
+    #[serde(default = "default_up")]
+    pub up: NodePin<Vector3>,
+    /// Polygonal aperture for shaped bokeh, 0 keeps the lens circular.
+    #[serde(default)]
+    pub bokeh_blades: NodePin<u32>,
+    /// Exposure in stops, zero leaves the brightness of the render unchanged.
+    #[serde(default)]
+    pub exposure: NodePin<f64>,
+    /// Color temperature in Kelvin that appears white.
+    #[serde(default = "default_white_balance")]
+    pub white_balance: NodePin<f64>,
+    /// Yaw and pitch are derived from `target` when it is connected.
+    #[serde(default)]
+    look_at: bool,
+    #[serde(default)]
+    pub control_mode: CameraControlMode,
+    /// Camera space velocity, right, up and forward.
+    #[serde(skip)]
+    velocity: Vector3,
+    /// Distance the movement keys cover per second, the mouse wheel changes it while in view mode.
+    #[serde(default = "CameraNode::default_speed")]
+    pub speed: f64,
+    /// Saved views to come back to, see [`Self::capture_bookmark`].
+    #[serde(default)]
+    pub bookmarks: Vec<CameraState>,
+    /// Recalled bookmarks are blended into instead of jumped to.
+    #[serde(default)]
+    pub interpolate_bookmarks: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+    /// Blend toward a recalled bookmark, see [`Self::advance_transition`].
+    #[serde(skip)]
+    transition: Option<CameraTransition>,
+    /// Follows the app settings, see [`Self::set_up_axis`].
+    #[serde(skip)]
+    up_axis: UpAxis,
 
     previous_mouse_pos: Option<Pos2>,
 }
@@ -38,6 +88,22 @@ impl Default for CameraNode {
             vfov: NodePin::new(Angle::degrees(30.0)),
             aperture: NodePin::new(0.8),
             focus_distance: NodePin::new(focus_distance),
+            auto_focus: false,
+            projection: CameraProjection::Perspective,
+            target: NodePin::new(look_at),
+            up: default_up(),
+            bokeh_blades: NodePin::new(0),
+            exposure: NodePin::new(0.0),
+            white_balance: default_white_balance(),
+            look_at: false,
+            speed: Self::DEFAULT_SPEED,
+            control_mode: CameraControlMode::Fly,
+            velocity: Vector3::zeros(),
+            bookmarks: Vec::new(),
+            interpolate_bookmarks: false,
+            collapsed: false,
+            transition: None,
+            up_axis: UpAxis::default(),
 
             previous_mouse_pos: None,
         }
@@ -46,16 +112,46 @@ impl Default for CameraNode {
 
 impl CameraNode {
     pub const NAME: &str = "Camera";
-    pub const INPUTS: [u64; 6] = [
+    pub const INPUTS: [u64; 11] = [
         NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
+    /// Label shown instead of a remote node name while yaw and pitch follow the target.
+    const LOOK_AT_NAME: &str = "Look At";
+    /// Label shown instead of a remote node name while the focus distance follows the scene.
+    const AUTO_FOCUS_NAME: &str = "Auto Focus";
     pub const OUTPUTS: [u64; 1] = [NodeFlags::CAMERA.bits()];
 
+    pub const DEFAULT_SPEED: f64 = 2.0;
+    pub const MIN_SPEED: f64 = 0.01;
+    pub const MAX_SPEED: f64 = 10_000.0;
+    /// Holding shift moves the camera this many times faster.
+    pub const SPRINT_MULTIPLIER: f64 = 5.0;
+    /// Scrolling this many points doubles or halves the speed.
+    const SCROLL_PER_DOUBLING: f64 = 200.0;
+    /// How quickly the velocity follows the movement keys, it covers about 63% of the way in `1 / ACCELERATION`
+    /// seconds.
+    const ACCELERATION: f64 = 10.0;
+    /// Rotation of an orbiting camera per point the mouse is dragged.
+    const ORBIT_DEGREES_PER_POINT: f64 = 0.3;
+    /// Room left around a framed sphere, relative to its size.
+    const FRAME_MARGIN: f64 = 1.2;
+    /// Duration of the blend toward a recalled bookmark.
+    const TRANSITION_SECS: f64 = 0.6;
+
+    pub fn default_speed() -> f64 {
+        Self::DEFAULT_SPEED
+    }
+
     pub fn inputs(&self) -> &[u64] {
         &Self::INPUTS
     }
@@ -63,6 +159,213 @@ impl CameraNode {
     pub fn outputs(&self) -> &[u64] {
         &Self::OUTPUTS
     }
+
+    /// Yaw and pitch in degrees pointing the camera at the target, `None` when the
+    /// target pin is not connected or the target is at the camera position.
+    fn look_at_angles(&self) -> Option<(f64, f64)> {
+        if !self.look_at {
+            return None;
+        }
+
+        let forward = self.up_axis.to_y_up(self.target.get() - self.position.get());
+        let distance = forward.magnitude();
+        if distance < f64::EPSILON {
+            return None;
+        }
+
+        let yaw = forward.z.atan2(forward.x);
+        let pitch = (forward.y / distance).clamp(-1.0, 1.0).asin();
+        Some((yaw.to_degrees(), pitch.to_degrees()))
+    }
+
+    /// Moves the camera back along its view direction until the sphere fills the vertical field of view,
+    /// with the focus on its center. An orbiting camera orbits the sphere afterwards.
+    pub fn frame_sphere(&mut self, center: Point3, radius: f64) {
+        let radius = Self::FRAME_MARGIN * radius.abs();
+        let forward = self.orientation().forward;
+
+        let distance = match &mut self.projection {
+            CameraProjection::Perspective => {
+                let vfov = self.vfov.get().clamp(
+                    Angle::degrees(*Camera::VFOV_RANGE.start()),
+                    Angle::degrees(*Camera::VFOV_RANGE.end()),
+                );
+                radius / (0.5 * vfov.as_radians()).sin()
+            },
+            CameraProjection::Orthographic { scale } => {
+                *scale = (2.0 * radius).max(CameraProjection::MIN_SCALE);
+                2.0 * radius
+            },
+        };
+        let distance = distance.max(CameraControlMode::MIN_DISTANCE);
+
+        *self.position.as_mut() = center - forward * distance;
+        *self.focus_distance.as_mut() = distance;
+        if let CameraControlMode::Orbit { .. } = self.control_mode {
+            self.control_mode = CameraControlMode::Orbit {
+                target: center,
+                distance,
+            };
+        }
+    }
+
+    /// Distance to the first surface of `scene` along the view direction, `None` if the center of the view sees
+    /// nothing.
+    pub fn auto_focus_distance(&self, scene: &Scene) -> Option<f64> {
+        let origin = from_vector3_to_vector3f32(&self.position.get());
+        let direction = from_vector3_to_vector3f32(&self.orientation().forward);
+        cpu::first_hit_distance(scene, origin, direction)
+            .map(|distance| (distance as f64).max(Camera::MIN_FOCUS_DISTANCE))
+    }
+
+    fn state(&self, name: String) -> CameraState {
+        CameraState {
+            name,
+            position: self.position.get(),
+            yaw: self.yaw.get(),
+            pitch: self.pitch.get(),
+            vfov: self.vfov.get(),
+            aperture: self.aperture.get(),
+            focus_distance: self.focus_distance.get(),
+        }
+    }
+
+    /// Sets the pins to `state`, the angles keep the units they are shown in.
+    fn apply_state(&mut self, state: &CameraState) {
+        *self.position.as_mut() = state.position;
+        *self.yaw.as_mut() = state.yaw.with_unit(self.yaw.get().unit());
+        *self.pitch.as_mut() = state.pitch.with_unit(self.pitch.get().unit());
+        *self.vfov.as_mut() = state.vfov.with_unit(self.vfov.get().unit());
+        *self.aperture.as_mut() = state.aperture;
+        *self.focus_distance.as_mut() = state.focus_distance;
+        self.velocity = Vector3::zeros();
+        if let CameraControlMode::Orbit { .. } = self.control_mode {
+            self.control_mode = self.focus_orbit();
+        }
+    }
+
+    /// Saves the current view as a new bookmark.
+    pub fn capture_bookmark(&mut self) {
+        let name = format!("View {}", self.bookmarks.len() + 1);
+        self.bookmarks.push(self.state(name));
+    }
+
+    /// Jumps to the bookmark at `idx`, or starts blending into it with `interpolate_bookmarks`.
+    pub fn recall_bookmark(&mut self, idx: usize) {
+        let Some(bookmark) = self.bookmarks.get(idx).cloned() else {
+            return;
+        };
+
+        if self.interpolate_bookmarks {
+            self.transition = Some(CameraTransition {
+                from: self.state(String::new()),
+                to: bookmark,
+                started_at: None,
+            });
+        } else {
+            self.transition = None;
+            self.apply_state(&bookmark);
+        }
+    }
+
+    /// Moves a running transition on to `time` in seconds, returns `true` while it still needs frames.
+    pub fn advance_transition(&mut self, time: f64) -> bool {
+        let Some(transition) = &mut self.transition else {
+            return false;
+        };
+
+        let started_at = *transition.started_at.get_or_insert(time);
+        let t = (time - started_at) / Self::TRANSITION_SECS;
+        if t >= 1.0 {
+            let state = transition.to.clone();
+            self.transition = None;
+            self.apply_state(&state);
+            return false;
+        }
+
+        // Smoothstep, so the camera eases in and out of the move.
+        let state = transition.from.lerp(&transition.to, t * t * (3.0 - 2.0 * t));
+        self.apply_state(&state);
+        true
+    }
+
+    /// Orbit around the point in focus, so switching to it keeps the current view.
+    fn focus_orbit(&self) -> CameraControlMode {
+        let distance = self.focus_distance.get();
+        CameraControlMode::Orbit {
+            target: self.position.get() + self.orientation().forward * distance,
+            distance,
+        }
+    }
+}
+
+/// Camera settings saved under a name, see [`CameraNode::bookmarks`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    pub name: String,
+    pub position: Point3,
+    pub yaw: Angle,
+    pub pitch: Angle,
+    pub vfov: Angle,
+    pub aperture: f64,
+    pub focus_distance: f64,
+}
+
+impl CameraState {
+    /// Blends the settings by `t` in 0..=1, the yaw turns the short way around. The name is taken from `other`.
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        let lerp_angle = |a: Angle, b: Angle| Angle::degrees(lerp(a.as_degrees(), b.as_degrees()));
+        let yaw_turn = (other.yaw.as_degrees() - self.yaw.as_degrees() + 180.0).rem_euclid(360.0) - 180.0;
+
+        Self {
+            name: other.name.clone(),
+            position: self.position.lerp(&other.position, t),
+            yaw: Angle::degrees(self.yaw.as_degrees() + yaw_turn * t),
+            pitch: lerp_angle(self.pitch, other.pitch),
+            vfov: lerp_angle(self.vfov, other.vfov),
+            aperture: lerp(self.aperture, other.aperture),
+            focus_distance: lerp(self.focus_distance, other.focus_distance),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CameraTransition {
+    from: CameraState,
+    to: CameraState,
+    /// Set by the first [`CameraNode::advance_transition`], the recall itself doesn't know the time.
+    started_at: Option<f64>,
+}
+
+/// How the camera responds to the mouse and the movement keys in view mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CameraControlMode {
+    /// Movement keys fly the camera and dragging looks around, the mouse wheel changes the speed.
+    #[default]
+    Fly,
+    /// Dragging rotates the camera around `target` at `distance`, the mouse wheel moves it closer or away.
+    Orbit { target: Point3, distance: f64 },
+}
+
+impl CameraControlMode {
+    /// Closest an orbiting camera gets to its target.
+    pub const MIN_DISTANCE: f64 = 0.01;
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Fly => "Fly",
+            Self::Orbit { .. } => "Orbit",
+        }
+    }
+}
+
+fn default_up() -> NodePin<Vector3> {
+    NodePin::new(Vector3::new(0.0, 1.0, 0.0))
+}
+
+fn default_white_balance() -> NodePin<f64> {
+    NodePin::new(Camera::NEUTRAL_WHITE_BALANCE as f64)
 }
 
 impl MessageHandling for CameraNode {
@@ -73,47 +376,145 @@ impl MessageHandling for CameraNode {
 
                 let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_camera_node_mut();
-                vector_input_view(ui, LABEL, &mut node.position, remote_value)
+                ui.vertical(|ui| {
+                    control_mode_view(ui, pin.id.node, node);
+                    vector_input_view(ui, LABEL, &mut node.position, remote_value)
+                })
+                .inner
             },
             1 => {
                 const LABEL: &str = "Yaw";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_camera_node_mut();
-                as_number_input_view(ui, LABEL, &mut node.yaw, remote_value)
+                let remote_value = match node.look_at_angles() {
+                    Some((yaw, _)) => Some((
+                        CameraNode::LOOK_AT_NAME,
+                        node.yaw.get().unit().value(Angle::degrees(yaw)),
+                    )),
+                    None => remote_value,
+                };
+                angle_input_view(ui, LABEL, &mut node.yaw, remote_value)
             },
             2 => {
                 const LABEL: &str = "Pitch";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_camera_node_mut();
-                as_number_input_view(ui, LABEL, &mut node.pitch, remote_value)
+                let remote_value = match node.look_at_angles() {
+                    Some((_, pitch)) => Some((
+                        CameraNode::LOOK_AT_NAME,
+                        node.pitch.get().unit().value(Angle::degrees(pitch)),
+                    )),
+                    None => remote_value,
+                };
+                angle_input_view(ui, LABEL, &mut node.pitch, remote_value)
             },
             3 => {
                 const LABEL: &str = "VFOV";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_camera_node_mut();
-                as_number_input_view(ui, LABEL, &mut node.vfov, remote_value)
+                ui.vertical(|ui| {
+                    projection_view(ui, pin.id.node, &mut node.projection);
+                    angle_input_view_in_range(ui, LABEL, &mut node.vfov, remote_value, Camera::VFOV_RANGE)
+                })
+                .inner
             },
             4 => {
                 const LABEL: &str = "Aperture";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_camera_node_mut();
-                number_input_view(ui, LABEL, &mut node.aperture, remote_value)
+                number_input_view_with_speed(
+                    ui,
+                    LABEL,
+                    &mut node.aperture,
+                    remote_value,
+                    Camera::APERTURE_RANGE,
+                    0.01,
+                )
             },
             5 => {
                 const LABEL: &str = "Focus Distance";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_camera_node_mut();
-                number_input_view(ui, LABEL, &mut node.focus_distance, remote_value)
+                let remote_value = if node.auto_focus {
+                    Some((CameraNode::AUTO_FOCUS_NAME, node.focus_distance.get()))
+                } else {
+                    remote_value
+                };
+                ui.vertical(|ui| {
+                    ui.checkbox(&mut node.auto_focus, "Auto focus");
+                    number_input_view_in_range(
+                        ui,
+                        LABEL,
+                        &mut node.focus_distance,
+                        remote_value,
+                        Camera::MIN_FOCUS_DISTANCE..=f64::INFINITY,
+                    )
+                })
+                .inner
+            },
+            6 => {
+                const LABEL: &str = "Target";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_camera_node_mut();
+                vector_input_view(ui, LABEL, &mut node.target, remote_value)
+            },
+            7 => {
+                const LABEL: &str = "Up";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_camera_node_mut();
+                vector_input_view(ui, LABEL, &mut node.up, remote_value)
+            },
+            8 => {
+                const LABEL: &str = "Bokeh Blades";
+
+                let remote_value =
+                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let node = self_node.as_camera_node_mut();
+                number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.bokeh_blades,
+                    remote_value,
+                    0..=*Camera::BOKEH_BLADES_RANGE.end(),
+                )
+            },
+            9 => {
+                const LABEL: &str = "Exposure";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_camera_node_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.exposure, remote_value, Camera::EXPOSURE_RANGE)
+            },
+            10 => {
+                const LABEL: &str = "White Balance";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_camera_node_mut();
+                number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.white_balance,
+                    remote_value,
+                    Camera::WHITE_BALANCE_RANGE,
+                )
             },
             _ => unreachable!(),
         })
     }
 
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        if to.id.input == 6 {
+            self_node.as_camera_node_mut().look_at = true;
+        }
+    }
+
     fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
         let node = self_node.as_camera_node_mut();
         match to.id.input {
@@ -123,11 +524,73 @@ impl MessageHandling for CameraNode {
             3 => node.vfov.reset(),
             4 => node.aperture.reset(),
             5 => node.focus_distance.reset(),
+            6 => {
+                node.target.reset();
+                node.look_at = false;
+            },
+            7 => node.up.reset(),
+            8 => node.bokeh_blades.reset(),
+            9 => node.exposure.reset(),
+            10 => node.white_balance.reset(),
             _ => unreachable!(),
         }
     }
 }
 
+/// Dropdown choosing the projection, with the scale editor for orthographic cameras.
+fn projection_view(ui: &mut Ui, node_id: NodeId, projection: &mut CameraProjection) {
+    let perspective = CameraProjection::Perspective;
+    let orthographic = match *projection {
+        CameraProjection::Orthographic { .. } => *projection,
+        CameraProjection::Perspective => CameraProjection::Orthographic {
+            scale: CameraProjection::DEFAULT_SCALE,
+        },
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Projection");
+        egui::ComboBox::from_id_salt(("camera_projection", node_id))
+            .selected_text(projection.name())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(projection, perspective, perspective.name());
+                ui.selectable_value(projection, orthographic, orthographic.name());
+            });
+    });
+
+    if let CameraProjection::Orthographic { scale } = projection {
+        ui.horizontal(|ui| {
+            ui.label("Scale");
+            ui.add(egui::DragValue::new(scale).range(CameraProjection::MIN_SCALE..=f64::INFINITY));
+        });
+    }
+}
+
+/// Dropdown choosing how the camera is controlled, with the distance editor for orbiting cameras.
+fn control_mode_view(ui: &mut Ui, node_id: NodeId, node: &mut CameraNode) {
+    let fly = CameraControlMode::Fly;
+    let orbit = match node.control_mode {
+        CameraControlMode::Orbit { .. } => node.control_mode,
+        CameraControlMode::Fly => node.focus_orbit(),
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Controls");
+        egui::ComboBox::from_id_salt(("camera_control_mode", node_id))
+            .selected_text(node.control_mode.name())
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut node.control_mode, fly, fly.name());
+                ui.selectable_value(&mut node.control_mode, orbit, orbit.name());
+            });
+    });
+
+    if let CameraControlMode::Orbit { distance, .. } = &mut node.control_mode {
+        ui.horizontal(|ui| {
+            ui.label("Distance");
+            ui.add(egui::DragValue::new(distance).range(CameraControlMode::MIN_DISTANCE..=f64::INFINITY));
+        });
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Orientation {
     pub forward: Vector3,
@@ -144,22 +607,79 @@ impl CameraNode {
         )
         .normalize();
 
-        let world_up = Vector3::new(0.0, 1.0, 0.0);
-        let right = forward.cross(&world_up);
+        // When looking along the up direction the cross product vanishes,
+        // so fall back to the world axis least aligned with the view direction.
+        let right = forward.cross(&self.up.get());
+        let right = if right.magnitude_squared() > 1e-12 {
+            right.normalize()
+        } else {
+            let axis = forward.iamin();
+            forward.cross(&Vector3::ith(axis, 1.0)).normalize()
+        };
         let up = right.cross(&forward);
 
-        Orientation { forward, right, up }
+        Orientation {
+            forward: self.up_axis.from_y_up(forward),
+            right: self.up_axis.from_y_up(right),
+            up: self.up_axis.from_y_up(up),
+        }
+    }
+
+    pub fn up_axis(&self) -> UpAxis {
+        self.up_axis
     }
 
-    pub fn after_events(&mut self, input_state: &InputState) {
-        let translation_scale = 2.0 * input_state.stable_dt as f64;
-        let look_pressed = input_state.pointer.secondary_down();
-        let forward_pressed = input_state.key_pressed(Key::W);
-        let backward_pressed = input_state.key_pressed(Key::S);
-        let left_pressed = input_state.key_pressed(Key::A);
-        let right_pressed = input_state.key_pressed(Key::D);
-        let down_pressed = input_state.key_pressed(Key::Q);
-        let up_pressed = input_state.key_pressed(Key::E);
+    /// The position stays in place while the view turns along with the world axes.
+    pub fn set_up_axis(&mut self, up_axis: UpAxis) {
+        self.up_axis = up_axis;
+    }
+
+    /// Applies mouse-look and keyboard movement. Movement keys are ignored
+    /// when `keyboard_enabled` is false, e.g. while a text field has focus.
+    /// `pointer_enabled` is `false` while the pointer is over the UI rather than the render,
+    /// dragging and scrolling there must not move the camera.
+    /// Scrolling with Alt held zooms instead of changing the speed or the orbit distance.
+    pub fn after_events(
+        &mut self,
+        input_state: &InputState,
+        bindings: &KeyBindings,
+        keyboard_enabled: bool,
+        pointer_enabled: bool,
+    ) {
+        let mut scroll = if pointer_enabled {
+            input_state.raw_scroll_delta.y as f64
+        } else {
+            0.0
+        };
+        if input_state.modifiers.alt && scroll != 0.0 {
+            self.zoom(scroll);
+            scroll = 0.0;
+        }
+        let look_pressed = pointer_enabled && input_state.pointer.secondary_down();
+
+        if let CameraControlMode::Orbit { target, distance } = self.control_mode {
+            self.orbit(input_state, target, distance, look_pressed, scroll);
+            return;
+        }
+
+        if scroll != 0.0 {
+            let speed = self.speed * (scroll / Self::SCROLL_PER_DOUBLING).exp2();
+            self.speed = speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+        }
+        let sprint = if input_state.modifiers.shift {
+            Self::SPRINT_MULTIPLIER
+        } else {
+            1.0
+        };
+        let top_speed = sprint * self.speed;
+        let dt = input_state.stable_dt as f64;
+        let held = |action| bindings.down(action, input_state);
+        let forward_held = held(Action::CameraForward);
+        let backward_held = held(Action::CameraBackward);
+        let left_held = held(Action::CameraLeft);
+        let right_held = held(Action::CameraRight);
+        let down_held = held(Action::CameraDown);
+        let up_held = held(Action::CameraUp);
         let mouse_pos = input_state.pointer.latest_pos().unwrap_or_default();
         let viewport_size = input_state
             .viewport()
@@ -167,7 +687,8 @@ impl CameraNode {
             .map(|rect| rect.size())
             .unwrap_or_default();
 
-        if look_pressed {
+        // Yaw and pitch follow the target in look-at mode.
+        if look_pressed && !self.look_at {
             if let Some(prev_mouse_pos) = self.previous_mouse_pos {
                 let orientation = self.orientation();
                 let c1 = orientation.right;
@@ -202,14 +723,28 @@ impl CameraNode {
             }
         }
 
-        {
+        // Movement keys are regular letters, so they must not move the camera
+        // while the user is typing into a text field.
+        let target_velocity = if keyboard_enabled {
             let v = |b| if b { 1.0 } else { 0.0 };
-            let translation = Vector3::new(
-                translation_scale * (v(right_pressed) - v(left_pressed)),
-                translation_scale * (v(up_pressed) - v(down_pressed)),
-                translation_scale * (v(forward_pressed) - v(backward_pressed)),
-            );
+            top_speed
+                * Vector3::new(
+                    v(right_held) - v(left_held),
+                    v(up_held) - v(down_held),
+                    v(forward_held) - v(backward_held),
+                )
+        } else {
+            Vector3::zeros()
+        };
 
+        // Easing towards the target velocity accelerates while keys are held and damps the motion after.
+        self.velocity += (target_velocity - self.velocity) * (1.0 - (-Self::ACCELERATION * dt).exp());
+        if target_velocity == Vector3::zeros() && self.velocity.magnitude() < 1e-3 * self.speed {
+            self.velocity = Vector3::zeros();
+        }
+
+        if self.velocity != Vector3::zeros() {
+            let translation = self.velocity * dt;
             let orientation = self.orientation();
             *self.position.as_mut() = self.position.get()
                 + orientation.right * translation.x
@@ -220,6 +755,36 @@ impl CameraNode {
         self.previous_mouse_pos = Some(mouse_pos);
     }
 
+    /// Dragging rotates the camera around the target and the mouse wheel moves it closer or away.
+    /// The target stays in the middle of the view, yaw and pitch give the direction it is seen from.
+    fn orbit(&mut self, input_state: &InputState, target: Point3, distance: f64, look_pressed: bool, scroll: f64) {
+        if look_pressed && !self.look_at {
+            let delta = input_state.pointer.delta();
+            let degrees = |points: f32| Angle::degrees(Self::ORBIT_DEGREES_PER_POINT * points as f64);
+            *self.yaw.as_mut() = self.yaw.get() + degrees(delta.x);
+            *self.pitch.as_mut() =
+                (self.pitch.get() + degrees(-delta.y)).clamp(Angle::degrees(-89.0), Angle::degrees(89.0));
+        }
+
+        // Scrolling up moves closer, just like it speeds up flying.
+        let distance = (distance * (-scroll / Self::SCROLL_PER_DOUBLING).exp2()).max(CameraControlMode::MIN_DISTANCE);
+        self.control_mode = CameraControlMode::Orbit { target, distance };
+
+        *self.position.as_mut() = target - self.orientation().forward * distance;
+        self.velocity = Vector3::zeros();
+        self.previous_mouse_pos = input_state.pointer.latest_pos();
+    }
+
+    /// Scrolling up narrows the field of view, each [`Self::SCROLL_PER_DOUBLING`] halves it.
+    fn zoom(&mut self, scroll: f64) {
+        let vfov = self.vfov.get();
+        let zoomed = Angle::degrees(vfov.as_degrees() * (-scroll / Self::SCROLL_PER_DOUBLING).exp2());
+        *self.vfov.as_mut() = zoomed.with_unit(vfov.unit()).clamp(
+            Angle::degrees(*Camera::VFOV_RANGE.start()),
+            Angle::degrees(*Camera::VFOV_RANGE.end()),
+        );
+    }
+
     fn generate_ray_dir(&self, mouse_pos: Pos2, viewport_size: Vec2) -> Vector3 {
         let position = self.position.get();
         let focus_distance = self.focus_distance.get();
@@ -244,3 +809,239 @@ impl CameraNode {
 pub fn camera_node_by_id(camera_id: NodeId, snarl: &Snarl<Node>) -> Option<&CameraNode> {
     snarl.get_node(camera_id).and_then(Node::camera_node_ref)
 }
+
+#[cfg(test)]
+mod tests {
+    use egui::{Event, Key, Modifiers};
+
+    use super::*;
+    use crate::raytracer::scene::Sphere;
+
+    fn key_press(key: Key) -> InputState {
+        let mut input_state = InputState::default();
+        input_state.stable_dt = 0.1;
+        input_state.keys_down.insert(key);
+        input_state.events.push(Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: Modifiers::NONE,
+        });
+        input_state
+    }
+
+    #[test]
+    fn test_camera_moves_on_key_press() {
+        let mut camera = CameraNode::default();
+        let start = camera.position.get();
+
+        camera.after_events(&key_press(Key::W), &KeyBindings::default(), true, true);
+
+        assert_ne!(camera.position.get(), start);
+    }
+
+    #[test]
+    fn test_held_key_accelerates_and_release_damps() {
+        let mut camera = CameraNode::default();
+        let mut held = InputState::default();
+        held.stable_dt = 0.1;
+        held.keys_down.insert(Key::W);
+        let released = InputState {
+            stable_dt: 0.1,
+            ..Default::default()
+        };
+
+        let step = |camera: &mut CameraNode, input_state: &InputState| {
+            let start = camera.position.get();
+            camera.after_events(input_state, &KeyBindings::default(), true, true);
+            (camera.position.get() - start).magnitude()
+        };
+        let steps = [&held, &held, &held].map(|input_state| step(&mut camera, input_state));
+        assert!(steps[0] > 0.0 && steps.is_sorted(), "{steps:?}");
+
+        let glide = step(&mut camera, &released);
+        assert!(glide > 0.0 && glide < steps[2]);
+        for _ in 0..100 {
+            step(&mut camera, &released);
+        }
+        assert_eq!(step(&mut camera, &released), 0.0);
+    }
+
+    #[test]
+    fn test_scroll_and_shift_speed_up_the_camera() {
+        let distance = |input_state: &InputState| {
+            let mut camera = CameraNode::default();
+            let start = camera.position.get();
+            camera.after_events(input_state, &KeyBindings::default(), true, true);
+            (camera.position.get() - start).magnitude()
+        };
+        let walk = distance(&key_press(Key::W));
+
+        let mut scrolled = key_press(Key::W);
+        scrolled.raw_scroll_delta.y = CameraNode::SCROLL_PER_DOUBLING as f32;
+        assert!((distance(&scrolled) - 2.0 * walk).abs() < 1e-9);
+
+        let mut sprinting = key_press(Key::W);
+        sprinting.modifiers.shift = true;
+        assert!((distance(&sprinting) - CameraNode::SPRINT_MULTIPLIER * walk).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_scroll_over_the_ui_keeps_the_speed() {
+        let mut camera = CameraNode::default();
+        let mut scrolled = key_press(Key::W);
+        scrolled.raw_scroll_delta.y = CameraNode::SCROLL_PER_DOUBLING as f32;
+
+        camera.after_events(&scrolled, &KeyBindings::default(), true, false);
+
+        assert_eq!(camera.speed, CameraNode::DEFAULT_SPEED);
+    }
+
+    #[test]
+    fn test_alt_scroll_zooms_within_the_vfov_range() {
+        let mut camera = CameraNode::default();
+        let mut scrolled = InputState::default();
+        scrolled.modifiers.alt = true;
+        scrolled.raw_scroll_delta.y = CameraNode::SCROLL_PER_DOUBLING as f32;
+
+        camera.after_events(&scrolled, &KeyBindings::default(), true, true);
+        assert_eq!(camera.vfov.get(), Angle::degrees(15.0));
+        assert_eq!(camera.speed, CameraNode::DEFAULT_SPEED);
+
+        scrolled.raw_scroll_delta.y = -10.0 * CameraNode::SCROLL_PER_DOUBLING as f32;
+        camera.after_events(&scrolled, &KeyBindings::default(), true, true);
+        assert_eq!(camera.vfov.get().as_degrees(), *Camera::VFOV_RANGE.end());
+    }
+
+    #[test]
+    fn test_orbit_keeps_target_in_view() {
+        let mut camera = CameraNode::default();
+        camera.control_mode = camera.focus_orbit();
+        let CameraControlMode::Orbit { target, distance } = camera.control_mode else {
+            unreachable!()
+        };
+
+        let mut scrolled = key_press(Key::W);
+        scrolled.raw_scroll_delta.y = CameraNode::SCROLL_PER_DOUBLING as f32;
+        camera.after_events(&scrolled, &KeyBindings::default(), true, true);
+
+        let to_target = target - camera.position.get();
+        assert!((to_target.magnitude() - 0.5 * distance).abs() < 1e-9);
+        assert!((to_target.normalize() - camera.orientation().forward).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_look_at_points_forward_at_target() {
+        let mut camera = CameraNode::default();
+        camera.look_at = true;
+        camera.position = NodePin::new(Vector3::new(1.0, 2.0, 3.0));
+        camera.target = NodePin::new(Vector3::new(-4.0, 0.0, 5.0));
+
+        let (yaw, pitch) = camera.look_at_angles().unwrap();
+        camera.yaw = NodePin::new(Angle::degrees(yaw));
+        camera.pitch = NodePin::new(Angle::degrees(pitch));
+
+        let expected = (camera.target.get() - camera.position.get()).normalize();
+        assert!((camera.orientation().forward - expected).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_framed_sphere_fills_the_view() {
+        let mut camera = CameraNode::default();
+        let center = Vector3::new(3.0, 1.0, -2.0);
+        camera.frame_sphere(center, 2.0);
+
+        let to_center = center - camera.position.get();
+        assert!((to_center.normalize() - camera.orientation().forward).magnitude() < 1e-9);
+        assert!((to_center.magnitude() - camera.focus_distance.get()).abs() < 1e-9);
+
+        let half_vfov = 0.5 * camera.vfov.get().as_radians();
+        let visible_radius = to_center.magnitude() * half_vfov.sin();
+        assert!((visible_radius - CameraNode::FRAME_MARGIN * 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_auto_focus_on_sphere_in_view() {
+        let camera = CameraNode::default();
+        let center = camera.position.get() + 10.0 * camera.orientation().forward;
+        let mut scene = Scene {
+            spheres: vec![Sphere::new(center, 2.0, 0)],
+            ..Default::default()
+        };
+
+        let distance = camera.auto_focus_distance(&scene).unwrap();
+        assert!((distance - 8.0).abs() < 1e-4);
+
+        scene.spheres[0] = Sphere::new(center + 5.0 * camera.orientation().right, 2.0, 0);
+        assert_eq!(camera.auto_focus_distance(&scene), None);
+    }
+
+    #[test]
+    fn test_bookmark_transition_ends_at_bookmark() {
+        let mut camera = CameraNode::default();
+        *camera.yaw.as_mut() = Angle::degrees(170.0);
+        camera.capture_bookmark();
+        let bookmark = camera.bookmarks[0].clone();
+
+        *camera.yaw.as_mut() = Angle::degrees(-170.0);
+        *camera.position.as_mut() = Vector3::new(5.0, 0.0, 0.0);
+        camera.interpolate_bookmarks = true;
+        camera.recall_bookmark(0);
+
+        assert!(camera.advance_transition(10.0));
+        assert!(camera.advance_transition(10.0 + 0.5 * CameraNode::TRANSITION_SECS));
+        // Halfway between -170 and 170 degrees the short way is at 180 degrees, not at 0.
+        assert!((camera.yaw.get().as_degrees().rem_euclid(360.0) - 180.0).abs() < 1e-9);
+
+        assert!(!camera.advance_transition(10.0 + CameraNode::TRANSITION_SECS));
+        assert_eq!(camera.state(bookmark.name.clone()), bookmark);
+    }
+
+    #[test]
+    fn test_orientation_is_finite_for_any_pitch() {
+        let mut camera = CameraNode::default();
+
+        for step in -180..=180 {
+            let pitch = step as f64 * 0.5;
+            camera.pitch = NodePin::new(Angle::degrees(pitch));
+
+            let orientation = camera.orientation();
+            for vector in [orientation.forward, orientation.right, orientation.up] {
+                assert!(vector.iter().all(|value| value.is_finite()), "pitch {pitch}: {vector:?}");
+                assert!((vector.magnitude() - 1.0).abs() < 1e-9, "pitch {pitch}: {vector:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_z_up_turns_the_view_onto_the_z_axis() {
+        let mut camera = CameraNode::default();
+        camera.pitch = NodePin::new(Angle::degrees(0.0));
+        camera.set_up_axis(UpAxis::Z);
+
+        let orientation = camera.orientation();
+        assert!(orientation.forward.z.abs() < 1e-9, "{:?}", orientation.forward);
+        assert!((orientation.up - Vector3::z()).magnitude() < 1e-9);
+
+        camera.look_at = true;
+        camera.target = NodePin::new(camera.position.get() + Vector3::new(1.0, 2.0, 3.0));
+        let (yaw, pitch) = camera.look_at_angles().unwrap();
+        camera.yaw = NodePin::new(Angle::degrees(yaw));
+        camera.pitch = NodePin::new(Angle::degrees(pitch));
+        let expected = Vector3::new(1.0, 2.0, 3.0).normalize();
+        assert!((camera.orientation().forward - expected).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn test_camera_ignores_keys_while_typing() {
+        let mut camera = CameraNode::default();
+        let start = camera.position.get();
+
+        for key in [Key::W, Key::A, Key::S, Key::D, Key::Q, Key::E] {
+            camera.after_events(&key_press(key), &KeyBindings::default(), false, true);
+        }
+
+        assert_eq!(camera.position.get(), start);
+    }
+}
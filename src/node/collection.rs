@@ -1,6 +1,6 @@
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, NodeId, OutPin};
+use egui_snarl::{InPin, InPinId, NodeId, OutPin};
 use serde::{Deserialize, Serialize};
 
 use super::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
@@ -12,6 +12,15 @@ use super::{Node, NodeFlags, collect_for_node};
 pub struct CollectionNode {
     nodes: Vec<NodeId>,
     inputs: Vec<u64>,
+    /// Shown as the title instead of the node name when not empty.
+    #[serde(default)]
+    pub label: String,
+    /// Names of the members by position, empty ones show the name of the connected node.
+    /// Older files have none, so it can be shorter than `nodes`.
+    #[serde(default)]
+    slot_names: Vec<String>,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -22,6 +31,9 @@ impl Default for CollectionNode {
         Self {
             nodes: Vec::new(),
             inputs: vec![NodeFlags::ALL.bits()],
+            label: String::new(),
+            slot_names: Vec::new(),
+            collapsed: false,
             subscription: Subscription::default(),
         }
     }
@@ -33,15 +45,45 @@ impl CollectionNode {
     pub const OUTPUTS: [u64; 1] = [NodeFlags::COLLECTION.bits()];
 
     pub fn insert(&mut self, idx: usize, node: NodeId) {
+        self.slot_names_mut().insert(idx, String::new());
         self.nodes.insert(idx, node);
         self.inputs.insert(idx, NodeFlags::ALL.bits());
     }
 
     pub fn remove(&mut self, idx: usize) {
+        self.slot_names_mut().remove(idx);
         self.nodes.remove(idx);
         self.inputs.remove(idx);
     }
 
+    /// Moves the member at `from` to `to`, the members in between keep their order.
+    pub fn move_input(&mut self, from: usize, to: usize) {
+        let node = self.nodes.remove(from);
+        self.nodes.insert(to, node);
+        let input = self.inputs.remove(from);
+        self.inputs.insert(to, input);
+        let name = self.slot_names_mut().remove(from);
+        self.slot_names_mut().insert(to, name);
+    }
+
+    /// Forgets the members but keeps the label, for copies that are not connected to anything.
+    pub fn detach(&mut self) {
+        *self = Self {
+            label: std::mem::take(&mut self.label),
+            ..Self::default()
+        };
+    }
+
+    pub fn slot_name(&self, idx: usize) -> &str {
+        self.slot_names.get(idx).map_or("", String::as_str)
+    }
+
+    /// One name for every member.
+    pub fn slot_names_mut(&mut self) -> &mut Vec<String> {
+        self.slot_names.resize(self.nodes.len(), String::new());
+        &mut self.slot_names
+    }
+
     pub fn to_node_ids(&self) -> Vec<NodeId> {
         self.nodes.clone()
     }
@@ -55,15 +97,78 @@ impl CollectionNode {
     }
 }
 
+/// Payload of a member dragged to another position in the collection.
+struct DraggedInput {
+    node: NodeId,
+    input: usize,
+}
+
+/// Reorders a member along with its wire and notifies the subscribers.
+fn move_input(mut self_node: SelfNodeMut, from: usize, to: usize) {
+    let node_id = self_node.id;
+    let (first, last) = (from.min(to), from.max(to));
+
+    let mut remotes = (first..=last)
+        .map(|input| {
+            let in_pin = self_node.snarl.in_pin(InPinId { node: node_id, input });
+            for &remote in &in_pin.remotes {
+                self_node.snarl.disconnect(remote, in_pin.id);
+            }
+            in_pin.remotes
+        })
+        .collect::<Vec<_>>();
+    let moved = remotes.remove(from - first);
+    remotes.insert(to - first, moved);
+
+    for (offset, pin_remotes) in remotes.into_iter().enumerate() {
+        let in_pin_id = InPinId {
+            node: node_id,
+            input: first + offset,
+        };
+        for remote in pin_remotes {
+            self_node.snarl.connect(remote, in_pin_id);
+        }
+    }
+
+    let node = self_node.as_collection_node_mut();
+    node.move_input(from, to);
+
+    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+        caller(self_node)
+    }
+}
+
 impl MessageHandling for CollectionNode {
     fn handle_input_show(self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
-        let name = pin
-            .remotes
-            .first()
-            .map(|out_pin| self_node.snarl[out_pin.node].name())
-            .unwrap_or_default();
+        let slot_name = self_node.as_collection_node_ref().slot_name(pin.id.input);
+        let name = match slot_name {
+            "" => pin
+                .remotes
+                .first()
+                .map(|out_pin| self_node.snarl[out_pin.node].name())
+                .unwrap_or_default(),
+            slot_name => slot_name,
+        };
+        let label = format!("{} {name}", pin.id.input + 1);
 
-        Some(empty_input_view(ui, format!("{} {name}", pin.id.input + 1)))
+        // The trailing input is always empty, it is where new members get connected.
+        if pin.id.input >= self_node.as_collection_node_ref().nodes.len() {
+            return Some(empty_input_view(ui, label));
+        }
+
+        let payload = DraggedInput {
+            node: pin.id.node,
+            input: pin.id.input,
+        };
+        let drag_id = egui::Id::new(("collection_input", pin.id.node, pin.id.input));
+        let drag = ui.dnd_drag_source(drag_id, payload, |ui| empty_input_view(ui, label));
+
+        if let Some(dragged) = drag.response.dnd_release_payload::<DraggedInput>() {
+            if dragged.node == pin.id.node && dragged.input != pin.id.input {
+                move_input(self_node, dragged.input, pin.id.input);
+            }
+        }
+        Some(drag.inner)
     }
 
     fn handle_input_connect(mut self_node: SelfNodeMut, from: &OutPin, to: &InPin) {
@@ -100,3 +205,56 @@ impl MessageHandling for CollectionNode {
         node.subscription.handle_event(event_msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use egui_snarl::{OutPinId, Snarl};
+
+    use super::*;
+
+    #[test]
+    fn test_move_input_permutes_members() {
+        let mut snarl = Snarl::new();
+        let members = [1.0, 2.0, 3.0].map(|value| snarl.insert_node(egui::Pos2::ZERO, Node::Number(value)));
+
+        let mut collection_node = CollectionNode::default();
+        for (idx, &member) in members.iter().enumerate() {
+            collection_node.insert(idx, member);
+        }
+        let collection = snarl.insert_node(egui::Pos2::ZERO, Node::Collection(collection_node));
+        let output = |node| OutPinId { node, output: 0 };
+        let input = |input| InPinId {
+            node: collection,
+            input,
+        };
+        for (idx, &member) in members.iter().enumerate() {
+            snarl.connect(output(member), input(idx));
+        }
+
+        move_input(SelfNodeMut::new(collection, &mut snarl), 0, 2);
+
+        let [a, b, c] = members;
+        assert_eq!(snarl[collection].as_collection_node_ref().to_node_ids(), [b, c, a]);
+        assert_eq!(snarl[collection].inputs().len(), 4);
+        for (idx, member) in [b, c, a].into_iter().enumerate() {
+            assert_eq!(snarl.in_pin(input(idx)).remotes, [output(member)]);
+        }
+    }
+
+    #[test]
+    fn test_slot_names_follow_members() {
+        let mut snarl = Snarl::<Node>::new();
+        let [a, b] = [1.0, 2.0].map(|value| snarl.insert_node(egui::Pos2::ZERO, Node::Number(value)));
+
+        let mut node = CollectionNode::default();
+        node.insert(0, a);
+        node.insert(1, b);
+        node.slot_names_mut()[0] = "Floor".to_owned();
+
+        node.move_input(0, 1);
+        assert_eq!((node.slot_name(0), node.slot_name(1)), ("", "Floor"));
+
+        node.remove(0);
+        assert_eq!(node.slot_name(0), "Floor");
+    }
+}
@@ -0,0 +1,218 @@
+use egui::epaint::Hsva;
+use egui::{Rgba, Ui};
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use serde::{Deserialize, Serialize};
+
+use super::NodeFlags;
+use super::message::{MessageHandling, SelfNodeMut};
+use super::viewer::{
+    color_input_remote_value, color_input_view, number_input_remote_value, number_input_view,
+    number_input_view_in_range,
+};
+use crate::types::{Color, NodePin};
+
+/// Interpolates between two colors, `t` of 0 gives `a` and 1 gives `b`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ColorMixNode {
+    pub a: NodePin<Color>,
+    pub b: NodePin<Color>,
+    pub t: NodePin<f64>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl Default for ColorMixNode {
+    fn default() -> Self {
+        Self {
+            a: NodePin::new(Color::BLACK),
+            b: NodePin::new(Color::WHITE),
+            t: NodePin::new(0.5),
+            collapsed: false,
+        }
+    }
+}
+
+impl ColorMixNode {
+    pub const NAME: &str = "Color Mix";
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::COLOR.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    /// Blends in linear space, `t` is clamped to `0..=1`.
+    pub fn color_out(&self) -> Color {
+        let t = self.t.get().clamp(0.0, 1.0) as f32;
+        let (a, b) = (Rgba::from(self.a.get()), Rgba::from(self.b.get()));
+        Color::from(a * (1.0 - t) + b * t)
+    }
+}
+
+impl MessageHandling for ColorMixNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "A";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_color_mix_node_mut();
+                color_input_view(ui, LABEL, &mut node.a, remote_value)
+            },
+            1 => {
+                const LABEL: &str = "B";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_color_mix_node_mut();
+                color_input_view(ui, LABEL, &mut node.b, remote_value)
+            },
+            2 => {
+                const LABEL: &str = "T";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_color_mix_node_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.t, remote_value, 0.0..=1.0)
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_color_mix_node_mut();
+        match to.id.input {
+            0 => node.a.reset(),
+            1 => node.b.reset(),
+            2 => node.t.reset(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Color from hue in degrees, saturation and value. Hue wraps around, so it can be animated freely.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HsvColorNode {
+    pub hue: NodePin<f64>,
+    pub saturation: NodePin<f64>,
+    pub value: NodePin<f64>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl Default for HsvColorNode {
+    fn default() -> Self {
+        Self {
+            hue: NodePin::new(0.0),
+            saturation: NodePin::new(1.0),
+            value: NodePin::new(1.0),
+            collapsed: false,
+        }
+    }
+}
+
+impl HsvColorNode {
+    pub const NAME: &str = "HSV Color";
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::COLOR.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    /// Saturation and value are clamped to `0..=1`.
+    pub fn color_out(&self) -> Color {
+        let hue = self.hue.get().rem_euclid(360.0) / 360.0;
+        let saturation = self.saturation.get().clamp(0.0, 1.0);
+        let value = self.value.get().clamp(0.0, 1.0);
+        Color::from(Hsva::new(hue as _, saturation as _, value as _, 1.0))
+    }
+}
+
+impl MessageHandling for HsvColorNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "Hue";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_hsv_color_node_mut();
+                number_input_view(ui, LABEL, &mut node.hue, remote_value)
+            },
+            1 => {
+                const LABEL: &str = "Saturation";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_hsv_color_node_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.saturation, remote_value, 0.0..=1.0)
+            },
+            2 => {
+                const LABEL: &str = "Value";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_hsv_color_node_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.value, remote_value, 0.0..=1.0)
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_hsv_color_node_mut();
+        match to.id.input {
+            0 => node.hue.reset(),
+            1 => node.saturation.reset(),
+            2 => node.value.reset(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_mix() {
+        let mut node = ColorMixNode::default();
+
+        node.t = NodePin::new(0.0);
+        assert_eq!(node.color_out(), Color::BLACK);
+        node.t = NodePin::new(2.0);
+        assert_eq!(node.color_out(), Color::WHITE);
+
+        // Halfway in linear space is brighter than halfway in gamma space.
+        node.t = NodePin::new(0.5);
+        assert!(node.color_out().r() > 128);
+    }
+
+    #[test]
+    fn test_hsv_color() {
+        let mut node = HsvColorNode::default();
+        assert_eq!(node.color_out(), Color::from_rgb(255, 0, 0));
+
+        node.hue = NodePin::new(120.0);
+        assert_eq!(node.color_out(), Color::from_rgb(0, 255, 0));
+
+        node.hue = NodePin::new(-240.0);
+        assert_eq!(node.color_out(), Color::from_rgb(0, 255, 0));
+
+        node.value = NodePin::new(0.0);
+        assert_eq!(node.color_out(), Color::BLACK);
+    }
+}
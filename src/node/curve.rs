@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+use super::NodeFlags;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: f64,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CurveInterpolation {
+    #[default]
+    Linear,
+    Smoothstep,
+}
+
+impl CurveInterpolation {
+    pub const ALL: [Self; 2] = [Self::Linear, Self::Smoothstep];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "Linear",
+            Self::Smoothstep => "Smoothstep",
+        }
+    }
+
+    fn apply(&self, t: f64) -> f64 {
+        match self {
+            Self::Linear => t,
+            Self::Smoothstep => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// Maps the time in seconds to a number through keyframes, the value is held before the first and after the
+/// last one unless the curve loops.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CurveNode {
+    /// Kept sorted by time.
+    keyframes: Vec<Keyframe>,
+    pub interpolation: CurveInterpolation,
+    #[serde(default)]
+    pub looped: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    time: f64,
+}
+
+impl Default for CurveNode {
+    fn default() -> Self {
+        Self {
+            keyframes: vec![Keyframe { time: 0.0, value: 0.0 }, Keyframe { time: 1.0, value: 1.0 }],
+            interpolation: CurveInterpolation::default(),
+            looped: false,
+            collapsed: false,
+            time: 0.0,
+        }
+    }
+}
+
+impl CurveNode {
+    pub const NAME: &str = "Curve";
+    pub const INPUTS: [u64; 0] = [];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::NUMBER.bits()];
+
+    /// Returns `true` if the value can change with time.
+    pub fn set_time(&mut self, time: f64) -> bool {
+        self.time = time;
+        self.keyframes.len() > 1
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// Gives mutable access to the keyframes, they are sorted again once `edit` returns.
+    pub fn edit_keyframes<R>(&mut self, edit: impl FnOnce(&mut Vec<Keyframe>) -> R) -> R {
+        let result = edit(&mut self.keyframes);
+        self.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        result
+    }
+
+    /// New keyframe one second after the last one, holding its value.
+    pub fn add_keyframe(&mut self) {
+        let keyframe = match self.keyframes.last() {
+            Some(last) => Keyframe {
+                time: last.time + 1.0,
+                value: last.value,
+            },
+            None => Keyframe { time: 0.0, value: 0.0 },
+        };
+        self.keyframes.push(keyframe);
+    }
+
+    pub fn value_at(&self, time: f64) -> f64 {
+        let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) else {
+            return 0.0;
+        };
+
+        let span = last.time - first.time;
+        let time = if self.looped && span > 0.0 {
+            first.time + (time - first.time).rem_euclid(span)
+        } else {
+            time
+        };
+
+        if time <= first.time {
+            return first.value;
+        }
+        if time >= last.time {
+            return last.value;
+        }
+
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        let (a, b) = (self.keyframes[next - 1], self.keyframes[next]);
+        let t = self.interpolation.apply((time - a.time) / (b.time - a.time));
+        a.value + (b.value - a.value) * t
+    }
+
+    pub fn number_out(&self) -> f64 {
+        self.value_at(self.time)
+    }
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_curve_interpolates_between_keyframes() {
+        let mut curve = CurveNode::default();
+        curve.edit_keyframes(|keyframes| keyframes.push(Keyframe { time: 0.5, value: 2.0 }));
+
+        assert_eq!(curve.value_at(-1.0), 0.0);
+        assert_eq!(curve.value_at(0.25), 1.0);
+        assert_eq!(curve.value_at(0.75), 1.5);
+        assert_eq!(curve.value_at(2.0), 1.0);
+
+        curve.interpolation = CurveInterpolation::Smoothstep;
+        assert_eq!(curve.value_at(0.25), 1.0);
+        assert_eq!(curve.value_at(0.125), 2.0 * 0.15625);
+
+        curve.looped = true;
+        assert_eq!(curve.value_at(1.25), curve.value_at(0.25));
+    }
+}
@@ -2,26 +2,37 @@ use std::collections::HashMap;
 
 use egui::{Color32, Ui};
 use egui_snarl::ui::{PinInfo, WireStyle};
-use egui_snarl::{InPin, InPinId};
+use egui_snarl::{InPin, InPinId, OutPin};
+use thiserror::Error;
 
 use super::NodeFlags;
 use super::message::{MessageHandling, SelfNodeMut};
 use super::viewer::{NUMBER_COLOR, STRING_COLOR, format_float};
+use crate::types::Vector3;
 
 /// Node for evaluating algebraic expression
 /// It has number of inputs equal to number of variables in the expression.
+/// An expression of the form `vec(x, y, z)` outputs a vector instead of a number.
+/// The variable [`ExpressionNode::TIME`] is reserved for the time in seconds and has no input.
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct ExpressionNode {
     pub text: String,
     pub bindings: Vec<String>,
     pub values: Vec<f64>,
     pub expr: Expression,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    time: f64,
 }
 
 impl ExpressionNode {
     pub const NAME: &str = "Expression";
     pub const INPUTS: [u64; 0] = [];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::NUMBER.bits() | NodeFlags::EXPRESSION.bits()];
+    pub const VECTOR_OUTPUTS: [u64; 1] = [NodeFlags::VECTOR.bits()];
+    pub const TIME: &str = "t";
 
     pub fn new() -> Self {
         Self {
@@ -29,11 +40,54 @@ impl ExpressionNode {
             bindings: Vec::new(),
             values: Vec::new(),
             expr: Expression::Val(0.0),
+            collapsed: false,
+            time: 0.0,
+        }
+    }
+
+    /// Returns `true` if the expression depends on time, so its value changes every frame.
+    pub fn set_time(&mut self, time: f64) -> bool {
+        self.time = time;
+        self.uses_time()
+    }
+
+    pub fn uses_time(&self) -> bool {
+        let mut bindings = Vec::new();
+        self.expr.extend_bindings(&mut bindings);
+        bindings.iter().any(|name| name == Self::TIME)
+    }
+
+    fn variable(&self, name: &str) -> Option<f64> {
+        if name == Self::TIME {
+            Some(self.time)
+        } else {
+            binding_value(&self.bindings, &self.values, name)
+        }
+    }
+
+    /// Evaluates the expression, rejecting results that are not finite numbers
+    /// so they don't propagate into camera or material parameters.
+    pub fn eval(&self) -> Result<f64, EvalError> {
+        let value = self.expr.eval_with(&|name| self.variable(name))?;
+        if value.is_finite() { Ok(value) } else { Err(EvalError::NotFinite(value)) }
+    }
+
+    /// Same as [`Self::eval`] for vector expressions, scalar ones are used for every component.
+    pub fn eval_vector(&self) -> Result<Vector3, EvalError> {
+        let vector = self.expr.eval_vector_with(&|name| self.variable(name))?;
+        match vector.iter().find(|value| !value.is_finite()) {
+            Some(&value) => Err(EvalError::NotFinite(value)),
+            None => Ok(vector),
         }
     }
 
-    pub fn eval(&self) -> f64 {
-        self.expr.eval(&self.bindings, &self.values)
+    pub fn is_vector(&self) -> bool {
+        self.expr.is_vector()
+    }
+
+    /// `false` while the text doesn't parse, [`Self::expr`] then still holds the last expression that did.
+    pub fn text_parses(&self) -> bool {
+        syn::parse_str::<Expression>(&self.text).is_ok()
     }
 
     pub fn inputs(&self) -> &[u64] {
@@ -41,7 +95,7 @@ impl ExpressionNode {
     }
 
     pub fn outputs(&self) -> &[u64] {
-        &Self::OUTPUTS
+        if self.is_vector() { &Self::VECTOR_OUTPUTS } else { &Self::OUTPUTS }
     }
 }
 
@@ -93,6 +147,7 @@ impl MessageHandling for ExpressionNode {
 
                         let mut new_bindings = Vec::new();
                         node.expr.extend_bindings(&mut new_bindings);
+                        new_bindings.retain(|name| name != Self::TIME);
 
                         let old_bindings = std::mem::replace(&mut node.bindings, new_bindings.clone());
 
@@ -122,7 +177,7 @@ impl MessageHandling for ExpressionNode {
                                 Some(new_idx) if new_idx != idx => {
                                     let new_in_pin = InPinId {
                                         node: pin.id.node,
-                                        input: new_idx,
+                                        input: new_idx + 1,
                                     };
                                     for &remote in &old_inputs[idx].remotes {
                                         self_node.snarl.disconnect(remote, old_inputs[idx].id);
@@ -151,8 +206,15 @@ impl MessageHandling for ExpressionNode {
                             let new_value = self_node.snarl[remote.node].number_out();
                             let node = self_node.snarl[pin.id.node].as_expression_node_mut();
                             ui.label(&node.bindings[idx - 1]);
-                            ui.label(format_float(new_value));
-                            node.values[idx - 1] = new_value;
+                            match new_value {
+                                Ok(new_value) => {
+                                    ui.label(format_float(new_value));
+                                    node.values[idx - 1] = new_value;
+                                },
+                                Err(err) => {
+                                    ui.label("err").on_hover_text(err.to_string());
+                                },
+                            }
                             PinInfo::circle().with_fill(NUMBER_COLOR)
                         },
                         _ => unreachable!("Expr pins has only one wire"),
@@ -164,6 +226,26 @@ impl MessageHandling for ExpressionNode {
             },
         })
     }
+
+    /// The text is kept, while a variable falls back to zero instead of the last value it received.
+    fn handle_input_disconnect(self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.snarl[to.id.node].as_expression_node_mut();
+        if let Some(value) = to.id.input.checked_sub(1).and_then(|idx| node.values.get_mut(idx)) {
+            *value = 0.0;
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EvalError {
+    #[error("unknown variable `{0}`")]
+    UnknownVariable(String),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("result is not a finite number: {0}")]
+    NotFinite(f64),
+    #[error("`vec` can only be used as the whole expression")]
+    UnexpectedVector,
 }
 
 #[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
@@ -178,6 +260,95 @@ pub enum BinOp {
     Sub,
     Mul,
     Div,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+}
+
+impl BinOp {
+    const fn precedence(&self) -> u8 {
+        match self {
+            Self::Lt | Self::Gt | Self::Le | Self::Ge | Self::Eq => 1,
+            Self::Add | Self::Sub => 2,
+            Self::Mul | Self::Div => 3,
+        }
+    }
+
+    fn peek(input: syn::parse::ParseStream) -> Option<Self> {
+        // Two-character operators go first, `<` also matches the start of `<=`
+        if input.peek(syn::Token![<=]) {
+            Some(Self::Le)
+        } else if input.peek(syn::Token![>=]) {
+            Some(Self::Ge)
+        } else if input.peek(syn::Token![==]) {
+            Some(Self::Eq)
+        } else if input.peek(syn::Token![<]) {
+            Some(Self::Lt)
+        } else if input.peek(syn::Token![>]) {
+            Some(Self::Gt)
+        } else if input.peek(syn::Token![+]) {
+            Some(Self::Add)
+        } else if input.peek(syn::Token![-]) {
+            Some(Self::Sub)
+        } else if input.peek(syn::Token![*]) {
+            Some(Self::Mul)
+        } else if input.peek(syn::Token![/]) {
+            Some(Self::Div)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Vec,
+}
+
+impl Function {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Self::Sin,
+            "cos" => Self::Cos,
+            "tan" => Self::Tan,
+            "sqrt" => Self::Sqrt,
+            "abs" => Self::Abs,
+            "min" => Self::Min,
+            "max" => Self::Max,
+            "vec" => Self::Vec,
+            _ => return None,
+        })
+    }
+
+    pub const fn arity(&self) -> usize {
+        match self {
+            Self::Sin | Self::Cos | Self::Tan | Self::Sqrt | Self::Abs => 1,
+            Self::Min | Self::Max => 2,
+            Self::Vec => 3,
+        }
+    }
+
+    fn apply(&self, args: &[f64]) -> f64 {
+        match self {
+            Self::Sin => args[0].sin(),
+            Self::Cos => args[0].cos(),
+            Self::Tan => args[0].tan(),
+            Self::Sqrt => args[0].sqrt(),
+            Self::Abs => args[0].abs(),
+            Self::Min => args[0].min(args[1]),
+            Self::Max => args[0].max(args[1]),
+            Self::Vec => unreachable!("`vec` is evaluated by `Expression::eval_vector`"),
+        }
+    }
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -193,28 +364,94 @@ pub enum Expression {
         op: BinOp,
         rhs: Box<Expression>,
     },
+    Pow {
+        base: Box<Expression>,
+        exponent: Box<Expression>,
+    },
+    Call {
+        name: Function,
+        args: Vec<Expression>,
+    },
+    Cond {
+        cond: Box<Expression>,
+        then: Box<Expression>,
+        els: Box<Expression>,
+    },
 }
 
 impl Expression {
-    pub fn eval(&self, bindings: &[String], args: &[f64]) -> f64 {
-        let binding_index = |name: &str| bindings.iter().position(|binding| binding == name).unwrap();
+    /// Bindings and their values can be out of sync for a frame while the text is being edited,
+    /// so a missing variable is reported as an error instead of panicking.
+    pub fn eval(&self, bindings: &[String], args: &[f64]) -> Result<f64, EvalError> {
+        self.eval_with(&|name| binding_value(bindings, args, name))
+    }
 
-        match self {
-            Expression::Var(name) => args[binding_index(name)],
+    pub fn eval_vector(&self, bindings: &[String], args: &[f64]) -> Result<Vector3, EvalError> {
+        self.eval_vector_with(&|name| binding_value(bindings, args, name))
+    }
+
+    /// Evaluates with `variable` resolving the value of every variable name.
+    pub fn eval_with(&self, variable: &dyn Fn(&str) -> Option<f64>) -> Result<f64, EvalError> {
+        Ok(match self {
+            Expression::Var(name) => variable(name).ok_or_else(|| EvalError::UnknownVariable(name.clone()))?,
             Expression::Val(value) => *value,
             Expression::UnOp { op, expr } => match op {
-                UnOp::Pos => expr.eval(bindings, args),
-                UnOp::Neg => -expr.eval(bindings, args),
+                UnOp::Pos => expr.eval_with(variable)?,
+                UnOp::Neg => -expr.eval_with(variable)?,
             },
-            Expression::BinOp { lhs, op, rhs } => match op {
-                BinOp::Add => lhs.eval(bindings, args) + rhs.eval(bindings, args),
-                BinOp::Sub => lhs.eval(bindings, args) - rhs.eval(bindings, args),
-                BinOp::Mul => lhs.eval(bindings, args) * rhs.eval(bindings, args),
-                BinOp::Div => lhs.eval(bindings, args) / rhs.eval(bindings, args),
+            Expression::BinOp { lhs, op, rhs } => {
+                let lhs = lhs.eval_with(variable)?;
+                let rhs = rhs.eval_with(variable)?;
+                match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div if rhs == 0.0 => return Err(EvalError::DivisionByZero),
+                    BinOp::Div => lhs / rhs,
+                    BinOp::Lt => f64::from(lhs < rhs),
+                    BinOp::Gt => f64::from(lhs > rhs),
+                    BinOp::Le => f64::from(lhs <= rhs),
+                    BinOp::Ge => f64::from(lhs >= rhs),
+                    BinOp::Eq => f64::from(lhs == rhs),
+                }
+            },
+            Expression::Pow { base, exponent } => base.eval_with(variable)?.powf(exponent.eval_with(variable)?),
+            Expression::Call { name: Function::Vec, .. } => return Err(EvalError::UnexpectedVector),
+            Expression::Call { name, args: call_args } => {
+                let values = call_args
+                    .iter()
+                    .map(|arg| arg.eval_with(variable))
+                    .collect::<Result<Vec<_>, _>>()?;
+                name.apply(&values)
+            },
+            Expression::Cond { cond, then, els } => {
+                if cond.eval_with(variable)? != 0.0 {
+                    then.eval_with(variable)?
+                } else {
+                    els.eval_with(variable)?
+                }
+            },
+        })
+    }
+
+    pub fn eval_vector_with(&self, variable: &dyn Fn(&str) -> Option<f64>) -> Result<Vector3, EvalError> {
+        match self {
+            Expression::Call { name: Function::Vec, args: components } => Ok(Vector3::new(
+                components[0].eval_with(variable)?,
+                components[1].eval_with(variable)?,
+                components[2].eval_with(variable)?,
+            )),
+            expr => {
+                let value = expr.eval_with(variable)?;
+                Ok(Vector3::new(value, value, value))
             },
         }
     }
 
+    pub fn is_vector(&self) -> bool {
+        matches!(self, Expression::Call { name: Function::Vec, .. })
+    }
+
     pub fn extend_bindings(&self, bindings: &mut Vec<String>) {
         match self {
             Expression::Var(name) => {
@@ -230,10 +467,31 @@ impl Expression {
                 lhs.extend_bindings(bindings);
                 rhs.extend_bindings(bindings);
             },
+            Expression::Pow { base, exponent } => {
+                base.extend_bindings(bindings);
+                exponent.extend_bindings(bindings);
+            },
+            Expression::Call { args, .. } => {
+                for arg in args {
+                    arg.extend_bindings(bindings);
+                }
+            },
+            Expression::Cond { cond, then, els } => {
+                cond.extend_bindings(bindings);
+                then.extend_bindings(bindings);
+                els.extend_bindings(bindings);
+            },
         }
     }
 }
 
+fn binding_value(bindings: &[String], args: &[f64], name: &str) -> Option<f64> {
+    bindings
+        .iter()
+        .position(|binding| binding == name)
+        .and_then(|idx| args.get(idx).copied())
+}
+
 impl syn::parse::Parse for UnOp {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let lookahead = input.lookahead1();
@@ -252,7 +510,22 @@ impl syn::parse::Parse for UnOp {
 impl syn::parse::Parse for BinOp {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let lookahead = input.lookahead1();
-        if lookahead.peek(syn::Token![+]) {
+        if lookahead.peek(syn::Token![<=]) {
+            input.parse::<syn::Token![<=]>()?;
+            Ok(BinOp::Le)
+        } else if lookahead.peek(syn::Token![>=]) {
+            input.parse::<syn::Token![>=]>()?;
+            Ok(BinOp::Ge)
+        } else if lookahead.peek(syn::Token![==]) {
+            input.parse::<syn::Token![==]>()?;
+            Ok(BinOp::Eq)
+        } else if lookahead.peek(syn::Token![<]) {
+            input.parse::<syn::Token![<]>()?;
+            Ok(BinOp::Lt)
+        } else if lookahead.peek(syn::Token![>]) {
+            input.parse::<syn::Token![>]>()?;
+            Ok(BinOp::Gt)
+        } else if lookahead.peek(syn::Token![+]) {
             input.parse::<syn::Token![+]>()?;
             Ok(BinOp::Add)
         } else if lookahead.peek(syn::Token![-]) {
@@ -270,158 +543,279 @@ impl syn::parse::Parse for BinOp {
     }
 }
 
+/// Precedence from lowest to highest: `? :`, `< > <= >= ==`, `+ -`, `* /`, unary `+ -`, `^`.
+/// Binary operators are left-associative, except `^` which is right-associative,
+/// so `-2 ^ 2` is `-(2 ^ 2)` and `2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`.
+/// Comparisons evaluate to `1` or `0`, and the ternary picks `then` for any non-zero condition.
 impl syn::parse::Parse for Expression {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let lookahead = input.lookahead1();
-
-        let lhs;
-        if lookahead.peek(syn::token::Paren) {
-            let content;
-            syn::parenthesized!(content in input);
-            let expr = content.parse::<Expression>()?;
-            if input.is_empty() {
-                return Ok(expr);
-            }
-            lhs = expr;
-        // } else if lookahead.peek(syn::LitFloat) {
-        //     let lit = input.parse::<syn::LitFloat>()?;
-        //     let value = lit.base10_parse::<f64>()?;
-        //     let expr = Expr::Val(value);
-        //     if input.is_empty() {
-        //         return Ok(expr);
-        //     }
-        //     lhs = expr;
-        } else if lookahead.peek(syn::LitInt) {
-            let lit = input.parse::<syn::LitInt>()?;
-            let value = lit.base10_parse::<f64>()?;
-            let expr = Expression::Val(value);
-            if input.is_empty() {
-                return Ok(expr);
-            }
-            lhs = expr;
-        } else if lookahead.peek(syn::Ident) {
-            let ident = input.parse::<syn::Ident>()?;
-            let expr = Expression::Var(ident.to_string());
-            if input.is_empty() {
-                return Ok(expr);
-            }
-            lhs = expr;
-        } else {
-            let unop = input.parse::<UnOp>()?;
-
-            return Self::parse_with_unop(unop, input);
+        let cond = Self::parse_binop(input, 0)?;
+        if !input.peek(syn::Token![?]) {
+            return Ok(cond);
         }
 
-        let binop = input.parse::<BinOp>()?;
+        input.parse::<syn::Token![?]>()?;
+        let then = input.parse::<Expression>()?;
+        input.parse::<syn::Token![:]>()?;
+        let els = input.parse::<Expression>()?;
 
-        Self::parse_binop(Box::new(lhs), binop, input)
+        Ok(Self::Cond {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            els: Box::new(els),
+        })
     }
 }
 
 impl Expression {
-    fn parse_with_unop(op: UnOp, input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let lookahead = input.lookahead1();
+    fn parse_binop(input: syn::parse::ParseStream, min_precedence: u8) -> syn::Result<Self> {
+        let mut lhs = Self::parse_unop(input)?;
 
-        let lhs;
-        if lookahead.peek(syn::token::Paren) {
-            let content;
-            syn::parenthesized!(content in input);
-            let expr = Expression::UnOp {
-                op,
-                expr: Box::new(content.parse::<Expression>()?),
-            };
-            if input.is_empty() {
-                return Ok(expr);
+        while let Some(op) = BinOp::peek(input) {
+            if op.precedence() < min_precedence {
+                break;
             }
-            lhs = expr;
-        } else if lookahead.peek(syn::LitFloat) {
-            let lit = input.parse::<syn::LitFloat>()?;
-            let value = lit.base10_parse::<f64>()?;
-            let expr = Expression::UnOp {
-                op,
-                expr: Box::new(Expression::Val(value)),
-            };
-            if input.is_empty() {
-                return Ok(expr);
-            }
-            lhs = expr;
-        } else if lookahead.peek(syn::LitInt) {
-            let lit = input.parse::<syn::LitInt>()?;
-            let value = lit.base10_parse::<f64>()?;
-            let expr = Expression::UnOp {
+
+            input.parse::<BinOp>()?;
+            let rhs = Self::parse_binop(input, op.precedence() + 1)?;
+            lhs = Self::BinOp {
+                lhs: Box::new(lhs),
                 op,
-                expr: Box::new(Expression::Val(value)),
+                rhs: Box::new(rhs),
             };
-            if input.is_empty() {
-                return Ok(expr);
-            }
-            lhs = expr;
-        } else if lookahead.peek(syn::Ident) {
-            let ident = input.parse::<syn::Ident>()?;
-            let expr = Expression::UnOp {
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unop(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::Token![+]) || input.peek(syn::Token![-]) {
+            let op = input.parse::<UnOp>()?;
+            let expr = Self::parse_unop(input)?;
+            return Ok(Self::UnOp {
                 op,
-                expr: Box::new(Expression::Var(ident.to_string())),
-            };
-            if input.is_empty() {
-                return Ok(expr);
-            }
-            lhs = expr;
-        } else {
-            return Err(lookahead.error());
+                expr: Box::new(expr),
+            });
         }
 
-        let op = input.parse::<BinOp>()?;
+        let base = Self::parse_operand(input)?;
+        if input.peek(syn::Token![^]) {
+            input.parse::<syn::Token![^]>()?;
+            let exponent = Self::parse_unop(input)?;
+            return Ok(Self::Pow {
+                base: Box::new(base),
+                exponent: Box::new(exponent),
+            });
+        }
 
-        Self::parse_binop(Box::new(lhs), op, input)
+        Ok(base)
     }
 
-    fn parse_binop(lhs: Box<Expression>, op: BinOp, input: syn::parse::ParseStream) -> syn::Result<Self> {
+    fn parse_operand(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let lookahead = input.lookahead1();
 
-        let rhs;
         if lookahead.peek(syn::token::Paren) {
             let content;
             syn::parenthesized!(content in input);
-            rhs = Box::new(content.parse::<Expression>()?);
-            if input.is_empty() {
-                return Ok(Expression::BinOp { lhs, op, rhs });
-            }
+            content.parse::<Expression>()
         } else if lookahead.peek(syn::LitFloat) {
             let lit = input.parse::<syn::LitFloat>()?;
-            let value = lit.base10_parse::<f64>()?;
-            rhs = Box::new(Expression::Val(value));
-            if input.is_empty() {
-                return Ok(Expression::BinOp { lhs, op, rhs });
-            }
+            Ok(Expression::Val(lit.base10_parse::<f64>()?))
         } else if lookahead.peek(syn::LitInt) {
             let lit = input.parse::<syn::LitInt>()?;
-            let value = lit.base10_parse::<f64>()?;
-            rhs = Box::new(Expression::Val(value));
-            if input.is_empty() {
-                return Ok(Expression::BinOp { lhs, op, rhs });
-            }
+            Ok(Expression::Val(lit.base10_parse::<f64>()?))
         } else if lookahead.peek(syn::Ident) {
             let ident = input.parse::<syn::Ident>()?;
-            rhs = Box::new(Expression::Var(ident.to_string()));
-            if input.is_empty() {
-                return Ok(Expression::BinOp { lhs, op, rhs });
+            if input.peek(syn::token::Paren) {
+                Self::parse_call(&ident, input)
+            } else {
+                Ok(Expression::Var(ident.to_string()))
             }
         } else {
-            return Err(lookahead.error());
+            Err(lookahead.error())
         }
+    }
 
-        let next_op = input.parse::<BinOp>()?;
-
-        if let (BinOp::Add | BinOp::Sub, BinOp::Mul | BinOp::Div) = (op, next_op) {
-            let rhs = Self::parse_binop(rhs, next_op, input)?;
-            Ok(Self::BinOp {
-                lhs,
-                op,
-                rhs: Box::new(rhs),
-            })
-        } else {
-            let lhs = Self::BinOp { lhs, op, rhs };
-            Self::parse_binop(Box::new(lhs), next_op, input)
+    fn parse_call(ident: &syn::Ident, input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let name = Function::from_name(&ident.to_string())
+            .ok_or_else(|| syn::Error::new(ident.span(), format!("unknown function `{ident}`")))?;
+
+        let content;
+        syn::parenthesized!(content in input);
+        let args = content
+            .parse_terminated(<Expression as syn::parse::Parse>::parse, syn::Token![,])?
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        if args.len() != name.arity() {
+            return Err(syn::Error::new(
+                ident.span(),
+                format!("`{ident}` expects {} argument(s), got {}", name.arity(), args.len()),
+            ));
         }
+
+        Ok(Expression::Call { name, args })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui_snarl::{OutPinId, Snarl};
+
+    use super::*;
+    use crate::node::Node;
+    use crate::node::message::InputMessage;
+
+    fn eval(text: &str) -> f64 {
+        let expr = syn::parse_str::<Expression>(text).unwrap();
+        let mut bindings = Vec::new();
+        expr.extend_bindings(&mut bindings);
+        let values = bindings.iter().map(|_| 2.0).collect::<Vec<_>>();
+        expr.eval(&bindings, &values).unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("10 - 2 * 3 + 1"), 5.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("8 / 4 / 2"), 1.0);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_float_literal() {
+        let expr = syn::parse_str::<Expression>("3.14").unwrap();
+        assert!(matches!(expr, Expression::Val(value) if value == 3.14));
+        assert_eq!(expr.eval(&[], &[]), Ok(3.14));
+        assert_eq!(eval("1.5"), 1.5);
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(eval("2 ^ 3"), 8.0);
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(eval("2 * 3 ^ 2"), 18.0);
+        assert_eq!(eval("-2 ^ 2"), -4.0);
+        assert_eq!(eval("2 ^ -1"), 0.5);
+    }
+
+    #[test]
+    fn test_calls() {
+        assert_eq!(eval("sqrt(16) + abs(-1)"), 5.0);
+        assert_eq!(eval("max(x, 3) * min(1, y)"), 3.0);
+        assert_eq!(eval("sin(0) + cos(0)"), 1.0);
+    }
+
+    #[test]
+    fn test_call_bindings() {
+        let expr = syn::parse_str::<Expression>("max(a, b ^ c)").unwrap();
+        let mut bindings = Vec::new();
+        expr.extend_bindings(&mut bindings);
+        assert_eq!(bindings, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_invalid_calls() {
+        assert!(syn::parse_str::<Expression>("foo(1)").is_err());
+        assert!(syn::parse_str::<Expression>("sin(1, 2)").is_err());
+        assert!(syn::parse_str::<Expression>("min(1)").is_err());
+    }
+
+    #[test]
+    fn test_vector() {
+        let expr = syn::parse_str::<Expression>("vec(x, 2 * x, -1)").unwrap();
+        assert!(expr.is_vector());
+        let bindings = ["x".to_owned()];
+        assert_eq!(expr.eval_vector(&bindings, &[3.0]), Ok(Vector3::new(3.0, 6.0, -1.0)));
+        assert_eq!(expr.eval(&bindings, &[3.0]), Err(EvalError::UnexpectedVector));
+
+        let expr = syn::parse_str::<Expression>("x + 1").unwrap();
+        assert!(!expr.is_vector());
+        assert_eq!(expr.eval_vector(&bindings, &[1.0]), Ok(Vector3::new(2.0, 2.0, 2.0)));
+
+        let expr = syn::parse_str::<Expression>("vec(1, 2, 3) * 2").unwrap();
+        assert!(!expr.is_vector());
+        assert_eq!(expr.eval(&[], &[]), Err(EvalError::UnexpectedVector));
+        assert!(syn::parse_str::<Expression>("vec(1, 2)").is_err());
+    }
+
+    #[test]
+    fn test_comparisons() {
+        assert_eq!(eval("1 < 2"), 1.0);
+        assert_eq!(eval("1 + 2 >= 4"), 0.0);
+        assert_eq!(eval("2 * 2 == 4"), 1.0);
+        assert_eq!(eval("3 <= 3") + eval("3 > 3"), 1.0);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        assert_eq!(eval("1 < 2 ? 10 : 20"), 10.0);
+        assert_eq!(eval("x > 5 ? 1 : x < 0 ? 2 : 3"), 3.0);
+        assert_eq!(eval("0 ? 1 / 0 : 4 + 1"), 5.0);
+
+        let expr = syn::parse_str::<Expression>("a < b ? c : d").unwrap();
+        let mut bindings = Vec::new();
+        expr.extend_bindings(&mut bindings);
+        assert_eq!(bindings, ["a", "b", "c", "d"]);
+
+        assert!(syn::parse_str::<Expression>("1 ? 2").is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        let mut node = ExpressionNode::new();
+        assert!(!node.set_time(1.0));
+
+        node.expr = syn::parse_str::<Expression>("sin(t) + 1").unwrap();
+        assert!(node.set_time(std::f64::consts::FRAC_PI_2));
+        assert_eq!(node.eval(), Ok(2.0));
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let expr = syn::parse_str::<Expression>("x + y").unwrap();
+        let bindings = ["x".to_owned()];
+        assert_eq!(
+            expr.eval(&bindings, &[1.0]),
+            Err(EvalError::UnknownVariable("y".to_owned()))
+        );
+
+        let expr = syn::parse_str::<Expression>("1 / (2 - 2)").unwrap();
+        assert_eq!(expr.eval(&[], &[]), Err(EvalError::DivisionByZero));
+
+        let mut node = ExpressionNode::new();
+        node.expr = syn::parse_str::<Expression>("sqrt(-1)").unwrap();
+        assert!(matches!(node.eval(), Err(EvalError::NotFinite(_))));
+    }
+
+    #[test]
+    fn test_disconnect_resets_variable() {
+        let mut node = ExpressionNode::new();
+        node.text = "x + 1".to_owned();
+        node.expr = syn::parse_str(&node.text).unwrap();
+        node.bindings = vec!["x".to_owned()];
+        node.values = vec![5.0];
+
+        let mut snarl = Snarl::new();
+        let number = snarl.insert_node(egui::Pos2::ZERO, Node::Number(5.0));
+        let expression = snarl.insert_node(egui::Pos2::ZERO, Node::Expression(node));
+        let from = OutPinId {
+            node: number,
+            output: 0,
+        };
+        let to = InPinId {
+            node: expression,
+            input: 1,
+        };
+        snarl.connect(from, to);
+
+        let (from, to) = (snarl.out_pin(from), snarl.in_pin(to));
+        Node::send_msg(expression, &mut snarl, InputMessage::Disconnect { from: &from, to: &to });
+        snarl.disconnect(from.id, to.id);
+
+        let node = snarl[expression].as_expression_node_mut();
+        assert_eq!(node.values, [0.0]);
+        assert_eq!(node.eval(), Ok(1.0));
     }
 }
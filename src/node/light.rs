@@ -0,0 +1,673 @@
+use std::ops::RangeInclusive;
+
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use serde::{Deserialize, Serialize};
+
+use super::NodeFlags;
+use super::message::{CommonNodeMessage, CommonNodeResponse, EventMessage, EventResponse, MessageHandling, SelfNodeMut};
+use super::subscribtion::{Event, Subscription};
+use super::viewer::{
+    angle_input_view_in_range, color_input_remote_value, color_input_view, number_input_remote_value,
+    number_input_view, number_input_view_in_range, vector_input_remote_value, vector_input_view,
+};
+use crate::raytracer::scene::GpuLight;
+use crate::types::{Angle, Color, NodePin, Vector3};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum LightNode {
+    Point(PointLightNode),
+    Directional(DirectionalLightNode),
+    Spot(SpotLightNode),
+}
+
+impl LightNode {
+    pub const NAME: &str = "Light";
+
+    pub fn inputs(&self) -> &[u64] {
+        match self {
+            Self::Point(point) => point.inputs(),
+            Self::Directional(directional) => directional.inputs(),
+            Self::Spot(spot) => spot.inputs(),
+        }
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        match self {
+            Self::Point(point) => point.outputs(),
+            Self::Directional(directional) => directional.outputs(),
+            Self::Spot(spot) => spot.outputs(),
+        }
+    }
+
+    pub fn handle_msg(self_node: SelfNodeMut, msg: CommonNodeMessage) -> Option<CommonNodeResponse> {
+        match self_node.as_light_node_ref() {
+            Self::Point(_) => PointLightNode::handle_msg(self_node, msg),
+            Self::Directional(_) => DirectionalLightNode::handle_msg(self_node, msg),
+            Self::Spot(_) => SpotLightNode::handle_msg(self_node, msg),
+        }
+    }
+
+    pub fn as_point_mut(&mut self) -> &mut PointLightNode {
+        match self {
+            Self::Point(point) => point,
+            node => panic!("Node `{}` is not a `{}`", node.name(), PointLightNode::NAME),
+        }
+    }
+
+    pub fn as_directional_mut(&mut self) -> &mut DirectionalLightNode {
+        match self {
+            Self::Directional(directional) => directional,
+            node => panic!("Node `{}` is not a `{}`", node.name(), DirectionalLightNode::NAME),
+        }
+    }
+
+    pub fn as_spot_mut(&mut self) -> &mut SpotLightNode {
+        match self {
+            Self::Spot(spot) => spot,
+            node => panic!("Node `{}` is not a `{}`", node.name(), SpotLightNode::NAME),
+        }
+    }
+
+    pub const fn name(&self) -> &str {
+        match self {
+            Self::Point(_) => PointLightNode::NAME,
+            Self::Directional(_) => DirectionalLightNode::NAME,
+            Self::Spot(_) => SpotLightNode::NAME,
+        }
+    }
+
+    /// Lights outside of group 0 only reach the spheres of their own group.
+    pub fn light_group(&self) -> &NodePin<f64> {
+        match self {
+            Self::Point(point) => &point.light_group,
+            Self::Directional(directional) => &directional.light_group,
+            Self::Spot(spot) => &spot.light_group,
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        match self {
+            Self::Point(point) => point.disabled,
+            Self::Directional(directional) => directional.disabled,
+            Self::Spot(spot) => spot.disabled,
+        }
+    }
+
+    pub fn disabled_mut(&mut self) -> &mut bool {
+        match self {
+            Self::Point(point) => &mut point.disabled,
+            Self::Directional(directional) => &mut directional.disabled,
+            Self::Spot(spot) => &mut spot.disabled,
+        }
+    }
+
+    pub fn collapsed_mut(&mut self) -> &mut bool {
+        match self {
+            Self::Point(point) => &mut point.collapsed,
+            Self::Directional(directional) => &mut directional.collapsed,
+            Self::Spot(spot) => &mut spot.collapsed,
+        }
+    }
+
+    fn subscription_mut(&mut self) -> &mut Subscription {
+        match self {
+            Self::Point(point) => &mut point.subscription,
+            Self::Directional(directional) => &mut directional.subscription,
+            Self::Spot(spot) => &mut spot.subscription,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PointLightNode {
+    pub position: NodePin<Vector3>,
+    pub color: NodePin<Color>,
+    pub intensity: NodePin<f64>,
+
+    #[serde(default)]
+    pub light_group: NodePin<f64>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for PointLightNode {
+    fn default() -> Self {
+        Self {
+            position: NodePin::new(Vector3::new(0.0, 5.0, 0.0)),
+            color: NodePin::new(Color::WHITE),
+            intensity: NodePin::new(10.0),
+            light_group: NodePin::default(),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl PointLightNode {
+    pub const NAME: &str = "Point Light";
+    pub const INPUTS: [u64; 4] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::LIGHT_POINT.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+}
+
+impl MessageHandling for PointLightNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "Position";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_point_mut();
+
+                let old_value = node.position.get();
+                let info = vector_input_view(ui, LABEL, &mut node.position, remote_value);
+
+                if old_value != node.position.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            1 => {
+                const LABEL: &str = "Color";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_point_mut();
+
+                let old_value = node.color.get();
+                let info = color_input_view(ui, LABEL, &mut node.color, remote_value);
+
+                if old_value != node.color.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            2 => {
+                const LABEL: &str = "Intensity";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_point_mut();
+
+                let old_value = node.intensity.get();
+                let info = number_input_view(ui, LABEL, &mut node.intensity, remote_value);
+
+                if old_value != node.intensity.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            3 => {
+                const LABEL: &str = "Light Group";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_point_mut();
+
+                let old_value = node.light_group.get();
+                let info = number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.light_group,
+                    remote_value,
+                    GpuLight::LIGHT_GROUP_RANGE,
+                );
+
+                if old_value != node.light_group.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, _to: &InPin) {
+        let node = self_node.as_light_node_mut().as_point_mut();
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_light_node_mut().as_point_mut();
+        match to.id.input {
+            0 => node.position.reset(),
+            1 => node.color.reset(),
+            2 => node.intensity.reset(),
+            3 => node.light_group.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        self_node.as_light_node_mut().subscription_mut().handle_event(event_msg)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DirectionalLightNode {
+    /// Direction the light travels in, it does not have to be normalized.
+    pub direction: NodePin<Vector3>,
+    pub color: NodePin<Color>,
+
+    #[serde(default)]
+    pub light_group: NodePin<f64>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for DirectionalLightNode {
+    fn default() -> Self {
+        Self {
+            direction: NodePin::new(Vector3::new(-1.0, -2.0, -1.0)),
+            color: NodePin::new(Color::WHITE),
+            light_group: NodePin::default(),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl DirectionalLightNode {
+    pub const NAME: &str = "Directional Light";
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::LIGHT_DIRECTIONAL.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+}
+
+impl MessageHandling for DirectionalLightNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "Direction";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_directional_mut();
+
+                let old_value = node.direction.get();
+                let info = vector_input_view(ui, LABEL, &mut node.direction, remote_value);
+
+                if old_value != node.direction.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            1 => {
+                const LABEL: &str = "Color";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_directional_mut();
+
+                let old_value = node.color.get();
+                let info = color_input_view(ui, LABEL, &mut node.color, remote_value);
+
+                if old_value != node.color.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            2 => {
+                const LABEL: &str = "Light Group";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_directional_mut();
+
+                let old_value = node.light_group.get();
+                let info = number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.light_group,
+                    remote_value,
+                    GpuLight::LIGHT_GROUP_RANGE,
+                );
+
+                if old_value != node.light_group.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, _to: &InPin) {
+        let node = self_node.as_light_node_mut().as_directional_mut();
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_light_node_mut().as_directional_mut();
+        match to.id.input {
+            0 => node.direction.reset(),
+            1 => node.color.reset(),
+            2 => node.light_group.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        self_node.as_light_node_mut().subscription_mut().handle_event(event_msg)
+    }
+}
+
+/// Point light limited to a cone, the intensity fades out between the inner and the outer angle.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpotLightNode {
+    pub position: NodePin<Vector3>,
+    /// Axis of the cone, it does not have to be normalized.
+    pub direction: NodePin<Vector3>,
+    pub color: NodePin<Color>,
+    pub intensity: NodePin<f64>,
+    /// Angles between the axis and the edges of the cone.
+    pub inner_angle: NodePin<Angle>,
+    pub outer_angle: NodePin<Angle>,
+
+    #[serde(default)]
+    pub light_group: NodePin<f64>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for SpotLightNode {
+    fn default() -> Self {
+        Self {
+            position: NodePin::new(Vector3::new(0.0, 5.0, 0.0)),
+            direction: NodePin::new(Vector3::new(0.0, -1.0, 0.0)),
+            color: NodePin::new(Color::WHITE),
+            intensity: NodePin::new(10.0),
+            inner_angle: NodePin::new(Angle::degrees(20.0)),
+            outer_angle: NodePin::new(Angle::degrees(30.0)),
+            light_group: NodePin::default(),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl SpotLightNode {
+    pub const NAME: &str = "Spot Light";
+    pub const INPUTS: [u64; 7] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::LIGHT_SPOT.bits()];
+
+    /// Valid cone angles in degrees.
+    pub const ANGLE_RANGE: RangeInclusive<f64> = 0.0..=90.0;
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    /// Inner and outer angle clamped to [`Self::ANGLE_RANGE`], the inner one never exceeds the outer one.
+    pub fn cone_angles(&self) -> (Angle, Angle) {
+        let min = Angle::degrees(*Self::ANGLE_RANGE.start());
+        let max = Angle::degrees(*Self::ANGLE_RANGE.end());
+        let outer = self.outer_angle.get().clamp(min, max);
+        let inner = self.inner_angle.get().clamp(min, outer);
+        (inner, outer)
+    }
+}
+
+impl MessageHandling for SpotLightNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "Position";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.position.get();
+                let info = vector_input_view(ui, LABEL, &mut node.position, remote_value);
+
+                if old_value != node.position.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            1 => {
+                const LABEL: &str = "Direction";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.direction.get();
+                let info = vector_input_view(ui, LABEL, &mut node.direction, remote_value);
+
+                if old_value != node.direction.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            2 => {
+                const LABEL: &str = "Color";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.color.get();
+                let info = color_input_view(ui, LABEL, &mut node.color, remote_value);
+
+                if old_value != node.color.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            3 => {
+                const LABEL: &str = "Intensity";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.intensity.get();
+                let info = number_input_view(ui, LABEL, &mut node.intensity, remote_value);
+
+                if old_value != node.intensity.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            4 => {
+                const LABEL: &str = "Inner Angle";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.inner_angle.get();
+                let info = angle_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.inner_angle,
+                    remote_value,
+                    SpotLightNode::ANGLE_RANGE,
+                );
+
+                if old_value != node.inner_angle.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            5 => {
+                const LABEL: &str = "Outer Angle";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.outer_angle.get();
+                let info = angle_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.outer_angle,
+                    remote_value,
+                    SpotLightNode::ANGLE_RANGE,
+                );
+
+                if old_value != node.outer_angle.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            6 => {
+                const LABEL: &str = "Light Group";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_light_node_mut().as_spot_mut();
+
+                let old_value = node.light_group.get();
+                let info = number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.light_group,
+                    remote_value,
+                    GpuLight::LIGHT_GROUP_RANGE,
+                );
+
+                if old_value != node.light_group.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, _to: &InPin) {
+        let node = self_node.as_light_node_mut().as_spot_mut();
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_light_node_mut().as_spot_mut();
+        match to.id.input {
+            0 => node.position.reset(),
+            1 => node.direction.reset(),
+            2 => node.color.reset(),
+            3 => node.intensity.reset(),
+            4 => node.inner_angle.reset(),
+            5 => node.outer_angle.reset(),
+            6 => node.light_group.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        self_node.as_light_node_mut().subscription_mut().handle_event(event_msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spot_cone_angles_are_ordered() {
+        let mut node = SpotLightNode::default();
+        node.inner_angle = NodePin::new(Angle::degrees(50.0));
+        node.outer_angle = NodePin::new(Angle::degrees(120.0));
+
+        let (inner, outer) = node.cone_angles();
+        assert_eq!(outer, Angle::degrees(90.0));
+        assert_eq!(inner, Angle::degrees(50.0));
+
+        node.outer_angle = NodePin::new(Angle::degrees(10.0));
+        assert_eq!(node.cone_angles(), (Angle::degrees(10.0), Angle::degrees(10.0)));
+    }
+}
@@ -1,18 +1,25 @@
+use egui::{Color32, Painter, Pos2, Rect, Response, Rgba, Sense, Stroke, Ui, Vec2};
 use egui_snarl::NodeId;
 use serde::{Deserialize, Serialize};
 
 pub use self::checkerboard::CheckerboardNode;
 pub use self::dielectric::DielectricNode;
 pub use self::emissive::EmissiveNode;
+pub use self::isotropic::IsotropicNode;
 pub use self::lambertian::LambertianNode;
 pub use self::metal::MetalNode;
+pub use self::mix::MixMaterialNode;
+pub use self::subsurface::SubsurfaceNode;
 use super::message::{CommonNodeMessage, CommonNodeResponse, MessageHandling, SelfNodeMut};
 
 pub mod checkerboard;
 pub mod dielectric;
 pub mod emissive;
+pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
+pub mod mix;
+pub mod subsurface;
 
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub enum InputMaterial {
@@ -33,6 +40,9 @@ pub enum MaterialNode {
     Lambertian(LambertianNode),
     Emissive(EmissiveNode),
     Checkerboard(CheckerboardNode),
+    Isotropic(IsotropicNode),
+    Subsurface(SubsurfaceNode),
+    Mix(MixMaterialNode),
 }
 
 impl Default for MaterialNode {
@@ -51,6 +61,9 @@ impl MaterialNode {
             Self::Lambertian(_) => LambertianNode::NAME,
             Self::Emissive(_) => EmissiveNode::NAME,
             Self::Checkerboard(_) => CheckerboardNode::NAME,
+            Self::Isotropic(_) => IsotropicNode::NAME,
+            Self::Subsurface(_) => SubsurfaceNode::NAME,
+            Self::Mix(_) => MixMaterialNode::NAME,
         }
     }
 
@@ -61,6 +74,9 @@ impl MaterialNode {
             Self::Lambertian(lambert) => lambert.inputs(),
             Self::Emissive(emissive) => emissive.inputs(),
             Self::Checkerboard(checkerboard) => checkerboard.inputs(),
+            Self::Isotropic(isotropic) => isotropic.inputs(),
+            Self::Subsurface(subsurface) => subsurface.inputs(),
+            Self::Mix(mix) => mix.inputs(),
         }
     }
 
@@ -71,9 +87,124 @@ impl MaterialNode {
             Self::Lambertian(lambert) => lambert.outputs(),
             Self::Emissive(emissive) => emissive.outputs(),
             Self::Checkerboard(checkerboard) => checkerboard.outputs(),
+            Self::Isotropic(isotropic) => isotropic.outputs(),
+            Self::Subsurface(subsurface) => subsurface.outputs(),
+            Self::Mix(mix) => mix.outputs(),
         }
     }
 
+    pub fn is_disabled(&self) -> bool {
+        match self {
+            Self::Metal(metal) => metal.disabled,
+            Self::Dielectric(dielectric) => dielectric.disabled,
+            Self::Lambertian(lambert) => lambert.disabled,
+            Self::Emissive(emissive) => emissive.disabled,
+            Self::Checkerboard(checkerboard) => checkerboard.disabled,
+            Self::Isotropic(isotropic) => isotropic.disabled,
+            Self::Subsurface(subsurface) => subsurface.disabled,
+            Self::Mix(mix) => mix.disabled,
+        }
+    }
+
+    pub fn disabled_mut(&mut self) -> &mut bool {
+        match self {
+            Self::Metal(metal) => &mut metal.disabled,
+            Self::Dielectric(dielectric) => &mut dielectric.disabled,
+            Self::Lambertian(lambert) => &mut lambert.disabled,
+            Self::Emissive(emissive) => &mut emissive.disabled,
+            Self::Checkerboard(checkerboard) => &mut checkerboard.disabled,
+            Self::Isotropic(isotropic) => &mut isotropic.disabled,
+            Self::Subsurface(subsurface) => &mut subsurface.disabled,
+            Self::Mix(mix) => &mut mix.disabled,
+        }
+    }
+
+    pub fn collapsed_mut(&mut self) -> &mut bool {
+        match self {
+            Self::Metal(metal) => &mut metal.collapsed,
+            Self::Dielectric(dielectric) => &mut dielectric.collapsed,
+            Self::Lambertian(lambert) => &mut lambert.collapsed,
+            Self::Emissive(emissive) => &mut emissive.collapsed,
+            Self::Checkerboard(checkerboard) => &mut checkerboard.collapsed,
+            Self::Isotropic(isotropic) => &mut isotropic.collapsed,
+            Self::Subsurface(subsurface) => &mut subsurface.collapsed,
+            Self::Mix(mix) => &mut mix.collapsed,
+        }
+    }
+
+    /// Small sphere hinting at the look of the material. Connected textures are not sampled.
+    pub fn show_preview(&self, ui: &mut Ui) -> Response {
+        let size = Vec2::splat(ui.spacing().interact_size.y);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let painter = ui.painter();
+        let (center, radius) = (rect.center(), 0.5 * rect.width());
+        // Light comes from the top left.
+        let highlight = center - Vec2::splat(0.35 * radius);
+
+        match self {
+            Self::Metal(metal) => {
+                shade_sphere(painter, center, radius, metal.albedo.get());
+                // Rougher metal spreads the highlight out and dims it.
+                let fuzz = metal.fuzz.get().clamp(0.0, 1.0) as f32;
+                let highlight_color = Color32::from_white_alpha((255.0 * (1.0 - 0.7 * fuzz)) as u8);
+                painter.circle_filled(highlight, radius * (0.15 + 0.35 * fuzz), highlight_color);
+            },
+            Self::Dielectric(_) => {
+                painter.circle_filled(center, radius, Color32::from_white_alpha(24));
+                painter.circle_stroke(center, radius, Stroke::new(1.0, Color32::from_white_alpha(128)));
+                painter.circle_filled(highlight, 0.15 * radius, Color32::WHITE);
+            },
+            Self::Lambertian(lambert) => {
+                painter.circle_filled(center, radius, lambert.albedo.get());
+            },
+            Self::Emissive(emissive) => {
+                let emit = emissive.emit.get();
+                let color = Color32::from(Rgba::from_rgb(emit.x as _, emit.y as _, emit.z as _));
+                painter.circle_filled(center, radius, color.gamma_multiply(0.3));
+                painter.circle_filled(center, 0.75 * radius, color);
+            },
+            Self::Checkerboard(checkerboard) => {
+                painter.circle_filled(center, radius, checkerboard.even.get());
+                let quadrants = [
+                    Rect::from_min_max(rect.min, center),
+                    Rect::from_min_max(center, rect.max),
+                ];
+                for quadrant in quadrants {
+                    painter
+                        .with_clip_rect(quadrant)
+                        .circle_filled(center, radius, checkerboard.odd.get());
+                }
+            },
+            Self::Isotropic(isotropic) => {
+                // Denser fog lets less of the background through.
+                let density = isotropic.density.get().max(0.0) as f32;
+                let opacity = 1.0 - (-2.0 * density).exp();
+                painter.circle_filled(center, radius, isotropic.albedo.get().gamma_multiply(opacity));
+            },
+            Self::Subsurface(subsurface) => {
+                shade_sphere(painter, center, radius, subsurface.albedo.get());
+                // Light wandering further beneath the surface softens the shading with a brighter glow.
+                let glow = subsurface.radius.get().clamp(0.0, 1.0) as f32;
+                let glow_color = Color32::from_white_alpha((24.0 + 40.0 * glow) as u8);
+                painter.circle_filled(center, radius * (0.5 + 0.4 * glow), glow_color);
+            },
+            Self::Mix(_) => {
+                // The inputs are other nodes, so the halves only hint at two materials meeting.
+                painter.circle_filled(center, radius, Color32::from_gray(80));
+                let right = Rect::from_min_max(rect.center_top(), rect.max);
+                painter
+                    .with_clip_rect(right)
+                    .circle_filled(center, radius, Color32::from_gray(180));
+            },
+        }
+
+        response
+    }
+
     pub fn handle_msg(self_node: SelfNodeMut, msg: CommonNodeMessage) -> Option<CommonNodeResponse> {
         match self_node.as_material_node_ref() {
             Self::Metal(_) => MetalNode::handle_msg(self_node, msg),
@@ -81,6 +212,9 @@ impl MaterialNode {
             Self::Lambertian(_) => LambertianNode::handle_msg(self_node, msg),
             Self::Emissive(_) => EmissiveNode::handle_msg(self_node, msg),
             Self::Checkerboard(_) => CheckerboardNode::handle_msg(self_node, msg),
+            Self::Isotropic(_) => IsotropicNode::handle_msg(self_node, msg),
+            Self::Subsurface(_) => SubsurfaceNode::handle_msg(self_node, msg),
+            Self::Mix(_) => MixMaterialNode::handle_msg(self_node, msg),
         }
     }
 
@@ -119,6 +253,27 @@ impl MaterialNode {
         }
     }
 
+    pub fn as_isotropic_mut(&mut self) -> &mut IsotropicNode {
+        match self {
+            Self::Isotropic(isotropic) => isotropic,
+            node => panic!("Node `{}` is not a `{}`", node.name(), IsotropicNode::NAME),
+        }
+    }
+
+    pub fn as_subsurface_mut(&mut self) -> &mut SubsurfaceNode {
+        match self {
+            Self::Subsurface(subsurface) => subsurface,
+            node => panic!("Node `{}` is not a `{}`", node.name(), SubsurfaceNode::NAME),
+        }
+    }
+
+    pub fn as_mix_mut(&mut self) -> &mut MixMaterialNode {
+        match self {
+            Self::Mix(mix) => mix,
+            node => panic!("Node `{}` is not a `{}`", node.name(), MixMaterialNode::NAME),
+        }
+    }
+
     pub fn get_texture_node_id(&self) -> Option<NodeId> {
         match self {
             Self::Metal(metal) => metal.texture.get(),
@@ -126,6 +281,56 @@ impl MaterialNode {
             Self::Lambertian(lambert) => lambert.texture.get(),
             Self::Emissive(emissive) => emissive.texture.get(),
             Self::Checkerboard(_) => None,
+            Self::Isotropic(_) => None,
+            Self::Subsurface(_) => None,
+            Self::Mix(_) => None,
         }
     }
+
+    pub fn get_normal_texture_node_id(&self) -> Option<NodeId> {
+        match self {
+            Self::Metal(metal) => metal.normal_texture.get(),
+            Self::Lambertian(lambert) => lambert.normal_texture.get(),
+            Self::Dielectric(_)
+            | Self::Emissive(_)
+            | Self::Checkerboard(_)
+            | Self::Isotropic(_)
+            | Self::Subsurface(_)
+            | Self::Mix(_) => None,
+        }
+    }
+
+    pub fn get_roughness_texture_node_id(&self) -> Option<NodeId> {
+        match self {
+            Self::Metal(metal) => metal.roughness_texture.get(),
+            Self::Dielectric(dielectric) => dielectric.roughness_texture.get(),
+            Self::Lambertian(_)
+            | Self::Emissive(_)
+            | Self::Checkerboard(_)
+            | Self::Isotropic(_)
+            | Self::Subsurface(_)
+            | Self::Mix(_) => None,
+        }
+    }
+
+    pub fn get_height_texture_node_id(&self) -> Option<NodeId> {
+        match self {
+            Self::Metal(metal) => metal.height_texture.get(),
+            Self::Lambertian(lambert) => lambert.height_texture.get(),
+            Self::Dielectric(_)
+            | Self::Emissive(_)
+            | Self::Checkerboard(_)
+            | Self::Isotropic(_)
+            | Self::Subsurface(_)
+            | Self::Mix(_) => None,
+        }
+    }
+}
+
+/// Darker rim with the lit side of the sphere towards the top left.
+fn shade_sphere(painter: &Painter, center: Pos2, radius: f32, color: Color32) {
+    let [r, g, b, _] = color.to_array();
+    let shadow = Color32::from_rgb(r / 2, g / 2, b / 2);
+    painter.circle_filled(center, radius, shadow);
+    painter.circle_filled(center - Vec2::splat(0.1 * radius), 0.85 * radius, color);
 }
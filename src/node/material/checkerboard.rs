@@ -1,18 +1,31 @@
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, OutPin};
+use egui_snarl::{InPin, NodeId, OutPin};
 use serde::{Deserialize, Serialize};
 
 use crate::node::NodeFlags;
 use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::{Event, Subscription};
-use crate::node::viewer::{color_input_remote_value, color_input_view};
+use crate::node::viewer::{
+    color_input_remote_value, color_input_view, number_input_remote_value, number_input_view_in_range,
+};
+use crate::raytracer::scene::CheckerboardSpace;
 use crate::types::{Color, NodePin};
 
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct CheckerboardNode {
     pub even: NodePin<Color>,
     pub odd: NodePin<Color>,
+    #[serde(default = "default_scale")]
+    pub scale: NodePin<f64>,
+    /// Older scenes keep the solid tiles they were made with.
+    #[serde(default)]
+    pub space: CheckerboardSpace,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -23,6 +36,10 @@ impl Default for CheckerboardNode {
         Self {
             even: NodePin::new(Color::BLACK),
             odd: NodePin::new(Color::WHITE),
+            scale: default_scale(),
+            space: CheckerboardSpace::Solid,
+            disabled: false,
+            collapsed: false,
             subscription: Subscription::default(),
         }
     }
@@ -30,9 +47,10 @@ impl Default for CheckerboardNode {
 
 impl CheckerboardNode {
     pub const NAME: &str = "Checkerboard Material";
-    pub const INPUTS: [u64; 2] = [
+    pub const INPUTS: [u64; 3] = [
         NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
         NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_CHECKERBOARD.bits()];
 
@@ -45,6 +63,24 @@ impl CheckerboardNode {
     }
 }
 
+fn default_scale() -> NodePin<f64> {
+    NodePin::new(CheckerboardSpace::DEFAULT_SCALE as f64)
+}
+
+/// Dropdown choosing whether the tiles follow the texture coordinates or fill the world.
+fn space_view(ui: &mut Ui, node_id: NodeId, space: &mut CheckerboardSpace) {
+    ui.horizontal(|ui| {
+        ui.label("Space");
+        egui::ComboBox::from_id_salt(("checkerboard_space", node_id))
+            .selected_text(space.name())
+            .show_ui(ui, |ui| {
+                for value in [CheckerboardSpace::Solid, CheckerboardSpace::Uv] {
+                    ui.selectable_value(space, value, value.name());
+                }
+            });
+    });
+}
+
 impl MessageHandling for CheckerboardNode {
     fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
         Some(match pin.id.input {
@@ -80,6 +116,27 @@ impl MessageHandling for CheckerboardNode {
                 }
                 info
             },
+            2 => {
+                const LABEL: &str = "Scale";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_checkerboard_mut();
+
+                let old_value = (node.scale.get(), node.space);
+                let info = ui
+                    .vertical(|ui| {
+                        space_view(ui, pin.id.node, &mut node.space);
+                        number_input_view_in_range(ui, LABEL, &mut node.scale, remote_value, 0.0..=f64::MAX)
+                    })
+                    .inner;
+
+                if old_value != (node.scale.get(), node.space) {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
             _ => unreachable!(),
         })
     }
@@ -96,6 +153,7 @@ impl MessageHandling for CheckerboardNode {
         match to.id.input {
             0 => node.even.reset(),
             1 => node.odd.reset(),
+            2 => node.scale.reset(),
             _ => unreachable!(),
         }
 
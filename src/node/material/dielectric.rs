@@ -1,26 +1,66 @@
+use std::ops::RangeInclusive;
+
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, OutPin};
+use egui_snarl::{InPin, NodeId, OutPin};
 use serde::{Deserialize, Serialize};
 
-use crate::node::NodeFlags;
 use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::{Event, Subscription};
-use crate::node::viewer::{number_input_remote_value, number_input_view};
-use crate::types::NodePin;
+use crate::node::viewer::{
+    color_input_remote_value, color_input_view, empty_input_view, invalid_connection, number_input_remote_value,
+    number_input_view_with_speed,
+};
+use crate::node::{Node, NodeFlags, collect_for_node};
+use crate::types::{Color, NodePin};
 
-#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct DielectricNode {
     pub ior: NodePin<f64>,
+    /// Spread of the refracted and reflected rays, 0 is clear glass.
+    #[serde(default)]
+    pub roughness: NodePin<f64>,
+    /// Grayscale texture the roughness is multiplied with, e.g. for frosted patches.
+    #[serde(default)]
+    pub roughness_texture: NodePin<Option<NodeId>>,
+    /// Color light takes on after traveling a distance of 1 inside the glass, white is clear glass.
+    #[serde(default = "default_absorption")]
+    pub absorption: NodePin<Color>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
 }
 
+impl Default for DielectricNode {
+    fn default() -> Self {
+        Self {
+            ior: NodePin::default(),
+            roughness: NodePin::default(),
+            roughness_texture: NodePin::default(),
+            absorption: default_absorption(),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
 impl DielectricNode {
     pub const NAME: &str = "Dielectric Material";
-    pub const INPUTS: [u64; 1] = [NodeFlags::TYPICAL_NUMBER_INPUT.bits()];
+    pub const INPUTS: [u64; 4] = [
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_DIELECTRIC.bits()];
+    /// From air to diamond.
+    pub const IOR_RANGE: RangeInclusive<f64> = 1.0..=2.5;
 
     pub fn inputs(&self) -> &[u64] {
         &Self::INPUTS
@@ -31,6 +71,10 @@ impl DielectricNode {
     }
 }
 
+fn default_absorption() -> NodePin<Color> {
+    NodePin::new(Color::WHITE)
+}
+
 impl MessageHandling for DielectricNode {
     fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
         Some(match pin.id.input {
@@ -41,7 +85,14 @@ impl MessageHandling for DielectricNode {
                 let node = self_node.as_material_node_mut().as_dielectric_mut();
 
                 let old_value = node.ior.get();
-                let info = number_input_view(ui, LABEL, &mut node.ior, remote_value);
+                let info = number_input_view_with_speed(
+                    ui,
+                    LABEL,
+                    &mut node.ior,
+                    remote_value,
+                    DielectricNode::IOR_RANGE,
+                    0.01,
+                );
 
                 if old_value != node.ior.get() {
                     if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
@@ -50,6 +101,60 @@ impl MessageHandling for DielectricNode {
                 }
                 info
             },
+            1 => {
+                const LABEL: &str = "Roughness";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_dielectric_mut();
+
+                let old_value = node.roughness.get();
+                let info = number_input_view_with_speed(ui, LABEL, &mut node.roughness, remote_value, 0.0..=1.0, 0.01);
+
+                if old_value != node.roughness.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            2 => {
+                const LABEL: &str = "Roughness Map";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_dielectric_mut();
+                    node.roughness_texture.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            3 => {
+                const LABEL: &str = "Absorption";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_dielectric_mut();
+
+                let old_value = node.absorption.get();
+                let info = color_input_view(ui, LABEL, &mut node.absorption, remote_value);
+
+                if old_value != node.absorption.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
             _ => unreachable!(),
         })
     }
@@ -65,6 +170,9 @@ impl MessageHandling for DielectricNode {
         let node = self_node.as_material_node_mut().as_dielectric_mut();
         match to.id.input {
             0 => node.ior.reset(),
+            1 => node.roughness.reset(),
+            2 => node.roughness_texture.reset(),
+            3 => node.absorption.reset(),
             _ => unreachable!(),
         }
 
@@ -73,6 +181,19 @@ impl MessageHandling for DielectricNode {
         }
     }
 
+    fn handle_input_collect_ids(
+        self_node: SelfNodeMut,
+        predicate: &dyn Fn(&Node) -> bool,
+        destination: &mut eframe::wgpu::naga::FastIndexSet<NodeId>,
+    ) {
+        collect_for_node(
+            self_node.as_material_node_ref().get_roughness_texture_node_id(),
+            predicate,
+            destination,
+            self_node.snarl,
+        );
+    }
+
     fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
         let node = self_node.as_material_node_mut().as_dielectric_mut();
         node.subscription.handle_event(event_msg)
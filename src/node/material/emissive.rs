@@ -6,19 +6,41 @@ use serde::{Deserialize, Serialize};
 
 use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::{Event, Subscription};
-use crate::node::viewer::{empty_input_view, vector_input_remote_value, vector_input_view};
+use crate::node::viewer::{empty_input_view, invalid_connection, vector_input_remote_value, vector_input_view};
 use crate::node::{Node, NodeFlags, collect_for_node};
+use crate::raytracer::scene::Material;
 use crate::types::{NodePin, Vector3};
 
-#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct EmissiveNode {
     pub emit: NodePin<Vector3>,
     pub texture: NodePin<Option<NodeId>>,
+    /// Turned off, only the front faces emit light.
+    #[serde(default = "Material::default_two_sided")]
+    pub two_sided: bool,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
 }
 
+impl Default for EmissiveNode {
+    fn default() -> Self {
+        Self {
+            emit: NodePin::default(),
+            texture: NodePin::default(),
+            two_sided: Material::default_two_sided(),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
 impl EmissiveNode {
     pub const NAME: &str = "Emissive Material";
     pub const INPUTS: [u64; 2] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits(), NodeFlags::TEXTURE.bits()];
@@ -42,10 +64,15 @@ impl MessageHandling for EmissiveNode {
                 let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_material_node_mut().as_emissive_mut();
 
-                let old_value = node.emit.get();
-                let info = vector_input_view(ui, LABEL, &mut node.emit, remote_value);
+                let old_value = (node.emit.get(), node.two_sided);
+                let info = ui
+                    .vertical(|ui| {
+                        ui.checkbox(&mut node.two_sided, "Two-sided");
+                        vector_input_view(ui, LABEL, &mut node.emit, remote_value)
+                    })
+                    .inner;
 
-                if old_value != node.emit.get() {
+                if old_value != (node.emit.get(), node.two_sided) {
                     if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
                         caller(self_node)
                     }
@@ -58,8 +85,11 @@ impl MessageHandling for EmissiveNode {
                 let remote_value = match &*pin.remotes {
                     [] => None,
                     [remote] => Some(match &self_node.snarl[remote.node] {
-                        Node::Texture(_) => Some(remote.node),
-                        node => unreachable!("{LABEL} input not suppor connection with `{}`", node.name()),
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
                     }),
                     _ => None,
                 };
@@ -0,0 +1,125 @@
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use serde::{Deserialize, Serialize};
+
+use crate::node::NodeFlags;
+use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
+use crate::node::subscribtion::{Event, Subscription};
+use crate::node::viewer::{
+    color_input_remote_value, color_input_view, number_input_remote_value, number_input_view_in_range,
+};
+use crate::types::{Color, NodePin};
+
+/// Fills the sphere with fog or smoke, scattering light equally in all directions.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct IsotropicNode {
+    pub albedo: NodePin<Color>,
+    /// Chance of scattering per unit of distance travelled inside the volume.
+    pub density: NodePin<f64>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for IsotropicNode {
+    fn default() -> Self {
+        Self {
+            albedo: NodePin::new(Color::WHITE),
+            density: NodePin::new(Self::DEFAULT_DENSITY),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl IsotropicNode {
+    pub const NAME: &str = "Isotropic Material";
+    pub const INPUTS: [u64; 2] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_ISOTROPIC.bits()];
+
+    /// A ray crossing a unit sphere scatters about two times out of three.
+    pub const DEFAULT_DENSITY: f64 = 0.5;
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+}
+
+impl MessageHandling for IsotropicNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "Albedo";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_isotropic_mut();
+
+                let old_value = node.albedo.get();
+                let info = color_input_view(ui, LABEL, &mut node.albedo, remote_value);
+
+                if old_value != node.albedo.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            1 => {
+                const LABEL: &str = "Density";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_isotropic_mut();
+
+                let old_value = node.density.get();
+                let info = number_input_view_in_range(ui, LABEL, &mut node.density, remote_value, 0.0..=f64::MAX);
+
+                if old_value != node.density.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, _to: &InPin) {
+        let node = self_node.as_material_node_mut().as_isotropic_mut();
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_material_node_mut().as_isotropic_mut();
+        match to.id.input {
+            0 => node.albedo.reset(),
+            1 => node.density.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        let node = self_node.as_material_node_mut().as_isotropic_mut();
+        node.subscription.handle_event(event_msg)
+    }
+}
@@ -6,14 +6,32 @@ use serde::{Deserialize, Serialize};
 
 use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::{Event, Subscription};
-use crate::node::viewer::{color_input_remote_value, color_input_view, empty_input_view};
+use crate::node::viewer::{
+    angle_input_view, color_input_remote_value, color_input_view, empty_input_view, invalid_connection,
+    number_input_remote_value,
+};
 use crate::node::{Node, NodeFlags, collect_for_node};
-use crate::types::{Color, NodePin};
+use crate::types::{Angle, Color, NodePin};
 
 #[derive(Clone, Serialize, Deserialize, PartialEq)]
 pub struct LambertianNode {
     pub albedo: NodePin<Color>,
     pub texture: NodePin<Option<NodeId>>,
+    /// Tangent space normals, red is along U and green along V.
+    #[serde(default)]
+    pub normal_texture: NodePin<Option<NodeId>>,
+    /// Grayscale bump map, the normal tilts away from higher values.
+    #[serde(default)]
+    pub height_texture: NodePin<Option<NodeId>>,
+
+    /// Turns the textures around a sphere, e.g. to bring a meridian of a planet to the front.
+    #[serde(default)]
+    pub texture_rotation: NodePin<Angle>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -24,6 +42,11 @@ impl Default for LambertianNode {
         Self {
             albedo: NodePin::new(Color::LIGHT_GRAY),
             texture: NodePin::default(),
+            normal_texture: NodePin::default(),
+            height_texture: NodePin::default(),
+            texture_rotation: NodePin::default(),
+            disabled: false,
+            collapsed: false,
             subscription: Subscription::default(),
         }
     }
@@ -31,7 +54,13 @@ impl Default for LambertianNode {
 
 impl LambertianNode {
     pub const NAME: &str = "Lambertian Material";
-    pub const INPUTS: [u64; 2] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits(), NodeFlags::TEXTURE.bits()];
+    pub const INPUTS: [u64; 5] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_LAMBERT.bits()];
 
     pub fn inputs(&self) -> &[u64] {
@@ -68,8 +97,11 @@ impl MessageHandling for LambertianNode {
                 let remote_value = match &*pin.remotes {
                     [] => None,
                     [remote] => Some(match &self_node.snarl[remote.node] {
-                        Node::Texture(_) => Some(remote.node),
-                        node => unreachable!("{LABEL} input not suppor connection with `{}`", node.name()),
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
                     }),
                     _ => None,
                 };
@@ -81,6 +113,66 @@ impl MessageHandling for LambertianNode {
 
                 empty_input_view(ui, LABEL)
             },
+            2 => {
+                const LABEL: &str = "Normal Map";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_lambert_mut();
+                    node.normal_texture.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            3 => {
+                const LABEL: &str = "Height Map";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_lambert_mut();
+                    node.height_texture.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            4 => {
+                const LABEL: &str = "Texture Rotation";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_lambert_mut();
+
+                let old_value = node.texture_rotation.get();
+                let info = angle_input_view(ui, LABEL, &mut node.texture_rotation, remote_value);
+
+                if old_value != node.texture_rotation.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
             _ => unreachable!(),
         })
     }
@@ -98,6 +190,9 @@ impl MessageHandling for LambertianNode {
         match to.id.input {
             0 => node.albedo.reset(),
             1 => node.texture.reset(),
+            2 => node.normal_texture.reset(),
+            3 => node.height_texture.reset(),
+            4 => node.texture_rotation.reset(),
             _ => unreachable!(),
         }
 
@@ -117,6 +212,18 @@ impl MessageHandling for LambertianNode {
             destination,
             self_node.snarl,
         );
+        collect_for_node(
+            self_node.as_material_node_mut().get_normal_texture_node_id(),
+            predicate,
+            destination,
+            self_node.snarl,
+        );
+        collect_for_node(
+            self_node.as_material_node_mut().get_height_texture_node_id(),
+            predicate,
+            destination,
+            self_node.snarl,
+        );
     }
 
     fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
@@ -6,16 +6,38 @@ use serde::{Deserialize, Serialize};
 use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
 use crate::node::subscribtion::{Event, Subscription};
 use crate::node::viewer::{
-    color_input_remote_value, color_input_view, empty_input_view, number_input_remote_value, number_input_view,
+    angle_input_view, color_input_remote_value, color_input_view, empty_input_view, invalid_connection,
+    number_input_remote_value, number_input_view_in_range, number_input_view_with_speed,
 };
 use crate::node::{Node, NodeFlags, collect_for_node};
-use crate::types::{Color, NodePin};
+use crate::types::{Angle, Color, NodePin};
 
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct MetalNode {
     pub albedo: NodePin<Color>,
     pub fuzz: NodePin<f64>,
     pub texture: NodePin<Option<NodeId>>,
+    /// Tangent space normals, red is along U and green along V.
+    #[serde(default)]
+    pub normal_texture: NodePin<Option<NodeId>>,
+    /// Stretches the fuzz along U when positive and along V when negative, 0 is isotropic.
+    #[serde(default)]
+    pub anisotropy: NodePin<f64>,
+    /// Grayscale texture the fuzz is multiplied with, for gloss that varies over the surface.
+    #[serde(default)]
+    pub roughness_texture: NodePin<Option<NodeId>>,
+    /// Grayscale bump map, the normal tilts away from higher values.
+    #[serde(default)]
+    pub height_texture: NodePin<Option<NodeId>>,
+
+    /// Turns the textures around a sphere, e.g. to bring a meridian of a planet to the front.
+    #[serde(default)]
+    pub texture_rotation: NodePin<Angle>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -23,10 +45,15 @@ pub struct MetalNode {
 
 impl MetalNode {
     pub const NAME: &str = "Metal Material";
-    pub const INPUTS: [u64; 3] = [
+    pub const INPUTS: [u64; 8] = [
         NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_METAL.bits()];
 
@@ -65,7 +92,7 @@ impl MessageHandling for MetalNode {
                 let node = self_node.as_material_node_mut().as_metal_mut();
 
                 let old_value = node.fuzz.get();
-                let info = number_input_view(ui, LABEL, &mut node.fuzz, remote_value);
+                let info = number_input_view_with_speed(ui, LABEL, &mut node.fuzz, remote_value, 0.0..=1.0, 0.01);
 
                 if old_value != node.fuzz.get() {
                     if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
@@ -80,8 +107,11 @@ impl MessageHandling for MetalNode {
                 let remote_value = match &*pin.remotes {
                     [] => None,
                     [remote] => Some(match &self_node.snarl[remote.node] {
-                        Node::Texture(_) => Some(remote.node),
-                        node => unreachable!("{LABEL} input not suppor connection with `{}`", node.name()),
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
                     }),
                     _ => None,
                 };
@@ -93,6 +123,104 @@ impl MessageHandling for MetalNode {
 
                 empty_input_view(ui, LABEL)
             },
+            3 => {
+                const LABEL: &str = "Normal Map";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_metal_mut();
+                    node.normal_texture.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            4 => {
+                const LABEL: &str = "Anisotropy";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_metal_mut();
+
+                let old_value = node.anisotropy.get();
+                let info = number_input_view_in_range(ui, LABEL, &mut node.anisotropy, remote_value, -1.0..=1.0);
+
+                if old_value != node.anisotropy.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            5 => {
+                const LABEL: &str = "Roughness Map";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_metal_mut();
+                    node.roughness_texture.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            6 => {
+                const LABEL: &str = "Height Map";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_metal_mut();
+                    node.height_texture.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            7 => {
+                const LABEL: &str = "Texture Rotation";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_metal_mut();
+
+                let old_value = node.texture_rotation.get();
+                let info = angle_input_view(ui, LABEL, &mut node.texture_rotation, remote_value);
+
+                if old_value != node.texture_rotation.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
             _ => unreachable!(),
         })
     }
@@ -110,6 +238,11 @@ impl MessageHandling for MetalNode {
             0 => node.albedo.reset(),
             1 => node.fuzz.reset(),
             2 => node.texture.reset(),
+            3 => node.normal_texture.reset(),
+            4 => node.anisotropy.reset(),
+            5 => node.roughness_texture.reset(),
+            6 => node.height_texture.reset(),
+            7 => node.texture_rotation.reset(),
             _ => unreachable!(),
         }
 
@@ -129,6 +262,24 @@ impl MessageHandling for MetalNode {
             destination,
             self_node.snarl,
         );
+        collect_for_node(
+            self_node.as_material_node_ref().get_normal_texture_node_id(),
+            predicate,
+            destination,
+            self_node.snarl,
+        );
+        collect_for_node(
+            self_node.as_material_node_ref().get_roughness_texture_node_id(),
+            predicate,
+            destination,
+            self_node.snarl,
+        );
+        collect_for_node(
+            self_node.as_material_node_ref().get_height_texture_node_id(),
+            predicate,
+            destination,
+            self_node.snarl,
+        );
     }
 
     fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
@@ -0,0 +1,186 @@
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, NodeId, OutPin};
+use serde::{Deserialize, Serialize};
+
+use crate::node::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
+use crate::node::subscribtion::{Event, Subscription};
+use crate::node::viewer::{
+    MATERIAL_COLOR, empty_input_view, invalid_connection, number_input_remote_value, number_input_view_with_speed,
+};
+use crate::node::{Node, NodeFlags, collect_for_node};
+use crate::types::NodePin;
+
+/// Blends two materials by a mask, e.g. rust spreading over clean metal.
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+pub struct MixMaterialNode {
+    pub first: NodePin<Option<NodeId>>,
+    pub second: NodePin<Option<NodeId>>,
+    /// Grayscale texture, white shows the second material.
+    pub mask: NodePin<Option<NodeId>>,
+    /// Share of the second material where no mask is connected.
+    pub factor: NodePin<f64>,
+
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for MixMaterialNode {
+    fn default() -> Self {
+        Self {
+            first: NodePin::default(),
+            second: NodePin::default(),
+            mask: NodePin::default(),
+            factor: NodePin::new(0.5),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl MixMaterialNode {
+    pub const NAME: &str = "Mix Material";
+    pub const INPUTS: [u64; 4] = [
+        NodeFlags::MATERIALS.bits(),
+        NodeFlags::MATERIALS.bits(),
+        NodeFlags::TEXTURE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::MATERIAL_MIX.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    /// Forgets the connected materials and mask but keeps the factor, for copies that are not connected to anything.
+    pub fn detach(&mut self) {
+        self.first.reset();
+        self.second.reset();
+        self.mask.reset();
+    }
+}
+
+fn material_view(ui: &mut Ui, label: &str) -> PinInfo {
+    ui.label(label);
+    PinInfo::circle().with_fill(MATERIAL_COLOR)
+}
+
+impl MessageHandling for MixMaterialNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 | 1 => {
+                let label = if pin.id.input == 0 { "First" } else { "Second" };
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Material(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(label, node);
+                            return Some(material_view(ui, label));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_mix_mut();
+                    match pin.id.input {
+                        0 => node.first.set(value),
+                        _ => node.second.set(value),
+                    }
+                }
+
+                material_view(ui, label)
+            },
+            2 => {
+                const LABEL: &str = "Mask";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Texture(_) | Node::NoiseTexture(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_material_node_mut().as_mix_mut();
+                    node.mask.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            3 => {
+                const LABEL: &str = "Factor";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_material_node_mut().as_mix_mut();
+
+                let old_value = node.factor.get();
+                let info = number_input_view_with_speed(ui, LABEL, &mut node.factor, remote_value, 0.0..=1.0, 0.01);
+
+                if old_value != node.factor.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, _to: &InPin) {
+        let node = self_node.as_material_node_mut().as_mix_mut();
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_material_node_mut().as_mix_mut();
+        match to.id.input {
+            0 => node.first.reset(),
+            1 => node.second.reset(),
+            2 => node.mask.reset(),
+            3 => node.factor.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_collect_ids(
+        mut self_node: SelfNodeMut,
+        predicate: &dyn Fn(&Node) -> bool,
+        destination: &mut eframe::wgpu::naga::FastIndexSet<NodeId>,
+    ) {
+        let node = self_node.as_material_node_mut().as_mix_mut();
+        let (first, second, mask) = (node.first.get(), node.second.get(), node.mask.get());
+        collect_for_node(first, predicate, destination, self_node.snarl);
+        collect_for_node(second, predicate, destination, self_node.snarl);
+        collect_for_node(mask, predicate, destination, self_node.snarl);
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        let node = self_node.as_material_node_mut().as_mix_mut();
+        node.subscription.handle_event(event_msg)
+    }
+}
@@ -4,14 +4,18 @@ use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, NodeId, OutPin, Snarl};
 
 use super::Node;
+use super::background::BackgroundNode;
 use super::camera::CameraNode;
 use super::collection::CollectionNode;
+use super::color::{ColorMixNode, HsvColorNode};
+use super::light::LightNode;
 use super::material::MaterialNode;
 use super::primitive::PrimitiveNode;
 use super::render::RenderNode;
 use super::scene::SceneNode;
 use super::subscribtion::{Event, EventCallback};
-use super::texture::TextureNode;
+use super::texture::{NoiseTextureNode, TextureNode};
+use super::vecmath::VectorMathNode;
 
 pub enum InputMessage<'a> {
     Show {
@@ -122,6 +126,14 @@ impl SelfNodeMut<'_> {
         self.node_mut().as_primitive_node_mut()
     }
 
+    pub fn as_light_node_ref(&self) -> &LightNode {
+        self.node_ref().as_light_node_ref()
+    }
+
+    pub fn as_light_node_mut(&mut self) -> &mut LightNode {
+        self.node_mut().as_light_node_mut()
+    }
+
     pub fn as_collection_node_ref(&self) -> &CollectionNode {
         self.node_ref().as_collection_node_ref()
     }
@@ -138,6 +150,22 @@ impl SelfNodeMut<'_> {
         self.node_mut().as_camera_node_mut()
     }
 
+    pub fn as_background_node_mut(&mut self) -> &mut BackgroundNode {
+        self.node_mut().as_background_node_mut()
+    }
+
+    pub fn as_vector_math_node_mut(&mut self) -> &mut VectorMathNode {
+        self.node_mut().as_vector_math_node_mut()
+    }
+
+    pub fn as_color_mix_node_mut(&mut self) -> &mut ColorMixNode {
+        self.node_mut().as_color_mix_node_mut()
+    }
+
+    pub fn as_hsv_color_node_mut(&mut self) -> &mut HsvColorNode {
+        self.node_mut().as_hsv_color_node_mut()
+    }
+
     pub fn as_render_node_ref(&self) -> &RenderNode {
         self.node_ref().as_render_node_ref()
     }
@@ -149,6 +177,10 @@ impl SelfNodeMut<'_> {
     pub fn as_texture_node_mut(&mut self) -> &mut TextureNode {
         self.node_mut().as_texture_node_mut()
     }
+
+    pub fn as_noise_texture_node_mut(&mut self) -> &mut NoiseTextureNode {
+        self.node_mut().as_noise_texture_node_mut()
+    }
 }
 
 pub trait MessageHandling {
@@ -1,3 +1,5 @@
+use std::ops::RangeInclusive;
+
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, NodeId, OutPin};
@@ -12,14 +14,19 @@ use super::{NodeFlags, collect_for_node};
 use crate::node::Node;
 use crate::node::subscribtion::Event;
 use crate::node::viewer::{
+    UNTYPED_COLOR, color_input_remote_value, color_input_view, empty_input_view, invalid_connection,
     material_input_remote_value, material_input_view, number_input_remote_value, number_input_view,
-    vector_input_remote_value, vector_input_view,
+    number_input_view_in_range, vector_input_remote_value, vector_input_view,
 };
-use crate::types::{NodePin, Vector3};
+use crate::raytracer::scene::{CsgOperation, GpuLight};
+use crate::types::{Color, Matrix4, NodePin, Vector3, linear_rgb};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum PrimitiveNode {
     Sphere(SphereNode),
+    Csg(CsgNode),
+    Transform(TransformNode),
+    Instance(InstanceNode),
 }
 
 impl PrimitiveNode {
@@ -28,30 +35,94 @@ impl PrimitiveNode {
     pub fn inputs(&self) -> &[u64] {
         match self {
             Self::Sphere(sphere) => sphere.inputs(),
+            Self::Csg(csg) => csg.inputs(),
+            Self::Transform(transform) => transform.inputs(),
+            Self::Instance(instance) => instance.inputs(),
         }
     }
 
     pub fn outputs(&self) -> &[u64] {
         match self {
             Self::Sphere(sphere) => sphere.outputs(),
+            Self::Csg(csg) => csg.outputs(),
+            Self::Transform(transform) => transform.outputs(),
+            Self::Instance(instance) => instance.outputs(),
         }
     }
 
     pub fn handle_msg(self_node: SelfNodeMut, msg: CommonNodeMessage) -> Option<CommonNodeResponse> {
         match self_node.as_primitive_node_ref() {
             Self::Sphere(_) => SphereNode::handle_msg(self_node, msg),
+            Self::Csg(_) => CsgNode::handle_msg(self_node, msg),
+            Self::Transform(_) => TransformNode::handle_msg(self_node, msg),
+            Self::Instance(_) => InstanceNode::handle_msg(self_node, msg),
         }
     }
 
-    pub fn as_sphere_ref(&self) -> &SphereNode {
+    pub fn is_disabled(&self) -> bool {
         match self {
-            Self::Sphere(sphere) => sphere,
+            Self::Sphere(sphere) => sphere.disabled,
+            Self::Csg(csg) => csg.disabled,
+            Self::Transform(transform) => transform.disabled,
+            Self::Instance(instance) => instance.disabled,
+        }
+    }
+
+    pub fn disabled_mut(&mut self) -> &mut bool {
+        match self {
+            Self::Sphere(sphere) => &mut sphere.disabled,
+            Self::Csg(csg) => &mut csg.disabled,
+            Self::Transform(transform) => &mut transform.disabled,
+            Self::Instance(instance) => &mut instance.disabled,
+        }
+    }
+
+    pub fn collapsed_mut(&mut self) -> &mut bool {
+        match self {
+            Self::Sphere(sphere) => &mut sphere.collapsed,
+            Self::Csg(csg) => &mut csg.collapsed,
+            Self::Transform(transform) => &mut transform.collapsed,
+            Self::Instance(instance) => &mut instance.collapsed,
+        }
+    }
+
+    pub fn sphere_ref(&self) -> Option<&SphereNode> {
+        match self {
+            Self::Sphere(sphere) => Some(sphere),
+            _ => None,
         }
     }
 
+    pub fn as_sphere_ref(&self) -> &SphereNode {
+        self.sphere_ref()
+            .unwrap_or_else(|| panic!("Primitive is not a `{}`", SphereNode::NAME))
+    }
+
     pub fn as_sphere_mut(&mut self) -> &mut SphereNode {
         match self {
             Self::Sphere(sphere) => sphere,
+            _ => panic!("Primitive is not a `{}`", SphereNode::NAME),
+        }
+    }
+
+    pub fn as_csg_mut(&mut self) -> &mut CsgNode {
+        match self {
+            Self::Csg(csg) => csg,
+            _ => panic!("Primitive is not a `{}`", CsgNode::NAME),
+        }
+    }
+
+    pub fn as_transform_mut(&mut self) -> &mut TransformNode {
+        match self {
+            Self::Transform(transform) => transform,
+            _ => panic!("Primitive is not a `{}`", TransformNode::NAME),
+        }
+    }
+
+    pub fn as_instance_mut(&mut self) -> &mut InstanceNode {
+        match self {
+            Self::Instance(instance) => instance,
+            _ => panic!("Primitive is not a `{}`", InstanceNode::NAME),
         }
     }
 }
@@ -61,6 +132,31 @@ pub struct SphereNode {
     pub center: NodePin<Vector3>,
     pub radius: NodePin<f64>,
     pub material: NodePin<InputMaterial>,
+    /// Lights outside of group 0 only reach the spheres of their own group.
+    #[serde(default)]
+    pub light_group: NodePin<f64>,
+    /// Only used by spheres flagged with `is_light`.
+    #[serde(default = "SphereNode::default_light_color")]
+    pub light_color: NodePin<Color>,
+    #[serde(default = "SphereNode::default_light_intensity")]
+    pub light_intensity: NodePin<f64>,
+    /// The part of the sphere in front of the plane with this normal is cut away, zero keeps the whole sphere.
+    #[serde(default)]
+    pub clip_normal: NodePin<Vector3>,
+    /// Distance of the clip plane from the center along `clip_normal`.
+    #[serde(default)]
+    pub clip_offset: NodePin<f64>,
+    /// Leaves the clipped side open, like a bowl, instead of closing it with a flat cap.
+    #[serde(default)]
+    pub clip_open: bool,
+
+    /// Makes the sphere glow with `light_color` scaled by `light_intensity` in place of its material.
+    #[serde(default)]
+    pub is_light: bool,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -72,6 +168,15 @@ impl Default for SphereNode {
             center: Default::default(),
             radius: NodePin::new(1.0),
             material: Default::default(),
+            light_group: NodePin::default(),
+            light_color: Self::default_light_color(),
+            light_intensity: Self::default_light_intensity(),
+            clip_normal: NodePin::default(),
+            clip_offset: NodePin::default(),
+            clip_open: false,
+            is_light: false,
+            disabled: false,
+            collapsed: false,
             subscription: Subscription::default(),
         }
     }
@@ -79,13 +184,31 @@ impl Default for SphereNode {
 
 impl SphereNode {
     pub const NAME: &str = "Sphere Primitive";
-    pub const INPUTS: [u64; 3] = [
+    pub const INPUTS: [u64; 8] = [
         NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::MATERIALS.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::PRIMITIVE_SPHERE.bits()];
 
+    fn default_light_color() -> NodePin<Color> {
+        NodePin::new(Color::WHITE)
+    }
+
+    fn default_light_intensity() -> NodePin<f64> {
+        NodePin::new(4.0)
+    }
+
+    /// Emitted radiance of a sphere flagged as a light.
+    pub fn light_emit(&self) -> Vector3 {
+        self.light_intensity.get().max(0.0) * linear_rgb(self.light_color.get()).cast::<f64>()
+    }
+
     pub fn inputs(&self) -> &[u64] {
         &Self::INPUTS
     }
@@ -146,6 +269,93 @@ impl MessageHandling for SphereNode {
                 }
                 info
             },
+            3 => {
+                const LABEL: &str = "Light Group";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_primitive_node_mut().as_sphere_mut();
+
+                let old_value = node.light_group.get();
+                let info = number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.light_group,
+                    remote_value,
+                    GpuLight::LIGHT_GROUP_RANGE,
+                );
+
+                if old_value != node.light_group.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            4 => {
+                const LABEL: &str = "Light Color";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_primitive_node_mut().as_sphere_mut();
+
+                let old_value = node.light_color.get();
+                let info = color_input_view(ui, LABEL, &mut node.light_color, remote_value);
+
+                if old_value != node.light_color.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            5 => {
+                const LABEL: &str = "Light Intensity";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_primitive_node_mut().as_sphere_mut();
+
+                let old_value = node.light_intensity.get();
+                let info =
+                    number_input_view_in_range(ui, LABEL, &mut node.light_intensity, remote_value, 0.0..=f64::MAX);
+
+                if old_value != node.light_intensity.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            6 => {
+                const LABEL: &str = "Clip Normal";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_primitive_node_mut().as_sphere_mut();
+
+                let old_value = node.clip_normal.get();
+                let info = vector_input_view(ui, LABEL, &mut node.clip_normal, remote_value);
+
+                if old_value != node.clip_normal.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            7 => {
+                const LABEL: &str = "Clip Offset";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_primitive_node_mut().as_sphere_mut();
+
+                let old_value = node.clip_offset.get();
+                let info = number_input_view(ui, LABEL, &mut node.clip_offset, remote_value);
+
+                if old_value != node.clip_offset.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
             _ => unreachable!(),
         })
     }
@@ -163,6 +373,11 @@ impl MessageHandling for SphereNode {
             0 => node.center.reset(),
             1 => node.radius.reset(),
             2 => node.material.reset(),
+            3 => node.light_group.reset(),
+            4 => node.light_color.reset(),
+            5 => node.light_intensity.reset(),
+            6 => node.clip_normal.reset(),
+            7 => node.clip_offset.reset(),
             _ => unreachable!(),
         }
 
@@ -187,3 +402,468 @@ impl MessageHandling for SphereNode {
         node.subscription.handle_event(event_msg)
     }
 }
+
+/// Boolean combination of two spheres. The spheres are only drawn as part of it, each with its own material.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct CsgNode {
+    pub a: NodePin<Option<NodeId>>,
+    pub b: NodePin<Option<NodeId>>,
+    pub operation: CsgOperation,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl CsgNode {
+    pub const NAME: &str = "CSG Primitive";
+    pub const INPUTS: [u64; 2] = [NodeFlags::PRIMITIVE_SPHERE.bits(), NodeFlags::PRIMITIVE_SPHERE.bits()];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::PRIMITIVE_CSG.bits()];
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    /// Forgets the spheres but keeps the operation, for copies that are not connected to anything.
+    pub fn detach(&mut self) {
+        self.a.reset();
+        self.b.reset();
+    }
+
+    pub fn show_output(mut self_node: SelfNodeMut, _pin: &OutPin, ui: &mut Ui) -> PinInfo {
+        let node_id = self_node.id;
+        let node = self_node.as_primitive_node_mut().as_csg_mut();
+        let old_value = node.operation;
+        egui::ComboBox::from_id_salt(("csg_operation", node_id))
+            .selected_text(node.operation.name())
+            .show_ui(ui, |ui| {
+                for operation in CsgOperation::ALL {
+                    ui.selectable_value(&mut node.operation, operation, operation.name());
+                }
+            });
+
+        if old_value != node.operation {
+            if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                caller(self_node);
+            }
+        }
+
+        PinInfo::circle().with_fill(UNTYPED_COLOR)
+    }
+}
+
+impl MessageHandling for CsgNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let label = match pin.id.input {
+            0 => "Sphere A",
+            1 => "Sphere B",
+            _ => unreachable!(),
+        };
+
+        let remote_value = match &*pin.remotes {
+            [] => None,
+            [remote] => Some(match &self_node.snarl[remote.node] {
+                Node::Primitive(PrimitiveNode::Sphere(_)) => remote.node,
+                node => {
+                    invalid_connection(label, node);
+                    return Some(empty_input_view(ui, label));
+                },
+            }),
+            _ => None,
+        };
+
+        if let Some(value) = remote_value {
+            let node = self_node.as_primitive_node_mut().as_csg_mut();
+            let pin = if pin.id.input == 0 { &mut node.a } else { &mut node.b };
+            pin.set(Some(value));
+        }
+
+        Some(empty_input_view(ui, label))
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, from: &OutPin, to: &InPin) {
+        let node = self_node.as_primitive_node_mut().as_csg_mut();
+        match to.id.input {
+            0 => node.a.set(Some(from.id.node)),
+            1 => node.b.set(Some(from.id.node)),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_primitive_node_mut().as_csg_mut();
+        match to.id.input {
+            0 => node.a.reset(),
+            1 => node.b.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_collect_ids(
+        mut self_node: SelfNodeMut,
+        predicate: &dyn Fn(&Node) -> bool,
+        destination: &mut eframe::wgpu::naga::FastIndexSet<NodeId>,
+    ) {
+        let node = self_node.as_primitive_node_mut().as_csg_mut();
+        let (a, b) = (node.a.get(), node.b.get());
+        collect_for_node(a, predicate, destination, self_node.snarl);
+        collect_for_node(b, predicate, destination, self_node.snarl);
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        let node = self_node.as_primitive_node_mut().as_csg_mut();
+        node.subscription.handle_event(event_msg)
+    }
+}
+
+/// Translates, rotates and scales the spheres of a primitive or a collection. The spheres keep their own
+/// center and radius, rays are moved into their space instead.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TransformNode {
+    pub input: NodePin<Option<NodeId>>,
+    pub translate: NodePin<Vector3>,
+    /// Euler angles in degrees, applied about x, then y, then z.
+    pub rotate: NodePin<Vector3>,
+    pub scale: NodePin<Vector3>,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for TransformNode {
+    fn default() -> Self {
+        Self {
+            input: NodePin::default(),
+            translate: NodePin::default(),
+            rotate: NodePin::default(),
+            scale: NodePin::new(Vector3::repeat(1.0)),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl TransformNode {
+    pub const NAME: &str = "Transform";
+    pub const INPUTS: [u64; 4] = [
+        NodeFlags::PRIMITIVES.bits() | NodeFlags::COLLECTION.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::PRIMITIVE_TRANSFORM.bits()];
+
+    pub fn matrix(&self) -> Matrix4 {
+        trs_matrix(self.translate.get(), self.rotate.get(), self.scale.get())
+    }
+
+    /// Forgets the input but keeps the transform, for copies that are not connected to anything.
+    pub fn detach(&mut self) {
+        self.input.reset();
+    }
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+}
+
+impl MessageHandling for TransformNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let label = match pin.id.input {
+            0 => "Primitive",
+            1 => "Translate",
+            2 => "Rotate",
+            3 => "Scale",
+            _ => unreachable!(),
+        };
+
+        if pin.id.input == 0 {
+            let remote_value = match &*pin.remotes {
+                [] => None,
+                [remote] => Some(match &self_node.snarl[remote.node] {
+                    Node::Primitive(_) | Node::Collection(_) => remote.node,
+                    node => {
+                        invalid_connection(label, node);
+                        return Some(empty_input_view(ui, label));
+                    },
+                }),
+                _ => None,
+            };
+
+            if let Some(value) = remote_value {
+                let node = self_node.as_primitive_node_mut().as_transform_mut();
+                node.input.set(Some(value));
+            }
+            return Some(empty_input_view(ui, label));
+        }
+
+        let remote_value = vector_input_remote_value(pin, self_node.snarl, label);
+        let node = self_node.as_primitive_node_mut().as_transform_mut();
+        let value = match pin.id.input {
+            1 => &mut node.translate,
+            2 => &mut node.rotate,
+            _ => &mut node.scale,
+        };
+
+        let old_value = value.get();
+        let info = vector_input_view(ui, label, value, remote_value);
+
+        if old_value != value.get() {
+            if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                caller(self_node)
+            }
+        }
+        Some(info)
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, from: &OutPin, to: &InPin) {
+        let node = self_node.as_primitive_node_mut().as_transform_mut();
+        if to.id.input == 0 {
+            node.input.set(Some(from.id.node));
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_primitive_node_mut().as_transform_mut();
+        match to.id.input {
+            0 => node.input.reset(),
+            1 => node.translate.reset(),
+            2 => node.rotate.reset(),
+            3 => node.scale.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_collect_ids(
+        mut self_node: SelfNodeMut,
+        predicate: &dyn Fn(&Node) -> bool,
+        destination: &mut eframe::wgpu::naga::FastIndexSet<NodeId>,
+    ) {
+        let input = self_node.as_primitive_node_mut().as_transform_mut().input.get();
+        collect_for_node(input, predicate, destination, self_node.snarl);
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        let node = self_node.as_primitive_node_mut().as_transform_mut();
+        node.subscription.handle_event(event_msg)
+    }
+}
+
+/// Scale, then rotation by Euler angles in degrees about x, then y, then z, then translation.
+fn trs_matrix(translate: Vector3, rotate: Vector3, scale: Vector3) -> Matrix4 {
+    let rotate = rotate.map(f64::to_radians);
+    let rotation = nalgebra::Rotation3::from_euler_angles(rotate.x, rotate.y, rotate.z);
+    Matrix4::new_translation(&translate) * rotation.to_homogeneous() * Matrix4::new_nonuniform_scaling(&scale)
+}
+
+/// Repeats the spheres of a primitive or a collection, every copy is moved by one more step than the one before.
+/// The copies are only made when the scene is rebuilt, not every frame.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InstanceNode {
+    pub input: NodePin<Option<NodeId>>,
+    pub count: NodePin<u32>,
+    pub translate_step: NodePin<Vector3>,
+    /// Euler angles in degrees, like [`TransformNode::rotate`].
+    pub rotate_step: NodePin<Vector3>,
+    pub scale_step: NodePin<Vector3>,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for InstanceNode {
+    fn default() -> Self {
+        Self {
+            input: NodePin::default(),
+            count: NodePin::new(3),
+            translate_step: NodePin::new(Vector3::new(2.0, 0.0, 0.0)),
+            rotate_step: NodePin::default(),
+            scale_step: NodePin::new(Vector3::repeat(1.0)),
+            disabled: false,
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl InstanceNode {
+    pub const NAME: &str = "Instance";
+    pub const INPUTS: [u64; 5] = [
+        NodeFlags::PRIMITIVES.bits() | NodeFlags::COLLECTION.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::PRIMITIVE_INSTANCE.bits()];
+    pub const COUNT_RANGE: RangeInclusive<u32> = 1..=1024;
+
+    /// Transform of every copy, the first one stays in place.
+    pub fn copies(&self) -> Vec<Matrix4> {
+        let step = trs_matrix(self.translate_step.get(), self.rotate_step.get(), self.scale_step.get());
+        let (min_count, max_count) = Self::COUNT_RANGE.into_inner();
+        let count = self.count.get().clamp(min_count, max_count);
+        std::iter::successors(Some(Matrix4::identity()), |copy| Some(step * copy))
+            .take(count as usize)
+            .collect()
+    }
+
+    /// Forgets the input but keeps the steps, for copies that are not connected to anything.
+    pub fn detach(&mut self) {
+        self.input.reset();
+    }
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+}
+
+impl MessageHandling for InstanceNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let label = match pin.id.input {
+            0 => "Primitive",
+            1 => "Count",
+            2 => "Translate Step",
+            3 => "Rotate Step",
+            4 => "Scale Step",
+            _ => unreachable!(),
+        };
+
+        match pin.id.input {
+            0 => {
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Primitive(_) | Node::Collection(_) => remote.node,
+                        node => {
+                            invalid_connection(label, node);
+                            return Some(empty_input_view(ui, label));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_primitive_node_mut().as_instance_mut();
+                    node.input.set(Some(value));
+                }
+                Some(empty_input_view(ui, label))
+            },
+            1 => {
+                let remote_value =
+                    number_input_remote_value(pin, self_node.snarl, label).map(|(name, value)| (name, value as u32));
+                let node = self_node.as_primitive_node_mut().as_instance_mut();
+
+                let old_value = node.count.get();
+                let info = number_input_view_in_range(ui, label, &mut node.count, remote_value, Self::COUNT_RANGE);
+
+                if old_value != node.count.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                Some(info)
+            },
+            _ => {
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, label);
+                let node = self_node.as_primitive_node_mut().as_instance_mut();
+                let value = match pin.id.input {
+                    2 => &mut node.translate_step,
+                    3 => &mut node.rotate_step,
+                    _ => &mut node.scale_step,
+                };
+
+                let old_value = value.get();
+                let info = vector_input_view(ui, label, value, remote_value);
+
+                if old_value != value.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                Some(info)
+            },
+        }
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, from: &OutPin, to: &InPin) {
+        let node = self_node.as_primitive_node_mut().as_instance_mut();
+        if to.id.input == 0 {
+            node.input.set(Some(from.id.node));
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_primitive_node_mut().as_instance_mut();
+        match to.id.input {
+            0 => node.input.reset(),
+            1 => node.count.reset(),
+            2 => node.translate_step.reset(),
+            3 => node.rotate_step.reset(),
+            4 => node.scale_step.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_collect_ids(
+        mut self_node: SelfNodeMut,
+        predicate: &dyn Fn(&Node) -> bool,
+        destination: &mut eframe::wgpu::naga::FastIndexSet<NodeId>,
+    ) {
+        let input = self_node.as_primitive_node_mut().as_instance_mut().input.get();
+        collect_for_node(input, predicate, destination, self_node.snarl);
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        let node = self_node.as_primitive_node_mut().as_instance_mut();
+        node.subscription.handle_event(event_msg)
+    }
+}
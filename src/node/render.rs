@@ -1,4 +1,7 @@
-use eframe::egui_wgpu::RenderState;
+use std::collections::HashMap;
+
+use eframe::egui_wgpu::{CallbackResources, RenderState};
+use egui_snarl::NodeId;
 use serde::{Deserialize, Serialize};
 
 use self::raytracer::RaytracerRenderNode;
@@ -66,17 +69,48 @@ impl RenderNode {
         }
     }
 
-    pub fn register(&self, render_state: &RenderState) {
+    pub fn register(&mut self, node_id: NodeId, render_state: &RenderState) {
         match self {
-            Self::Triangle(render) => render.register(render_state),
-            Self::Raytracer(render) => render.register(render_state),
+            Self::Triangle(render) => render.register(node_id, render_state),
+            Self::Raytracer(render) => render.register(node_id, render_state),
         }
     }
 
-    pub fn unregister(&self, render_state: &RenderState) {
+    pub fn unregister(&self, node_id: NodeId, render_state: &RenderState) {
         match self {
-            Self::Triangle(render) => render.unregister(render_state),
-            Self::Raytracer(render) => render.unregister(render_state),
+            Self::Triangle(render) => render.unregister(node_id, render_state),
+            Self::Raytracer(render) => render.unregister(node_id, render_state),
+        }
+    }
+}
+
+/// GPU resources of every registered render node of one kind. `callback_resources` holds
+/// a single value per type, so the resources are kept by the id of their node.
+pub struct RenderResources<T>(HashMap<NodeId, T>);
+
+impl<T: Send + Sync + 'static> RenderResources<T> {
+    pub fn insert(render_state: &RenderState, node_id: NodeId, resources: T) {
+        render_state
+            .renderer
+            .write()
+            .callback_resources
+            .entry::<Self>()
+            .or_insert_with(|| Self(HashMap::new()))
+            .0
+            .insert(node_id, resources);
+    }
+
+    pub fn remove(render_state: &RenderState, node_id: NodeId) {
+        if let Some(resources) = render_state.renderer.write().callback_resources.get_mut::<Self>() {
+            resources.0.remove(&node_id);
         }
     }
+
+    pub fn get(callback_resources: &CallbackResources, node_id: NodeId) -> Option<&T> {
+        callback_resources.get::<Self>()?.0.get(&node_id)
+    }
+
+    pub fn get_mut(callback_resources: &mut CallbackResources, node_id: NodeId) -> Option<&mut T> {
+        callback_resources.get_mut::<Self>()?.0.get_mut(&node_id)
+    }
 }
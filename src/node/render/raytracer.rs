@@ -1,18 +1,29 @@
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
 use eframe::egui_wgpu::{Callback, CallbackResources, CallbackTrait, RenderState, ScreenDescriptor};
 use eframe::wgpu;
-use egui::{PaintCallbackInfo, Ui};
+use egui::{Align2, Color32, FontId, PaintCallbackInfo, Stroke, StrokeKind, Ui};
 use egui_snarl::ui::PinInfo;
 use egui_snarl::{InPin, NodeId, OutPin, Snarl};
 use serde::{Deserialize, Serialize};
 
+use super::RenderResources;
+use crate::node::background::background_node_by_id;
 use crate::node::camera::{CameraNode, camera_node_by_id};
 use crate::node::message::{MessageHandling, SelfNodeMut};
-use crate::node::scene::{SceneNode, SceneNodeResponse};
-use crate::node::viewer::{empty_input_view, number_input_remote_value, number_input_view};
+use crate::node::scene::SceneNode;
+use crate::node::viewer::{
+    angle_input_view, empty_input_view, invalid_connection, number_input_remote_value, number_input_view,
+    number_input_view_in_range,
+};
 use crate::node::{Node, NodeFlags, collect_for_node};
 use crate::raytracer::scene::Scene;
-use crate::raytracer::{Camera, Raytracer, RenderParams, SamplingParams};
-use crate::types::NodePin;
+use crate::raytracer::{
+    AaPattern, Background, BloomParams, Camera, CropRect, DebugParams, ExposureParams, FrameTimings, OutputChannel,
+    Raytracer, RenderParams, RenderParamsValidationError, RenderStats, SamplingParams, SkyParams,
+};
+use crate::types::{Angle, NodePin, UpAxis};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct RaytracerRenderNode {
@@ -21,10 +32,86 @@ pub struct RaytracerRenderNode {
     num_bounces: NodePin<u32>,
     camera: NodePin<Option<NodeId>>,
     scene: Option<NodeId>,
+    #[serde(default)]
+    time_limit_secs: NodePin<f64>,
+    #[serde(default)]
+    seed: NodePin<u32>,
+    /// Normalized crop rectangle, only the pixels inside of it receive samples.
+    #[serde(default)]
+    crop_left: NodePin<f64>,
+    #[serde(default)]
+    crop_top: NodePin<f64>,
+    #[serde(default = "crop_end")]
+    crop_right: NodePin<f64>,
+    #[serde(default = "crop_end")]
+    crop_bottom: NodePin<f64>,
+    /// Background node replacing the sky behind the scene, the sky stays visible while it is not connected.
+    #[serde(default)]
+    background: NodePin<Option<NodeId>>,
+    /// Hits farther away than this count as misses, zero means no limit.
+    #[serde(default)]
+    max_ray_distance: NodePin<f64>,
+    #[serde(default = "transmission_bounces")]
+    num_transmission_bounces: NodePin<u32>,
+    /// Turns the sky around the vertical axis, e.g. to move its reflections on metal.
+    #[serde(default)]
+    env_rotation: NodePin<Angle>,
+    #[serde(default)]
+    pub highlight_backfaces: bool,
+    /// Older scenes keep the nearest texel lookup they were made with.
+    #[serde(default)]
+    pub bilinear_filtering: bool,
+    #[serde(default)]
+    pub exact_fresnel: bool,
+    #[serde(default)]
+    pub aa_pattern: AaPattern,
+    #[serde(default)]
+    pub exposure: ExposureParams,
+    #[serde(default)]
+    pub denoise: bool,
+    #[serde(default)]
+    pub output_channel: OutputChannel,
+    /// Only applies while bloom is enabled in the app settings.
+    #[serde(default)]
+    pub bloom: BloomParams,
+    #[serde(default)]
+    pub locked_resolution: LockedResolution,
+    #[serde(default = "SamplingParams::default_resolution_scale")]
+    pub resolution_scale: f32,
+    /// Render at full resolution once the camera stops, `resolution_scale` only applies while it moves.
+    #[serde(default)]
+    pub scale_while_moving: bool,
+    #[serde(default)]
+    pub adaptive: bool,
+    #[serde(default = "SamplingParams::default_variance_threshold")]
+    pub variance_threshold: f32,
+    #[serde(default)]
+    pub sun_enabled: bool,
+    #[serde(default = "SkyParams::default_sun_angular_radius")]
+    pub sun_angular_radius: Angle,
+    #[serde(default)]
+    pub collapsed: bool,
+    /// Stops adding samples without discarding them, unlike convergence this is up to the user.
+    #[serde(skip)]
+    pub paused: bool,
+    /// Set by the user to start over, taken by the next draw.
+    #[serde(skip)]
+    pub restart: bool,
 
     max_viewport_resolution: u32,
     #[serde(skip)]
     disconnect_scene: bool,
+    /// Scene node and its revision last sent to the renderer. Several renders can share a scene,
+    /// so each of them has to notice the recalculation on its own.
+    #[serde(skip)]
+    scene_revision: Option<(NodeId, u64)>,
+    #[serde(skip)]
+    last_camera: Option<Camera>,
+    #[serde(skip)]
+    moved_at: Option<Instant>,
+    /// Follows the app settings like the up axis of cameras.
+    #[serde(skip)]
+    pub up_axis: UpAxis,
 }
 
 impl RaytracerRenderNode {
@@ -36,16 +123,67 @@ impl RaytracerRenderNode {
             num_bounces: NodePin::new(sampling.num_bounces),
             camera: Default::default(),
             scene: Default::default(),
+            time_limit_secs: NodePin::new(sampling.time_limit_secs),
+            seed: NodePin::new(sampling.seed),
+            crop_left: NodePin::new(0.0),
+            crop_top: NodePin::new(0.0),
+            crop_right: crop_end(),
+            crop_bottom: crop_end(),
+            background: Default::default(),
+            max_ray_distance: NodePin::new(0.0),
+            num_transmission_bounces: transmission_bounces(),
+            env_rotation: NodePin::default(),
+            highlight_backfaces: false,
+            bilinear_filtering: sampling.bilinear_filtering,
+            exact_fresnel: sampling.exact_fresnel,
+            aa_pattern: sampling.aa_pattern,
+            exposure: ExposureParams::default(),
+            denoise: false,
+            output_channel: OutputChannel::default(),
+            bloom: BloomParams::default(),
+            locked_resolution: LockedResolution::default(),
+            resolution_scale: sampling.resolution_scale,
+            scale_while_moving: false,
+            adaptive: sampling.adaptive,
+            variance_threshold: sampling.variance_threshold,
+            sun_enabled: false,
+            sun_angular_radius: SkyParams::default_sun_angular_radius(),
+            collapsed: false,
+            paused: false,
+            restart: false,
 
             max_viewport_resolution,
             disconnect_scene: false,
+            scene_revision: None,
+            last_camera: None,
+            moved_at: None,
+            up_axis: UpAxis::default(),
         }
     }
 
+    /// Forgets the camera and scene nodes, for copies that are not connected to anything.
+    pub fn detach(&mut self) {
+        self.camera.reset();
+        self.background.reset();
+        self.scene = None;
+        self.scene_revision = None;
+    }
+
     pub fn camera_id(&self) -> Option<NodeId> {
         self.camera.get()
     }
 
+    pub fn scene_id(&self) -> Option<NodeId> {
+        self.scene
+    }
+
+    /// Points the render at other camera and scene nodes, e.g. after the links of a loaded graph were checked.
+    pub fn relink(&mut self, camera: Option<NodeId>, scene: Option<NodeId>) {
+        self.camera.set(camera);
+        self.scene = scene;
+        self.scene_revision = None;
+    }
+
     pub fn camera_node<'a>(&self, snarl: &'a Snarl<Node>) -> Option<&'a CameraNode> {
         self.camera
             .get()
@@ -57,18 +195,124 @@ impl RaytracerRenderNode {
             max_samples_per_pixel: self.max_samples_per_pixel.get(),
             num_samples_per_pixel: self.num_samples_per_pixel.get(),
             num_bounces: self.num_bounces.get(),
+            num_transmission_bounces: self.num_transmission_bounces.get(),
+            time_limit_secs: self.time_limit_secs.get(),
+            seed: self.seed.get(),
+            bilinear_filtering: self.bilinear_filtering,
+            exact_fresnel: self.exact_fresnel,
+            aa_pattern: self.aa_pattern,
+            resolution_scale: self.resolution_scale,
+            adaptive: self.adaptive,
+            variance_threshold: self.variance_threshold,
+        }
+    }
+
+    fn sky_params(&self) -> SkyParams {
+        SkyParams {
+            sun_enabled: self.sun_enabled,
+            sun_angular_radius: self.sun_angular_radius,
+            env_rotation: self.env_rotation.get(),
+            up_axis: self.up_axis,
+            ..Default::default()
+        }
+    }
+
+    /// Remembers when `camera` last changed and returns the resolution scale to render with.
+    fn track_camera(&mut self, camera: Camera) -> f32 {
+        /// Camera drags deliver changes every few frames, a short pause must not restore the full resolution.
+        const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+        if self.last_camera.is_some_and(|last_camera| last_camera != camera) {
+            self.moved_at = Some(Instant::now());
+        }
+        self.last_camera = Some(camera);
+
+        let moving = self.moved_at.is_some_and(|moved_at| moved_at.elapsed() < SETTLE_TIME);
+        if !self.scale_while_moving || moving { self.resolution_scale } else { 1.0 }
+    }
+
+    fn render_params(
+        &self,
+        camera: Camera,
+        background: Background,
+        resolution_scale: f32,
+        bloom: bool,
+    ) -> RenderParams {
+        RenderParams {
+            camera,
+            sky: self.sky_params(),
+            sampling: SamplingParams {
+                resolution_scale,
+                ..self.sampling_params()
+            },
+            debug: self.debug_params(),
+            exposure: self.exposure,
+            denoise: self.denoise,
+            crop: self.crop(),
+            background,
+            output_channel: self.output_channel,
+            bloom: bloom.then_some(self.bloom),
+            max_ray_distance: self.max_ray_distance.get() as f32,
+        }
+    }
+
+    fn background(&self, snarl: &Snarl<Node>) -> Background {
+        self.background
+            .get()
+            .and_then(|background_id| background_node_by_id(background_id, snarl))
+            .map_or(Background::Sky, |background_node| background_node.background())
+    }
+
+    fn crop(&self) -> CropRect {
+        CropRect {
+            min: [self.crop_left.get() as f32, self.crop_top.get() as f32],
+            max: [self.crop_right.get() as f32, self.crop_bottom.get() as f32],
+        }
+    }
+
+    fn debug_params(&self) -> DebugParams {
+        DebugParams {
+            highlight_backfaces: self.highlight_backfaces,
         }
     }
+
+    /// Keeps the total sample count a multiple of the samples per frame. A total that was just edited
+    /// snaps in the direction it changed from `previous_max`, so single steps of the drag value stick.
+    fn fit_max_samples(&mut self, previous_max: u32) {
+        let max = self.max_samples_per_pixel.get();
+        let num = self.num_samples_per_pixel.get().max(1);
+        let fitted = match max.cmp(&previous_max) {
+            std::cmp::Ordering::Greater => max.div_ceil(num).saturating_mul(num),
+            std::cmp::Ordering::Less => (max / num).max(1) * num,
+            std::cmp::Ordering::Equal => SamplingParams::fit_max_samples(max, num),
+        };
+        self.max_samples_per_pixel.set(fitted);
+    }
+}
+
+/// Remote numbers are cut to `range` before they reach the pin.
+fn remote_count(remote_value: Option<(&'static str, f64)>, range: RangeInclusive<u32>) -> Option<(&'static str, u32)> {
+    remote_value.map(|(name, value)| (name, (value as u32).clamp(*range.start(), *range.end())))
 }
 
 impl RaytracerRenderNode {
     pub const NAME: &str = "Raytracer Render";
-    pub const INPUTS: [u64; 5] = [
+    pub const INPUTS: [u64; 15] = [
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
         NodeFlags::CAMERA.bits(),
         NodeFlags::SCENE.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::BACKGROUND.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
     ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::RENDER_RAYTRACER.bits()];
 
@@ -80,27 +324,119 @@ impl RaytracerRenderNode {
         &Self::OUTPUTS
     }
 
-    pub fn register(&self, render_state: &RenderState) {
-        RaytracerRenderResources::register(render_state, self, (0, 0));
+    /// Fresh resources start with an empty scene, so the scene is sent again on the next draw.
+    pub fn register(&mut self, node_id: NodeId, render_state: &RenderState) {
+        self.scene_revision = None;
+        RaytracerRenderResources::register(render_state, node_id, self, (0, 0));
     }
 
-    pub fn unregister(&self, render_state: &RenderState) {
-        RaytracerRenderResources::unregister(render_state);
+    pub fn unregister(&self, node_id: NodeId, render_state: &RenderState) {
+        RaytracerRenderResources::unregister(render_state, node_id);
     }
 
-    pub fn draw(self_node: SelfNodeMut, viewport: egui::Rect, painter: &egui::Painter) {
+    /// Parameters and scene for rendering an image outside of the viewport, like a frame of an animation, with
+    /// `samples_per_pixel` paths for every pixel. `None` while the render has no camera or scene.
+    pub fn still(self_node: SelfNodeMut, samples_per_pixel: u32, bloom: bool) -> Option<(RenderParams, Scene)> {
+        Self::auto_focus(SelfNodeMut::new(self_node.id, self_node.snarl));
         let node = self_node.as_render_node_ref().as_raytracer_render_ref();
-        let render_params = node.camera_node(self_node.snarl).map(|camera_node| RenderParams {
-            camera: Camera::from_node(camera_node),
-            sky: Default::default(),
-            sampling: node.sampling_params(),
+        let camera = node.camera_node(self_node.snarl).map(Camera::from_node)?;
+        let scene_node_id = node.scene?;
+        let mut render_params = node.render_params(camera, node.background(self_node.snarl), 1.0, bloom);
+        render_params.sampling.max_samples_per_pixel =
+            SamplingParams::fit_max_samples(samples_per_pixel, render_params.sampling.num_samples_per_pixel);
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene_node_id, self_node.snarl));
+        let scene = self_node.snarl[scene_node_id].as_scene_node_ref().as_scene().clone();
+        Some((render_params, scene))
+    }
+
+    /// Brings the scene up to date and tells whether it still has placeholders for textures that are loading.
+    /// A [`Self::still`] of it would show the placeholders.
+    pub fn is_loading_textures(self_node: SelfNodeMut) -> bool {
+        let Some(scene_node_id) = self_node.as_render_node_ref().as_raytracer_render_ref().scene else {
+            return false;
+        };
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene_node_id, self_node.snarl));
+        self_node.snarl[scene_node_id].as_scene_node_ref().is_loading_textures()
+    }
+
+    /// Focuses the camera on the first surface of the scene in the center of the view, if it has auto focus on.
+    /// The focus stays where it is while the center sees nothing.
+    fn auto_focus(self_node: SelfNodeMut) {
+        let node = self_node.as_render_node_ref().as_raytracer_render_ref();
+        let (Some(camera_id), Some(scene_node_id)) = (node.camera.get(), node.scene) else {
+            return;
+        };
+        if !camera_node_by_id(camera_id, self_node.snarl).is_some_and(|camera| camera.auto_focus) {
+            return;
+        }
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene_node_id, self_node.snarl));
+        let scene = self_node.snarl[scene_node_id].as_scene_node_ref().as_scene();
+        let distance =
+            camera_node_by_id(camera_id, self_node.snarl).and_then(|camera| camera.auto_focus_distance(scene));
+        if let Some(distance) = distance {
+            *self_node.snarl[camera_id].as_camera_node_mut().focus_distance.as_mut() = distance;
+        }
+    }
+
+    /// Keeps requesting repaints until the render converges, which then gets marked by a border.
+    /// A paused render needs no repaints and holds back the scene until it is resumed.
+    /// `output_resolution` of the connected output applies unless the render locks its own resolution.
+    /// Returns `true` once the render reached its samples per pixel and stopped taking samples.
+    pub fn draw(
+        self_node: SelfNodeMut,
+        viewport: egui::Rect,
+        painter: &egui::Painter,
+        render_state: &RenderState,
+        output_resolution: Option<LockedResolution>,
+        bloom: bool,
+    ) -> bool {
+        Self::auto_focus(SelfNodeMut::new(self_node.id, self_node.snarl));
+        let node = self_node.as_render_node_ref().as_raytracer_render_ref();
+        let camera = node.camera_node(self_node.snarl).map(Camera::from_node);
+        let background = node.background(self_node.snarl);
+        let render_params = camera.map(|camera| {
+            let node = self_node.snarl[self_node.id]
+                .as_render_node_mut()
+                .as_raytracer_render_mut();
+            let resolution_scale = node.track_camera(camera);
+            node.render_params(camera, background, resolution_scale, bloom)
         });
+        let restart = std::mem::take(
+            &mut self_node.snarl[self_node.id]
+                .as_render_node_mut()
+                .as_raytracer_render_mut()
+                .restart,
+        );
+        let node = self_node.as_render_node_ref().as_raytracer_render_ref();
+        let locked_resolution = match output_resolution {
+            Some(resolution) if !node.locked_resolution.enabled => resolution,
+            _ => node.locked_resolution,
+        };
+        let locked_size = locked_resolution.size(node.max_viewport_resolution);
+        let resolution_scale = node.resolution_scale;
+        let paused = node.paused;
 
-        let scene = if let Some(scene_node_id) = node.scene {
-            if let SceneNodeResponse::Recalculated =
-                SceneNode::handle_recalculate(SelfNodeMut::new(scene_node_id, self_node.snarl))
-            {
-                Some(self_node.snarl[scene_node_id].as_scene_node_ref().as_scene().clone())
+        let scene = if paused {
+            None
+        } else if let Some(scene_node_id) = node.scene {
+            let last_revision = node.scene_revision;
+            SceneNode::handle_recalculate(SelfNodeMut::new(scene_node_id, self_node.snarl));
+
+            let scene_node = self_node.snarl[scene_node_id].as_scene_node_ref();
+            if scene_node.is_loading_textures() {
+                // Nothing else repaints a converged render once the textures arrive.
+                painter.ctx().request_repaint_after(Duration::from_millis(100));
+            }
+            let revision = Some((scene_node_id, scene_node.revision()));
+            if revision != last_revision {
+                let scene = scene_node.as_scene().clone();
+                self_node.snarl[self_node.id]
+                    .as_render_node_mut()
+                    .as_raytracer_render_mut()
+                    .scene_revision = revision;
+                Some(scene)
             } else {
                 None
             }
@@ -114,39 +450,242 @@ impl RaytracerRenderNode {
             None
         };
 
-        if let Some(render_params) = render_params {
-            let callback = Callback::new_paint_callback(viewport, Drawer { render_params, scene });
-            painter.add(callback);
+        let Some(render_params) = render_params else {
+            return false;
+        };
+
+        let validated = render_params
+            .validate()
+            .map_err(|err| format!("invalid parameters: {err}"))
+            .and_then(|()| locked_size.map_err(|err| format!("invalid locked resolution: {err}")));
+
+        match validated {
+            Ok(locked_size) => {
+                let rect = match locked_size {
+                    Some(size) => {
+                        painter.rect_filled(viewport, 0.0, Color32::BLACK);
+                        letterbox(viewport, size)
+                    },
+                    None => viewport,
+                };
+                let (converged, sky_error, render_errors) = {
+                    let renderer = render_state.renderer.read();
+                    let resources =
+                        RenderResources::<RaytracerRenderResources>::get(&renderer.callback_resources, self_node.id);
+                    // A recalculated scene or a restart starts over, so the render can't be converged yet.
+                    let converged = scene.is_none()
+                        && !restart
+                        && resources.is_some_and(|resources| resources.is_converged(&render_params));
+                    (
+                        converged,
+                        resources.and_then(|resources| resources.sky_error()),
+                        resources.map(RaytracerRenderResources::errors).unwrap_or_default(),
+                    )
+                };
+
+                let pixels = viewport.size() * painter.ctx().pixels_per_point();
+                let drawer = Drawer {
+                    node_id: self_node.id,
+                    render_params,
+                    scene,
+                    viewport_size: locked_size.unwrap_or((pixels.x.round() as u32, pixels.y.round() as u32)),
+                    paused,
+                    restart,
+                };
+                painter.add(Callback::new_paint_callback(rect, drawer));
+
+                if paused {
+                    draw_paused_marker(rect, painter);
+                } else if converged {
+                    draw_converged_marker(rect, painter);
+                }
+                if let Some(err) = sky_error {
+                    draw_sky_warning(rect, painter, &err);
+                }
+                if !render_errors.is_empty() {
+                    draw_render_errors(rect, painter, &render_errors);
+                }
+                // Keeps the frames coming until a still camera restores the full resolution.
+                if !paused && (!converged || render_params.sampling.resolution_scale != resolution_scale) {
+                    painter.ctx().request_repaint();
+                }
+                converged
+            },
+            Err(err) => {
+                painter.text(
+                    viewport.center(),
+                    Align2::CENTER_CENTER,
+                    err,
+                    FontId::proportional(16.0),
+                    Color32::RED,
+                );
+                false
+            },
         }
     }
 }
 
+fn crop_end() -> NodePin<f64> {
+    NodePin::new(1.0)
+}
+
+fn transmission_bounces() -> NodePin<u32> {
+    NodePin::new(SamplingParams::default_num_transmission_bounces())
+}
+
+/// Fixed internal resolution of the render, independent of the window size.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LockedResolution {
+    pub enabled: bool,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for LockedResolution {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 1280,
+            height: 720,
+        }
+    }
+}
+
+impl LockedResolution {
+    /// Returns `None` when the render should follow the window size.
+    fn size(&self, max_viewport_resolution: u32) -> Result<Option<(u32, u32)>, String> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("{}x{} has a zero side", self.width, self.height));
+        }
+
+        if self.width as u64 * self.height as u64 > max_viewport_resolution as u64 {
+            return Err(format!(
+                "{}x{} exceeds the maximum of {max_viewport_resolution} pixels",
+                self.width, self.height
+            ));
+        }
+
+        Ok(Some((self.width, self.height)))
+    }
+}
+
+fn draw_converged_marker(rect: egui::Rect, painter: &egui::Painter) {
+    const COLOR: Color32 = Color32::from_rgb(0x40, 0xb0, 0x40);
+
+    painter.rect_stroke(rect, 0.0, Stroke::new(2.0, COLOR), StrokeKind::Inside);
+    painter.text(
+        rect.right_top() + egui::vec2(-8.0, 8.0),
+        Align2::RIGHT_TOP,
+        "✔",
+        FontId::proportional(16.0),
+        COLOR,
+    );
+}
+
+fn draw_paused_marker(rect: egui::Rect, painter: &egui::Painter) {
+    const COLOR: Color32 = Color32::from_rgb(0xd0, 0xa0, 0x30);
+
+    painter.rect_stroke(rect, 0.0, Stroke::new(2.0, COLOR), StrokeKind::Inside);
+    painter.text(
+        rect.right_top() + egui::vec2(-8.0, 8.0),
+        Align2::RIGHT_TOP,
+        "⏸",
+        FontId::proportional(16.0),
+        COLOR,
+    );
+}
+
+fn draw_sky_warning(rect: egui::Rect, painter: &egui::Painter, err: &str) {
+    painter.text(
+        rect.left_bottom() + egui::vec2(8.0, -8.0),
+        Align2::LEFT_BOTTOM,
+        format!("⚠ invalid sky, showing a gradient: {err}"),
+        FontId::proportional(14.0),
+        Color32::from_rgb(0xd0, 0xa0, 0x30),
+    );
+}
+
+fn draw_render_errors(rect: egui::Rect, painter: &egui::Painter, errors: &[String]) {
+    painter.text(
+        rect.center_top() + egui::vec2(0.0, 8.0),
+        Align2::CENTER_TOP,
+        errors.join("\n"),
+        FontId::proportional(14.0),
+        Color32::RED,
+    );
+}
+
+/// Largest rect with the aspect ratio of `size` centered in `viewport`.
+fn letterbox(viewport: egui::Rect, size: (u32, u32)) -> egui::Rect {
+    let aspect = size.0 as f32 / size.1 as f32;
+    let fitted = if viewport.aspect_ratio() > aspect {
+        egui::vec2(viewport.height() * aspect, viewport.height())
+    } else {
+        egui::vec2(viewport.width(), viewport.width() / aspect)
+    };
+    egui::Rect::from_center_size(viewport.center(), fitted)
+}
+
 impl MessageHandling for RaytracerRenderNode {
     fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
         Some(match pin.id.input {
             0 => {
                 const LABEL: &str = "Total samples per pixel";
 
-                let remote_value =
-                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let remote_value = remote_count(
+                    number_input_remote_value(pin, self_node.snarl, LABEL),
+                    SamplingParams::MAX_SAMPLES_RANGE,
+                );
                 let node = self_node.as_render_node_mut().as_raytracer_render_mut();
-                number_input_view(ui, LABEL, &mut node.max_samples_per_pixel, remote_value)
+                let previous_max = node.max_samples_per_pixel.get();
+                let pin_info = number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.max_samples_per_pixel,
+                    remote_value,
+                    SamplingParams::MAX_SAMPLES_RANGE,
+                );
+                node.fit_max_samples(previous_max);
+                pin_info
             },
             1 => {
                 const LABEL: &str = "Samples per pixel per frame";
 
-                let remote_value =
-                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let remote_value = remote_count(
+                    number_input_remote_value(pin, self_node.snarl, LABEL),
+                    SamplingParams::NUM_SAMPLES_RANGE,
+                );
                 let node = self_node.as_render_node_mut().as_raytracer_render_mut();
-                number_input_view(ui, LABEL, &mut node.num_samples_per_pixel, remote_value)
+                let previous_max = node.max_samples_per_pixel.get();
+                let pin_info = number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.num_samples_per_pixel,
+                    remote_value,
+                    SamplingParams::NUM_SAMPLES_RANGE,
+                );
+                node.fit_max_samples(previous_max);
+                pin_info
             },
             2 => {
                 const LABEL: &str = "Bounces per ray";
 
-                let remote_value =
-                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let remote_value = remote_count(
+                    number_input_remote_value(pin, self_node.snarl, LABEL),
+                    SamplingParams::NUM_BOUNCES_RANGE,
+                );
                 let node = self_node.as_render_node_mut().as_raytracer_render_mut();
-                number_input_view(ui, LABEL, &mut node.num_bounces, remote_value)
+                number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.num_bounces,
+                    remote_value,
+                    SamplingParams::NUM_BOUNCES_RANGE,
+                )
             },
             3 => {
                 const LABEL: &str = "Camera";
@@ -155,7 +694,10 @@ impl MessageHandling for RaytracerRenderNode {
                     [] => None,
                     [remote] => Some(match &self_node.snarl[remote.node] {
                         Node::Camera(_) => Some(remote.node),
-                        node => unreachable!("{LABEL} input not suppor connection with `{}`", node.name()),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
                     }),
                     _ => None,
                 };
@@ -174,7 +716,10 @@ impl MessageHandling for RaytracerRenderNode {
                     [] => None,
                     [remote] => Some(match &mut self_node.snarl[remote.node] {
                         Node::Scene(_) => Some(remote.node),
-                        node => unreachable!("{LABEL} input not suppor connection with `{}`", node.name()),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
                     }),
                     _ => None,
                 };
@@ -192,6 +737,101 @@ impl MessageHandling for RaytracerRenderNode {
 
                 empty_input_view(ui, LABEL)
             },
+            5 => {
+                const LABEL: &str = "Time limit (s)";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view(ui, LABEL, &mut node.time_limit_secs, remote_value)
+            },
+            6 => {
+                const LABEL: &str = "Seed";
+
+                let remote_value =
+                    number_input_remote_value(pin, self_node.snarl, LABEL).map(|(name, value)| (name, value as u32));
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view(ui, LABEL, &mut node.seed, remote_value)
+            },
+            7 => {
+                const LABEL: &str = "Crop Left";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.crop_left, remote_value, 0.0..=1.0)
+            },
+            8 => {
+                const LABEL: &str = "Crop Top";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.crop_top, remote_value, 0.0..=1.0)
+            },
+            9 => {
+                const LABEL: &str = "Crop Right";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.crop_right, remote_value, 0.0..=1.0)
+            },
+            10 => {
+                const LABEL: &str = "Crop Bottom";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.crop_bottom, remote_value, 0.0..=1.0)
+            },
+            11 => {
+                const LABEL: &str = "Background";
+
+                let remote_value = match &*pin.remotes {
+                    [] => None,
+                    [remote] => Some(match &self_node.snarl[remote.node] {
+                        Node::Background(_) => Some(remote.node),
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
+                    }),
+                    _ => None,
+                };
+
+                if let Some(value) = remote_value {
+                    let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                    node.background.set(value);
+                }
+
+                empty_input_view(ui, LABEL)
+            },
+            12 => {
+                const LABEL: &str = "Max ray distance";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view_in_range(ui, LABEL, &mut node.max_ray_distance, remote_value, 0.0..=f64::MAX)
+            },
+            13 => {
+                const LABEL: &str = "Glass bounces";
+
+                let remote_value = remote_count(
+                    number_input_remote_value(pin, self_node.snarl, LABEL),
+                    SamplingParams::NUM_BOUNCES_RANGE,
+                );
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                number_input_view_in_range(
+                    ui,
+                    LABEL,
+                    &mut node.num_transmission_bounces,
+                    remote_value,
+                    SamplingParams::NUM_BOUNCES_RANGE,
+                )
+            },
+            14 => {
+                const LABEL: &str = "Sky rotation";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_raytracer_render_mut();
+                angle_input_view(ui, LABEL, &mut node.env_rotation, remote_value)
+            },
             _ => unreachable!(),
         })
     }
@@ -207,6 +847,16 @@ impl MessageHandling for RaytracerRenderNode {
                 node.scene = None;
                 node.disconnect_scene = true
             },
+            5 => node.time_limit_secs.reset(),
+            6 => node.seed.reset(),
+            7 => node.crop_left.reset(),
+            8 => node.crop_top.reset(),
+            9 => node.crop_right.reset(),
+            10 => node.crop_bottom.reset(),
+            11 => node.background.reset(),
+            12 => node.max_ray_distance.reset(),
+            13 => node.num_transmission_bounces.reset(),
+            14 => node.env_rotation.reset(),
             _ => unreachable!(),
         }
     }
@@ -216,17 +866,23 @@ impl MessageHandling for RaytracerRenderNode {
         predicate: &dyn Fn(&Node) -> bool,
         destination: &mut wgpu::naga::FastIndexSet<NodeId>,
     ) {
-        let camera_node_id = self_node.as_render_node_ref().as_raytracer_render_ref().camera.get();
-        let scene_node_id = self_node.as_render_node_ref().as_raytracer_render_ref().scene;
+        let node = self_node.as_render_node_ref().as_raytracer_render_ref();
+        let (camera_node_id, scene_node_id, background_node_id) =
+            (node.camera.get(), node.scene, node.background.get());
 
         collect_for_node(camera_node_id, predicate, destination, self_node.snarl);
         collect_for_node(scene_node_id, predicate, destination, self_node.snarl);
+        collect_for_node(background_node_id, predicate, destination, self_node.snarl);
     }
 }
 
 struct Drawer {
+    node_id: NodeId,
     render_params: RenderParams,
     scene: Option<Scene>,
+    viewport_size: (u32, u32),
+    paused: bool,
+    restart: bool,
 }
 
 impl CallbackTrait for Drawer {
@@ -234,13 +890,35 @@ impl CallbackTrait for Drawer {
         &self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        screen_descriptor: &ScreenDescriptor,
-        _egui_encoder: &mut wgpu::CommandEncoder,
+        _screen_descriptor: &ScreenDescriptor,
+        egui_encoder: &mut wgpu::CommandEncoder,
         callback_resources: &mut CallbackResources,
     ) -> Vec<wgpu::CommandBuffer> {
-        if let Some(resources) = callback_resources.get_mut::<RaytracerRenderResources>() {
-            let viewport_size = (screen_descriptor.size_in_pixels[0], screen_descriptor.size_in_pixels[1]);
-            resources.prepare(device, queue, &self.render_params, self.scene.as_ref(), viewport_size);
+        let resources = RenderResources::<RaytracerRenderResources>::get_mut(callback_resources, self.node_id);
+        if let Some(resources) = resources {
+            resources.renderer.set_paused(self.paused);
+            if self.restart {
+                resources.renderer.reset();
+            }
+            // Parameters are validated before the callback is added, so the only error left here
+            // is a zero-sized viewport (e.g. a minimized window). Skip the frame in that case.
+            match resources.prepare(
+                device,
+                queue,
+                egui_encoder,
+                &self.render_params,
+                self.scene.as_ref(),
+                self.viewport_size,
+            ) {
+                Ok(()) => resources.skip_reason = None,
+                Err(err) => {
+                    let reason = err.to_string();
+                    if resources.skip_reason.as_ref() != Some(&reason) {
+                        eprintln!("Skipping raytracer frames: {reason}");
+                        resources.skip_reason = Some(reason);
+                    }
+                },
+            }
         }
         Vec::new()
     }
@@ -251,7 +929,7 @@ impl CallbackTrait for Drawer {
         render_pass: &mut wgpu::RenderPass<'static>,
         callback_resources: &CallbackResources,
     ) {
-        if let Some(resources) = callback_resources.get::<RaytracerRenderResources>() {
+        if let Some(resources) = RenderResources::<RaytracerRenderResources>::get(callback_resources, self.node_id) {
             resources.paint(render_pass);
         }
     }
@@ -259,6 +937,8 @@ impl CallbackTrait for Drawer {
 
 pub struct RaytracerRenderResources {
     renderer: Raytracer,
+    /// Why frames are being skipped, logged once until a frame is prepared again.
+    skip_reason: Option<String>,
 }
 
 impl RaytracerRenderResources {
@@ -282,41 +962,96 @@ impl RaytracerRenderResources {
                 max_viewport_resolution,
             )
             .expect("Raytracer creation failed"),
+            skip_reason: None,
         }
     }
 
-    pub fn register(render_state: &RenderState, node: &RaytracerRenderNode, viewport_size: (u32, u32)) {
-        let render_params = RenderParams {
+    pub fn register(
+        render_state: &RenderState,
+        node_id: NodeId,
+        node: &RaytracerRenderNode,
+        viewport_size: (u32, u32),
+    ) {
+        let mut render_params = RenderParams {
             camera: Default::default(),
-            sky: Default::default(),
+            sky: node.sky_params(),
             sampling: node.sampling_params(),
+            debug: node.debug_params(),
+            exposure: node.exposure,
+            denoise: node.denoise,
+            crop: node.crop(),
+            background: Background::Sky,
+            output_channel: node.output_channel,
+            bloom: None,
+            max_ray_distance: node.max_ray_distance.get() as f32,
         };
+        // Invalid node parameters are reported in the viewport on draw,
+        // creating the resources must not fail because of them.
+        if render_params.validate().is_err() {
+            render_params.sampling = SamplingParams::default();
+            render_params.exposure = ExposureParams::default();
+            render_params.crop = CropRect::default();
+            render_params.sky = SkyParams::default();
+            render_params.max_ray_distance = 0.0;
+        }
 
-        render_state.renderer.write().callback_resources.insert(Self::new(
+        let resources = Self::new(
             render_state,
             &render_params,
             viewport_size,
             node.max_viewport_resolution,
-        ));
+        );
+        RenderResources::insert(render_state, node_id, resources);
     }
 
-    pub fn unregister(render_state: &RenderState) {
-        render_state.renderer.write().callback_resources.remove::<Self>();
+    pub fn unregister(render_state: &RenderState, node_id: NodeId) {
+        RenderResources::<Self>::remove(render_state, node_id);
     }
 
     pub fn prepare(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
         render_params: &RenderParams,
         scene: Option<&Scene>,
         viewport_size: (u32, u32),
-    ) {
+    ) -> Result<(), RenderParamsValidationError> {
         self.renderer
-            .prepare_frame(device, queue, render_params, scene, viewport_size);
+            .prepare_frame(device, queue, encoder, render_params, scene, viewport_size)
     }
 
     pub fn paint(&self, rpass: &mut wgpu::RenderPass<'static>) {
         self.renderer.render_frame(rpass);
     }
+
+    pub fn stats(&self) -> RenderStats {
+        self.renderer.stats()
+    }
+
+    pub fn frame_timings(&self) -> FrameTimings {
+        self.renderer.frame_timings()
+    }
+
+    pub fn sky_error(&self) -> Option<String> {
+        self.renderer.sky_error().map(str::to_owned)
+    }
+
+    /// Problems that keep the render on an outdated scene or shader.
+    pub fn errors(&self) -> Vec<String> {
+        let scene_error = self
+            .renderer
+            .scene_error()
+            .map(|err| format!("⚠ scene too large, showing the previous one: {err}"));
+        let shader_error = self
+            .renderer
+            .shader_error()
+            .map(|err| format!("⚠ shader failed to compile, showing the previous one:\n{err}"));
+        scene_error.into_iter().chain(shader_error).collect()
+    }
+
+    /// Returns `true` if the render with `render_params` is finished and needs no more frames.
+    pub fn is_converged(&self, render_params: &RenderParams) -> bool {
+        self.renderer.is_converged() && self.renderer.is_current(render_params)
+    }
 }
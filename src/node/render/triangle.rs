@@ -2,24 +2,54 @@ use eframe::egui_wgpu::{Callback, CallbackResources, CallbackTrait, RenderState,
 use eframe::wgpu::util::DeviceExt;
 use egui::{PaintCallbackInfo, Ui};
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, OutPin};
+use egui_snarl::{InPin, NodeId, OutPin};
 use serde::{Deserialize, Serialize};
 
+use super::RenderResources;
 use crate::node::NodeFlags;
 use crate::node::message::{MessageHandling, SelfNodeMut};
-use crate::node::viewer::{number_input_remote_value, number_input_view};
-use crate::types::NodePin;
+use crate::node::viewer::{color_input_remote_value, color_input_view, number_input_remote_value, number_input_view};
+use crate::types::{Color, NodePin};
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TriangleRenderNode {
-    pub angle: NodePin<f64>,
+    /// Rotation around the vertical axis.
+    #[serde(alias = "angle")]
+    pub yaw: NodePin<f64>,
+    /// Rotation around the horizontal axis.
+    #[serde(default)]
+    pub pitch: NodePin<f64>,
+    /// Multiplies the colors of the corners, white leaves them as they are.
+    #[serde(default = "TriangleRenderNode::default_tint")]
+    pub tint: NodePin<Color>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl Default for TriangleRenderNode {
+    fn default() -> Self {
+        Self {
+            yaw: NodePin::default(),
+            pitch: NodePin::default(),
+            tint: Self::default_tint(),
+            collapsed: false,
+        }
+    }
 }
 
 impl TriangleRenderNode {
     pub const NAME: &str = "Triangle Render";
-    pub const INPUTS: [u64; 1] = [NodeFlags::TYPICAL_NUMBER_INPUT.bits()];
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::RENDER_TRIANGLE.bits()];
 
+    fn default_tint() -> NodePin<Color> {
+        NodePin::new(Color::WHITE)
+    }
+
     pub fn inputs(&self) -> &[u64] {
         &Self::INPUTS
     }
@@ -28,23 +58,28 @@ impl TriangleRenderNode {
         &Self::OUTPUTS
     }
 
-    pub fn register(&self, render_state: &RenderState) {
-        TriangleRenderResources::register(render_state);
+    pub fn register(&self, node_id: NodeId, render_state: &RenderState) {
+        TriangleRenderResources::register(render_state, node_id);
     }
 
-    pub fn unregister(&self, render_state: &RenderState) {
-        TriangleRenderResources::unregister(render_state);
+    pub fn unregister(&self, node_id: NodeId, render_state: &RenderState) {
+        TriangleRenderResources::unregister(render_state, node_id);
     }
 }
 
 impl TriangleRenderNode {
-    pub fn recalc_angle(&mut self, drag: f64) {
-        self.angle.set(self.angle.get() + drag * 0.01);
+    /// Horizontal dragging changes the yaw, vertical dragging the pitch.
+    pub fn recalc_angle(&mut self, dx: f64, dy: f64) {
+        self.yaw.set(self.yaw.get() + dx * 0.01);
+        self.pitch.set(self.pitch.get() + dy * 0.01);
     }
 
-    pub fn draw(&self, viewport: egui::Rect, painter: &egui::Painter) {
+    pub fn draw(&self, node_id: NodeId, viewport: egui::Rect, painter: &egui::Painter) {
         let callback = Callback::new_paint_callback(viewport, Drawer {
-            angle: self.angle.get(),
+            node_id,
+            yaw: self.yaw.get(),
+            pitch: self.pitch.get(),
+            tint: self.tint.get().to_normalized_gamma_f32(),
         });
         painter.add(callback);
     }
@@ -54,11 +89,25 @@ impl MessageHandling for TriangleRenderNode {
     fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
         Some(match pin.id.input {
             0 => {
-                const LABEL: &str = "Angle";
+                const LABEL: &str = "Yaw";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_render_node_mut().as_triangle_render_mut();
-                number_input_view(ui, LABEL, &mut node.angle, remote_value)
+                number_input_view(ui, LABEL, &mut node.yaw, remote_value)
+            },
+            1 => {
+                const LABEL: &str = "Pitch";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_triangle_render_mut();
+                number_input_view(ui, LABEL, &mut node.pitch, remote_value)
+            },
+            2 => {
+                const LABEL: &str = "Tint";
+
+                let remote_value = color_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_render_node_mut().as_triangle_render_mut();
+                color_input_view(ui, LABEL, &mut node.tint, remote_value)
             },
             _ => unreachable!(),
         })
@@ -67,14 +116,19 @@ impl MessageHandling for TriangleRenderNode {
     fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
         let node = self_node.as_render_node_mut().as_triangle_render_mut();
         match to.id.input {
-            0 => node.angle.reset(),
+            0 => node.yaw.reset(),
+            1 => node.pitch.reset(),
+            2 => node.tint.reset(),
             _ => unreachable!(),
         }
     }
 }
 
 struct Drawer {
-    angle: f64,
+    node_id: NodeId,
+    yaw: f64,
+    pitch: f64,
+    tint: [f32; 4],
 }
 
 // The callback for WGPU is in two stages: prepare, and paint.
@@ -94,8 +148,8 @@ impl CallbackTrait for Drawer {
         _egui_encoder: &mut wgpu::CommandEncoder,
         callback_resources: &mut CallbackResources,
     ) -> Vec<wgpu::CommandBuffer> {
-        if let Some(resources) = callback_resources.get::<TriangleRenderResources>() {
-            resources.prepare(device, queue, self.angle as _);
+        if let Some(resources) = RenderResources::<TriangleRenderResources>::get(callback_resources, self.node_id) {
+            resources.prepare(device, queue, self.yaw as _, self.pitch as _, self.tint);
         }
         Vec::new()
     }
@@ -106,7 +160,7 @@ impl CallbackTrait for Drawer {
         render_pass: &mut wgpu::RenderPass<'static>,
         callback_resources: &CallbackResources,
     ) {
-        if let Some(resources) = callback_resources.get::<TriangleRenderResources>() {
+        if let Some(resources) = RenderResources::<TriangleRenderResources>::get(callback_resources, self.node_id) {
             resources.paint(render_pass);
         }
     }
@@ -171,7 +225,7 @@ impl TriangleRenderResources {
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&[0.0]),
+            contents: bytemuck::cast_slice(&[0.0f32; 8]),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
         });
 
@@ -191,21 +245,20 @@ impl TriangleRenderResources {
         }
     }
 
-    pub fn register(render_state: &RenderState) {
-        render_state
-            .renderer
-            .write()
-            .callback_resources
-            .insert(Self::new(render_state));
+    pub fn register(render_state: &RenderState, node_id: NodeId) {
+        RenderResources::insert(render_state, node_id, Self::new(render_state));
     }
 
-    pub fn unregister(render_state: &RenderState) {
-        render_state.renderer.write().callback_resources.remove::<Self>();
+    pub fn unregister(render_state: &RenderState, node_id: NodeId) {
+        RenderResources::<Self>::remove(render_state, node_id);
     }
 
-    pub fn prepare(&self, _device: &wgpu::Device, queue: &wgpu::Queue, angle: f32) {
-        // Update our uniform buffer with the angle from the UI
-        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[angle]));
+    pub fn prepare(&self, _device: &wgpu::Device, queue: &wgpu::Queue, yaw: f32, pitch: f32, tint: [f32; 4]) {
+        // Update our uniform buffer with the angles and the tint from the UI,
+        // the tint starts at the next 16 byte boundary like a `vec4` in the shader.
+        let [r, g, b, a] = tint;
+        let uniforms = [yaw, pitch, 0.0, 0.0, r, g, b, a];
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&uniforms));
     }
 
     pub fn paint(&self, rpass: &mut wgpu::RenderPass<'static>) {
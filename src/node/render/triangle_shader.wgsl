@@ -4,7 +4,9 @@ struct VertexOut {
 };
 
 struct Uniforms {
-    angle: f32,
+    yaw: f32,
+    pitch: f32,
+    tint: vec4<f32>,
 };
 
 @group(0) @binding(0)
@@ -26,9 +28,13 @@ var<private> v_colors: array<vec4<f32>, 3> = array<vec4<f32>, 3>(
 fn vs_main(@builtin(vertex_index) v_idx: u32) -> VertexOut {
     var out: VertexOut;
 
-    out.position = vec4<f32>(v_positions[v_idx], 0.0, 1.0);
-    out.position.x = out.position.x * cos(uniforms.angle);
-    out.color = v_colors[v_idx];
+    // Rotate around the vertical axis first, then around the horizontal one.
+    let p = v_positions[v_idx];
+    let yawed = vec3<f32>(p.x * cos(uniforms.yaw), p.y, -p.x * sin(uniforms.yaw));
+    let y = yawed.y * cos(uniforms.pitch) - yawed.z * sin(uniforms.pitch);
+
+    out.position = vec4<f32>(yawed.x, y, 0.0, 1.0);
+    out.color = v_colors[v_idx] * uniforms.tint;
 
     return out;
 }
@@ -5,19 +5,20 @@ use bitflags::bitflags;
 use eframe::wgpu::naga::FastIndexSet;
 use egui::Ui;
 use egui_snarl::ui::PinInfo;
-use egui_snarl::{InPin, NodeId, OutPin};
+use egui_snarl::{InPin, NodeId, OutPin, Snarl};
 use serde::{Deserialize, Serialize};
 
 use super::collect_for_node;
-use super::material::InputMaterial;
+use super::material::{InputMaterial, MaterialNode};
 use super::message::{CommonNodeMessage, InputMessage, MessageHandling, SelfNodeMut};
 use super::primitive::PrimitiveNode;
 use crate::node::message::{CommonNodeResponse, EventMessage, EventResponse};
 use crate::node::subscribtion::Event;
-use crate::node::viewer::empty_input_view;
+use crate::node::viewer::{empty_input_view, invalid_connection};
 use crate::node::{Node, NodeFlags};
-use crate::raytracer::scene::{Material, Scene, Sphere, TextureData};
-use crate::types::NodePin;
+use crate::raytracer::Texture;
+use crate::raytracer::scene::{CsgOp, GpuLight, Material, Scene, Sphere, TextureCache, TextureData, TextureLoad};
+use crate::types::{Matrix4f32, NodePin, Vector3f32};
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,Serialize, Deserialize)]
@@ -58,6 +59,8 @@ pub enum SceneNodeResponse {
 #[derive(Clone, Default, Serialize, Deserialize)]
 pub struct SceneNode {
     pub data: NodePin<Option<NodeId>>,
+    #[serde(default)]
+    pub collapsed: bool,
 
     inner_scene: Scene,
 
@@ -66,11 +69,18 @@ pub struct SceneNode {
 
     #[serde(skip)]
     dirty: SceneDirtyFlags,
+    /// Bumped on every recalculation, renders compare it to find out if they have the latest scene.
+    #[serde(skip)]
+    revision: u64,
+    /// Generation of the texture cache when the scene was built with placeholders for textures still loading.
+    #[serde(skip)]
+    pending_textures: Option<u64>,
 }
 
 impl SceneNode {
     pub const NAME: &str = "Scene";
-    pub const INPUTS: [u64; 1] = [NodeFlags::PRIMITIVES.bits() | NodeFlags::COLLECTION.bits()];
+    pub const INPUTS: [u64; 1] =
+        [NodeFlags::PRIMITIVES.bits() | NodeFlags::LIGHTS.bits() | NodeFlags::COLLECTION.bits()];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::SCENE.bits()];
 
     pub fn inputs(&self) -> &[u64] {
@@ -91,8 +101,11 @@ impl MessageHandling for SceneNode {
                 let remote_value = match &*pin.remotes {
                     [] => None,
                     [remote] => Some(match &self_node.snarl[remote.node] {
-                        Node::Primitive(_) | Node::Collection(_) => remote.node,
-                        node => unreachable!("{LABEL} input not suppor connection with `{}`", node.name()),
+                        Node::Primitive(_) | Node::Light(_) | Node::Collection(_) => remote.node,
+                        node => {
+                            invalid_connection(LABEL, node);
+                            return Some(empty_input_view(ui, LABEL));
+                        },
                     }),
                     _ => None,
                 };
@@ -140,10 +153,81 @@ impl SceneNode {
         &self.inner_scene
     }
 
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Some textures of the scene are placeholders until their files are loaded.
+    pub fn is_loading_textures(&self) -> bool {
+        self.pending_textures.is_some()
+    }
+
     pub fn register_render(&mut self) {
         self.dirty = SceneDirtyFlags::ALL;
     }
 
+    /// Toggling a node on or off doesn't change its inputs, so the scenes built from it
+    /// are marked dirty here instead of through the subscription.
+    pub fn invalidate_tracking(snarl: &mut Snarl<Node>, node_id: NodeId) {
+        for node in snarl.nodes_mut() {
+            if let Node::Scene(scene_node) = node {
+                if scene_node.tracked_nodes.contains(&node_id) {
+                    scene_node.dirty = SceneDirtyFlags::ALL;
+                }
+            }
+        }
+    }
+
+    /// Subscriptions are not saved, so after a graph is loaded every scene has to be
+    /// recalculated once to get notified about changes of its nodes again.
+    pub fn restore_subscriptions(snarl: &mut Snarl<Node>) {
+        let scene_ids = snarl
+            .node_ids()
+            .filter_map(|(node_id, node)| matches!(node, Node::Scene(_)).then_some(node_id))
+            .collect::<Vec<_>>();
+
+        for scene_id in scene_ids {
+            Self::handle_recalculate(SelfNodeMut::new(scene_id, snarl));
+        }
+    }
+
+    /// Lists the size of every scene in the graph together with the problems found in it.
+    pub fn stats_view(ui: &mut Ui, snarl: &Snarl<Node>) {
+        let scenes = snarl.node_ids().filter_map(|(node_id, node)| match node {
+            Node::Scene(scene_node) => Some((node_id, scene_node.as_scene())),
+            _ => None,
+        });
+
+        for (node_id, scene) in scenes {
+            let stats = scene.stats();
+            let texture_mib = stats.texture_bytes as f64 / (1024.0 * 1024.0);
+            ui.collapsing(format!("{} {}", Self::NAME, node_id.0), |ui| {
+                egui::Grid::new(("scene_stats", node_id)).num_columns(2).show(ui, |ui| {
+                    ui.label("Spheres");
+                    ui.label(stats.spheres.to_string());
+                    ui.end_row();
+                    ui.label("Materials");
+                    ui.label(stats.materials.to_string());
+                    ui.end_row();
+                    ui.label("Lights");
+                    ui.label(stats.lights.to_string());
+                    ui.end_row();
+                    ui.label("Textures");
+                    ui.label(format!("{} ({texture_mib:.1} MiB)", stats.textures));
+                    ui.end_row();
+                });
+
+                let warnings = scene.validate();
+                if warnings.is_empty() {
+                    ui.label("No problems found");
+                }
+                for warning in warnings {
+                    ui.colored_label(ui.visuals().warn_fg_color, warning.to_string());
+                }
+            });
+        }
+    }
+
     pub fn handle_self_msg(self_node: SelfNodeMut, msg: SceneNodeMessage) -> SceneNodeResponse {
         match msg {
             SceneNodeMessage::Recalculate => Self::handle_recalculate(self_node),
@@ -151,8 +235,12 @@ impl SceneNode {
     }
 
     pub fn handle_recalculate(mut self_node: SelfNodeMut) -> SceneNodeResponse {
+        let texture_generation = TextureCache::shared().generation();
         let old_data = {
             let node = self_node.as_scene_node_mut();
+            if matches!(node.pending_textures, Some(pending) if pending != texture_generation) {
+                node.dirty |= SceneDirtyFlags::TEXTURE_VALUE;
+            }
             if node.dirty != SceneDirtyFlags::NONE {
                 Some((mem::take(&mut node.inner_scene), mem::take(&mut node.tracked_nodes)))
             } else {
@@ -168,7 +256,12 @@ impl SceneNode {
                     predicate: &|node| {
                         matches!(
                             node,
-                            Node::Primitive(_) | Node::Material(_) | Node::Texture(_) | Node::Collection(_)
+                            Node::Primitive(_)
+                                | Node::Light(_)
+                                | Node::Material(_)
+                                | Node::Texture(_)
+                                | Node::NoiseTexture(_)
+                                | Node::Collection(_)
                         )
                     },
                     destination: &mut nodes,
@@ -222,56 +315,218 @@ impl SceneNode {
 
             let mut textures: Vec<TextureData> = Vec::new();
             let mut texture_indices = HashMap::new();
+            let mut pending_textures = false;
 
             let mut materials = Vec::new();
             let mut material_indices = HashMap::new();
 
             let mut spheres = Vec::new();
+            let mut sphere_indices = HashMap::new();
+            let mut csg_ops = Vec::new();
+            let mut lights = Vec::new();
+            // Spheres whose material is disabled share one default material.
+            let mut fallback_material_idx = None;
+
+            // Transform of every copy of a sphere. Inner transforms and instances are collected before the
+            // outer ones, so each one is applied on top of the copies already found for its spheres.
+            let mut placements: HashMap<NodeId, Vec<Matrix4f32>> = HashMap::new();
+            for &node_id in &nodes {
+                let (copies, input) = match self_node.node_by_id_ref(node_id) {
+                    Node::Primitive(PrimitiveNode::Transform(transform_node)) if !transform_node.disabled => {
+                        (vec![transform_node.matrix()], transform_node.input.get())
+                    },
+                    Node::Primitive(PrimitiveNode::Instance(instance_node)) if !instance_node.disabled => {
+                        (instance_node.copies(), instance_node.input.get())
+                    },
+                    _ => continue,
+                };
+
+                let mut transformed = FastIndexSet::default();
+                collect_for_node(
+                    input,
+                    &|node| matches!(node, Node::Primitive(PrimitiveNode::Sphere(_))),
+                    &mut transformed,
+                    self_node.snarl,
+                );
+                for sphere_id in transformed {
+                    let placement = placements
+                        .entry(sphere_id)
+                        .or_insert_with(|| vec![Matrix4f32::identity()]);
+                    *placement = copies
+                        .iter()
+                        .flat_map(|copy| placement.iter().map(move |inner| copy.cast::<f32>() * inner))
+                        .collect();
+                }
+            }
+
+            for &node_id in &nodes {
+                let node = self_node.node_by_id_ref(node_id);
+                if node.is_disabled() {
+                    continue;
+                }
 
-            for node_id in nodes {
-                match self_node.node_by_id_ref(node_id) {
+                match node {
                     Node::Texture(texture_node) => {
-                        let eq_predicate = |data: &TextureData| {
+                        let srgb = texture_node.decodes_srgb();
+                        let same_image = |data: &TextureData| {
                             data.key.as_deref() == Some(texture_node.path.as_str())
                                 && data.scale == texture_node.scale.get() as f32
+                                && data.srgb == srgb
+                        };
+                        let (scale_u, scale_v) = texture_node.uv_scale();
+                        let wrap = texture_node.wrap;
+                        let eq_predicate = |data: &TextureData| {
+                            same_image(data) && data.scale_u == scale_u && data.scale_v == scale_v && data.wrap == wrap
                         };
 
-                        if let Some(texture_id) = textures.iter().position(eq_predicate) {
+                        if let Some(texture_id) = reuse_texture(&mut textures, &mut old_scene.textures, eq_predicate) {
                             texture_indices.insert(node_id, texture_id);
-                        } else if let Some(texture_id) = old_scene.textures.iter().position(eq_predicate) {
-                            let data = old_scene.textures.remove(texture_id);
-                            textures.push(data);
+                        } else if let Some(data) = textures
+                            .iter()
+                            .chain(&old_scene.textures)
+                            .find(|&data| same_image(data))
+                            .cloned()
+                        {
+                            // Only the sampling changed, the texels don't need to be loaded again.
+                            textures.push(data.with_uv_scale(scale_u, scale_v).with_wrap(wrap));
                             texture_indices.insert(node_id, textures.len() - 1);
                         } else {
-                            let data =
-                                TextureData::load_scaled(texture_node.path.clone(), texture_node.scale.get() as _);
-                            textures.push(data);
+                            let scale = texture_node.scale.get() as _;
+                            let data = match TextureCache::shared().load_scaled(&texture_node.path, scale) {
+                                TextureLoad::Loaded(data) => data,
+                                TextureLoad::Loading => {
+                                    // Flat grey stands in for the image until it is loaded.
+                                    pending_textures = true;
+                                    TextureData::new(Texture::new_from_color(Vector3f32::repeat(0.5)))
+                                },
+                                // A missing file, e.g. in a scene opened on another machine, shows
+                                // in magenta until the path is fixed.
+                                TextureLoad::Failed => TextureData::missing(),
+                            };
+                            let data = if srgb { data.decode_srgb() } else { data };
+                            textures.push(data.with_uv_scale(scale_u, scale_v).with_wrap(wrap));
                             texture_indices.insert(node_id, textures.len() - 1);
                         }
                     },
+                    Node::NoiseTexture(noise_texture_node) => {
+                        // Regenerating is slow, so noise is only rebuilt when its parameters change.
+                        let key = noise_texture_node.texture_key();
+                        let eq_predicate = |data: &TextureData| data.key.as_deref() == Some(key.as_str());
+
+                        let texture_id = reuse_texture(&mut textures, &mut old_scene.textures, eq_predicate)
+                            .unwrap_or_else(|| {
+                                textures.push(noise_texture_node.texture_data());
+                                textures.len() - 1
+                            });
+                        texture_indices.insert(node_id, texture_id);
+                    },
+                    Node::Material(MaterialNode::Mix(mix_node)) => {
+                        // The mixed materials are collected before the mix, missing or disabled ones are replaced
+                        // by the default material.
+                        let mut material_idx = |node_id: Option<NodeId>| {
+                            let collected = node_id.and_then(|node_id| material_indices.get(&node_id).copied());
+                            match collected {
+                                Some(material_idx) => material_idx,
+                                None => fallback_material(&mut fallback_material_idx, &mut materials, &mut textures),
+                            }
+                        };
+                        let (first, second) = (material_idx(mix_node.first.get()), material_idx(mix_node.second.get()));
+                        let mask_texture_id = mix_node
+                            .mask
+                            .get()
+                            .and_then(|node_id| texture_indices.get(&node_id).copied());
+                        materials.push(Material::from_mix_node(
+                            mix_node,
+                            first,
+                            second,
+                            mask_texture_id,
+                            &mut textures,
+                        ));
+                        material_indices.insert(node_id, materials.len() - 1);
+                    },
                     Node::Material(material_node) => {
                         let texture_id = material_node
                             .get_texture_node_id()
                             .and_then(|node_id| texture_indices.get(&node_id).copied());
-                        let material = Material::from_node(material_node, texture_id, &mut textures);
+                        let normal_texture_id = material_node
+                            .get_normal_texture_node_id()
+                            .and_then(|node_id| texture_indices.get(&node_id).copied());
+                        let roughness_texture_id = material_node
+                            .get_roughness_texture_node_id()
+                            .and_then(|node_id| texture_indices.get(&node_id).copied());
+                        let height_texture_id = material_node
+                            .get_height_texture_node_id()
+                            .and_then(|node_id| texture_indices.get(&node_id).copied());
+                        let material = Material::from_node(
+                            material_node,
+                            texture_id,
+                            normal_texture_id,
+                            roughness_texture_id,
+                            height_texture_id,
+                            &mut textures,
+                        );
                         materials.push(material);
                         material_indices.insert(node_id, materials.len() - 1);
                     },
                     Node::Primitive(PrimitiveNode::Sphere(sphere_node)) => {
                         let material_idx = match sphere_node.material.as_ref() {
+                            _ if sphere_node.is_light => {
+                                materials.push(Material::from_sphere_light(sphere_node, &mut textures));
+                                materials.len() - 1
+                            },
                             InputMaterial::Internal(material_node) => {
                                 let texture_id = material_node
                                     .get_texture_node_id()
                                     .and_then(|node_id| texture_indices.get(&node_id).copied());
-                                let material = Material::from_node(material_node, texture_id, &mut textures);
+                                let normal_texture_id = material_node
+                                    .get_normal_texture_node_id()
+                                    .and_then(|node_id| texture_indices.get(&node_id).copied());
+                                let roughness_texture_id = material_node
+                                    .get_roughness_texture_node_id()
+                                    .and_then(|node_id| texture_indices.get(&node_id).copied());
+                                let height_texture_id = material_node
+                                    .get_height_texture_node_id()
+                                    .and_then(|node_id| texture_indices.get(&node_id).copied());
+                                let material = Material::from_node(
+                                    material_node,
+                                    texture_id,
+                                    normal_texture_id,
+                                    roughness_texture_id,
+                                    height_texture_id,
+                                    &mut textures,
+                                );
                                 materials.push(material);
                                 materials.len() - 1
                             },
-                            InputMaterial::External(node_id) => material_indices[node_id],
+                            InputMaterial::External(node_id) => match material_indices.get(node_id) {
+                                Some(&material_idx) => material_idx,
+                                None => fallback_material(&mut fallback_material_idx, &mut materials, &mut textures),
+                            },
                         };
 
                         let sphere = Sphere::from_node(sphere_node, material_idx as u32);
-                        spheres.push(sphere);
+                        let first_idx = spheres.len();
+                        match placements.get(&node_id) {
+                            Some(placement) => {
+                                spheres.extend(placement.iter().map(|transform| sphere.with_transform(transform)))
+                            },
+                            None => spheres.push(sphere),
+                        }
+                        sphere_indices.insert(node_id, first_idx..spheres.len());
+                    },
+                    Node::Primitive(PrimitiveNode::Csg(csg_node)) => {
+                        // The spheres are collected before the node using them, a disabled one drops the
+                        // whole operation. Copies of the spheres are combined pairwise.
+                        let a = csg_node.a.get().and_then(|node_id| sphere_indices.get(&node_id));
+                        let b = csg_node.b.get().and_then(|node_id| sphere_indices.get(&node_id));
+                        if let (Some(a), Some(b)) = (a, b) {
+                            for (a, b) in a.clone().zip(b.clone()) {
+                                csg_ops.push(CsgOp::new(csg_node.operation, a as u32, b as u32));
+                            }
+                        }
+                    },
+                    Node::Light(light_node) => {
+                        lights.push(GpuLight::from_node(light_node));
                     },
                     _ => (),
                 }
@@ -282,7 +537,12 @@ impl SceneNode {
                 spheres,
                 materials,
                 textures,
+                lights,
+                csg_ops,
             };
+            node.tracked_nodes = nodes;
+            node.revision = node.revision.wrapping_add(1);
+            node.pending_textures = pending_textures.then_some(texture_generation);
 
             // Самый первый рендер с флагом инициализации не проходит до конца,
             // поэтому нужен будет повторный. В дальнейшем эта ошибка не повторяется.
@@ -298,3 +558,169 @@ impl SceneNode {
         }
     }
 }
+
+/// Index of a matching texture, moved over from the previous scene if it isn't in `textures` yet.
+fn reuse_texture(
+    textures: &mut Vec<TextureData>,
+    old_textures: &mut Vec<TextureData>,
+    eq_predicate: impl Fn(&TextureData) -> bool,
+) -> Option<usize> {
+    if let Some(texture_id) = textures.iter().position(&eq_predicate) {
+        Some(texture_id)
+    } else {
+        let old_texture_id = old_textures.iter().position(&eq_predicate)?;
+        textures.push(old_textures.remove(old_texture_id));
+        Some(textures.len() - 1)
+    }
+}
+
+/// Index of the default material standing in for missing ones, it is added the first time it is needed.
+fn fallback_material(
+    fallback_material_idx: &mut Option<usize>,
+    materials: &mut Vec<Material>,
+    textures: &mut Vec<TextureData>,
+) -> usize {
+    *fallback_material_idx.get_or_insert_with(|| {
+        let material = Material::from_node(&MaterialNode::default(), None, None, None, None, textures);
+        materials.push(material);
+        materials.len() - 1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Pos2;
+
+    use super::*;
+    use crate::node::collection::CollectionNode;
+    use crate::node::material::{MetalNode, MixMaterialNode};
+    use crate::node::primitive::{InstanceNode, SphereNode};
+    use crate::types::Vector3;
+
+    #[test]
+    fn test_sphere_in_two_collections_is_added_once() {
+        let mut snarl = Snarl::new();
+        let sphere_node = Node::Primitive(PrimitiveNode::Sphere(SphereNode::default()));
+        let sphere = snarl.insert_node(Pos2::ZERO, sphere_node);
+
+        let mut collection = |members: &[NodeId]| {
+            let mut collection_node = CollectionNode::default();
+            for (idx, &member) in members.iter().enumerate() {
+                collection_node.insert(idx, member);
+            }
+            snarl.insert_node(Pos2::ZERO, Node::Collection(collection_node))
+        };
+        let left = collection(&[sphere]);
+        let right = collection(&[sphere]);
+        let root = collection(&[left, right]);
+
+        let mut scene_node = SceneNode::default();
+        scene_node.data.set(Some(root));
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(scene_node));
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl));
+        assert_eq!(snarl[scene].as_scene_node_ref().as_scene().spheres.len(), 1);
+    }
+
+    #[test]
+    fn test_disabled_sphere_is_skipped() {
+        let mut snarl = Snarl::new();
+        let spheres = [false, true].map(|disabled| {
+            let mut sphere_node = SphereNode::default();
+            sphere_node.disabled = disabled;
+            snarl.insert_node(Pos2::ZERO, Node::Primitive(PrimitiveNode::Sphere(sphere_node)))
+        });
+
+        let mut collection_node = CollectionNode::default();
+        for (idx, &sphere) in spheres.iter().enumerate() {
+            collection_node.insert(idx, sphere);
+        }
+        let collection = snarl.insert_node(Pos2::ZERO, Node::Collection(collection_node));
+
+        let mut scene_node = SceneNode::default();
+        scene_node.data.set(Some(collection));
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(scene_node));
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl));
+        assert_eq!(snarl[scene].as_scene_node_ref().as_scene().spheres.len(), 1);
+
+        *snarl[spheres[1]].disabled_mut().unwrap() = false;
+        SceneNode::invalidate_tracking(&mut snarl, spheres[1]);
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl));
+        assert_eq!(snarl[scene].as_scene_node_ref().as_scene().spheres.len(), 2);
+    }
+
+    #[test]
+    fn test_light_sphere_is_emissive() {
+        let mut snarl = Snarl::new();
+        let mut sphere_node = SphereNode::default();
+        sphere_node.is_light = true;
+        let sphere = snarl.insert_node(Pos2::ZERO, Node::Primitive(PrimitiveNode::Sphere(sphere_node)));
+
+        let mut scene_node = SceneNode::default();
+        scene_node.data.set(Some(sphere));
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(scene_node));
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl));
+        let inner_scene = snarl[scene].as_scene_node_ref().as_scene();
+        assert_eq!(inner_scene.spheres.len(), 1);
+        assert!(matches!(inner_scene.materials[..], [Material::Emissive { .. }]));
+    }
+
+    #[test]
+    fn test_mix_references_its_materials() {
+        let mut snarl = Snarl::new();
+        let metal = snarl.insert_node(Pos2::ZERO, Node::Material(MaterialNode::Metal(MetalNode::default())));
+        let mut mix_node = MixMaterialNode::default();
+        mix_node.first.set(Some(metal));
+        let mix = snarl.insert_node(Pos2::ZERO, Node::Material(MaterialNode::Mix(mix_node)));
+
+        let mut sphere_node = SphereNode::default();
+        sphere_node.material.set(InputMaterial::External(mix));
+        let sphere = snarl.insert_node(Pos2::ZERO, Node::Primitive(PrimitiveNode::Sphere(sphere_node)));
+
+        let mut scene_node = SceneNode::default();
+        scene_node.data.set(Some(sphere));
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(scene_node));
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl));
+        let inner_scene = snarl[scene].as_scene_node_ref().as_scene();
+        // The unconnected second material is the default one.
+        let [
+            Material::Metal { .. },
+            Material::Lambertian { .. },
+            Material::Mix { first, second, .. },
+        ] = inner_scene.materials[..]
+        else {
+            panic!("expected a metal, a default and a mix material");
+        };
+        assert_eq!((first, second), (0, 1));
+        assert_eq!(inner_scene.spheres[0].material_idx(), 2);
+        assert!(inner_scene.validate().is_empty());
+    }
+
+    #[test]
+    fn test_instanced_sphere_becomes_a_row() {
+        let mut snarl = Snarl::new();
+        let sphere_node = Node::Primitive(PrimitiveNode::Sphere(SphereNode::default()));
+        let sphere = snarl.insert_node(Pos2::ZERO, sphere_node);
+
+        let mut instance_node = InstanceNode::default();
+        instance_node.input.set(Some(sphere));
+        instance_node.count.set(10);
+        instance_node.translate_step.set(Vector3::new(3.0, 0.0, 0.0));
+        let instance = snarl.insert_node(Pos2::ZERO, Node::Primitive(PrimitiveNode::Instance(instance_node)));
+
+        let mut scene_node = SceneNode::default();
+        scene_node.data.set(Some(instance));
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(scene_node));
+
+        SceneNode::handle_recalculate(SelfNodeMut::new(scene, &mut snarl));
+        let spheres = &snarl[scene].as_scene_node_ref().as_scene().spheres;
+        assert_eq!(spheres.len(), 10);
+        for (idx, sphere) in spheres.iter().enumerate() {
+            let center = Vector3f32::new(3.0 * idx as f32, 0.0, 0.0);
+            assert!((sphere.world_center() - center).norm() < 1e-4);
+        }
+    }
+}
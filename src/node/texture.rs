@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use egui::Ui;
 use egui_snarl::ui::{PinInfo, WireStyle};
 use egui_snarl::{InPin, OutPin};
@@ -6,15 +8,31 @@ use serde::{Deserialize, Serialize};
 use super::NodeFlags;
 use super::message::{EventMessage, EventResponse, MessageHandling, SelfNodeMut};
 use super::subscribtion::Subscription;
-use super::viewer::STRING_COLOR;
+use super::viewer::{STRING_COLOR, UNTYPED_COLOR};
 use crate::node::subscribtion::Event;
-use crate::node::viewer::{number_input_remote_value, number_input_view};
+use crate::node::viewer::{number_input_remote_value, number_input_view, number_input_view_in_range};
+use crate::raytracer::scene::{TextureCache, TextureData};
+use crate::raytracer::{Texture, WrapMode};
 use crate::types::NodePin;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TextureNode {
     pub path: String,
+    /// Multiplier for the texel values.
     pub scale: NodePin<f64>,
+    /// Repeats of the texture along U and V, older scenes load as `1`.
+    #[serde(default = "default_uv_scale")]
+    pub scale_u: NodePin<f64>,
+    #[serde(default = "default_uv_scale")]
+    pub scale_v: NodePin<f64>,
+    #[serde(default)]
+    pub wrap: WrapMode,
+    /// LDR images hold sRGB colors, which are decoded to linear values. Off for normal, roughness and height
+    /// maps. Older scenes load with it on, since most of their textures are color maps.
+    #[serde(default = "default_srgb")]
+    pub srgb: bool,
+    #[serde(default)]
+    pub collapsed: bool,
 
     #[serde(skip)]
     subscription: Subscription,
@@ -25,14 +43,31 @@ impl Default for TextureNode {
         Self {
             path: String::new(),
             scale: NodePin::new(1.0),
+            scale_u: default_uv_scale(),
+            scale_v: default_uv_scale(),
+            wrap: WrapMode::default(),
+            srgb: default_srgb(),
+            collapsed: false,
             subscription: Subscription::default(),
         }
     }
 }
 
+fn default_uv_scale() -> NodePin<f64> {
+    NodePin::new(1.0)
+}
+
+fn default_srgb() -> bool {
+    true
+}
+
 impl TextureNode {
     pub const NAME: &str = "Texture";
-    pub const INPUTS: [u64; 1] = [NodeFlags::TYPICAL_NUMBER_INPUT.bits()];
+    pub const INPUTS: [u64; 3] = [
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
     pub const OUTPUTS: [u64; 1] = [NodeFlags::TEXTURE.bits() | NodeFlags::STRING.bits()];
 
     pub fn inputs(&self) -> &[u64] {
@@ -43,19 +78,53 @@ impl TextureNode {
         &Self::OUTPUTS
     }
 
+    pub fn uv_scale(&self) -> (f32, f32) {
+        (self.scale_u.get() as _, self.scale_v.get() as _)
+    }
+
+    /// HDR images are linear already.
+    pub fn decodes_srgb(&self) -> bool {
+        self.srgb && !Texture::is_hdr_path(&self.path)
+    }
+
     pub fn show_output(mut self_node: SelfNodeMut, _pin: &OutPin, ui: &mut Ui) -> PinInfo {
+        let node_id = self_node.id;
         let node = self_node.as_texture_node_mut();
-        let old_value = node.path.clone();
-        let edit: egui::TextEdit<'_> = egui::TextEdit::singleline(&mut node.path)
-            .clip_text(false)
-            .desired_width(0.0)
-            .margin(ui.spacing().item_spacing);
-        ui.horizontal(|ui| {
-            ui.add(edit);
-            ui.label("Path");
+        let old_value = (node.path.clone(), node.wrap, node.srgb);
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                let edit = egui::TextEdit::singleline(&mut node.path)
+                    .clip_text(false)
+                    .desired_width(0.0)
+                    .margin(ui.spacing().item_spacing);
+                ui.add(edit);
+                if ui.button("Browse…").clicked() {
+                    if let Some(path) = pick_image() {
+                        node.path = path;
+                    }
+                }
+                ui.label("Path");
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(("texture_wrap", node_id))
+                    .selected_text(node.wrap.name())
+                    .show_ui(ui, |ui| {
+                        for wrap in WrapMode::ALL {
+                            ui.selectable_value(&mut node.wrap, wrap, wrap.name());
+                        }
+                    });
+                ui.label("Wrap");
+            });
+            ui.checkbox(&mut node.srgb, "sRGB color")
+                .on_hover_text("Turn off for normal, roughness and height maps");
+            thumbnail_view(ui, &node.path);
+            if let Some(err) = TextureCache::shared().error(&node.path, node.scale.get() as _) {
+                ui.colored_label(ui.visuals().warn_fg_color, "⚠ Rendered as missing texture")
+                    .on_hover_text(err);
+            }
         });
 
-        if old_value != node.path {
+        if old_value != (node.path.clone(), node.wrap, node.srgb) {
             if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
                 caller(self_node);
             }
@@ -67,11 +136,65 @@ impl TextureNode {
     }
 }
 
+/// Extensions of the image files a texture can load.
+const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "bmp", "tga", "hdr", "exr"];
+
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// `path` relative to the working directory if it is inside of it, as the texture path of a node.
+pub fn image_path(path: &Path) -> String {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf));
+
+    relative.unwrap_or_else(|| path.to_path_buf()).display().to_string()
+}
+
+/// Asks for an image file. `None` if cancelled.
+fn pick_image() -> Option<String> {
+    let path = rfd::FileDialog::new()
+        .add_filter("Image", &IMAGE_EXTENSIONS)
+        .pick_file()?;
+    Some(image_path(&path))
+}
+
+/// Small preview of the image at `path`, or an error marker with the reason on hover if it doesn't load.
+fn thumbnail_view(ui: &mut Ui, path: &str) {
+    const SIZE: f32 = 64.0;
+
+    if path.is_empty() {
+        return;
+    }
+
+    let uri = match std::path::absolute(path) {
+        Ok(path) => format!("file://{}", path.display()),
+        Err(err) => {
+            ui.colored_label(ui.visuals().error_fg_color, "⚠ Invalid path")
+                .on_hover_text(err.to_string());
+            return;
+        },
+    };
+    let image = egui::Image::new(uri).max_size(egui::Vec2::splat(SIZE));
+    match image.load_for_size(ui.ctx(), egui::Vec2::splat(SIZE)) {
+        Ok(_) => {
+            ui.add(image);
+        },
+        Err(err) => {
+            ui.colored_label(ui.visuals().error_fg_color, "⚠ Failed to load")
+                .on_hover_text(err.to_string());
+        },
+    }
+}
+
 impl MessageHandling for TextureNode {
     fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
         Some(match pin.id.input {
             0 => {
-                const LABEL: &str = "Scale";
+                const LABEL: &str = "Brightness";
 
                 let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
                 let node = self_node.as_texture_node_mut();
@@ -86,6 +209,38 @@ impl MessageHandling for TextureNode {
                 }
                 info
             },
+            1 => {
+                const LABEL: &str = "Scale U";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_texture_node_mut();
+
+                let old_value = node.scale_u.get();
+                let info = number_input_view(ui, LABEL, &mut node.scale_u, remote_value);
+
+                if old_value != node.scale_u.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            2 => {
+                const LABEL: &str = "Scale V";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_texture_node_mut();
+
+                let old_value = node.scale_v.get();
+                let info = number_input_view(ui, LABEL, &mut node.scale_v, remote_value);
+
+                if old_value != node.scale_v.get() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
             _ => unreachable!(),
         })
     }
@@ -101,6 +256,8 @@ impl MessageHandling for TextureNode {
         let node = self_node.as_texture_node_mut();
         match to.id.input {
             0 => node.scale.reset(),
+            1 => node.scale_u.reset(),
+            2 => node.scale_v.reset(),
             _ => unreachable!(),
         }
 
@@ -114,3 +271,151 @@ impl MessageHandling for TextureNode {
         node.subscription.handle_event(event_msg)
     }
 }
+
+/// Tileable grayscale noise generated in place of a loaded image.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NoiseTextureNode {
+    pub resolution: u32,
+    /// Noise cells along each side of the texture.
+    pub scale: NodePin<f64>,
+    pub seed: NodePin<f64>,
+    #[serde(default)]
+    pub collapsed: bool,
+
+    #[serde(skip)]
+    subscription: Subscription,
+}
+
+impl Default for NoiseTextureNode {
+    fn default() -> Self {
+        Self {
+            resolution: 256,
+            scale: NodePin::new(4.0),
+            seed: NodePin::new(0.0),
+            collapsed: false,
+            subscription: Subscription::default(),
+        }
+    }
+}
+
+impl NoiseTextureNode {
+    pub const NAME: &str = "Noise Texture";
+    pub const INPUTS: [u64; 2] = [
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    pub const OUTPUTS: [u64; 1] = [NodeFlags::TEXTURE.bits()];
+
+    const MAX_RESOLUTION: u32 = 2048;
+    const MAX_SCALE: f64 = 256.0;
+
+    pub fn inputs(&self) -> &[u64] {
+        &Self::INPUTS
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        &Self::OUTPUTS
+    }
+
+    fn cells(&self) -> u32 {
+        self.scale.get().round().clamp(1.0, Self::MAX_SCALE) as _
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed.get().round() as _
+    }
+
+    fn resolution(&self) -> u32 {
+        self.resolution.clamp(1, Self::MAX_RESOLUTION)
+    }
+
+    pub fn texture_key(&self) -> String {
+        TextureData::noise_key(self.resolution(), self.cells(), self.seed())
+    }
+
+    pub fn texture_data(&self) -> TextureData {
+        TextureData::noise(self.resolution(), self.cells(), self.seed())
+    }
+
+    pub fn show_output(mut self_node: SelfNodeMut, _pin: &OutPin, ui: &mut Ui) -> PinInfo {
+        let node = self_node.as_noise_texture_node_mut();
+        let old_value = node.resolution;
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut node.resolution).range(1..=Self::MAX_RESOLUTION));
+            ui.label("Resolution");
+        });
+
+        if old_value != node.resolution {
+            if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                caller(self_node);
+            }
+        }
+
+        PinInfo::circle().with_fill(UNTYPED_COLOR)
+    }
+}
+
+impl MessageHandling for NoiseTextureNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        Some(match pin.id.input {
+            0 => {
+                const LABEL: &str = "Scale";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_noise_texture_node_mut();
+
+                let old_value = node.cells();
+                let info = number_input_view_in_range(ui, LABEL, &mut node.scale, remote_value, 1.0..=Self::MAX_SCALE);
+
+                if old_value != node.cells() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            1 => {
+                const LABEL: &str = "Seed";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_noise_texture_node_mut();
+
+                let old_value = node.seed();
+                let info = number_input_view(ui, LABEL, &mut node.seed, remote_value);
+
+                if old_value != node.seed() {
+                    if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+                        caller(self_node)
+                    }
+                }
+                info
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_connect(mut self_node: SelfNodeMut, _from: &OutPin, _to: &InPin) {
+        let node = self_node.as_noise_texture_node_mut();
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_noise_texture_node_mut();
+        match to.id.input {
+            0 => node.scale.reset(),
+            1 => node.seed.reset(),
+            _ => unreachable!(),
+        }
+
+        if let Some(caller) = node.subscription.event_caller(Event::OnChange) {
+            caller(self_node)
+        }
+    }
+
+    fn handle_event(mut self_node: SelfNodeMut, event_msg: EventMessage) -> Option<EventResponse> {
+        let node = self_node.as_noise_texture_node_mut();
+        node.subscription.handle_event(event_msg)
+    }
+}
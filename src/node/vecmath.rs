@@ -0,0 +1,193 @@
+use egui::Ui;
+use egui_snarl::ui::PinInfo;
+use egui_snarl::{InPin, OutPin};
+use serde::{Deserialize, Serialize};
+
+use super::NodeFlags;
+use super::message::{MessageHandling, SelfNodeMut};
+use super::viewer::{number_input_remote_value, number_input_view, vector_input_remote_value, vector_input_view};
+use crate::types::{NodePin, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VectorMathOp {
+    Add,
+    Scale,
+    Dot,
+    Cross,
+    Normalize,
+    Length,
+}
+
+impl VectorMathOp {
+    const TWO_VECTORS: [u64; 2] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+    ];
+    const VECTOR_AND_NUMBER: [u64; 2] = [
+        NodeFlags::TYPICAL_VECTOR_INPUT.bits(),
+        NodeFlags::TYPICAL_NUMBER_INPUT.bits(),
+    ];
+    const ONE_VECTOR: [u64; 1] = [NodeFlags::TYPICAL_VECTOR_INPUT.bits()];
+
+    const VECTOR_OUTPUTS: [u64; 1] = [NodeFlags::VECTOR.bits()];
+    const NUMBER_OUTPUTS: [u64; 1] = [NodeFlags::NUMBER.bits()];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Add => "Vector Add",
+            Self::Scale => "Vector Scale",
+            Self::Dot => "Vector Dot",
+            Self::Cross => "Vector Cross",
+            Self::Normalize => "Vector Normalize",
+            Self::Length => "Vector Length",
+        }
+    }
+
+    pub const fn inputs(&self) -> &'static [u64] {
+        match self {
+            Self::Add | Self::Dot | Self::Cross => &Self::TWO_VECTORS,
+            Self::Scale => &Self::VECTOR_AND_NUMBER,
+            Self::Normalize | Self::Length => &Self::ONE_VECTOR,
+        }
+    }
+
+    pub const fn outputs(&self) -> &'static [u64] {
+        if self.outputs_number() { &Self::NUMBER_OUTPUTS } else { &Self::VECTOR_OUTPUTS }
+    }
+
+    pub const fn outputs_number(&self) -> bool {
+        matches!(self, Self::Dot | Self::Length)
+    }
+}
+
+/// Arithmetic on vectors, the operation decides which of the pins are used.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VectorMathNode {
+    pub op: VectorMathOp,
+    pub a: NodePin<Vector3>,
+    pub b: NodePin<Vector3>,
+    pub factor: NodePin<f64>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl VectorMathNode {
+    pub fn new(op: VectorMathOp) -> Self {
+        Self {
+            op,
+            a: NodePin::new(Vector3::zeros()),
+            b: NodePin::new(Vector3::zeros()),
+            factor: NodePin::new(1.0),
+            collapsed: false,
+        }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        self.op.name()
+    }
+
+    pub fn inputs(&self) -> &[u64] {
+        self.op.inputs()
+    }
+
+    pub fn outputs(&self) -> &[u64] {
+        self.op.outputs()
+    }
+
+    /// Result of operations outputting a number, the length of the vector for the others.
+    pub fn number_out(&self) -> f64 {
+        let (a, b) = (self.a.get(), self.b.get());
+        match self.op {
+            VectorMathOp::Dot => a.dot(&b),
+            VectorMathOp::Length => a.magnitude(),
+            _ => self.vector_out().magnitude(),
+        }
+    }
+
+    /// Result of operations outputting a vector, numbers are used for every component.
+    /// Normalizing a zero vector gives a zero vector.
+    pub fn vector_out(&self) -> Vector3 {
+        let (a, b) = (self.a.get(), self.b.get());
+        match self.op {
+            VectorMathOp::Add => a + b,
+            VectorMathOp::Scale => a * self.factor.get(),
+            VectorMathOp::Cross => a.cross(&b),
+            VectorMathOp::Normalize => a.try_normalize(f64::EPSILON).unwrap_or_default(),
+            VectorMathOp::Dot | VectorMathOp::Length => Vector3::repeat(self.number_out()),
+        }
+    }
+}
+
+impl MessageHandling for VectorMathNode {
+    fn handle_input_show(mut self_node: SelfNodeMut, pin: &InPin, ui: &mut Ui) -> Option<PinInfo> {
+        let op = self_node.as_vector_math_node_mut().op;
+
+        Some(match (op, pin.id.input) {
+            (VectorMathOp::Scale, 1) => {
+                const LABEL: &str = "Factor";
+
+                let remote_value = number_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_vector_math_node_mut();
+                number_input_view(ui, LABEL, &mut node.factor, remote_value)
+            },
+            (_, 0) => {
+                const LABEL: &str = "A";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_vector_math_node_mut();
+                vector_input_view(ui, LABEL, &mut node.a, remote_value)
+            },
+            (_, 1) => {
+                const LABEL: &str = "B";
+
+                let remote_value = vector_input_remote_value(pin, self_node.snarl, LABEL);
+                let node = self_node.as_vector_math_node_mut();
+                vector_input_view(ui, LABEL, &mut node.b, remote_value)
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    fn handle_input_disconnect(mut self_node: SelfNodeMut, _from: &OutPin, to: &InPin) {
+        let node = self_node.as_vector_math_node_mut();
+        match (node.op, to.id.input) {
+            (VectorMathOp::Scale, 1) => node.factor.reset(),
+            (_, 0) => node.a.reset(),
+            (_, 1) => node.b.reset(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(op: VectorMathOp, a: Vector3, b: Vector3) -> VectorMathNode {
+        let mut node = VectorMathNode::new(op);
+        node.a = NodePin::new(a);
+        node.b = NodePin::new(b);
+        node
+    }
+
+    #[test]
+    fn test_vector_math() {
+        let (x, y) = (Vector3::x(), Vector3::y());
+
+        assert_eq!(node(VectorMathOp::Add, x, y).vector_out(), Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(node(VectorMathOp::Cross, x, y).vector_out(), Vector3::z());
+        assert_eq!(node(VectorMathOp::Dot, x, y).number_out(), 0.0);
+        assert_eq!(node(VectorMathOp::Length, 3.0 * x + 4.0 * y, y).number_out(), 5.0);
+
+        let mut scale = node(VectorMathOp::Scale, x, y);
+        scale.factor = NodePin::new(2.0);
+        assert_eq!(scale.vector_out(), 2.0 * x);
+    }
+
+    #[test]
+    fn test_normalize_zero_vector() {
+        let zero = Vector3::zeros();
+        assert_eq!(node(VectorMathOp::Normalize, zero, zero).vector_out(), zero);
+        assert_eq!(node(VectorMathOp::Normalize, 2.0 * Vector3::y(), zero).vector_out(), Vector3::y());
+    }
+}
@@ -1,17 +1,38 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
 use eframe::egui_wgpu::RenderState;
-use egui::emath::Numeric;
+use egui::emath::{Numeric, TSTransform};
 use egui::epaint::Hsva;
-use egui::{Color32, Ui, WidgetText};
+use egui::{Align2, Color32, FontId, Pos2, Ui, WidgetText};
 use egui_snarl::ui::{AnyPins, PinInfo, SnarlViewer, WireStyle};
 use egui_snarl::{InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
 
-use super::material::InputMaterial;
+use super::background::BackgroundNode;
+use super::camera::CameraNode;
+use super::collection::CollectionNode;
+use super::color::{ColorMixNode, HsvColorNode};
+use super::curve::{CurveInterpolation, CurveNode};
+use super::light::{DirectionalLightNode, PointLightNode, SpotLightNode};
+use super::material::{
+    CheckerboardNode, DielectricNode, EmissiveNode, InputMaterial, IsotropicNode, LambertianNode, MetalNode,
+    MixMaterialNode, SubsurfaceNode,
+};
 use super::message::{CommonNodeResponse, InputMessage, InputResponse, SelfNodeMut};
-use super::render::raytracer::RaytracerRenderNode;
-use super::texture::TextureNode;
-use super::{Node, RenderNode};
+use super::primitive::{CsgNode, InstanceNode, PrimitiveNode, SphereNode, TransformNode};
+use super::render::RenderResources;
+use super::render::raytracer::{LockedResolution, RaytracerRenderNode, RaytracerRenderResources};
+use super::render::triangle::TriangleRenderNode;
+use super::scene::SceneNode;
+use super::texture::{NoiseTextureNode, TextureNode, image_path, is_image_path};
+use super::{Node, OutputNode, RenderNode, can_connect};
 use crate::node::expression::ExpressionNode;
-use crate::types::{Color, NodePin, Vector3};
+use crate::raytracer::{
+    AaPattern, BloomParams, ExposureParams, FrameTimings, OutputChannel, SamplingParams, SkyParams,
+};
+use crate::shortcuts::KeyBindings;
+use crate::types::{Angle, AngleUnit, Color, NodePin, UpAxis, Vector3};
 use crate::widget::color_picker::{Alpha, color_button, color_edit_button_srgba};
 
 pub const STRING_COLOR: Color32 = Color32::from_rgb(0x00, 0xb0, 0x00);
@@ -19,6 +40,13 @@ pub const NUMBER_COLOR: Color32 = Color32::from_rgb(0xb0, 0x00, 0x00);
 pub const VECTOR_COLOR: Color32 = Color32::from_rgb(0x00, 0x00, 0xb0);
 pub const MATERIAL_COLOR: Color32 = Color32::from_rgb(0xb0, 0x00, 0xb0);
 pub const UNTYPED_COLOR: Color32 = Color32::from_rgb(0xb0, 0xb0, 0xb0);
+pub const INVALID_COLOR: Color32 = Color32::from_rgb(0xff, 0x40, 0x40);
+
+/// How far pasted and duplicated nodes are moved from the originals.
+const PASTE_OFFSET: egui::Vec2 = egui::vec2(40.0, 40.0);
+
+/// Groups of the graph menu, in the order they are listed.
+const NODE_CATEGORIES: [&str; 4] = ["Materials", "Primitives", "Data", "Render"];
 
 pub struct NodeConfig {
     pub render_state: RenderState,
@@ -27,24 +55,54 @@ pub struct NodeConfig {
 
 pub struct NodeViewer {
     config: NodeConfig,
-    render: Option<NodeId>,
+    /// Render nodes connected to an output, each one gets its own tile of the viewport.
+    renders: Vec<NodeId>,
+    /// Every raytracer render connected to an output converged as of the last draw.
+    render_complete: bool,
+    render_stats: bool,
+    frame_timings: bool,
+    bloom: bool,
+    viewport_clear_color: Color32,
+    wire_labels: bool,
+    /// Output pin positions from the previous frame, used to place wire labels.
+    output_positions: HashMap<OutPinId, Pos2>,
+    /// Serialized graph from before the first edit made this frame.
+    snapshot: Option<String>,
+    /// Copied nodes with their positions, already detached from the graph.
+    clipboard: Vec<(Pos2, Node)>,
+    /// Text typed into the search field of the graph menu.
+    graph_menu_filter: String,
+    /// Pan and zoom of the graph as of the last frame it was shown.
+    graph_view: TSTransform,
+    /// Saved pan and zoom, applied the next time the graph is shown.
+    restored_graph_view: Option<TSTransform>,
+    /// Wires already reported as invalid, so the warning isn't repeated every frame.
+    invalid_wires: HashSet<(OutPinId, InPinId)>,
+    /// Shown over the viewport for a while, e.g. why a connection was refused.
+    message: Option<(String, Instant)>,
 }
 
 impl NodeViewer {
-    pub fn new(render_state: RenderState, max_viewport_resolution: u32, snarl: &Snarl<Node>) -> Self {
-        let mut render = None;
-
-        for (from_pin, to_pin) in snarl.wires() {
-            if snarl[to_pin.node].output_node_ref().is_some() {
-                if let Some(render_node) = snarl[from_pin.node].render_node_ref() {
-                    render_node.register(&render_state);
-                    render = Some(from_pin.node);
-                }
-            }
-        }
+    pub fn new(render_state: RenderState, max_viewport_resolution: u32, snarl: &mut Snarl<Node>) -> Self {
+        relink_inputs(snarl);
+        SceneNode::restore_subscriptions(snarl);
 
         Self {
-            render,
+            renders: register_renders(snarl, &render_state),
+            render_complete: false,
+            render_stats: false,
+            frame_timings: false,
+            bloom: false,
+            viewport_clear_color: Color32::TRANSPARENT,
+            wire_labels: false,
+            output_positions: HashMap::new(),
+            snapshot: None,
+            clipboard: Vec::new(),
+            graph_menu_filter: String::new(),
+            graph_view: TSTransform::IDENTITY,
+            restored_graph_view: None,
+            invalid_wires: HashSet::new(),
+            message: None,
             config: NodeConfig {
                 render_state,
                 max_viewport_resolution,
@@ -53,109 +111,511 @@ impl NodeViewer {
     }
 
     pub fn draw(&mut self, viewport: &egui::Rect, painter: &egui::Painter, snarl: &mut Snarl<Node>) {
-        if let Some(id) = self.render {
+        if self.renders.is_empty() {
+            painter.rect_filled(*viewport, 0.0, self.viewport_clear_color);
+        }
+
+        let mut raytracers = 0;
+        let mut converged = 0;
+        for (&id, tile) in self.renders.iter().zip(render_tiles(*viewport, self.renders.len())) {
             match snarl.get_node(id).and_then(Node::render_node_ref) {
                 Some(RenderNode::Triangle(render)) => {
-                    render.draw(*viewport, painter);
+                    render.draw(id, tile, painter);
                 },
                 Some(RenderNode::Raytracer(_render)) => {
-                    RaytracerRenderNode::draw(SelfNodeMut::new(id, snarl), *viewport, painter);
+                    let output_resolution = output_resolution(id, snarl);
+                    raytracers += 1;
+                    converged += usize::from(RaytracerRenderNode::draw(
+                        SelfNodeMut::new(id, snarl),
+                        tile,
+                        painter,
+                        &self.config.render_state,
+                        output_resolution,
+                        self.bloom,
+                    ));
+                    if self.render_stats {
+                        self.draw_render_stats(id, &tile, painter);
+                    }
+                    if self.frame_timings {
+                        self.draw_frame_timings(id, &tile, painter);
+                    }
                 },
                 None => (),
             }
         }
+        self.render_complete = raytracers > 0 && converged == raytracers;
+
+        self.draw_message(viewport, painter);
+    }
+
+    fn show_message(&mut self, message: String) {
+        eprintln!("{message}");
+        self.message = Some((message, Instant::now()));
+    }
+
+    fn draw_message(&mut self, viewport: &egui::Rect, painter: &egui::Painter) {
+        const MESSAGE_DURATION: Duration = Duration::from_secs(4);
+
+        let Some((message, shown_at)) = &self.message else {
+            return;
+        };
+        let Some(remaining) = MESSAGE_DURATION.checked_sub(shown_at.elapsed()) else {
+            self.message = None;
+            return;
+        };
+
+        painter.text(
+            viewport.center_bottom() - egui::vec2(0.0, 24.0),
+            Align2::CENTER_BOTTOM,
+            message,
+            FontId::proportional(16.0),
+            painter.ctx().style().visuals.warn_fg_color,
+        );
+        painter.ctx().request_repaint_after(remaining);
+    }
+
+    /// Pauses every shown raytracer render, or resumes them if they are all paused already.
+    pub fn toggle_render_pause(&self, snarl: &mut Snarl<Node>) {
+        let raytracers = |snarl: &Snarl<Node>| {
+            self.renders.iter().filter_map(|&id| match snarl.get_node(id) {
+                Some(Node::Render(RenderNode::Raytracer(render))) => Some(render.paused),
+                _ => None,
+            })
+        };
+        let paused = !raytracers(snarl).all(|paused| paused);
+
+        for &id in &self.renders {
+            if let Some(Node::Render(RenderNode::Raytracer(render))) = snarl.get_node_mut(id) {
+                render.paused = paused;
+            }
+        }
+    }
+
+    /// Render nodes connected to an output, in the order of their viewport tiles.
+    pub fn renders(&self) -> &[NodeId] {
+        &self.renders
+    }
+
+    /// Starts every shown raytracer render over, does nothing without one.
+    pub fn restart_renders(&self, snarl: &mut Snarl<Node>) {
+        for &id in &self.renders {
+            if let Some(Node::Render(RenderNode::Raytracer(render))) = snarl.get_node_mut(id) {
+                render.restart = true;
+            }
+        }
+    }
+
+    pub fn is_render_complete(&self) -> bool {
+        self.render_complete
+    }
+
+    pub fn set_render_stats(&mut self, enabled: bool) {
+        self.render_stats = enabled;
+    }
+
+    pub fn set_frame_timings(&mut self, enabled: bool) {
+        self.frame_timings = enabled;
+    }
+
+    pub fn bloom(&self) -> bool {
+        self.bloom
+    }
+
+    pub fn render_state(&self) -> &RenderState {
+        &self.config.render_state
     }
 
-    pub fn after_show(&mut self, ui: &mut Ui, response: &egui::Response, snarl: &mut Snarl<Node>) {
-        if let Some(id) = self.render {
+    pub fn set_bloom(&mut self, enabled: bool) {
+        self.bloom = enabled;
+    }
+
+    pub fn set_viewport_clear_color(&mut self, color: Color32) {
+        self.viewport_clear_color = color;
+    }
+
+    /// Paints the timings of the previous raytracer frame in the top right corner of the viewport.
+    fn draw_frame_timings(&self, node_id: NodeId, viewport: &egui::Rect, painter: &egui::Painter) {
+        const MARGIN: f32 = 8.0;
+
+        let Some(timings) = RenderResources::<RaytracerRenderResources>::get(
+            &self.config.render_state.renderer.read().callback_resources,
+            node_id,
+        )
+        .map(RaytracerRenderResources::frame_timings) else {
+            return;
+        };
+
+        let text = if FrameTimings::ENABLED {
+            let ms = |duration: Duration| format!("{:7.2} ms", duration.as_secs_f64() * 1000.0);
+            format!(
+                "frame        {}\nprepare      {}\npaint        {}\nscene build  {}\nsamples      {:7} spp",
+                ms(timings.frame),
+                ms(timings.prepare),
+                ms(timings.paint),
+                ms(timings.scene_update),
+                timings.samples
+            )
+        } else {
+            "Frame timings need the\n`frame-timings` feature".to_owned()
+        };
+        let galley = painter.layout_no_wrap(text, FontId::monospace(12.0), Color32::WHITE);
+
+        let background = egui::Rect::from_min_size(
+            viewport.right_top() + egui::vec2(-MARGIN - galley.size().x - MARGIN * 2.0, MARGIN),
+            galley.size() + egui::vec2(MARGIN, MARGIN) * 2.0,
+        );
+        painter.rect_filled(background, 4.0, Color32::from_black_alpha(160));
+        painter.galley(background.min + egui::vec2(MARGIN, MARGIN), galley, Color32::WHITE);
+    }
+
+    /// Paints the raytracer progress from the previous frame in the top left corner of the viewport.
+    fn draw_render_stats(&self, node_id: NodeId, viewport: &egui::Rect, painter: &egui::Painter) {
+        const MARGIN: f32 = 8.0;
+        const BAR_HEIGHT: f32 = 4.0;
+
+        let Some(stats) = RenderResources::<RaytracerRenderResources>::get(
+            &self.config.render_state.renderer.read().callback_resources,
+            node_id,
+        )
+        .map(RaytracerRenderResources::stats) else {
+            return;
+        };
+
+        let text = format!(
+            "{} / {} samples\n{} px\n{:.1} s elapsed\n{:.1} samples/s\n{:.1}% noise",
+            stats.accumulated_samples,
+            stats.max_samples,
+            stats.pixel_count,
+            stats.elapsed.as_secs_f32(),
+            stats.samples_per_second,
+            stats.noise * 100.0
+        );
+        let galley = painter.layout_no_wrap(text, FontId::monospace(12.0), Color32::WHITE);
+
+        let width = galley.size().x.max(120.0);
+        let background = egui::Rect::from_min_size(
+            viewport.min + egui::vec2(MARGIN, MARGIN),
+            egui::vec2(width, galley.size().y + BAR_HEIGHT + MARGIN) + egui::vec2(MARGIN, MARGIN) * 2.0,
+        );
+        painter.rect_filled(background, 4.0, Color32::from_black_alpha(160));
+
+        let text_pos = background.min + egui::vec2(MARGIN, MARGIN);
+        let bar = egui::Rect::from_min_size(
+            text_pos + egui::vec2(0.0, galley.size().y + MARGIN),
+            egui::vec2(width, BAR_HEIGHT),
+        );
+        painter.galley(text_pos, galley, Color32::WHITE);
+        painter.rect_filled(bar, 2.0, Color32::from_gray(60));
+        painter.rect_filled(
+            bar.with_max_x(bar.min.x + bar.width() * stats.progress.clamp(0.0, 1.0)),
+            2.0,
+            Color32::from_rgb(0x40, 0xb0, 0x40),
+        );
+    }
+
+    pub fn after_show(
+        &mut self,
+        ui: &mut Ui,
+        response: &egui::Response,
+        snarl: &mut Snarl<Node>,
+        bindings: &KeyBindings,
+        keyboard_enabled: bool,
+    ) {
+        // Input goes to the render under the pointer, or to the first one when the pointer is elsewhere.
+        let pointer = response.interact_pointer_pos().or_else(|| response.hover_pos());
+        let tiles = render_tiles(response.rect, self.renders.len());
+        let active = pointer
+            .and_then(|pos| tiles.iter().position(|tile| tile.contains(pos)))
+            .unwrap_or(0);
+
+        // Right-dragging or scrolling over a window on top of the render is meant for the window,
+        // a drag that started on the render keeps going when it leaves it.
+        let pointer_enabled = response.hovered() || response.is_pointer_button_down_on();
+
+        if let Some(&id) = self.renders.get(active) {
             match snarl[id].as_render_node_mut() {
                 RenderNode::Triangle(render) => {
-                    let drag = response.drag_delta().x;
-                    render.recalc_angle(drag as _);
+                    let drag = response.drag_delta();
+                    render.recalc_angle(drag.x as _, drag.y as _);
                 },
                 RenderNode::Raytracer(render) => {
                     if let Some(camera) = render
                         .camera_id()
                         .and_then(|camera_id| snarl.get_node_mut(camera_id).and_then(Node::camera_node_mut))
                     {
-                        ui.input(|i| camera.after_events(i));
+                        ui.input(|i| camera.after_events(i, bindings, keyboard_enabled, pointer_enabled));
                     }
                 },
             }
         }
     }
 
-    fn unregister_render(&mut self, snarl: &mut Snarl<Node>) {
-        if let Some(id) = self.render.take() {
-            if let Some(render_node) = snarl.get_node(id).and_then(Node::render_node_ref) {
-                render_node.unregister(&self.config.render_state);
+    /// Feeds the time in seconds to every expression node and camera transition.
+    /// Returns `true` if any of them depends on it and the graph has to be shown again next frame.
+    pub fn set_time(&mut self, snarl: &mut Snarl<Node>, time: f64) -> bool {
+        let mut animated = false;
+        for node in snarl.nodes_mut() {
+            match node {
+                Node::Expression(expr_node) => animated |= expr_node.set_time(time),
+                Node::Curve(curve) => animated |= curve.set_time(time),
+                Node::Camera(camera_node) => animated |= camera_node.advance_transition(time),
+                _ => {},
             }
         }
+        animated
     }
-}
 
-impl SnarlViewer<Node> for NodeViewer {
-    #[inline]
-    fn connect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
-        // Validate connection
-        if snarl[from.id.node].outputs()[from.id.output] & snarl[to.id.node].inputs()[to.id.input] != 0 {
-            for &remote in &to.remotes {
-                let out_pin = snarl.out_pin(remote);
-                self.disconnect(&out_pin, to, snarl);
+    /// Hands the up axis of the app settings to the cameras and the skies of raytracer renders.
+    pub fn set_up_axis(&mut self, snarl: &mut Snarl<Node>, up_axis: UpAxis) {
+        for node in snarl.nodes_mut() {
+            match node {
+                Node::Camera(camera_node) => camera_node.set_up_axis(up_axis),
+                Node::Render(RenderNode::Raytracer(render)) => render.up_axis = up_axis,
+                _ => {},
             }
+        }
+    }
 
-            snarl.connect(from.id, to.id);
-            Node::send_msg(to.id.node, snarl, InputMessage::Connect { from, to });
-            // snarl[from.id.node].connect_output(from, to);
+    pub fn set_wire_labels(&mut self, enabled: bool) {
+        if !enabled {
+            self.output_positions.clear();
+        }
+        self.wire_labels = enabled;
+    }
 
-            if snarl[to.id.node].output_node_ref().is_some() {
-                if let Some(render_node) = snarl[from.id.node].render_node_ref() {
-                    render_node.register(&self.config.render_state);
-                    self.render = Some(from.id.node);
-                }
+    /// Paints the value flowing into `pin` halfway along each of its wires.
+    fn show_wire_labels(&self, pin: &InPin, ui: &Ui, snarl: &Snarl<Node>) {
+        let to = ui.min_rect().left_center();
+        let painter = ui.ctx().layer_painter(ui.layer_id());
+
+        for remote in &pin.remotes {
+            let (Some(from), Some(label)) = (self.output_positions.get(remote), snarl[remote.node].value_label())
+            else {
+                continue;
+            };
+
+            let galley = painter.layout_no_wrap(label, FontId::monospace(10.0), ui.visuals().strong_text_color());
+            let rect = Align2::CENTER_CENTER.anchor_size(from.lerp(to, 0.5), galley.size());
+            painter.rect_filled(rect.expand(2.0), 2.0, ui.visuals().extreme_bg_color);
+            painter.galley(rect.min, galley, Color32::PLACEHOLDER);
+        }
+    }
+
+    /// Swaps the whole graph, e.g. when undoing an edit, and registers the render node of the new one.
+    pub fn replace_snarl(&mut self, snarl: &mut Snarl<Node>, new_snarl: Snarl<Node>) {
+        for id in self.renders.clone() {
+            self.unregister_render(id, snarl);
+        }
+        self.output_positions.clear();
+        self.invalid_wires.clear();
+        *snarl = new_snarl;
+        relink_inputs(snarl);
+        SceneNode::restore_subscriptions(snarl);
+        self.renders = register_renders(snarl, &self.config.render_state);
+    }
+
+    /// A restored view that wasn't shown yet is returned as is, so it survives a session with hidden nodes.
+    pub fn graph_view(&self) -> TSTransform {
+        self.restored_graph_view.unwrap_or(self.graph_view)
+    }
+
+    pub fn restore_graph_view(&mut self, view: TSTransform) {
+        self.restored_graph_view = Some(view);
+    }
+
+    pub fn copy_nodes(&mut self, nodes: &[NodeId], snarl: &Snarl<Node>) {
+        if nodes.is_empty() {
+            return;
+        }
+
+        self.clipboard = nodes
+            .iter()
+            .filter_map(|&node_id| snarl.get_node_info(node_id))
+            .map(|info| (info.pos, info.value.detached()))
+            .collect();
+    }
+
+    /// Inserts the copied nodes with fresh ids and without wires. Every paste lands
+    /// a bit further away, so pasting repeatedly does not stack the nodes.
+    pub fn paste_nodes(&mut self, snarl: &mut Snarl<Node>) {
+        if self.clipboard.is_empty() {
+            return;
+        }
+
+        self.record_snapshot(snarl);
+        for (pos, node) in &mut self.clipboard {
+            *pos += PASTE_OFFSET;
+            snarl.insert_node(*pos, node.detached());
+        }
+    }
+
+    /// Removes the nodes together with their wires as a single undo step.
+    pub fn remove_nodes(&mut self, nodes: &[NodeId], snarl: &mut Snarl<Node>) {
+        for &node_id in nodes {
+            if snarl.get_node(node_id).is_some() {
+                self.remove_node(node_id, snarl);
             }
         }
     }
 
+    fn remove_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) {
+        self.record_snapshot(snarl);
+
+        if self.renders.contains(&node_id) {
+            self.unregister_render(node_id, snarl);
+        }
+
+        // Pins are looked up again after every disconnect, since dropping a wire
+        // into a collection shifts the wires of its following inputs.
+        for input in (0..snarl[node_id].inputs().len()).rev() {
+            let in_pin_id = InPinId { node: node_id, input };
+            while let Some(&out_pin_id) = snarl.in_pin(in_pin_id).remotes.first() {
+                self.disconnect(&snarl.out_pin(out_pin_id), &snarl.in_pin(in_pin_id), snarl);
+            }
+        }
+
+        for output in 0..snarl[node_id].outputs().len() {
+            let out_pin_id = OutPinId { node: node_id, output };
+            while let Some(&in_pin_id) = snarl.out_pin(out_pin_id).remotes.first() {
+                self.disconnect(&snarl.out_pin(out_pin_id), &snarl.in_pin(in_pin_id), snarl);
+            }
+        }
+
+        forget_invalid_wires(&mut self.invalid_wires, node_id);
+        snarl.remove_node(node_id);
+    }
+
+    fn duplicate_node(&mut self, node_id: NodeId, snarl: &mut Snarl<Node>) {
+        if let Some(info) = snarl.get_node_info(node_id) {
+            let (pos, node) = (info.pos + PASTE_OFFSET, info.value.detached());
+            self.record_snapshot(snarl);
+            snarl.insert_node(pos, node);
+        }
+    }
+
+    /// Adds a texture node for every image file dropped at `screen_pos`, each one offset from the previous.
+    pub fn insert_dropped_textures(&mut self, files: &[egui::DroppedFile], screen_pos: Pos2, snarl: &mut Snarl<Node>) {
+        let paths: Vec<_> = files
+            .iter()
+            .filter_map(|file| file.path.as_deref())
+            .filter(|path| is_image_path(path))
+            .collect();
+        if paths.len() < files.len() {
+            let skipped = files.len() - paths.len();
+            self.show_message(format!("Skipped {skipped} dropped files that aren't images"));
+        }
+        if paths.is_empty() {
+            return;
+        }
+
+        let (_, factory, ..) = Node::fabrics()
+            .into_iter()
+            .find(|(name, ..)| *name == TextureNode::NAME)
+            .expect("textures can be added from the graph menu");
+        self.record_snapshot(snarl);
+        let mut pos = self.graph_view().inverse() * screen_pos;
+        for path in paths {
+            let mut node = factory(&self.config);
+            node.as_texture_node_mut().path = image_path(path);
+            snarl.insert_node(pos, node);
+            pos += PASTE_OFFSET;
+        }
+    }
+
+    /// Returns the graph from before the edits made since the last call, if there were any.
+    pub fn take_snapshot(&mut self) -> Option<String> {
+        self.snapshot.take()
+    }
+
+    /// Remembers the graph before it gets edited. Only the first edit of a frame is recorded,
+    /// so nested callbacks like `connect` calling `disconnect` end up as a single undo step.
+    pub fn record_snapshot(&mut self, snarl: &Snarl<Node>) {
+        if self.snapshot.is_none() {
+            self.snapshot = serde_json::to_string(snarl).ok();
+        }
+    }
+
+    /// The same render can feed several outputs, it stays until the last one is disconnected.
+    fn forget_unused_render(&mut self, id: NodeId, snarl: &mut Snarl<Node>) {
+        if self.renders.contains(&id) && !feeds_output(id, snarl) {
+            self.unregister_render(id, snarl);
+        }
+    }
+
+    fn unregister_render(&mut self, id: NodeId, snarl: &mut Snarl<Node>) {
+        self.renders.retain(|&render| render != id);
+        if let Some(render_node) = snarl.get_node(id).and_then(Node::render_node_ref) {
+            render_node.unregister(id, &self.config.render_state);
+        }
+    }
+}
+
+impl SnarlViewer<Node> for NodeViewer {
     #[inline]
-    fn disconnect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
-        Node::send_msg(to.id.node, snarl, InputMessage::Disconnect { from, to });
-        snarl.disconnect(from.id, to.id);
+    fn connect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
+        match connection_refusal(snarl, from.id, to.id) {
+            Some(ConnectionRefusal::Cycle) => {
+                self.show_message(format!(
+                    "Connecting `{}` to `{}` would create a cycle",
+                    snarl[from.id.node].name(),
+                    snarl[to.id.node].name()
+                ));
+                return;
+            },
+            Some(ConnectionRefusal::Incompatible) => return,
+            None => (),
+        }
+
+        self.record_snapshot(snarl);
 
-        if self.render == Some(from.id.node) {
-            self.unregister_render(snarl);
+        for &remote in &to.remotes {
+            let out_pin = snarl.out_pin(remote);
+            self.disconnect(&out_pin, to, snarl);
         }
 
-        if let Some(collection_node) = snarl[to.id.node].collection_node_ref() {
-            // Reconnect rest of inputs
-            for input in (to.id.input + 1)..collection_node.inputs().len() {
-                let to_pin_id = InPinId {
-                    node: to.id.node,
-                    input,
-                };
-                let to_pin = snarl.in_pin(to_pin_id);
+        snarl.connect(from.id, to.id);
+        Node::send_msg(to.id.node, snarl, InputMessage::Connect { from, to });
+        // snarl[from.id.node].connect_output(from, to);
 
-                for from_pin_id in to_pin.remotes {
-                    snarl.disconnect(from_pin_id, to_pin_id);
-                    snarl.connect(from_pin_id, InPinId {
-                        node: to.id.node,
-                        input: input - 1,
-                    });
-                }
+        if snarl[to.id.node].output_node_ref().is_some() && !self.renders.contains(&from.id.node) {
+            if let Some(render_node) = snarl[from.id.node].render_node_mut() {
+                render_node.register(from.id.node, &self.config.render_state);
+                self.renders.push(from.id.node);
             }
         }
     }
 
+    #[inline]
+    fn disconnect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
+        self.record_snapshot(snarl);
+        self.invalid_wires.remove(&(from.id, to.id));
+        detach_wire(from, to, snarl);
+        self.forget_unused_render(from.id.node, snarl);
+    }
+
+    /// Called when all wires of an input are dropped at once. Each of them goes through the disconnect
+    /// handler of the node, so it resets its value and notifies its subscribers.
     #[inline]
     fn drop_inputs(&mut self, pin: &InPin, snarl: &mut Snarl<Node>) {
-        println!("Dropping input");
-        // FIXME: where is this called?
-        // snarl[pin.id.node].disconnect_input(pin);
-        snarl.drop_inputs(pin.id);
+        self.record_snapshot(snarl);
+        for from in drop_input_wires(pin, snarl) {
+            self.invalid_wires.remove(&(from, pin.id));
+            self.forget_unused_render(from.node, snarl);
+        }
+    }
+
+    fn current_transform(&mut self, to_global: &mut TSTransform, _snarl: &mut Snarl<Node>) {
+        if let Some(view) = self.restored_graph_view.take() {
+            *to_global = view;
+        }
+        self.graph_view = *to_global;
     }
 
     fn title(&mut self, node: &Node) -> String {
-        node.name().to_owned()
+        match node {
+            Node::Collection(collection) if !collection.label.is_empty() => collection.label.clone(),
+            node => node.name().to_owned(),
+        }
     }
 
     fn inputs(&mut self, node: &Node) -> usize {
@@ -168,18 +628,50 @@ impl SnarlViewer<Node> for NodeViewer {
 
     #[allow(refining_impl_trait)]
     fn show_input(&mut self, pin: &InPin, ui: &mut Ui, snarl: &mut Snarl<Node>) -> PinInfo {
-        let response = Node::send_msg(pin.id.node, snarl, InputMessage::Show { pin, ui });
-        match response {
+        // Wires loaded from a file or left behind when a node changed its outputs can join pins of different
+        // types, the node doesn't get to see them.
+        if let Some(remote) = invalid_wire(snarl, pin.id, &mut self.invalid_wires) {
+            return invalid_input_view(ui, snarl[remote.node].name());
+        }
+
+        // A collapsed node still shows its inputs into a hidden child, pins take their remote values when shown.
+        let collapsed = snarl[pin.id.node].collapsed_mut().is_some_and(|collapsed| *collapsed);
+        let mut hidden_ui = collapsed.then(|| ui.new_child(egui::UiBuilder::new().invisible()));
+        let input_ui = match &mut hidden_ui {
+            Some(hidden_ui) => hidden_ui,
+            None => &mut *ui,
+        };
+
+        let response = Node::send_msg(pin.id.node, snarl, InputMessage::Show { pin, ui: input_ui });
+        let pin_info = match response {
             Some(CommonNodeResponse::Input(InputResponse::Info(pin_info))) => pin_info,
             _ => unreachable!("{} node has no inputs", snarl[pin.id.node].name()),
+        };
+
+        if self.wire_labels {
+            self.show_wire_labels(pin, ui, snarl);
         }
+
+        pin_info
     }
 
     #[allow(refining_impl_trait)]
     fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, snarl: &mut Snarl<Node>) -> PinInfo {
-        match &mut snarl[pin.id.node] {
-            Node::Material(_) => PinInfo::circle().with_fill(MATERIAL_COLOR),
+        let connections = output_connections(pin.id.node, snarl);
+        let pin_info = match &mut snarl[pin.id.node] {
+            Node::Material(material) => {
+                material.show_preview(ui);
+                if connections > 0 {
+                    ui.label(egui::RichText::new(format!("×{connections}")).small().weak())
+                        .on_hover_text(format!("Used by {connections} input(s)"));
+                }
+                PinInfo::circle().with_fill(MATERIAL_COLOR)
+            },
             Node::Texture(_) => TextureNode::show_output(SelfNodeMut::new(pin.id.node, snarl), pin, ui),
+            Node::Primitive(PrimitiveNode::Csg(_)) => {
+                CsgNode::show_output(SelfNodeMut::new(pin.id.node, snarl), pin, ui)
+            },
+            Node::NoiseTexture(_) => NoiseTextureNode::show_output(SelfNodeMut::new(pin.id.node, snarl), pin, ui),
             Node::Output(_) => {
                 unreachable!("Output node has no outputs")
             },
@@ -188,6 +680,16 @@ impl SnarlViewer<Node> for NodeViewer {
                 ui.add(egui::DragValue::new(value));
                 PinInfo::circle().with_fill(NUMBER_COLOR)
             },
+            Node::Integer(value) => {
+                assert_eq!(pin.id.output, 0, "Integer node has only one output");
+                ui.add(egui::DragValue::new(value).speed(1.0));
+                PinInfo::circle().with_fill(NUMBER_COLOR)
+            },
+            Node::Bool(value) => {
+                assert_eq!(pin.id.output, 0, "Bool node has only one output");
+                ui.checkbox(value, "");
+                PinInfo::circle().with_fill(NUMBER_COLOR)
+            },
             Node::String(value) => {
                 assert_eq!(pin.id.output, 0, "String node has only one output");
                 let edit = egui::TextEdit::singleline(value)
@@ -204,6 +706,16 @@ impl SnarlViewer<Node> for NodeViewer {
                 color_edit_button_srgba(ui, value, Alpha::BlendOrAdditive);
                 PinInfo::circle().with_fill(VECTOR_COLOR)
             },
+            Node::ColorMix(color_mix) => {
+                assert_eq!(pin.id.output, 0, "Color mix node has only one output");
+                color_button(ui, color_mix.color_out(), false);
+                PinInfo::circle().with_fill(VECTOR_COLOR)
+            },
+            Node::HsvColor(hsv_color) => {
+                assert_eq!(pin.id.output, 0, "HSV color node has only one output");
+                color_button(ui, hsv_color.color_out(), false);
+                PinInfo::circle().with_fill(VECTOR_COLOR)
+            },
             Node::Vector(vector) => {
                 assert_eq!(pin.id.output, 0, "Number node has only one output");
                 ui.add(egui::DragValue::new(&mut vector[0]));
@@ -212,13 +724,41 @@ impl SnarlViewer<Node> for NodeViewer {
                 PinInfo::circle().with_fill(VECTOR_COLOR)
             },
             Node::Expression(expr_node) => {
-                let value = expr_node.eval();
                 assert_eq!(pin.id.output, 0, "Expr node has only one output");
-                ui.label(format_float(value));
+                let (text, color) = if expr_node.is_vector() {
+                    (expr_node.eval_vector().map(format_vector), VECTOR_COLOR)
+                } else {
+                    (expr_node.eval().map(format_float), NUMBER_COLOR)
+                };
+                match text {
+                    Ok(text) => ui.label(text),
+                    Err(err) => ui.colored_label(ui.visuals().error_fg_color, "err").on_hover_text(err.to_string()),
+                };
+                PinInfo::circle().with_fill(color)
+            },
+            Node::Curve(curve) => {
+                assert_eq!(pin.id.output, 0, "Curve node has only one output");
+                ui.label(format_float(curve.number_out()));
                 PinInfo::circle().with_fill(NUMBER_COLOR)
             },
+            Node::VectorMath(vector_math) => {
+                assert_eq!(pin.id.output, 0, "Vector math node has only one output");
+                if vector_math.op.outputs_number() {
+                    ui.label(format_float(vector_math.number_out()));
+                    PinInfo::circle().with_fill(NUMBER_COLOR)
+                } else {
+                    ui.label(format_vector(vector_math.vector_out()));
+                    PinInfo::circle().with_fill(VECTOR_COLOR)
+                }
+            },
             _ => PinInfo::circle().with_fill(UNTYPED_COLOR),
+        };
+
+        if self.wire_labels {
+            self.output_positions.insert(pin.id, ui.min_rect().right_center());
         }
+
+        pin_info
     }
 
     fn has_graph_menu(&mut self, _pos: egui::Pos2, _snarl: &mut Snarl<Node>) -> bool {
@@ -227,10 +767,36 @@ impl SnarlViewer<Node> for NodeViewer {
 
     fn show_graph_menu(&mut self, pos: egui::Pos2, ui: &mut Ui, snarl: &mut Snarl<Node>) {
         ui.label("Add node");
-        for (name, factory, ..) in Node::fabrics() {
-            if ui.button(name).clicked() {
-                snarl.insert_node(pos, factory(&self.config));
-                ui.close_menu();
+        ui.add(egui::TextEdit::singleline(&mut self.graph_menu_filter).hint_text("Search..."))
+            .request_focus();
+
+        let fabrics = Node::fabrics()
+            .into_iter()
+            .filter(|(name, ..)| fuzzy_match(&self.graph_menu_filter, name))
+            .collect::<Vec<_>>();
+
+        if fabrics.is_empty() {
+            ui.weak("No matching nodes");
+        }
+
+        for category in NODE_CATEGORIES {
+            let mut fabrics = fabrics
+                .iter()
+                .filter(|(name, ..)| node_category(name) == category)
+                .peekable();
+            if fabrics.peek().is_none() {
+                continue;
+            }
+
+            ui.separator();
+            ui.weak(category);
+            for (name, factory, ..) in fabrics {
+                if ui.button(*name).clicked() {
+                    self.record_snapshot(snarl);
+                    snarl.insert_node(pos, factory(&self.config));
+                    self.graph_menu_filter.clear();
+                    ui.close_menu();
+                }
             }
         }
     }
@@ -248,12 +814,14 @@ impl SnarlViewer<Node> for NodeViewer {
                     let dst_in_candidates = Node::fabrics().into_iter().filter_map(|(name, factory, inputs, _)| {
                         inputs
                             .iter()
-                            .position(|input| *input & src_out != 0)
+                            .position(|&input| can_connect(src_out, input))
                             .map(|idx| (name, factory, idx))
                     });
 
                     for (name, factory, idx) in dst_in_candidates {
                         if ui.button(name).clicked() {
+                            self.record_snapshot(snarl);
+
                             // Create new node.
                             let node = snarl.insert_node(pos, factory(&self.config));
 
@@ -276,12 +844,14 @@ impl SnarlViewer<Node> for NodeViewer {
                     let dst_out_candidates = Node::fabrics().into_iter().filter_map(|(name, factory, _, outputs)| {
                         outputs
                             .iter()
-                            .position(|output| *output & src_in != 0)
+                            .position(|&output| can_connect(output, src_in))
                             .map(|idx| (name, factory, idx))
                     });
 
                     for (name, factory, idx) in dst_out_candidates {
                         if ui.button(name).clicked() {
+                            self.record_snapshot(snarl);
+
                             // Create new node.
                             let node = snarl.insert_node(pos, factory(&self.config));
 
@@ -301,6 +871,64 @@ impl SnarlViewer<Node> for NodeViewer {
         };
     }
 
+    fn has_body(&mut self, node: &Node) -> bool {
+        matches!(node, Node::Curve(curve) if !curve.collapsed)
+    }
+
+    fn show_body(
+        &mut self,
+        node_id: NodeId,
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
+        ui: &mut Ui,
+        snarl: &mut Snarl<Node>,
+    ) {
+        let Node::Curve(curve) = &mut snarl[node_id] else {
+            return;
+        };
+        if curve.collapsed {
+            return;
+        }
+
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt(("curve_interpolation", node_id))
+                    .selected_text(curve.interpolation.name())
+                    .show_ui(ui, |ui| {
+                        for interpolation in CurveInterpolation::ALL {
+                            ui.selectable_value(&mut curve.interpolation, interpolation, interpolation.name());
+                        }
+                    });
+                ui.checkbox(&mut curve.looped, "Loop");
+            });
+
+            let mut remove = None;
+            egui::Grid::new(("curve_keyframes", node_id)).show(ui, |ui| {
+                ui.weak("Time");
+                ui.weak("Value");
+                ui.end_row();
+
+                curve.edit_keyframes(|keyframes| {
+                    for (idx, keyframe) in keyframes.iter_mut().enumerate() {
+                        ui.add(egui::DragValue::new(&mut keyframe.time).speed(0.01).suffix(" s"));
+                        ui.add(egui::DragValue::new(&mut keyframe.value).speed(0.01));
+                        if ui.button("Remove").clicked() {
+                            remove = Some(idx);
+                        }
+                        ui.end_row();
+                    }
+                    if let Some(idx) = remove {
+                        keyframes.remove(idx);
+                    }
+                });
+            });
+
+            if ui.button("Add key").clicked() {
+                curve.add_keyframe();
+            }
+        });
+    }
+
     fn has_node_menu(&mut self, _node: &Node) -> bool {
         true
     }
@@ -308,33 +936,226 @@ impl SnarlViewer<Node> for NodeViewer {
     fn show_node_menu(
         &mut self,
         node_id: NodeId,
-        inputs: &[InPin],
-        outputs: &[OutPin],
+        _inputs: &[InPin],
+        _outputs: &[OutPin],
         ui: &mut Ui,
         snarl: &mut Snarl<Node>,
     ) {
         ui.label("Node menu");
-        if ui.button("Remove").clicked() {
-            if self.render == Some(node_id) {
-                self.unregister_render(snarl);
+        if let Node::Render(RenderNode::Raytracer(render)) = &mut snarl[node_id] {
+            ui.checkbox(&mut render.highlight_backfaces, "Highlight back faces");
+            ui.checkbox(&mut render.bilinear_filtering, "Bilinear texture filtering");
+            ui.checkbox(&mut render.exact_fresnel, "Exact Fresnel for glass");
+            ui.checkbox(&mut render.denoise, "Denoise");
+            ui.checkbox(&mut render.adaptive, "Adaptive sampling");
+            if render.adaptive {
+                ui.horizontal(|ui| {
+                    ui.label("Noise threshold");
+                    ui.add(
+                        egui::DragValue::new(&mut render.variance_threshold)
+                            .range(SamplingParams::VARIANCE_THRESHOLD_RANGE)
+                            .speed(0.001),
+                    );
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("Sample pattern");
+                egui::ComboBox::from_id_salt(("aa_pattern", node_id))
+                    .selected_text(render.aa_pattern.name())
+                    .show_ui(ui, |ui| {
+                        for pattern in AaPattern::ALL {
+                            ui.selectable_value(&mut render.aa_pattern, pattern, pattern.name());
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Output");
+                egui::ComboBox::from_id_salt(("output_channel", node_id))
+                    .selected_text(render.output_channel.name())
+                    .show_ui(ui, |ui| {
+                        for channel in OutputChannel::ALL {
+                            ui.selectable_value(&mut render.output_channel, channel, channel.name());
+                        }
+                    });
+            });
+            ui.checkbox(&mut render.paused, "Pause rendering");
+            if ui.button("Restart render").clicked() {
+                render.restart = true;
             }
 
-            for in_pin in inputs {
-                for out_pin_id in &in_pin.remotes {
-                    let out_pin = snarl.out_pin(*out_pin_id);
-                    self.disconnect(&out_pin, &in_pin, snarl);
+            let exposure = &mut render.exposure;
+            ui.checkbox(&mut exposure.auto, "Auto exposure");
+            ui.horizontal(|ui| {
+                if exposure.auto {
+                    ui.label("Key value");
+                    ui.add(
+                        egui::DragValue::new(&mut exposure.key_value)
+                            .range(ExposureParams::KEY_VALUE_RANGE)
+                            .speed(0.005),
+                    );
+                } else {
+                    ui.label("Exposure");
+                    ui.add(egui::DragValue::new(&mut exposure.exposure).speed(0.05).suffix(" EV"));
                 }
-            }
+            });
+
+            let bloom = &mut render.bloom;
+            ui.horizontal(|ui| {
+                ui.label("Bloom threshold");
+                ui.add(
+                    egui::DragValue::new(&mut bloom.threshold)
+                        .range(BloomParams::THRESHOLD_RANGE)
+                        .speed(0.01),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Bloom intensity");
+                ui.add(
+                    egui::DragValue::new(&mut bloom.intensity)
+                        .range(BloomParams::INTENSITY_RANGE)
+                        .speed(0.005),
+                );
+            });
 
-            for out_pin in outputs {
-                for in_pin_id in &out_pin.remotes {
-                    let in_pin = snarl.in_pin(*in_pin_id);
-                    self.disconnect(out_pin, &in_pin, snarl);
+            let resolution = &mut render.locked_resolution;
+            ui.checkbox(&mut resolution.enabled, "Lock resolution");
+            ui.add_enabled_ui(resolution.enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut resolution.width).range(1..=u32::MAX).suffix(" px"));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut resolution.height).range(1..=u32::MAX).suffix(" px"));
+                });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Resolution scale");
+                ui.add(
+                    egui::DragValue::new(&mut render.resolution_scale)
+                        .range(SamplingParams::RESOLUTION_SCALE_RANGE)
+                        .speed(0.01),
+                );
+            });
+            ui.checkbox(&mut render.scale_while_moving, "Only while the camera moves");
+
+            ui.checkbox(&mut render.sun_enabled, "Sun disk");
+            ui.add_enabled_ui(render.sun_enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Sun radius");
+                    ui.add(
+                        egui::DragValue::new(render.sun_angular_radius.as_mut())
+                            .range(SkyParams::SUN_ANGULAR_RADIUS_RANGE)
+                            .speed(0.01)
+                            .suffix("°"),
+                    );
+                });
+            });
+            ui.separator();
+        }
+        if let Node::Output(output) = &mut snarl[node_id] {
+            ui.checkbox(&mut output.fixed_resolution, "Fixed resolution");
+            ui.separator();
+        }
+        if snarl[node_id].camera_node_ref().is_some() {
+            let spheres = snarl
+                .node_ids()
+                .filter_map(|(sphere_id, node)| {
+                    let sphere = node.primitive_node_ref()?.sphere_ref()?;
+                    Some((sphere_id, sphere.center.get(), sphere.radius.get()))
+                })
+                .collect::<Vec<_>>();
+
+            ui.add_enabled_ui(!spheres.is_empty(), |ui| {
+                ui.menu_button("Frame sphere", |ui| {
+                    for (sphere_id, center, radius) in spheres {
+                        if ui.button(format!("{} {}", SphereNode::NAME, sphere_id.0)).clicked() {
+                            self.record_snapshot(snarl);
+                            snarl[node_id].as_camera_node_mut().frame_sphere(center, radius);
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            ui.menu_button("Bookmarks", |ui| {
+                if ui.button("Save view").clicked() {
+                    self.record_snapshot(snarl);
+                    snarl[node_id].as_camera_node_mut().capture_bookmark();
                 }
-            }
+                let camera = snarl[node_id].as_camera_node_mut();
+                ui.checkbox(&mut camera.interpolate_bookmarks, "Interpolate");
+                ui.separator();
 
-            snarl.remove_node(node_id);
+                let mut recalled = None;
+                let mut removed = None;
+                for (idx, bookmark) in camera.bookmarks.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button("Go").clicked() {
+                            recalled = Some(idx);
+                        }
+                        ui.add(egui::TextEdit::singleline(&mut bookmark.name).desired_width(100.0));
+                        if ui.button("Remove").clicked() {
+                            removed = Some(idx);
+                        }
+                    });
+                }
 
+                if recalled.is_some() || removed.is_some() {
+                    self.record_snapshot(snarl);
+                }
+                let camera = snarl[node_id].as_camera_node_mut();
+                if let Some(idx) = recalled {
+                    camera.recall_bookmark(idx);
+                }
+                if let Some(idx) = removed {
+                    camera.bookmarks.remove(idx);
+                }
+            });
+            ui.separator();
+        }
+        if let Some(collection) = snarl[node_id].collection_node_ref() {
+            let member_names = collection
+                .to_node_ids()
+                .into_iter()
+                .map(|member| snarl[member].name())
+                .collect::<Vec<_>>();
+            let collection = snarl[node_id].as_collection_node_mut();
+
+            ui.horizontal(|ui| {
+                ui.label("Label");
+                ui.add(egui::TextEdit::singleline(&mut collection.label).hint_text(CollectionNode::NAME));
+            });
+            let slots = collection.slot_names_mut().iter_mut().zip(member_names);
+            for (idx, (slot_name, member_name)) in slots.enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}", idx + 1));
+                    ui.add(egui::TextEdit::singleline(slot_name).hint_text(member_name));
+                });
+            }
+            ui.separator();
+        }
+        if let Node::Primitive(PrimitiveNode::Sphere(sphere)) = &mut snarl[node_id] {
+            let light_changed = ui.checkbox(&mut sphere.is_light, "Light").changed();
+            let clip_changed = ui.checkbox(&mut sphere.clip_open, "Open clipped side").changed();
+            if light_changed || clip_changed {
+                SceneNode::invalidate_tracking(snarl, node_id);
+            }
+            ui.separator();
+        }
+        if let Some(disabled) = snarl[node_id].disabled_mut() {
+            if ui.checkbox(disabled, "Disabled").changed() {
+                SceneNode::invalidate_tracking(snarl, node_id);
+            }
+            ui.separator();
+        }
+        if let Some(collapsed) = snarl[node_id].collapsed_mut() {
+            ui.checkbox(collapsed, "Collapsed");
+            ui.separator();
+        }
+        if ui.button("Duplicate").clicked() {
+            self.duplicate_node(node_id, snarl);
+            ui.close_menu();
+        }
+        if ui.button("Remove").clicked() {
+            self.remove_node(node_id, snarl);
             ui.close_menu();
         }
     }
@@ -355,14 +1176,35 @@ impl SnarlViewer<Node> for NodeViewer {
             Node::Output(_) => {
                 ui.label("Displays anything connected to it");
             },
+            Node::Primitive(PrimitiveNode::Csg(_)) => {
+                ui.label("Combines two spheres, which are then only drawn as part of the combination");
+            },
+            Node::Primitive(PrimitiveNode::Transform(_)) => {
+                ui.label("Translates, rotates and scales every sphere of the connected primitive or collection");
+            },
+            Node::Primitive(PrimitiveNode::Instance(_)) => {
+                ui.label("Repeats the connected primitive or collection, each copy one step further than the last");
+            },
             Node::Number(_) => {
-                ui.label("Outputs integer value");
+                ui.label("Outputs number value");
+            },
+            Node::Integer(_) => {
+                ui.label("Outputs integer value, number inputs take it as is");
+            },
+            Node::Bool(_) => {
+                ui.label("Outputs boolean value, number inputs take it as 0 or 1");
             },
             Node::String(_) => {
                 ui.label("Outputs string value");
             },
-            Node::Expression(_) => {
+            Node::Expression(ref expr_node) => {
                 ui.label("Evaluates algebraic expression with input for each unique variable name");
+                ui.label(format!("`{}` is the time in seconds", ExpressionNode::TIME));
+                ui.separator();
+                expression_status(ui, expr_node);
+            },
+            Node::Curve(_) => {
+                ui.label("Outputs number interpolated between keyframes at the current time in seconds");
             },
             _ => {
                 ui.label("<No description available>");
@@ -378,28 +1220,327 @@ impl SnarlViewer<Node> for NodeViewer {
         _outputs: &[OutPin],
         snarl: &Snarl<Node>,
     ) -> egui::Frame {
-        match snarl[node] {
-            Node::Output(_) => frame.fill(egui::Color32::from_rgb(70, 70, 80)),
-            Node::Number(_) => frame.fill(egui::Color32::from_rgb(70, 40, 40)),
-            Node::String(_) => frame.fill(egui::Color32::from_rgb(40, 70, 40)),
-            Node::Expression(_) => frame.fill(egui::Color32::from_rgb(70, 66, 40)),
-            _ => frame.fill(egui::Color32::from_rgb(40, 40, 70)),
+        let fill = match snarl[node] {
+            Node::Output(_) => egui::Color32::from_rgb(70, 70, 80),
+            Node::Number(_) | Node::Integer(_) | Node::Bool(_) => egui::Color32::from_rgb(70, 40, 40),
+            Node::String(_) => egui::Color32::from_rgb(40, 70, 40),
+            Node::Expression(_) | Node::Curve(_) => egui::Color32::from_rgb(70, 66, 40),
+            _ => egui::Color32::from_rgb(40, 40, 70),
+        };
+
+        if snarl[node].is_disabled() {
+            frame.fill(fill.gamma_multiply(0.4))
+        } else {
+            frame.fill(fill)
         }
     }
 }
 
+/// Why the expression has the inputs it has: the text is only applied when it parses, so the variables can be
+/// those of the last valid text.
+fn expression_status(ui: &mut Ui, expr_node: &ExpressionNode) {
+    let error_color = ui.visuals().error_fg_color;
+    if expr_node.text_parses() {
+        ui.label("Valid expression");
+    } else {
+        ui.colored_label(error_color, "Invalid expression, the last valid one is used");
+    }
+
+    if expr_node.bindings.is_empty() {
+        ui.label("No variables");
+    } else {
+        let variables = expr_node
+            .bindings
+            .iter()
+            .zip(&expr_node.values)
+            .map(|(name, &value)| format!("`{name}` = {}", format_float(value)))
+            .collect::<Vec<_>>();
+        ui.label(format!("Variables: {}", variables.join(", ")));
+    }
+
+    let result = if expr_node.is_vector() {
+        expr_node.eval_vector().map(format_vector)
+    } else {
+        expr_node.eval().map(format_float)
+    };
+    match result {
+        Ok(text) => ui.label(format!("Result: {text}")),
+        Err(err) => ui.colored_label(error_color, format!("Result: {err}")),
+    };
+}
+
+/// The output type of the wire's source is one the input accepts. Pins that don't exist anymore take nothing.
+fn wire_is_valid(snarl: &Snarl<Node>, from: OutPinId, to: InPinId) -> bool {
+    snarl[to.node].can_accept(to.input, &snarl[from.node], from.output)
+}
+
+/// Lets the node of `to` handle the disconnect before the wire is removed. Dropping a wire into a collection
+/// removes its input, the wires of the following inputs move up by one.
+fn detach_wire(from: &OutPin, to: &InPin, snarl: &mut Snarl<Node>) {
+    Node::send_msg(to.id.node, snarl, InputMessage::Disconnect { from, to });
+    snarl.disconnect(from.id, to.id);
+
+    if let Some(collection_node) = snarl[to.id.node].collection_node_ref() {
+        for input in (to.id.input + 1)..collection_node.inputs().len() {
+            let to_pin_id = InPinId {
+                node: to.id.node,
+                input,
+            };
+            let to_pin = snarl.in_pin(to_pin_id);
+
+            for from_pin_id in to_pin.remotes {
+                snarl.disconnect(from_pin_id, to_pin_id);
+                snarl.connect(from_pin_id, InPinId {
+                    node: to.id.node,
+                    input: input - 1,
+                });
+            }
+        }
+    }
+}
+
+/// Detaches every wire into `pin` and returns their sources.
+fn drop_input_wires(pin: &InPin, snarl: &mut Snarl<Node>) -> Vec<OutPinId> {
+    for &remote in &pin.remotes {
+        detach_wire(&snarl.out_pin(remote), pin, snarl);
+    }
+    pin.remotes.clone()
+}
+
+/// The source of a wire into `to` that the input can't take. Each such wire is logged once, `reported` keeps
+/// the ones already logged.
+fn invalid_wire(snarl: &Snarl<Node>, to: InPinId, reported: &mut HashSet<(OutPinId, InPinId)>) -> Option<OutPinId> {
+    let from = *snarl
+        .in_pin(to)
+        .remotes
+        .iter()
+        .find(|&&from| !wire_is_valid(snarl, from, to))?;
+    if reported.insert((from, to)) {
+        eprintln!(
+            "Input {} of `{}` can't take a value from `{}`, the wire is ignored",
+            to.input,
+            snarl[to.node].name(),
+            snarl[from.node].name(),
+        );
+    }
+    Some(from)
+}
+
+/// Drops the reported wires of a removed node, its id can be taken by a later node.
+fn forget_invalid_wires(reported: &mut HashSet<(OutPinId, InPinId)>, node_id: NodeId) {
+    reported.retain(|(from, to)| from.node != node_id && to.node != node_id);
+}
+
+/// `from` is downstream of `to`, or the same node, so a wire from `from` into `to` would close a loop that the
+/// traversals of the graph, like [`super::collect_for_node`], don't expect.
+fn creates_cycle(snarl: &Snarl<Node>, from: NodeId, to: NodeId) -> bool {
+    let mut stack = vec![to];
+    let mut visited = HashSet::new();
+    while let Some(node) = stack.pop() {
+        if node == from {
+            return true;
+        }
+        if visited.insert(node) {
+            stack.extend(
+                snarl
+                    .wires()
+                    .filter(|(out_pin, _)| out_pin.node == node)
+                    .map(|(_, in_pin)| in_pin.node),
+            );
+        }
+    }
+    false
+}
+
+#[derive(Debug, PartialEq)]
+enum ConnectionRefusal {
+    Cycle,
+    Incompatible,
+}
+
+/// Why [`NodeViewer::connect`] leaves a wire from `from` into `to` out of the graph, `None` if it adds it.
+fn connection_refusal(snarl: &Snarl<Node>, from: OutPinId, to: InPinId) -> Option<ConnectionRefusal> {
+    if creates_cycle(snarl, from.node, to.node) {
+        Some(ConnectionRefusal::Cycle)
+    } else if !wire_is_valid(snarl, from, to) {
+        Some(ConnectionRefusal::Incompatible)
+    } else {
+        None
+    }
+}
+
+/// One of [`NODE_CATEGORIES`] for the node with the given name.
+fn node_category(name: &str) -> &'static str {
+    match name {
+        MetalNode::NAME
+        | DielectricNode::NAME
+        | LambertianNode::NAME
+        | EmissiveNode::NAME
+        | CheckerboardNode::NAME
+        | IsotropicNode::NAME
+        | SubsurfaceNode::NAME
+        | MixMaterialNode::NAME
+        | TextureNode::NAME
+        | NoiseTextureNode::NAME => "Materials",
+        SphereNode::NAME
+        | CsgNode::NAME
+        | TransformNode::NAME
+        | InstanceNode::NAME
+        | PointLightNode::NAME
+        | DirectionalLightNode::NAME
+        | SpotLightNode::NAME
+        | CollectionNode::NAME => "Primitives",
+        CameraNode::NAME
+        | BackgroundNode::NAME
+        | SceneNode::NAME
+        | TriangleRenderNode::NAME
+        | RaytracerRenderNode::NAME
+        | OutputNode::NAME => "Render",
+        _ => "Data",
+    }
+}
+
+/// Characters of `query` appear in `name` in the same order, ignoring case and spaces.
+fn fuzzy_match(query: &str, name: &str) -> bool {
+    let mut name_chars = name.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .all(|query_char| name_chars.any(|name_char| name_char == query_char))
+}
+
+/// Registers every render node connected to an output node.
+fn register_renders(snarl: &mut Snarl<Node>, render_state: &RenderState) -> Vec<NodeId> {
+    let mut renders = Vec::new();
+    for (from_pin, to_pin) in snarl.wires() {
+        let is_render = snarl[from_pin.node].render_node_ref().is_some();
+        if is_render && snarl[to_pin.node].output_node_ref().is_some() && !renders.contains(&from_pin.node) {
+            renders.push(from_pin.node);
+        }
+    }
+
+    for &id in &renders {
+        if let Some(render_node) = snarl[id].render_node_mut() {
+            render_node.register(id, render_state);
+        }
+    }
+
+    renders
+}
+
+/// Renders and scenes keep the ids of the camera, scene and scene data nodes they are wired to. In a loaded
+/// graph these are taken from the wires again, links without a wire are kept only while they still point at a
+/// node of the expected kind.
+fn relink_inputs(snarl: &mut Snarl<Node>) {
+    let wires = snarl.wires().collect::<Vec<_>>();
+    let wired = |node_id: NodeId, input: usize| {
+        wires
+            .iter()
+            .find(|(_, to_pin)| to_pin.node == node_id && to_pin.input == input)
+            .map(|(from_pin, _)| from_pin.node)
+    };
+    let checked = |snarl: &Snarl<Node>, link: Option<NodeId>, expected: fn(&Node) -> bool| {
+        let valid = link.filter(|&id| snarl.get_node(id).is_some_and(expected));
+        if let Some(id) = link.filter(|_| valid.is_none()) {
+            eprintln!("Dropped the link to node {}, it is missing or of the wrong kind", id.0);
+        }
+        valid
+    };
+
+    let node_ids = snarl.node_ids().map(|(node_id, _)| node_id).collect::<Vec<_>>();
+    for node_id in node_ids {
+        match &snarl[node_id] {
+            Node::Render(RenderNode::Raytracer(render)) => {
+                let camera = wired(node_id, 3).or(render.camera_id());
+                let camera = checked(snarl, camera, |node| matches!(node, Node::Camera(_)));
+                let scene = wired(node_id, 4).or(render.scene_id());
+                let scene = checked(snarl, scene, |node| matches!(node, Node::Scene(_)));
+                if let Node::Render(RenderNode::Raytracer(render)) = &mut snarl[node_id] {
+                    render.relink(camera, scene);
+                }
+            },
+            Node::Scene(scene_node) => {
+                let data = wired(node_id, 0).or(scene_node.data.get());
+                let data = checked(snarl, data, |node| {
+                    matches!(node, Node::Primitive(_) | Node::Light(_) | Node::Collection(_))
+                });
+                snarl[node_id].as_scene_node_mut().data.set(data);
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Number of inputs wired to any of the node's outputs.
+pub fn output_connections(node_id: NodeId, snarl: &Snarl<Node>) -> usize {
+    snarl.wires().filter(|(from_pin, _)| from_pin.node == node_id).count()
+}
+
+/// Whether the node is still wired to any output node.
+fn feeds_output(node_id: NodeId, snarl: &Snarl<Node>) -> bool {
+    snarl
+        .wires()
+        .any(|(from_pin, to_pin)| from_pin.node == node_id && snarl[to_pin.node].output_node_ref().is_some())
+}
+
+/// Fixed resolution of the first output fed by the node that has one.
+fn output_resolution(node_id: NodeId, snarl: &Snarl<Node>) -> Option<LockedResolution> {
+    snarl
+        .wires()
+        .filter(|(from_pin, _)| from_pin.node == node_id)
+        .filter_map(|(_, to_pin)| snarl[to_pin.node].output_node_ref())
+        .map(OutputNode::resolution)
+        .find(|resolution| resolution.enabled)
+}
+
+/// Splits the viewport into a grid with a tile for each of `count` renders, filled row by row.
+fn render_tiles(viewport: egui::Rect, count: usize) -> Vec<egui::Rect> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let columns = (count as f32).sqrt().ceil() as usize;
+    let rows = count.div_ceil(columns);
+    let size = egui::vec2(viewport.width() / columns as f32, viewport.height() / rows as f32);
+
+    (0..count)
+        .map(|idx| {
+            let offset = egui::vec2((idx % columns) as f32 * size.x, (idx / columns) as f32 * size.y);
+            egui::Rect::from_min_size(viewport.min + offset, size)
+        })
+        .collect()
+}
+
 pub fn format_float(value: f64) -> String {
     let value = (value * 1000.0).round() / 1000.0;
     format!("{value}")
 }
 
+pub fn format_vector(vector: Vector3) -> String {
+    format!(
+        "({}, {}, {})",
+        format_float(vector.x),
+        format_float(vector.y),
+        format_float(vector.z)
+    )
+}
+
+/// A failed expression evaluation yields `None`, which leaves the pin's last valid value in place.
 pub fn number_input_remote_value(pin: &InPin, snarl: &Snarl<Node>, label: &str) -> Option<(&'static str, f64)> {
     match &*pin.remotes {
         [] => None,
         [remote] => Some(match &snarl[remote.node] {
             Node::Number(value) => (Node::NUMBER_NAME, *value),
-            Node::Expression(expr) => (ExpressionNode::NAME, expr.eval()),
-            node => unreachable!("{label} input not suppor connection with `{}`", node.name()),
+            Node::Integer(value) => (Node::INTEGER_NAME, *value as f64),
+            Node::Bool(value) => (Node::BOOL_NAME, f64::from(u8::from(*value))),
+            Node::Expression(expr) => (ExpressionNode::NAME, expr.eval().ok()?),
+            Node::Curve(curve) => (CurveNode::NAME, curve.number_out()),
+            Node::VectorMath(vector_math) => (vector_math.name(), vector_math.number_out()),
+            node => {
+                invalid_connection(label, node);
+                return None;
+            },
         }),
         _ => None,
     }
@@ -411,11 +1552,63 @@ pub fn number_input_view<N>(
     node_pin: &mut NodePin<N>,
     remote_value: Option<(&'static str, N)>,
 ) -> PinInfo
+where
+    N: Numeric,
+{
+    number_input_view_in_range(ui, label, node_pin, remote_value, N::MIN..=N::MAX)
+}
+
+/// Same as [`number_input_view`], but the editable value can't leave `range`.
+/// Values coming from a remote node are shown as is.
+pub fn number_input_view_in_range<N>(
+    ui: &mut Ui,
+    label: &str,
+    node_pin: &mut NodePin<N>,
+    remote_value: Option<(&'static str, N)>,
+    range: RangeInclusive<N>,
+) -> PinInfo
+where
+    N: Numeric,
+{
+    number_drag_input_view(ui, label, node_pin, remote_value, |value| {
+        egui::DragValue::new(value).range(range)
+    })
+}
+
+/// Same as [`number_input_view_in_range`], dragging the value changes it by `speed` per point.
+/// Inputs with a narrow range need a lower speed than the default of 1 to be adjusted precisely.
+/// Values already outside of `range`, like the IOR of an air bubble in glass, are kept until they are edited.
+pub fn number_input_view_with_speed<N>(
+    ui: &mut Ui,
+    label: &str,
+    node_pin: &mut NodePin<N>,
+    remote_value: Option<(&'static str, N)>,
+    range: RangeInclusive<N>,
+    speed: f64,
+) -> PinInfo
+where
+    N: Numeric,
+{
+    number_drag_input_view(ui, label, node_pin, remote_value, |value| {
+        egui::DragValue::new(value)
+            .range(range)
+            .clamp_existing_to_range(false)
+            .speed(speed)
+    })
+}
+
+fn number_drag_input_view<N>(
+    ui: &mut Ui,
+    label: &str,
+    node_pin: &mut NodePin<N>,
+    remote_value: Option<(&'static str, N)>,
+    drag_value: impl FnOnce(&mut N) -> egui::DragValue<'_>,
+) -> PinInfo
 where
     N: Numeric,
 {
     ui.horizontal(|ui| {
-        ui.label(label);
+        let label = ui.add(egui::Label::new(label).sense(egui::Sense::click()));
         let enabled = match remote_value {
             None => true,
             Some(remote) => {
@@ -423,31 +1616,82 @@ where
                 false
             },
         };
-        ui.add_enabled(enabled, egui::DragValue::new(node_pin.as_mut()));
+        let response = ui.add_enabled(enabled, drag_value(node_pin.as_mut()));
+        if enabled {
+            label
+                .union(response)
+                .context_menu(|ui| reset_to_default_button(ui, node_pin));
+        }
     });
     PinInfo::circle().with_fill(NUMBER_COLOR)
 }
 
-pub fn as_number_input_view<N, M>(
+/// Entry of the context menu of an input that isn't connected.
+fn reset_to_default_button<T: Clone + PartialEq>(ui: &mut Ui, node_pin: &mut NodePin<T>) {
+    let button = egui::Button::new("Reset to default");
+    if ui.add_enabled(node_pin.can_reset_to_default(), button).clicked() {
+        node_pin.reset_to_default();
+        ui.close_menu();
+    }
+}
+
+/// Angles are shown in the unit stored in the pin, a right click on the input switches it.
+/// Remote numbers are always degrees, converted to the unit of the pin for display.
+pub fn angle_input_view(
     ui: &mut Ui,
     label: &str,
-    node_pin: &mut NodePin<N>,
-    remote_value: Option<(&'static str, M)>,
-) -> PinInfo
-where
-    N: AsMut<f64>,
-    M: Into<N>,
-{
+    node_pin: &mut NodePin<Angle>,
+    remote_value: Option<(&'static str, f64)>,
+) -> PinInfo {
+    angle_input_view_in_range(ui, label, node_pin, remote_value, f64::NEG_INFINITY..=f64::INFINITY)
+}
+
+/// Same as [`angle_input_view`], but the editable value can't leave `range` given in degrees.
+pub fn angle_input_view_in_range(
+    ui: &mut Ui,
+    label: &str,
+    node_pin: &mut NodePin<Angle>,
+    remote_value: Option<(&'static str, f64)>,
+    range: RangeInclusive<f64>,
+) -> PinInfo {
     ui.horizontal(|ui| {
-        ui.label(label);
+        let label = ui.add(egui::Label::new(label).sense(egui::Sense::click()));
+        let unit = node_pin.get().unit();
         let enabled = match remote_value {
             None => true,
             Some(remote) => {
-                node_pin.set(remote.1.into());
+                node_pin.set(Angle::degrees(remote.1).with_unit(unit));
                 false
             },
         };
-        ui.add_enabled(enabled, egui::DragValue::new(node_pin.as_mut().as_mut()));
+
+        let range = unit.value(Angle::degrees(*range.start()))..=unit.value(Angle::degrees(*range.end()));
+        let speed = match unit {
+            AngleUnit::Degrees => 1.0,
+            AngleUnit::Radians => 0.01,
+        };
+        let mut value = unit.value(node_pin.get());
+        let drag = egui::DragValue::new(&mut value)
+            .range(range)
+            .speed(speed)
+            .suffix(unit.suffix());
+        let response = ui.add_enabled(enabled, drag);
+        if response.changed() {
+            *node_pin.as_mut() = unit.angle(value);
+        }
+
+        label.union(response).context_menu(|ui| {
+            for other in AngleUnit::ALL {
+                if ui.radio(other == unit, other.name()).clicked() {
+                    node_pin.set_unit(other);
+                    ui.close_menu();
+                }
+            }
+            if enabled {
+                ui.separator();
+                reset_to_default_button(ui, node_pin);
+            }
+        });
     });
     PinInfo::circle().with_fill(NUMBER_COLOR)
 }
@@ -457,6 +1701,7 @@ pub fn vector_input_remote_value(pin: &InPin, snarl: &Snarl<Node>, label: &str)
         [] => None,
         [remote] => Some(match &snarl[remote.node] {
             Node::Number(value) => (Node::NUMBER_NAME, Vector3::new(*value, *value, *value)),
+            Node::Integer(value) => (Node::INTEGER_NAME, Vector3::repeat(*value as f64)),
             Node::Vector(vector) => (Node::VECTOR_NAME, *vector),
             Node::Color(color) => {
                 let color = color.to_normalized_gamma_f32();
@@ -465,11 +1710,27 @@ pub fn vector_input_remote_value(pin: &InPin, snarl: &Snarl<Node>, label: &str)
                     Vector3::new(color[0] as _, color[1] as _, color[2] as _),
                 )
             },
-            Node::Expression(expr) => {
-                let value = expr.eval();
-                (ExpressionNode::NAME, Vector3::new(value, value, value))
+            Node::ColorMix(color_mix) => {
+                let color = color_mix.color_out().to_normalized_gamma_f32();
+                (
+                    ColorMixNode::NAME,
+                    Vector3::new(color[0] as _, color[1] as _, color[2] as _),
+                )
+            },
+            Node::HsvColor(hsv_color) => {
+                let color = hsv_color.color_out().to_normalized_gamma_f32();
+                (
+                    HsvColorNode::NAME,
+                    Vector3::new(color[0] as _, color[1] as _, color[2] as _),
+                )
+            },
+            Node::Expression(expr) => (ExpressionNode::NAME, expr.eval_vector().ok()?),
+            Node::Curve(curve) => (CurveNode::NAME, Vector3::repeat(curve.number_out())),
+            Node::VectorMath(vector_math) => (vector_math.name(), vector_math.vector_out()),
+            node => {
+                invalid_connection(label, node);
+                return None;
             },
-            node => unreachable!("{label} input not suppor connection with `{}`", node.name()),
         }),
         _ => None,
     }
@@ -482,7 +1743,7 @@ pub fn vector_input_view(
     remote_value: Option<(&'static str, Vector3)>,
 ) -> PinInfo {
     ui.horizontal(|ui| {
-        ui.label(label);
+        let label = ui.add(egui::Label::new(label).sense(egui::Sense::click()));
         let enabled = match remote_value {
             None => true,
             Some(remote) => {
@@ -490,9 +1751,12 @@ pub fn vector_input_view(
                 false
             },
         };
-        ui.add_enabled(enabled, egui::DragValue::new(&mut node_pin.as_mut()[0]));
-        ui.add_enabled(enabled, egui::DragValue::new(&mut node_pin.as_mut()[1]));
-        ui.add_enabled(enabled, egui::DragValue::new(&mut node_pin.as_mut()[2]));
+        let x = ui.add_enabled(enabled, egui::DragValue::new(&mut node_pin.as_mut()[0]));
+        let y = ui.add_enabled(enabled, egui::DragValue::new(&mut node_pin.as_mut()[1]));
+        let z = ui.add_enabled(enabled, egui::DragValue::new(&mut node_pin.as_mut()[2]));
+        if enabled {
+            (label | x | y | z).context_menu(|ui| reset_to_default_button(ui, node_pin));
+        }
     });
     PinInfo::circle().with_fill(VECTOR_COLOR)
 }
@@ -502,7 +1766,13 @@ pub fn color_input_remote_value(pin: &InPin, snarl: &Snarl<Node>, label: &str) -
         [] => None,
         [remote] => Some(match &snarl[remote.node] {
             Node::Number(value) => (Node::NUMBER_NAME, Color::from_gray((*value * 255.0).round() as u8)),
+            Node::Integer(value) => {
+                let gray = (*value as f64).clamp(0.0, 1.0) * 255.0;
+                (Node::INTEGER_NAME, Color::from_gray(gray as u8))
+            },
             Node::Color(color) => (Node::COLOR_NAME, *color),
+            Node::ColorMix(color_mix) => (ColorMixNode::NAME, color_mix.color_out()),
+            Node::HsvColor(hsv_color) => (HsvColorNode::NAME, hsv_color.color_out()),
             Node::Vector(vector) => (
                 Node::VECTOR_NAME,
                 Color::from_rgb(
@@ -511,11 +1781,40 @@ pub fn color_input_remote_value(pin: &InPin, snarl: &Snarl<Node>, label: &str) -
                     (vector.z * 255.0).round() as u8,
                 ),
             ),
+            Node::Expression(expr) if expr.is_vector() => {
+                let vector = expr.eval_vector().ok()?;
+                (
+                    ExpressionNode::NAME,
+                    Color::from_rgb(
+                        (vector.x * 255.0).round() as u8,
+                        (vector.y * 255.0).round() as u8,
+                        (vector.z * 255.0).round() as u8,
+                    ),
+                )
+            },
             Node::Expression(expr) => {
-                let value = expr.eval();
+                let value = expr.eval().ok()?;
                 (ExpressionNode::NAME, Color::from_gray((value * 255.0).round() as u8))
             },
-            node => unreachable!("{label} input not suppor connection with `{}`", node.name()),
+            Node::Curve(curve) => (
+                CurveNode::NAME,
+                Color::from_gray((curve.number_out() * 255.0).round() as u8),
+            ),
+            Node::VectorMath(vector_math) => {
+                let vector = vector_math.vector_out();
+                (
+                    vector_math.name(),
+                    Color::from_rgb(
+                        (vector.x * 255.0).round() as u8,
+                        (vector.y * 255.0).round() as u8,
+                        (vector.z * 255.0).round() as u8,
+                    ),
+                )
+            },
+            node => {
+                invalid_connection(label, node);
+                return None;
+            },
         }),
         _ => None,
     }
@@ -528,19 +1827,17 @@ pub fn color_input_view(
     remote_value: Option<(&'static str, Color)>,
 ) -> PinInfo {
     ui.horizontal(|ui| {
-        ui.label(label);
+        let label = ui.add(egui::Label::new(label).sense(egui::Sense::click()));
         match remote_value {
             None => {
-                color_edit_button_srgba(ui, node_pin.as_mut(), Alpha::BlendOrAdditive);
+                let response = color_edit_button_srgba(ui, node_pin.as_mut(), Alpha::BlendOrAdditive);
+                label
+                    .union(response)
+                    .context_menu(|ui| reset_to_default_button(ui, node_pin));
             },
             Some(remote) => {
-                let show_color_button = match remote.0 {
-                    Node::NUMBER_NAME => true,
-                    Node::COLOR_NAME => false,
-                    Node::VECTOR_NAME => true,
-                    ExpressionNode::NAME => true,
-                    node => unreachable!("{label} input not suppor connection with `{node}`"),
-                };
+                // Color nodes already show their color, others get a preview of the converted value.
+                let show_color_button = !matches!(remote.0, Node::COLOR_NAME | ColorMixNode::NAME | HsvColorNode::NAME);
 
                 node_pin.set(remote.1);
 
@@ -562,7 +1859,10 @@ pub fn material_input_remote_value(
         [] => None,
         [remote] => Some(match &snarl[remote.node] {
             Node::Material(material) => (material.name(), InputMaterial::External(remote.node)),
-            node => unreachable!("{label} input not suppor connection with `{}`", node.name()),
+            node => {
+                invalid_connection(label, node);
+                return None;
+            },
         }),
         _ => None,
     }
@@ -590,3 +1890,147 @@ pub fn empty_input_view(ui: &mut Ui, label: impl Into<WidgetText>) -> PinInfo {
     ui.label(label);
     PinInfo::circle().with_fill(UNTYPED_COLOR)
 }
+
+/// Shown instead of an input whose wire comes from a node it can't take a value from.
+pub fn invalid_input_view(ui: &mut Ui, remote_name: &str) -> PinInfo {
+    ui.colored_label(ui.visuals().error_fg_color, "Invalid wire")
+        .on_hover_text(format!("Can't take a value from `{remote_name}`"));
+    PinInfo::circle().with_fill(INVALID_COLOR)
+}
+
+/// Called by inputs for a wire from a node they don't know how to read. The input keeps its own value.
+/// [`NodeViewer`] logs wires of mismatched types and doesn't show them to the input, so this is only reached
+/// by an input whose flags take a node it doesn't read.
+pub fn invalid_connection(label: &str, node: &Node) {
+    debug_assert!(
+        false,
+        "{label} input does not support connection with `{}`",
+        node.name()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loaded_render_resolves_camera_and_scene() {
+        let mut snarl = Snarl::new();
+        let camera = snarl.insert_node(Pos2::ZERO, Node::Camera(CameraNode::default()));
+        let scene = snarl.insert_node(Pos2::ZERO, Node::Scene(SceneNode::default()));
+        let render_node = RaytracerRenderNode::new(512);
+        let render = snarl.insert_node(Pos2::ZERO, Node::Render(RenderNode::Raytracer(render_node)));
+        let output = |node| OutPinId { node, output: 0 };
+        let input = |input| InPinId { node: render, input };
+        snarl.connect(output(camera), input(3));
+        snarl.connect(output(scene), input(4));
+
+        // Links that no longer match the wires, as if the ids had been reassigned.
+        let render_node = snarl[render].render_node_mut().unwrap().as_raytracer_render_mut();
+        render_node.relink(Some(scene), Some(NodeId(42)));
+
+        let mut loaded: Snarl<Node> = serde_json::from_str(&serde_json::to_string(&snarl).unwrap()).unwrap();
+        relink_inputs(&mut loaded);
+
+        let render_node = loaded[render].render_node_ref().unwrap().as_raytracer_render_ref();
+        assert_eq!(render_node.camera_id(), Some(camera));
+        assert_eq!(render_node.scene_id(), Some(scene));
+        assert!(render_node.camera_node(&loaded).is_some());
+    }
+
+    #[test]
+    fn test_mismatched_wire_is_ignored() {
+        let mut snarl = Snarl::new();
+        let camera = snarl.insert_node(Pos2::ZERO, Node::Camera(CameraNode::default()));
+        let render_node = RaytracerRenderNode::new(512);
+        let render = snarl.insert_node(Pos2::ZERO, Node::Render(RenderNode::Raytracer(render_node)));
+        // A camera wired into the sample count, as a file written by hand could have it.
+        let from = OutPinId {
+            node: camera,
+            output: 0,
+        };
+        let to = InPinId { node: render, input: 0 };
+        snarl.connect(from, to);
+
+        let mut reported = HashSet::new();
+        assert_eq!(invalid_wire(&snarl, to, &mut reported), Some(from));
+        assert_eq!(invalid_wire(&snarl, to, &mut reported), Some(from));
+        assert_eq!(reported.len(), 1);
+        let scene_input = InPinId { node: render, input: 4 };
+        assert_eq!(invalid_wire(&snarl, scene_input, &mut reported), None);
+
+        forget_invalid_wires(&mut reported, camera);
+        assert!(reported.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_inputs_are_pruned() {
+        let mut expression = ExpressionNode::new();
+        expression.text = "x + y".to_owned();
+        expression.expr = syn::parse_str(&expression.text).unwrap();
+        expression.bindings = vec!["x".to_owned(), "y".to_owned()];
+        expression.values = vec![1.0, 5.0];
+
+        let mut snarl = Snarl::new();
+        let number = snarl.insert_node(Pos2::ZERO, Node::Number(5.0));
+        let expression = snarl.insert_node(Pos2::ZERO, Node::Expression(expression));
+        let from = OutPinId {
+            node: number,
+            output: 0,
+        };
+        let to = InPinId {
+            node: expression,
+            input: 2,
+        };
+        snarl.connect(from, to);
+
+        assert_eq!(drop_input_wires(&snarl.in_pin(to), &mut snarl), [from]);
+        assert!(snarl.in_pin(to).remotes.is_empty());
+        assert_eq!(snarl[expression].as_expression_node_mut().eval(), Ok(1.0));
+
+        // The members after a dropped one move up together with their wires.
+        let collection = snarl.insert_node(Pos2::ZERO, Node::Collection(CollectionNode::default()));
+        let sphere = || Node::Primitive(PrimitiveNode::Sphere(SphereNode::default()));
+        let spheres = [(); 2].map(|()| snarl.insert_node(Pos2::ZERO, sphere()));
+        let output = |node| OutPinId { node, output: 0 };
+        let input = |input| InPinId {
+            node: collection,
+            input,
+        };
+        for (idx, &sphere) in spheres.iter().enumerate() {
+            snarl.connect(output(sphere), input(idx));
+            let (from, to) = (snarl.out_pin(output(sphere)), snarl.in_pin(input(idx)));
+            Node::send_msg(collection, &mut snarl, InputMessage::Connect { from: &from, to: &to });
+        }
+
+        let dropped = drop_input_wires(&snarl.in_pin(input(0)), &mut snarl);
+        assert_eq!(dropped, [output(spheres[0])]);
+        assert_eq!(snarl.in_pin(input(0)).remotes, [output(spheres[1])]);
+        let members = snarl[collection].collection_node_ref().unwrap().to_node_ids();
+        assert_eq!(members, [spheres[1]]);
+    }
+
+    #[test]
+    fn test_cycle_is_refused() {
+        let mut snarl = Snarl::new();
+        let [inner, middle, outer] =
+            [(); 3].map(|()| snarl.insert_node(Pos2::ZERO, Node::Collection(CollectionNode::default())));
+        let output = |node| OutPinId { node, output: 0 };
+        let input = |node| InPinId { node, input: 0 };
+        snarl.connect(output(inner), input(middle));
+        snarl.connect(output(middle), input(outer));
+
+        assert!(creates_cycle(&snarl, outer, inner));
+        assert!(creates_cycle(&snarl, middle, middle));
+        assert!(!creates_cycle(&snarl, inner, outer));
+
+        let refusal = |from, to| connection_refusal(&snarl, output(from), input(to));
+        assert_eq!(refusal(outer, inner), Some(ConnectionRefusal::Cycle));
+        assert_eq!(refusal(middle, middle), Some(ConnectionRefusal::Cycle));
+        assert_eq!(refusal(inner, outer), None);
+
+        let camera = snarl.insert_node(Pos2::ZERO, Node::Camera(CameraNode::default()));
+        let refusal = |from, to| connection_refusal(&snarl, output(from), input(to));
+        assert_eq!(refusal(camera, inner), Some(ConnectionRefusal::Incompatible));
+    }
+}
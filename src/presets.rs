@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use egui::Ui;
+use egui_snarl::ui::SnarlStyle;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppSettings;
+
+/// Named settings and graph styles to switch between, e.g. a dense layout for editing and a clean one for
+/// presenting.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Presets {
+    presets: HashMap<String, (AppSettings, SnarlStyle)>,
+}
+
+impl Presets {
+    pub fn save_as(&mut self, name: &str, settings: &AppSettings, style: &SnarlStyle) {
+        self.presets.insert(name.to_owned(), (settings.clone(), style.clone()));
+    }
+
+    /// Replaces the look and layout settings and the style with the preset. Key bindings, the mode, the open
+    /// windows and the up axis stay as they are, a preset saved before a key was rebound must not undo it.
+    /// Returns `false` if there is no preset with that name.
+    pub fn load(&self, name: &str, settings: &mut AppSettings, style: &mut SnarlStyle) -> bool {
+        let Some((preset_settings, preset_style)) = self.presets.get(name) else {
+            return false;
+        };
+        *settings = AppSettings {
+            visible_settings: settings.visible_settings,
+            edit_mode: settings.edit_mode,
+            show_nodes: settings.show_nodes,
+            visible_shortcuts: settings.visible_shortcuts,
+            notify_render_complete: settings.notify_render_complete,
+            up_axis: settings.up_axis,
+            key_bindings: settings.key_bindings.clone(),
+            ..preset_settings.clone()
+        };
+        *style = preset_style.clone();
+        true
+    }
+
+    pub fn delete(&mut self, name: &str) {
+        self.presets.remove(name);
+    }
+
+    fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.presets.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// Save-as, load and delete controls of the presets in the settings panel.
+#[derive(Default)]
+pub struct PresetsView {
+    name: String,
+}
+
+impl PresetsView {
+    pub fn show(&mut self, ui: &mut Ui, presets: &mut Presets, settings: &mut AppSettings, style: &mut SnarlStyle) {
+        ui.collapsing("Presets", |ui| {
+            let mut deleted = None;
+            egui::Grid::new("presets_grid").num_columns(3).show(ui, |ui| {
+                for name in presets.names() {
+                    ui.label(name);
+                    if ui.button("Load").clicked() {
+                        presets.load(name, settings, style);
+                        self.name = name.to_owned();
+                    }
+                    if ui.button("Delete").clicked() {
+                        deleted = Some(name.to_owned());
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(name) = deleted {
+                presets.delete(&name);
+            }
+
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.name).hint_text("Preset name"));
+                let name = self.name.trim();
+                if ui.add_enabled(!name.is_empty(), egui::Button::new("Save as")).clicked() {
+                    presets.save_as(name, settings, style);
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Key;
+
+    use super::*;
+    use crate::shortcuts::Action;
+
+    #[test]
+    fn test_presets_round_trip() {
+        let mut presets = Presets::default();
+        let editing = AppSettings {
+            show_wire_labels: true,
+            ..Default::default()
+        };
+        presets.save_as("Editing", &editing, &SnarlStyle::new());
+        let presets: Presets = serde_json::from_str(&serde_json::to_string(&presets).unwrap()).unwrap();
+
+        let mut settings = AppSettings {
+            visible_settings: true,
+            ..Default::default()
+        };
+        settings.key_bindings.set(Action::ToggleNodes, Key::J);
+        let mut style = SnarlStyle::new();
+        assert!(presets.load("Editing", &mut settings, &mut style));
+        assert!(settings.show_wire_labels);
+        assert!(settings.visible_settings);
+        assert_eq!(settings.key_bindings.key(Action::ToggleNodes), Key::J);
+        assert!(!presets.load("Presentation", &mut settings, &mut style));
+    }
+}
@@ -0,0 +1,1314 @@
+use std::f32::consts::*;
+
+use rayon::prelude::*;
+
+use super::scene::{CheckerboardSpace, CsgOp, CsgOperation, Material, Scene, Sphere, TextureData, TextureId};
+use super::{AaPattern, Background, GpuCamera, GpuSkyState, RenderParams, RenderParamsValidationError, WrapMode};
+use crate::types::Vector3f32;
+
+const EPSILON: f32 = 0.001;
+const MIN_T: f32 = 0.001;
+const MAX_T: f32 = 1000.0;
+/// Irradiance of the sun on a surface facing it, in the units of the radiance returned by the sky model. It
+/// isn't derived from a physical value but chosen by eye for the scenes in the shader, which has to use the same
+/// value. Spread over the solid angle of the disk, it keeps the sun as bright whatever its size.
+const SUN_IRRADIANCE: f32 = 40.0;
+const MISSING_MATERIAL_ALBEDO: [f32; 3] = [0.9921, 0.24705, 0.57254];
+/// Mixes of mixes are followed this many levels deep.
+const MAX_MIX_DEPTH: u32 = 4;
+/// Same as in the shader, the sky drawn while the sky model rejects its parameters.
+const FALLBACK_SKY_HORIZON: [f32; 3] = [1.0, 1.0, 1.0];
+const FALLBACK_SKY_ZENITH: [f32; 3] = [0.5, 0.7, 1.0];
+/// Same as in the shader, cells along each side of the grid stratified samples are spread over.
+const AA_STRATA: u32 = 4;
+/// Same as in the shader, steps of the R4 quasi-random sequence in 0.32 fixed point.
+const R4_ALPHA: [u32; 4] = [3679390609, 3152041523, 2700274806, 2313257605];
+
+/// Traces the scene on the CPU, one pixel per task, and returns the linear radiance of every pixel row by row
+/// from the top left. Each pixel averages `max_samples_per_pixel` paths, seeded like the shader's first frame.
+///
+/// Follows `raytracer_shader.wgsl` closely enough to serve as a reference for it, but only samples lights by
+/// hitting them: analytic lights, normal and height maps, adaptive sampling, the output channel and the debug and
+/// post-processing settings are ignored. Only the tests render with it, the app traces single rays for auto focus.
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn render(
+    scene: &Scene,
+    render_params: &RenderParams,
+    size: (u32, u32),
+) -> Result<Vec<[f32; 3]>, RenderParamsValidationError> {
+    render_params.validate()?;
+
+    let tracer = Tracer {
+        scene,
+        primitives: scene.bvh_primitives().iter().map(|primitive| primitive.id).collect(),
+        render_params,
+        camera: GpuCamera::new(&render_params.camera, size),
+        sky: render_params.sky.to_sky_state().0,
+        size,
+    };
+
+    let (width, height) = size;
+    Ok((0..width * height)
+        .into_par_iter()
+        .map(|idx| tracer.sample_pixel(idx % width, idx / width))
+        .collect())
+}
+
+struct Tracer<'a> {
+    scene: &'a Scene,
+    /// Ids of the spheres and CSG operations to test, as in the BVH.
+    primitives: Vec<u32>,
+    render_params: &'a RenderParams,
+    camera: GpuCamera,
+    sky: GpuSkyState,
+    size: (u32, u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Ray {
+    origin: Vector3f32,
+    direction: Vector3f32,
+}
+
+impl Ray {
+    fn at(&self, t: f32) -> Vector3f32 {
+        self.origin + t * self.direction
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Hit {
+    p: Vector3f32,
+    n: Vector3f32,
+    u: f32,
+    v: f32,
+    t: f32,
+    sphere_idx: usize,
+}
+
+impl Tracer<'_> {
+    fn sample_pixel(&self, x: u32, y: u32) -> [f32; 3] {
+        let (width, height) = self.size;
+        let sampling = &self.render_params.sampling;
+        let mut rng = Rng::new(x, y, width, sampling.seed);
+
+        let mut color = Vector3f32::zeros();
+        for sample_index in 0..sampling.max_samples_per_pixel {
+            let [jitter_x, jitter_y, lens_x, lens_y] = self.aa_sample(x, y, sample_index, &mut rng);
+            let u = (x as f32 + jitter_x) / width as f32;
+            let v = (y as f32 + jitter_y) / height as f32;
+            let ray = self.camera_ray((lens_x, lens_y), u, 1.0 - v);
+            color += self.ray_color(ray, &mut rng);
+        }
+
+        (color / sampling.max_samples_per_pixel as f32).into()
+    }
+
+    /// Same as `aaSample` in the shader.
+    fn aa_sample(&self, x: u32, y: u32, sample_index: u32, rng: &mut Rng) -> [f32; 4] {
+        let jitter = [rng.next_float(), rng.next_float(), rng.next_float(), rng.next_float()];
+        let scramble =
+            jenkins_hash(x.wrapping_add(y.wrapping_mul(self.size.0)) ^ jenkins_hash(self.render_params.sampling.seed));
+        match self.render_params.sampling.aa_pattern {
+            AaPattern::Random => jitter,
+            AaPattern::Stratified => {
+                let run = sample_index / (AA_STRATA * AA_STRATA);
+                let [pixel_x, pixel_y] = stratum(sample_index ^ scramble);
+                let [lens_x, lens_y] = stratum(sample_index ^ jenkins_hash(scramble ^ jenkins_hash(run)));
+                let cells = [pixel_x, pixel_y, lens_x, lens_y];
+                std::array::from_fn(|i| (cells[i] as f32 + jitter[i]) / AA_STRATA as f32)
+            },
+            AaPattern::QuasiRandom => {
+                let (h1, h2) = (jenkins_hash(scramble), jenkins_hash(jenkins_hash(scramble)));
+                let offset = [scramble, h1, h2, jenkins_hash(h2)];
+                std::array::from_fn(|i| {
+                    let point = offset[i].wrapping_add(sample_index.wrapping_mul(R4_ALPHA[i]));
+                    (point >> 8) as f32 / (1 << 24) as f32
+                })
+            },
+        }
+    }
+
+    fn camera_ray(&self, lens_sample: (f32, f32), u: f32, v: f32) -> Ray {
+        let camera = &self.camera;
+        let on_plane = camera.lower_left_corner + u * camera.horizontal + v * camera.vertical;
+        if camera.is_ortho == 1 {
+            return Ray {
+                origin: on_plane,
+                direction: camera.v.cross(&camera.u),
+            };
+        }
+
+        let (x, y) = if camera.bokeh_blades == 0 {
+            unit_disk_point(lens_sample)
+        } else {
+            unit_polygon_point(lens_sample, camera.bokeh_blades)
+        };
+        let origin = camera.eye + camera.lens_radius * (x * camera.u + y * camera.v);
+
+        Ray {
+            origin,
+            direction: on_plane - origin,
+        }
+    }
+
+    fn ray_color(&self, primary_ray: Ray, rng: &mut Rng) -> Vector3f32 {
+        let mut ray = primary_ray;
+        let mut color = Vector3f32::zeros();
+        let mut throughput = Vector3f32::repeat(1.0);
+
+        let sampling = &self.render_params.sampling;
+        let (mut diffuse_bounces, mut transmission_bounces) = (0, 0);
+        while diffuse_bounces < sampling.num_bounces && transmission_bounces < sampling.num_transmission_bounces {
+            let bounce = diffuse_bounces + transmission_bounces;
+            let Some(hit) = self.intersection(&ray) else {
+                // Only the camera sees the background, everything else is lit by the sky.
+                let direction = ray.direction.normalize();
+                let background = match self.render_params.background {
+                    Background::Solid { color } if bounce == 0 => Vector3f32::from(color),
+                    Background::Gradient { top, bottom } if bounce == 0 => {
+                        let t = 0.5 * (direction.dot(&Vector3f32::from(self.sky.up)) + 1.0);
+                        Vector3f32::from(bottom).lerp(&Vector3f32::from(top), t)
+                    },
+                    _ => self.sky_color(direction),
+                };
+                color += throughput.component_mul(&background);
+                break;
+            };
+
+            let sphere = &self.scene.spheres[hit.sphere_idx];
+            // Like the shader, the offset of the sphere's own material turns every texture, before a mix picks one.
+            let uv_offset = match self.scene.materials.get(sphere.material_idx() as usize) {
+                Some(Material::Lambertian { uv_offset, .. } | Material::Metal { uv_offset, .. }) => *uv_offset,
+                _ => 0.0,
+            };
+            let hit = Hit {
+                u: (hit.u + uv_offset).fract(),
+                ..hit
+            };
+            let material = self.mixed_material(sphere.material_idx() as usize, &hit, rng);
+            if matches!(material, Some(Material::Dielectric { .. })) {
+                transmission_bounces += 1;
+            } else {
+                diffuse_bounces += 1;
+            }
+            let Some(material) = material else {
+                let direction = hit.n + rng.next_in_unit_sphere();
+                ray = Ray {
+                    origin: hit.p,
+                    direction,
+                };
+                throughput.component_mul_assign(&Vector3f32::from(MISSING_MATERIAL_ALBEDO));
+                continue;
+            };
+
+            let (direction, attenuation) = match *material {
+                Material::Emissive { emit, two_sided } => {
+                    if two_sided || ray.direction.dot(&hit.n) < 0.0 {
+                        color += throughput.component_mul(&self.texture_lookup(emit, hit.u, hit.v));
+                    }
+                    break;
+                },
+                Material::Lambertian { albedo, tint, .. } => {
+                    let albedo = self
+                        .texture_lookup(albedo, hit.u, hit.v)
+                        .component_mul(&Vector3f32::from(tint));
+                    (sample_lambertian(&hit, rng), albedo)
+                },
+                Material::Metal {
+                    albedo,
+                    fuzz,
+                    anisotropy,
+                    roughness_map,
+                    tint,
+                    ..
+                } => {
+                    let fuzz = self.roughness_scale(roughness_map, &hit) * fuzz;
+                    let t = sphere_tangent(&hit.n);
+                    let b = hit.n.cross(&t);
+                    let (fuzz_u, fuzz_v) = (fuzz * (1.0 + anisotropy), fuzz * (1.0 - anisotropy));
+                    let r = rng.next_in_unit_sphere();
+                    let offset = fuzz_u * r.x * t + fuzz_v * r.y * b + 0.5 * (fuzz_u + fuzz_v) * r.z * hit.n;
+                    let albedo = self
+                        .texture_lookup(albedo, hit.u, hit.v)
+                        .component_mul(&Vector3f32::from(tint));
+                    (reflect(&ray.direction, &hit.n) + offset, albedo)
+                },
+                Material::Dielectric {
+                    refraction_index,
+                    roughness,
+                    roughness_map,
+                    absorption,
+                } => {
+                    let roughness = self.roughness_scale(roughness_map, &hit) * roughness;
+                    let exact_fresnel = sampling.exact_fresnel;
+                    let direction = scatter_dielectric(&ray, &hit, refraction_index, roughness, exact_fresnel, rng);
+                    let attenuation = if ray.direction.dot(&hit.n) > 0.0 {
+                        let distance = hit.t * ray.direction.norm();
+                        Vector3f32::from(absorption).map(|c| c.max(EPSILON).powf(distance))
+                    } else {
+                        Vector3f32::repeat(1.0)
+                    };
+                    (direction, attenuation)
+                },
+                Material::Checkerboard {
+                    even,
+                    odd,
+                    scale,
+                    space,
+                } => {
+                    let sines = match space {
+                        CheckerboardSpace::Uv => (scale * PI * hit.u).sin() * (scale * PI * hit.v).sin(),
+                        CheckerboardSpace::Solid => hit.p.map(|x| (scale * x).sin()).product(),
+                    };
+                    let texture = if sines < 0.0 { even } else { odd };
+                    (sample_lambertian(&hit, rng), self.texture_lookup(texture, hit.u, hit.v))
+                },
+                Material::Isotropic { albedo, density } => {
+                    let albedo = self.texture_lookup(albedo, hit.u, hit.v);
+                    let attenuation;
+                    (ray, attenuation) = self.scatter_isotropic(&ray, &hit, albedo, density, rng);
+                    throughput.component_mul_assign(&attenuation);
+                    continue;
+                },
+                Material::Subsurface { albedo, radius } => {
+                    let albedo = self.texture_lookup(albedo, hit.u, hit.v);
+                    let attenuation;
+                    (ray, attenuation) = scatter_subsurface(&ray, &hit, albedo, radius, rng);
+                    throughput.component_mul_assign(&attenuation);
+                    continue;
+                },
+                Material::Mix { .. } => unreachable!("mixes are resolved to one of their materials"),
+            };
+
+            ray = Ray {
+                origin: hit.p,
+                direction,
+            };
+            throughput.component_mul_assign(&attenuation);
+        }
+
+        color
+    }
+
+    /// Closest primitive along the ray, the scenes a reference render is used for are small enough to skip the BVH.
+    fn intersection(&self, ray: &Ray) -> Option<Hit> {
+        let max_t = MAX_T.min(self.render_params.gpu_max_ray_distance() / ray.direction.norm());
+        let mut closest: Option<Hit> = None;
+        for &id in &self.primitives {
+            let t_max = closest.map_or(max_t, |hit| hit.t);
+            let hit = ray_intersect_primitive(ray, self.scene, id, MIN_T, t_max);
+            if hit.is_some() {
+                closest = hit;
+            }
+        }
+        closest
+    }
+
+    fn sky_color(&self, direction: Vector3f32) -> Vector3f32 {
+        if self.sky.fallback != 0 {
+            return fallback_sky(direction, Vector3f32::from(self.sky.up));
+        }
+
+        let up = Vector3f32::from(self.sky.up);
+        let s = Vector3f32::from(self.sky.sun_direction);
+        let theta = direction.dot(&up).clamp(-1.0, 1.0).acos();
+        let gamma = direction.dot(&s).clamp(-1.0, 1.0).acos();
+        let mut color = self.sky_radiance(theta, gamma);
+
+        // Without light sampling the disk is only found by rays that hit it.
+        if self.sky.sun_enabled != 0 && direction.dot(&s) >= self.sky.sun_cos_angular_radius {
+            let tint = self.sky_radiance(s.dot(&up).clamp(-1.0, 1.0).acos(), 0.0);
+            let sun_color = SUN_IRRADIANCE * tint / tint.max().max(EPSILON);
+            color += sun_color / (2.0 * PI * (1.0 - self.sky.sun_cos_angular_radius));
+        }
+
+        color
+    }
+
+    fn sky_radiance(&self, theta: f32, gamma: f32) -> Vector3f32 {
+        Vector3f32::from_fn(|channel, _| {
+            let r = self.sky.radiances[channel];
+            let p = &self.sky.params[9 * channel..9 * channel + 9];
+
+            let cos_gamma = gamma.cos();
+            let cos_gamma2 = cos_gamma * cos_gamma;
+            let cos_theta = theta.cos().abs();
+
+            let exp_m = (p[4] * gamma).exp();
+            let ray_m = cos_gamma2;
+            let mie_m = (1.0 + cos_gamma2) / (1.0 + p[8] * p[8] - 2.0 * p[8] * cos_gamma).powf(1.5);
+            let zenith = cos_theta.sqrt();
+            let radiance_lhs = 1.0 + p[0] * (p[1] / (cos_theta + 0.01)).exp();
+            let radiance_rhs = p[2] + p[3] * exp_m + p[5] * ray_m + p[6] * mie_m + p[7] * zenith;
+            r * radiance_lhs * radiance_rhs
+        })
+    }
+
+    /// The sphere is a volume of constant density, see `scatterIsotropic` in the shader.
+    fn scatter_isotropic(
+        &self,
+        ray: &Ray,
+        hit: &Hit,
+        albedo: Vector3f32,
+        density: f32,
+        rng: &mut Rng,
+    ) -> (Ray, Vector3f32) {
+        let direction = ray.direction.normalize();
+
+        let (start, inside) = if direction.dot(&hit.n) < 0.0 {
+            let entered = Ray {
+                origin: hit.p,
+                direction,
+            };
+            let exit = ray_intersect_sphere(&entered, &self.scene.spheres, hit.sphere_idx, MIN_T, MAX_T);
+            (hit.p, exit.map_or(0.0, |exit| exit.t))
+        } else {
+            (ray.origin, (hit.p - ray.origin).norm())
+        };
+
+        let scatter_distance = -(1.0 - rng.next_float()).ln() / density.max(EPSILON);
+        if scatter_distance < inside {
+            let scattered = Ray {
+                origin: start + scatter_distance * direction,
+                direction: rng.next_in_unit_sphere().normalize(),
+            };
+            return (scattered, albedo);
+        }
+
+        let passed = Ray {
+            origin: start + inside * direction,
+            direction,
+        };
+        (passed, Vector3f32::repeat(1.0))
+    }
+
+    /// Red channel of the roughness map at the hit point, 1 without a map.
+    /// Follows mixes to one of their materials, see `mixedMaterialIdx` in the shader. `None` for a missing material
+    /// or mixes nested too deep.
+    fn mixed_material(&self, mut material_idx: usize, hit: &Hit, rng: &mut Rng) -> Option<&Material> {
+        for _ in 0..=MAX_MIX_DEPTH {
+            match *self.scene.materials.get(material_idx)? {
+                Material::Mix { first, second, mask } => {
+                    let mask = self.texture_lookup(mask, hit.u, hit.v).x;
+                    material_idx = if rng.next_float() < mask { second } else { first };
+                },
+                ref material => return Some(material),
+            }
+        }
+        None
+    }
+
+    fn roughness_scale(&self, roughness_map: Option<TextureId>, hit: &Hit) -> f32 {
+        roughness_map.map_or(1.0, |texture_id| self.texture_lookup(texture_id, hit.u, hit.v).x)
+    }
+
+    fn texture_lookup(&self, texture_id: TextureId, u: f32, v: f32) -> Vector3f32 {
+        let texture_data = &self.scene.textures[texture_id];
+        let (width, height) = texture_data.texture.dimensions();
+        let u = wrap_coordinate(u * texture_data.scale_u, texture_data.wrap);
+        let v = 1.0 - wrap_coordinate(v * texture_data.scale_v, texture_data.wrap);
+
+        if !self.render_params.sampling.bilinear_filtering {
+            let j = ((u * width as f32) as u32).min(width - 1);
+            let i = ((v * height as f32) as u32).min(height - 1);
+            return texel(texture_data, i, j);
+        }
+
+        // Texel centers sit at half-integer coordinates.
+        let x = u * width as f32 - 0.5;
+        let y = v * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+
+        let j0 = wrap_texel(x0 as i32, width, texture_data.wrap);
+        let j1 = wrap_texel(x0 as i32 + 1, width, texture_data.wrap);
+        let i0 = wrap_texel(y0 as i32, height, texture_data.wrap);
+        let i1 = wrap_texel(y0 as i32 + 1, height, texture_data.wrap);
+
+        let top = texel(texture_data, i0, j0).lerp(&texel(texture_data, i0, j1), x - x0);
+        let bottom = texel(texture_data, i1, j0).lerp(&texel(texture_data, i1, j1), x - x0);
+        top.lerp(&bottom, y - y0)
+    }
+}
+
+/// Distance from `origin` to the first surface of the scene along `direction`, e.g. to focus a camera on what
+/// its center ray sees.
+pub fn first_hit_distance(scene: &Scene, origin: Vector3f32, direction: Vector3f32) -> Option<f32> {
+    let ray = Ray {
+        origin,
+        direction: direction.normalize(),
+    };
+    scene
+        .bvh_primitives()
+        .iter()
+        .filter_map(|primitive| ray_intersect_primitive(&ray, scene, primitive.id, MIN_T, MAX_T))
+        .map(|hit| hit.t)
+        .min_by(f32::total_cmp)
+}
+
+/// `id` is a sphere index or a CSG operation index with [`CsgOp::PRIMITIVE_BIT`] set, like in the BVH.
+fn ray_intersect_primitive(ray: &Ray, scene: &Scene, id: u32, t_min: f32, t_max: f32) -> Option<Hit> {
+    if id & CsgOp::PRIMITIVE_BIT != 0 {
+        ray_intersect_csg(ray, scene, (id & !CsgOp::PRIMITIVE_BIT) as usize, t_min, t_max)
+    } else {
+        ray_intersect_sphere(ray, &scene.spheres, id as usize, t_min, t_max)
+    }
+}
+
+fn ray_intersect_sphere(world_ray: &Ray, spheres: &[Sphere], sphere_idx: usize, t_min: f32, t_max: f32) -> Option<Hit> {
+    let sphere = &spheres[sphere_idx];
+    let ray = &to_object_space(world_ray, sphere);
+    let oc = ray.origin - sphere.center();
+    let a = ray.direction.dot(&ray.direction);
+    let b = oc.dot(&ray.direction);
+    let c = oc.dot(&oc) - sphere.radius() * sphere.radius();
+    let discriminant = b * b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+
+    let is_clipped = |p: Vector3f32| {
+        sphere
+            .clip()
+            .is_some_and(|(normal, offset)| (p - sphere.center()).dot(&normal) > offset)
+    };
+    let hit_at = |t: f32| sphere_hit(ray, sphere, sphere_idx, t);
+
+    let surface = [(-b - discriminant.sqrt()) / a, (-b + discriminant.sqrt()) / a]
+        .into_iter()
+        .find(|&t| t < t_max && t > t_min && !is_clipped(ray.at(t)));
+    let cap = sphere
+        .clip()
+        .filter(|_| sphere.is_clip_capped())
+        .and_then(|(normal, offset)| {
+            let denominator = ray.direction.dot(&normal);
+            let t = (offset - oc.dot(&normal)) / denominator;
+            let inside = (ray.at(t) - sphere.center()).norm_squared() <= sphere.radius() * sphere.radius();
+            (denominator != 0.0 && t < surface.unwrap_or(t_max) && t > t_min && inside).then_some((t, normal))
+        });
+
+    let hit = match cap {
+        Some((t, normal)) => Some(Hit {
+            n: sphere.radius().signum() * normal,
+            ..hit_at(t)
+        }),
+        None => surface.map(hit_at),
+    };
+    hit.map(|hit| hit_to_world(hit, world_ray, sphere))
+}
+
+/// Same as `toObjectSpace` in the shader, `t` is the same along both rays.
+fn to_object_space(ray: &Ray, sphere: &Sphere) -> Ray {
+    let inverse = sphere.inverse_transform();
+    Ray {
+        origin: (inverse * ray.origin.push(1.0)).xyz(),
+        direction: (inverse * ray.direction.push(0.0)).xyz(),
+    }
+}
+
+/// Same as `hitToWorld` in the shader.
+fn hit_to_world(hit: Hit, world_ray: &Ray, sphere: &Sphere) -> Hit {
+    let inverse = sphere.inverse_transform();
+    Hit {
+        p: world_ray.at(hit.t),
+        n: (inverse.fixed_view::<3, 3>(0, 0).transpose() * hit.n).normalize(),
+        ..hit
+    }
+}
+
+fn sphere_hit(ray: &Ray, sphere: &Sphere, sphere_idx: usize, t: f32) -> Hit {
+    let p = ray.at(t);
+    let n = (p - sphere.center()) / sphere.radius();
+    let theta = (-n.y).acos();
+    let phi = (-n.z).atan2(n.x) + PI;
+    Hit {
+        p,
+        n,
+        u: 0.5 * FRAC_1_PI * phi,
+        v: FRAC_1_PI * theta,
+        t,
+        sphere_idx,
+    }
+}
+
+/// Entry and exit of the ray through the whole sphere, `None` if it misses.
+fn sphere_interval(world_ray: &Ray, sphere: &Sphere) -> Option<(f32, f32)> {
+    let ray = to_object_space(world_ray, sphere);
+    let oc = ray.origin - sphere.center();
+    let a = ray.direction.dot(&ray.direction);
+    let b = oc.dot(&ray.direction);
+    let c = oc.dot(&oc) - sphere.radius() * sphere.radius();
+    let discriminant = b * b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    Some(((-b - root) / a, (-b + root) / a))
+}
+
+/// Same as `rayIntersectCsg` in the shader.
+fn ray_intersect_csg(ray: &Ray, scene: &Scene, csg_idx: usize, t_min: f32, t_max: f32) -> Option<Hit> {
+    let op = &scene.csg_ops[csg_idx];
+    let sides = [op.a as usize, op.b as usize];
+    let intervals = sides.map(|sphere_idx| sphere_interval(ray, &scene.spheres[sphere_idx]));
+
+    let mut closest: Option<Hit> = None;
+    for side in 0..2 {
+        let Some((entry, exit)) = intervals[side] else {
+            continue;
+        };
+        let inside_other = |t: f32| intervals[1 - side].is_some_and(|(entry, exit)| entry < t && t < exit);
+        for t in [entry, exit] {
+            if t <= t_min || t >= closest.map_or(t_max, |hit| hit.t) {
+                continue;
+            }
+
+            let keep = match op.operation() {
+                CsgOperation::Union => !inside_other(t),
+                CsgOperation::Intersection => inside_other(t),
+                CsgOperation::Difference => (side == 0) != inside_other(t),
+            };
+            if keep {
+                let sphere = &scene.spheres[sides[side]];
+                let object_hit = sphere_hit(&to_object_space(ray, sphere), sphere, sides[side], t);
+                let mut hit = hit_to_world(object_hit, ray, sphere);
+                if op.operation() == CsgOperation::Difference && side == 1 {
+                    hit.n = -hit.n;
+                }
+                closest = Some(hit);
+            }
+        }
+    }
+    closest
+}
+
+fn sample_lambertian(hit: &Hit, rng: &mut Rng) -> Vector3f32 {
+    let (u, v) = pixar_onb(&hit.n);
+    let local = rng.next_in_cosine_weighted_hemisphere();
+    local.x * u + local.y * v + local.z * hit.n
+}
+
+/// Random walk beneath the surface, see `scatterSubsurface` in the shader.
+fn scatter_subsurface(ray: &Ray, hit: &Hit, albedo: Vector3f32, radius: f32, rng: &mut Rng) -> (Ray, Vector3f32) {
+    let direction = ray.direction.normalize();
+
+    if direction.dot(&hit.n) < 0.0 {
+        let (u, v) = pixar_onb(&-hit.n);
+        let local = rng.next_in_cosine_weighted_hemisphere();
+        let entered = Ray {
+            origin: hit.p,
+            direction: local.x * u + local.y * v - local.z * hit.n,
+        };
+        return (entered, Vector3f32::repeat(1.0));
+    }
+
+    let inside = (hit.p - ray.origin).norm();
+    let scatter_distance = -(1.0 - rng.next_float()).ln() * radius.max(EPSILON);
+    if scatter_distance < inside {
+        let scattered = Ray {
+            origin: ray.origin + scatter_distance * direction,
+            direction: rng.next_in_unit_sphere().normalize(),
+        };
+        return (scattered, albedo);
+    }
+
+    let left = Ray {
+        origin: hit.p,
+        direction: sample_lambertian(hit, rng),
+    };
+    (left, Vector3f32::repeat(1.0))
+}
+
+fn scatter_dielectric(
+    ray: &Ray,
+    hit: &Hit,
+    refraction_index: f32,
+    roughness: f32,
+    exact_fresnel: bool,
+    rng: &mut Rng,
+) -> Vector3f32 {
+    let wo = ray.direction;
+    let n = sample_ggx_normal(&hit.n, roughness, rng);
+    let (outward_normal, ni_over_nt, cosine) = if wo.dot(&n) > 0.0 {
+        (-n, refraction_index, refraction_index * wo.normalize().dot(&n))
+    } else {
+        (n, 1.0 / refraction_index, -wo.normalize().dot(&n))
+    };
+
+    let reflectance = if exact_fresnel {
+        fresnel_dielectric(wo.normalize().dot(&n).abs(), ni_over_nt)
+    } else {
+        schlick(cosine, refraction_index)
+    };
+    match refract(&wo, &outward_normal, ni_over_nt) {
+        Some(refracted) if rng.next_float() >= reflectance => refracted,
+        _ => reflect(&wo, &n),
+    }
+}
+
+fn sample_ggx_normal(n: &Vector3f32, roughness: f32, rng: &mut Rng) -> Vector3f32 {
+    if roughness <= 0.0 {
+        return *n;
+    }
+
+    let alpha = roughness * roughness;
+    let u1 = rng.next_float();
+    let u2 = rng.next_float();
+    let tan_theta2 = alpha * alpha * u1 / (1.0 - u1).max(EPSILON);
+    let cos_theta = 1.0 / (1.0 + tan_theta2).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    let (u, v) = pixar_onb(n);
+    phi.cos() * sin_theta * u + phi.sin() * sin_theta * v + cos_theta * n
+}
+
+/// Gradient drawn while the sky model rejects its parameters, as in the shader.
+fn fallback_sky(direction: Vector3f32, up: Vector3f32) -> Vector3f32 {
+    let t = 0.5 * (direction.dot(&up) + 1.0);
+    Vector3f32::from(FALLBACK_SKY_HORIZON).lerp(&Vector3f32::from(FALLBACK_SKY_ZENITH), t)
+}
+
+/// Tangent and bitangent completing `n` to an orthonormal basis.
+fn pixar_onb(n: &Vector3f32) -> (Vector3f32, Vector3f32) {
+    // https://www.jcgt.org/published/0006/01/01/paper-lowres.pdf
+    let s = if n.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (s + n.z);
+    let b = n.x * n.y * a;
+    let u = Vector3f32::new(1.0 + s * n.x * n.x * a, s * b, -s * n.x);
+    let v = Vector3f32::new(b, s + n.y * n.y * a, -n.y);
+    (u, v)
+}
+
+fn sphere_tangent(n: &Vector3f32) -> Vector3f32 {
+    let tangent_length = n.x.hypot(n.z);
+    if tangent_length < EPSILON {
+        return Vector3f32::x();
+    }
+
+    Vector3f32::new(n.z, 0.0, -n.x) / tangent_length
+}
+
+fn reflect(v: &Vector3f32, n: &Vector3f32) -> Vector3f32 {
+    v - 2.0 * v.dot(n) * n
+}
+
+fn refract(v: &Vector3f32, n: &Vector3f32, ni_over_nt: f32) -> Option<Vector3f32> {
+    let uv = v.normalize();
+    let dt = uv.dot(n);
+    let discriminant = 1.0 - ni_over_nt * ni_over_nt * (1.0 - dt * dt);
+    (discriminant > 0.0).then(|| (ni_over_nt * (uv - dt * n) - discriminant.sqrt() * n).normalize())
+}
+
+fn schlick(cosine: f32, refraction_index: f32) -> f32 {
+    let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
+    let r0 = r0 * r0;
+    r0 + ((1.0 - r0) * (1.0 - cosine)).powi(5)
+}
+
+/// Same as `fresnelDielectric` in the shader, `eta` is the index of the incident side over the transmitted one.
+fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let sin_t2 = eta * eta * (1.0 - cos_i * cos_i);
+    if sin_t2 >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin_t2).sqrt();
+    let rs = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let rp = (eta * cos_t - cos_i) / (eta * cos_t + cos_i);
+    0.5 * (rs * rs + rp * rp)
+}
+
+fn wrap_coordinate(x: f32, wrap: WrapMode) -> f32 {
+    match wrap {
+        WrapMode::Repeat => x.rem_euclid(1.0),
+        WrapMode::Clamp => x.clamp(0.0, 1.0),
+        WrapMode::Mirror => 1.0 - (1.0 - 2.0 * (0.5 * x).rem_euclid(1.0)).abs(),
+    }
+}
+
+fn wrap_texel(idx: i32, size: u32, wrap: WrapMode) -> u32 {
+    let n = size as i32;
+    match wrap {
+        WrapMode::Repeat => idx.rem_euclid(n) as u32,
+        WrapMode::Clamp | WrapMode::Mirror => idx.clamp(0, n - 1) as u32,
+    }
+}
+
+fn texel(texture_data: &TextureData, i: u32, j: u32) -> Vector3f32 {
+    let (width, _) = texture_data.texture.dimensions();
+    Vector3f32::from(texture_data.texture.as_slice()[(i * width + j) as usize])
+}
+
+/// The shader's PCG generator, seeded per pixel the same way.
+struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    fn new(x: u32, y: u32, width: u32, seed: u32) -> Self {
+        // Adapted from https://github.com/boksajak/referencePT
+        let frame = 0;
+        let pixel_seed = x.wrapping_add(y.wrapping_mul(width)) ^ jenkins_hash(frame ^ jenkins_hash(seed));
+        Self {
+            state: jenkins_hash(pixel_seed),
+        }
+    }
+
+    fn next_int(&mut self) -> u32 {
+        self.state = self.state.wrapping_mul(747796405).wrapping_add(2891336453);
+        let state = self.state;
+        let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+        (word >> 22) ^ word
+    }
+
+    fn next_float(&mut self) -> f32 {
+        self.next_int() as f32 / u32::MAX as f32
+    }
+
+    fn next_in_unit_sphere(&mut self) -> Vector3f32 {
+        let r = self.next_float().powf(0.33333);
+        let cos_theta = 1.0 - 2.0 * self.next_float();
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * self.next_float();
+        Vector3f32::new(r * sin_theta * phi.cos(), r * sin_theta * phi.sin(), cos_theta)
+    }
+
+    fn next_in_cosine_weighted_hemisphere(&mut self) -> Vector3f32 {
+        let r1 = self.next_float();
+        let r2 = self.next_float();
+        let phi = 2.0 * PI * r1;
+        Vector3f32::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), (1.0 - r2).sqrt())
+    }
+}
+
+fn stratum(index: u32) -> [u32; 2] {
+    let cell = index % (AA_STRATA * AA_STRATA);
+    [cell % AA_STRATA, cell / AA_STRATA]
+}
+
+fn unit_disk_point((u, v): (f32, f32)) -> (f32, f32) {
+    let r = u.sqrt();
+    let alpha = 2.0 * PI * v;
+    (r * alpha.cos(), r * alpha.sin())
+}
+
+fn unit_polygon_point((u, v): (f32, f32), corners: u32) -> (f32, f32) {
+    let sector_angle = 2.0 * PI / corners as f32;
+    let scaled = u * corners as f32;
+    let sector = (scaled as u32).min(corners - 1);
+    let angle = FRAC_PI_2 + sector as f32 * sector_angle;
+    let (sin1, cos1) = angle.sin_cos();
+    let (sin2, cos2) = (angle + sector_angle).sin_cos();
+
+    let r = v.sqrt();
+    let t = scaled.fract();
+    (r * (cos1 + t * (cos2 - cos1)), r * (sin1 + t * (sin2 - sin1)))
+}
+
+fn jenkins_hash(input: u32) -> u32 {
+    let mut x = input;
+    x = x.wrapping_add(x << 10);
+    x ^= x >> 6;
+    x = x.wrapping_add(x << 3);
+    x ^= x >> 11;
+    x = x.wrapping_add(x << 15);
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::{
+        Camera, CropRect, DebugParams, ExposureParams, OutputChannel, RAYTRACER_SHADER, SamplingParams, SkyParams,
+        Texture,
+    };
+    use crate::types::{Matrix4f32, Vector3};
+
+    fn render_params(background: Background) -> RenderParams {
+        RenderParams {
+            camera: Camera::default(),
+            sky: SkyParams::default(),
+            sampling: SamplingParams {
+                max_samples_per_pixel: 4,
+                ..Default::default()
+            },
+            debug: DebugParams::default(),
+            exposure: ExposureParams::default(),
+            denoise: false,
+            crop: CropRect::default(),
+            background,
+            output_channel: OutputChannel::default(),
+            bloom: None,
+            max_ray_distance: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_sun_irradiance_matches_the_shader() {
+        let shader_value = RAYTRACER_SHADER
+            .lines()
+            .find_map(|line| line.strip_prefix("const SUN_IRRADIANCE = ")?.split_once("f;"))
+            .map(|(value, _)| value.parse::<f32>().unwrap());
+        assert_eq!(shader_value, Some(SUN_IRRADIANCE));
+    }
+
+    #[test]
+    fn test_empty_scene_shows_background() {
+        let color = [0.25, 0.5, 0.75];
+        let image = render(&Scene::default(), &render_params(Background::Solid { color }), (8, 4)).unwrap();
+
+        assert_eq!(image, vec![color; 32]);
+    }
+
+    #[test]
+    fn test_emissive_sphere_fills_the_view() {
+        let emit = Vector3f32::new(2.0, 1.0, 0.5);
+        let scene = Scene {
+            spheres: vec![Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 0)],
+            materials: vec![Material::Emissive {
+                emit: 0,
+                two_sided: true,
+            }],
+            textures: vec![TextureData::new(Texture::new_from_color(emit))],
+            ..Default::default()
+        };
+        let image = render(&scene, &render_params(Background::default()), (4, 4)).unwrap();
+
+        assert_eq!(image, vec![<[f32; 3]>::from(emit); 16]);
+    }
+
+    #[test]
+    fn test_one_sided_light_is_dark_from_behind() {
+        let render_inside = |two_sided| {
+            let scene = Scene {
+                spheres: vec![Sphere::new(Vector3::new(0.0, 0.0, 0.0), 100.0, 0)],
+                materials: vec![Material::Emissive { emit: 0, two_sided }],
+                textures: vec![TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0)))],
+                ..Default::default()
+            };
+            render(&scene, &render_params(Background::default()), (2, 2)).unwrap()
+        };
+
+        assert_eq!(render_inside(true), vec![[1.0; 3]; 4]);
+        assert_eq!(render_inside(false), vec![[0.0; 3]; 4]);
+    }
+
+    #[test]
+    fn test_glass_absorbs_light_inside() {
+        let scene = Scene {
+            spheres: vec![
+                Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 0),
+                Sphere::new(Vector3::new(0.0, 0.0, -300.0), 200.0, 1),
+            ],
+            materials: vec![
+                Material::Dielectric {
+                    refraction_index: 1.5,
+                    roughness: 0.0,
+                    roughness_map: None,
+                    absorption: [0.5, 1.0, 0.5],
+                },
+                Material::Emissive {
+                    emit: 0,
+                    two_sided: true,
+                },
+            ],
+            textures: vec![TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0)))],
+            ..Default::default()
+        };
+        let image = render(&scene, &render_params(Background::default()), (3, 3)).unwrap();
+
+        let [r, g, b] = image[4];
+        assert!(g > 10.0 * r && g > 10.0 * b, "{:?}", image[4]);
+    }
+
+    #[test]
+    fn test_clear_glass_reflects_a_part_of_the_light() {
+        // A light behind the camera, only seen in the glass when it reflects, everything else inside a black room.
+        let scene = Scene {
+            spheres: vec![
+                Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 0),
+                Sphere::new(Vector3::new(0.0, 0.0, 300.0), 200.0, 1),
+                // Within MAX_T of the glass, so no path escapes to the sky.
+                Sphere::new(Vector3::new(0.0, 0.0, 0.0), 600.0, 2),
+            ],
+            materials: vec![
+                Material::Dielectric {
+                    refraction_index: 1.5,
+                    roughness: 0.0,
+                    roughness_map: None,
+                    absorption: [1.0; 3],
+                },
+                Material::Emissive {
+                    emit: 0,
+                    two_sided: true,
+                },
+                Material::Lambertian {
+                    albedo: 1,
+                    normal_map: None,
+                    height_map: None,
+                    tint: Material::DEFAULT_TINT,
+                    uv_offset: 0.0,
+                },
+            ],
+            textures: vec![
+                TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0))),
+                TextureData::new(Texture::new_from_color(Vector3f32::zeros())),
+            ],
+            ..Default::default()
+        };
+        let mut render_params = render_params(Background::default());
+        render_params.sampling.max_samples_per_pixel = 256;
+        let [r, ..] = render(&scene, &render_params, (3, 3)).unwrap()[4];
+
+        // Head-on, glass with an index of 1.5 reflects 4% of the light.
+        assert!(0.02 < r && r < 0.2, "{r}");
+    }
+
+    #[test]
+    fn test_glass_bounces_have_their_own_budget() {
+        let scene = Scene {
+            spheres: vec![
+                Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 0),
+                Sphere::new(Vector3::new(0.0, 0.0, -300.0), 200.0, 1),
+            ],
+            materials: vec![
+                Material::Dielectric {
+                    refraction_index: 1.5,
+                    roughness: 0.0,
+                    roughness_map: None,
+                    absorption: [1.0; 3],
+                },
+                Material::Emissive {
+                    emit: 0,
+                    two_sided: true,
+                },
+            ],
+            textures: vec![TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0)))],
+            ..Default::default()
+        };
+        let render_center = |num_transmission_bounces| {
+            let mut render_params = render_params(Background::default());
+            render_params.sampling.num_bounces = 1;
+            render_params.sampling.num_transmission_bounces = num_transmission_bounces;
+            render(&scene, &render_params, (3, 3)).unwrap()[4]
+        };
+
+        // Entering and leaving the glass takes two bounces, the light is reached after them.
+        assert!(render_center(16).iter().all(|c| *c > 0.0));
+        assert_eq!(render_center(1), [0.0; 3]);
+    }
+
+    #[test]
+    fn test_subsurface_lets_light_through() {
+        // A light behind the sphere, everything else inside a black room.
+        let black = Material::Lambertian {
+            albedo: 1,
+            normal_map: None,
+            height_map: None,
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
+        };
+        let render_front = |front: Material| {
+            let scene = Scene {
+                spheres: vec![
+                    Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 0),
+                    Sphere::new(Vector3::new(0.0, 0.0, -300.0), 200.0, 1),
+                    Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1000.0, 2),
+                ],
+                materials: vec![
+                    front,
+                    Material::Emissive {
+                        emit: 0,
+                        two_sided: true,
+                    },
+                    black.clone(),
+                ],
+                textures: vec![
+                    TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0))),
+                    TextureData::new(Texture::new_from_color(Vector3f32::zeros())),
+                ],
+                ..Default::default()
+            };
+            render(&scene, &render_params(Background::default()), (3, 3)).unwrap()[4]
+        };
+
+        let subsurface = render_front(Material::Subsurface {
+            albedo: 0,
+            radius: 20.0,
+        });
+        assert!(subsurface.iter().all(|c| *c > 0.0), "{subsurface:?}");
+        assert_eq!(render_front(black.clone()), [0.0; 3]);
+    }
+
+    #[test]
+    fn test_mix_follows_the_mask() {
+        let render_mix = |mask: f32| {
+            let scene = Scene {
+                spheres: vec![Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 2)],
+                materials: vec![
+                    Material::Emissive {
+                        emit: 0,
+                        two_sided: true,
+                    },
+                    Material::Emissive {
+                        emit: 1,
+                        two_sided: true,
+                    },
+                    Material::Mix {
+                        first: 0,
+                        second: 1,
+                        mask: 2,
+                    },
+                ],
+                textures: vec![
+                    TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0))),
+                    TextureData::new(Texture::new_from_color(Vector3f32::new(0.0, 0.5, 0.0))),
+                    TextureData::new(Texture::new_from_color(Vector3f32::repeat(mask))),
+                ],
+                ..Default::default()
+            };
+            render(&scene, &render_params(Background::default()), (2, 2)).unwrap()
+        };
+
+        assert_eq!(render_mix(0.0), vec![[1.0; 3]; 4]);
+        assert_eq!(render_mix(1.0), vec![[0.0, 0.5, 0.0]; 4]);
+    }
+
+    #[test]
+    fn test_white_furnace() {
+        // A grey sphere inside a room that glows with a radiance of 1 everywhere reflects half of it. Every path
+        // reaches the room after a single bounce, so the estimate is exact up to rounding.
+        let scene = Scene {
+            spheres: vec![
+                Sphere::new(Vector3::new(0.0, 0.0, -10.0), 5.0, 0),
+                Sphere::new(Vector3::new(0.0, 0.0, 0.0), 100.0, 1),
+            ],
+            materials: vec![
+                Material::Lambertian {
+                    albedo: 0,
+                    normal_map: None,
+                    height_map: None,
+                    tint: Material::DEFAULT_TINT,
+                    uv_offset: 0.0,
+                },
+                Material::Emissive {
+                    emit: 1,
+                    two_sided: true,
+                },
+            ],
+            textures: vec![
+                TextureData::new(Texture::new_from_color(Vector3f32::repeat(0.5))),
+                TextureData::new(Texture::new_from_color(Vector3f32::repeat(1.0))),
+            ],
+            ..Default::default()
+        };
+        let mut render_params = render_params(Background::default());
+        render_params.sampling.max_samples_per_pixel = 64;
+        let image = render(&scene, &render_params, (3, 3)).unwrap();
+
+        for c in image[4] {
+            assert!((c - 0.5).abs() < 1e-3, "{:?}", image[4]);
+        }
+    }
+
+    #[test]
+    fn test_render_is_deterministic() {
+        let scene = Scene::stub();
+        let render_params = render_params(Background::default());
+
+        let image = render(&scene, &render_params, (6, 4)).unwrap();
+        assert_eq!(image, render(&scene, &render_params, (6, 4)).unwrap());
+        assert!(image.iter().flatten().all(|c| c.is_finite() && *c >= 0.0));
+    }
+
+    #[test]
+    fn test_cosine_sampling_beats_uniform_sampling() {
+        // White lambertian under a sky brighter on one side, which reflects a radiance of exactly 1.
+        let radiance = |direction: Vector3f32| 1.0 + direction.x;
+        let mut rng = Rng::new(0, 0, 1, 0);
+        let (mut cosine, mut uniform) = (Vec::new(), Vec::new());
+        for _ in 0..100_000 {
+            // With a pdf of cos / pi the cosine and the 1 / pi of the BRDF cancel out.
+            cosine.push(radiance(rng.next_in_cosine_weighted_hemisphere()));
+
+            let z = rng.next_float();
+            let phi = 2.0 * PI * rng.next_float();
+            let r = (1.0 - z * z).sqrt();
+            let direction = Vector3f32::new(r * phi.cos(), r * phi.sin(), z);
+            uniform.push(radiance(direction) * z * FRAC_1_PI * 2.0 * PI);
+        }
+
+        let mean = |samples: &[f32]| samples.iter().sum::<f32>() / samples.len() as f32;
+        let variance = |samples: &[f32]| {
+            let mean = mean(samples);
+            samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / samples.len() as f32
+        };
+        assert!((mean(&cosine) - 1.0).abs() < 0.01);
+        assert!((mean(&uniform) - 1.0).abs() < 0.01);
+        assert!(variance(&cosine) < 0.5 * variance(&uniform));
+    }
+
+    #[test]
+    fn test_clipped_sphere() {
+        // Lower half of a unit sphere at the origin, hit by rays straight down.
+        let hemisphere = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, 0).with_clip(Vector3f32::y(), 0.0, true);
+        let down = |x: f32| Ray {
+            origin: Vector3f32::new(x, 5.0, 0.0),
+            direction: -Vector3f32::y(),
+        };
+
+        let hit = ray_intersect_sphere(&down(0.0), &[hemisphere], 0, MIN_T, f32::MAX).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+        assert_eq!(hit.n, Vector3f32::y());
+
+        let bowl = hemisphere.with_clip(Vector3f32::y(), 0.0, false);
+        let hit = ray_intersect_sphere(&down(0.0), &[bowl], 0, MIN_T, f32::MAX).unwrap();
+        assert!((hit.t - 6.0).abs() < 1e-5);
+        assert!(ray_intersect_sphere(&down(1.5), &[bowl], 0, MIN_T, f32::MAX).is_none());
+
+        let whole = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, 0);
+        let hit = ray_intersect_sphere(&down(0.0), &[whole], 0, MIN_T, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_csg_of_two_spheres() {
+        // Unit spheres at the origin and one above it, hit by a ray straight down their axis.
+        let ray = Ray {
+            origin: Vector3f32::new(0.0, 5.0, 0.0),
+            direction: -Vector3f32::y(),
+        };
+        let mut scene = Scene {
+            spheres: vec![
+                Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, 0),
+                Sphere::new(Vector3::new(0.0, 1.0, 0.0), 1.0, 0),
+            ],
+            ..Default::default()
+        };
+        let mut hit = |operation| {
+            scene.csg_ops = vec![CsgOp::new(operation, 0, 1)];
+            ray_intersect_csg(&ray, &scene, 0, MIN_T, f32::MAX).unwrap()
+        };
+
+        let union = hit(CsgOperation::Union);
+        assert!((union.t - 3.0).abs() < 1e-5);
+        assert_eq!(union.sphere_idx, 1);
+
+        let intersection = hit(CsgOperation::Intersection);
+        assert!((intersection.t - 4.0).abs() < 1e-5);
+        assert_eq!(intersection.sphere_idx, 0);
+
+        let difference = hit(CsgOperation::Difference);
+        assert!((difference.t - 5.0).abs() < 1e-5);
+        assert_eq!(difference.sphere_idx, 1);
+        assert!((difference.n - Vector3f32::y()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn test_scaled_sphere_hit() {
+        let transform = Matrix4f32::new_nonuniform_scaling(&Vector3f32::new(2.0, 1.0, 1.0));
+        let spheres = [Sphere::new(Vector3::zeros(), 1.0, 0).with_transform(&transform)];
+        let hit = |origin: Vector3f32, direction: Vector3f32| {
+            ray_intersect_sphere(&Ray { origin, direction }, &spheres, 0, MIN_T, f32::MAX)
+        };
+
+        let side = hit(Vector3f32::new(5.0, 0.0, 0.0), -Vector3f32::x()).unwrap();
+        assert!((side.t - 3.0).abs() < 1e-5);
+        assert!((side.p - Vector3f32::new(2.0, 0.0, 0.0)).norm() < 1e-5);
+        assert!((side.n - Vector3f32::x()).norm() < 1e-5);
+
+        let top = hit(Vector3f32::new(0.0, 5.0, 0.0), -Vector3f32::y()).unwrap();
+        assert!((top.t - 4.0).abs() < 1e-5);
+        assert!(hit(Vector3f32::new(5.0, 1.5, 0.0), -Vector3f32::x()).is_none());
+    }
+
+    #[test]
+    fn test_stratified_samples_cover_the_pixel() {
+        let scene = Scene::default();
+        let mut render_params = render_params(Background::default());
+        render_params.sampling.aa_pattern = AaPattern::Stratified;
+        let size = (8, 8);
+        let tracer = Tracer {
+            scene: &scene,
+            primitives: Vec::new(),
+            render_params: &render_params,
+            camera: GpuCamera::new(&render_params.camera, size),
+            sky: render_params.sky.to_sky_state().0,
+            size,
+        };
+
+        let mut rng = Rng::new(3, 5, size.0, 0);
+        let mut cells = [0; 16];
+        for sample_index in 16..32 {
+            let [x, y, lens_x, lens_y] = tracer.aa_sample(3, 5, sample_index, &mut rng);
+            assert!([x, y, lens_x, lens_y].iter().all(|c| (0.0..1.0).contains(c)));
+            cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+        }
+        assert_eq!(cells, [1; 16]);
+
+        // The lens cell of a sample is paired with its pixel cell differently from run to run.
+        let pairings: std::collections::BTreeSet<_> = (0..256)
+            .map(|sample_index| {
+                let [x, y, lens_x, lens_y] = tracer.aa_sample(3, 5, sample_index, &mut rng);
+                let cell = |u: f32, v: f32| (v * 4.0) as u32 * 4 + (u * 4.0) as u32;
+                cell(x, y) ^ cell(lens_x, lens_y)
+            })
+            .collect();
+        assert!(pairings.len() > 1, "{pairings:?}");
+    }
+
+    #[test]
+    fn test_quasi_random_samples_stay_exact() {
+        let scene = Scene::default();
+        let mut render_params = render_params(Background::default());
+        render_params.sampling.aa_pattern = AaPattern::QuasiRandom;
+        let size = (8, 8);
+        let tracer = Tracer {
+            scene: &scene,
+            primitives: Vec::new(),
+            render_params: &render_params,
+            camera: GpuCamera::new(&render_params.camera, size),
+            sky: render_params.sky.to_sky_state().0,
+            size,
+        };
+
+        // Late in a long render, consecutive samples are still one step of the sequence apart.
+        let mut rng = Rng::new(3, 5, size.0, 0);
+        let samples: Vec<_> = (65_520..65_536)
+            .map(|sample_index| tracer.aa_sample(3, 5, sample_index, &mut rng))
+            .collect();
+        for pair in samples.windows(2) {
+            assert!(pair[1].iter().all(|c| (0.0..1.0).contains(c)));
+            let step = (pair[1][0] - pair[0][0]).rem_euclid(1.0);
+            assert!((step - 0.85667488).abs() < 1e-5, "{step}");
+        }
+    }
+
+    #[test]
+    fn test_exact_fresnel_against_schlick() {
+        let ior = 1.5;
+        assert!((fresnel_dielectric(1.0, 1.0 / ior) - 0.04).abs() < 1e-6);
+        assert_eq!(fresnel_dielectric(0.1, ior), 1.0);
+
+        let mut previous = 0.0;
+        for i in 0..=100 {
+            let cos_i = 1.0 - i as f32 / 100.0;
+            let reflectance = fresnel_dielectric(cos_i, 1.0 / ior);
+            assert!(reflectance >= previous - 1e-6);
+            if cos_i >= 0.2 {
+                assert!((reflectance - schlick(cos_i, ior)).abs() < 0.05);
+            } else {
+                // Schlick falls short near grazing angles, where the exact reflectance goes to 1.
+                assert!(reflectance > schlick(cos_i, ior));
+            }
+            previous = reflectance;
+
+            // Light leaving the glass along the refracted ray is reflected just as much.
+            let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (ior * ior)).sqrt();
+            assert!((fresnel_dielectric(cos_t, ior) - reflectance).abs() < 1e-4);
+        }
+        assert!(previous > 0.99);
+    }
+}
@@ -60,12 +60,23 @@ pub struct StorageBuffer {
 
 impl StorageBuffer {
     pub fn new_from_bytes(device: &wgpu::Device, bytes: &[u8], binding_idx: u32, label: Option<&str>) -> Self {
+        Self::new_with_usage(device, bytes, binding_idx, wgpu::BufferUsages::empty(), label)
+    }
+
+    /// Like [`Self::new_from_bytes`], with `usage` on top of the usages every storage buffer has.
+    pub fn new_with_usage(
+        device: &wgpu::Device,
+        bytes: &[u8],
+        binding_idx: u32,
+        usage: wgpu::BufferUsages,
+        label: Option<&str>,
+    ) -> Self {
         const EMPTY: [u8; 32] = [0; 32];
 
         let bytes = if bytes.is_empty() { &EMPTY } else { bytes };
         let handle = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             contents: bytes,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | usage,
             label,
         });
 
@@ -6,30 +6,72 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use self::scene::Scene;
-pub use self::texture::Texture;
+pub use self::texture::{Texture, TextureError, WrapMode};
+pub use self::timings::FrameTimings;
+use self::timings::Stopwatch;
 use crate::node::camera::CameraNode;
-use crate::types::{Angle, Matrix4f32, Vector3, Vector3f32, from_vector3_to_vector3f32};
+use crate::types::{Angle, Matrix4f32, UpAxis, Vector3, Vector3f32, from_vector3_to_vector3f32};
 
+pub mod cpu;
 mod gpu_buffer;
 pub mod scene;
+#[cfg(debug_assertions)]
+mod shader_reload;
+pub mod still;
 mod texture;
+mod timings;
 
 use std::f32::consts::*;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub struct Raytracer {
     vertex_uniform_bind_group: wgpu::BindGroup,
     vertex_buffer: wgpu::Buffer,
     frame_data_buffer: UniformBuffer,
     image_bind_group: wgpu::BindGroup,
+    luminance_buffer: StorageBuffer,
+    noise_buffer: StorageBuffer,
+    noise_readback: NoiseReadback,
     camera_buffer: UniformBuffer,
     sampling_parameter_buffer: UniformBuffer,
     hw_sky_state_buffer: StorageBuffer,
     parameter_bind_group: wgpu::BindGroup,
     scene_group: SceneBuffersGroup,
-    pipeline: wgpu::RenderPipeline,
+    /// Kept to recreate the pipelines from a reloaded shader.
+    #[cfg(debug_assertions)]
+    pipeline_layout: wgpu::PipelineLayout,
+    pipelines: ShaderPipelines,
+    #[cfg(debug_assertions)]
+    shader_reload: Option<shader_reload::ShaderReload>,
+    /// Why the edited shader was rejected, the pipelines keep drawing with the previous one in the meantime.
+    shader_error: Option<String>,
+    target_format: wgpu::TextureFormat,
+    upscale_pipeline: wgpu::RenderPipeline,
+    upscale_bind_group_layout: wgpu::BindGroupLayout,
+    upscale_sampler: wgpu::Sampler,
+    /// Present while rendering below the viewport resolution or denoising.
+    offscreen_target: Option<OffscreenTarget>,
     latest_render_params: RenderParams,
     render_progress: RenderProgress,
     frame_number: u32,
+    /// First frame of the current render, statistics read back from earlier frames are discarded.
+    restarted_at: u32,
+    /// See [`Self::estimated_noise`].
+    estimated_noise: f32,
+    /// Keeps the accumulated samples without adding any, see [`Self::set_paused`].
+    paused: bool,
+    /// Added to the seed, so a restarted render takes different samples than the one it replaced.
+    reseed: u32,
+    /// Why the sky model rejected the latest sky parameters, the shader draws a gradient in the meantime.
+    sky_error: Option<String>,
+    /// Why the latest scene couldn't be uploaded, the previous one is drawn in the meantime.
+    scene_error: Option<String>,
+    /// Written while painting, which only has shared access.
+    timings: Mutex<FrameTimings>,
+    /// Started with the latest frame, see [`FrameTimings::frame`].
+    frame_stopwatch: Option<Stopwatch>,
 }
 
 impl Raytracer {
@@ -45,6 +87,10 @@ impl Raytracer {
             Ok(_) => {},
             Err(err) => return Err(err),
         }
+        let limits = device.limits();
+        let pixel_buffer_size = u64::from(max_viewport_resolution) * size_of::<[f32; 4]>() as u64;
+        check_buffer_size("image", pixel_buffer_size, &limits)?;
+        check_buffer_size("bloom", pixel_buffer_size, &limits)?;
 
         let uniforms = VertexUniforms {
             view_projection_matrix: unit_quad_projection_matrix(),
@@ -65,20 +111,58 @@ impl Raytracer {
         let frame_data_buffer = UniformBuffer::new(device, 16_u64, 0, Some("frame data buffer"));
 
         let image_buffer = {
-            let buffer = vec![[0.0; 3]; max_viewport_resolution as usize];
+            let buffer = vec![[0.0f32; 4]; max_viewport_resolution as usize];
             StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(buffer.as_slice()), 1, Some("image buffer"))
         };
 
+        let luminance_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(&[GpuLuminanceStats::default(); 2]),
+            2,
+            Some("luminance buffer"),
+        );
+
+        // Sum of the squared luminance of every sample, next to the sums of the image buffer.
+        let square_buffer = {
+            let buffer = vec![0.0f32; max_viewport_resolution as usize];
+            StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(&buffer), 3, Some("square buffer"))
+        };
+
+        let noise_buffer = StorageBuffer::new_with_usage(
+            device,
+            bytemuck::cast_slice(&[GpuNoiseStats::default(); 2]),
+            4,
+            wgpu::BufferUsages::COPY_SRC,
+            Some("noise buffer"),
+        );
+
+        // Two halves for the bloom passes, each one holds a quarter of the image resolution with some margin.
+        let bloom_buffer = {
+            let buffer = vec![[0.0f32; 4]; max_viewport_resolution as usize];
+            StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(&buffer), 5, Some("bloom buffer"))
+        };
+
         let image_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 frame_data_buffer.layout(wgpu::ShaderStages::FRAGMENT),
                 image_buffer.layout(wgpu::ShaderStages::FRAGMENT, false),
+                luminance_buffer.layout(wgpu::ShaderStages::FRAGMENT, false),
+                square_buffer.layout(wgpu::ShaderStages::FRAGMENT, false),
+                noise_buffer.layout(wgpu::ShaderStages::FRAGMENT, false),
+                bloom_buffer.layout(wgpu::ShaderStages::FRAGMENT, false),
             ],
             label: Some("image layout"),
         });
         let image_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &image_bind_group_layout,
-            entries: &[frame_data_buffer.binding(), image_buffer.binding()],
+            entries: &[
+                frame_data_buffer.binding(),
+                image_buffer.binding(),
+                luminance_buffer.binding(),
+                square_buffer.binding(),
+                noise_buffer.binding(),
+                bloom_buffer.binding(),
+            ],
             label: Some("image bind group"),
         });
 
@@ -95,11 +179,9 @@ impl Raytracer {
             Some("sampling parameter buffer"),
         );
 
-        let hw_sky_state_buffer = {
-            let sky_state = render_params.sky.to_sky_state()?;
-
-            StorageBuffer::new_from_bytes(device, bytemuck::bytes_of(&sky_state), 2, Some("sky state buffer"))
-        };
+        let (sky_state, sky_error) = render_params.sky.to_sky_state();
+        let hw_sky_state_buffer =
+            StorageBuffer::new_from_bytes(device, bytemuck::bytes_of(&sky_state), 2, Some("sky state buffer"));
 
         let parameter_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
@@ -120,12 +202,7 @@ impl Raytracer {
             label: Some("parameter bind group"),
         });
 
-        let scene_group = SceneBuffersGroup::new(scene, device);
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            source: wgpu::ShaderSource::Wgsl(include_str!("raytracer_shader.wgsl").into()),
-            label: Some("raytracer_shader.wgsl"),
-        });
+        let scene_group = SceneBuffersGroup::new(scene, device)?;
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             bind_group_layouts: &[
@@ -137,50 +214,69 @@ impl Raytracer {
             push_constant_ranges: &[],
             label: Some("raytracer layout"),
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            layout: Some(&pipeline_layout),
+        let pipelines = ShaderPipelines::new(device, &pipeline_layout, target_format, RAYTRACER_SHADER);
+
+        let upscale_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscale_shader.wgsl").into()),
+            label: Some("upscale_shader.wgsl"),
+        });
+
+        let upscale_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("upscale layout"),
+        });
+
+        let upscale_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&upscale_bind_group_layout],
+            push_constant_ranges: &[],
+            label: Some("upscale pipeline layout"),
+        });
+        let upscale_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: Some(&upscale_pipeline_layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: &upscale_shader,
                 entry_point: Some("vsMain"),
-                buffers: &[Vertex::desc()],
+                buffers: &[],
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: &upscale_shader,
                 entry_point: Some("fsMain"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: target_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+                targets: &[Some(target_format.into())],
                 compilation_options: Default::default(),
             }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                cull_mode: Some(wgpu::Face::Back),
-                // Requires Features::DEPTH_CLAMPING
-                conservative: false,
-                unclipped_depth: false,
-            },
+            primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            label: Some("raytracer pipeline"),
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
+            multisample: wgpu::MultisampleState::default(),
+            label: Some("upscale pipeline"),
             multiview: None,
             cache: None,
         });
 
+        let upscale_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            label: Some("upscale sampler"),
+            ..Default::default()
+        });
+
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             contents: bytemuck::cast_slice(VERTICES),
             usage: wgpu::BufferUsages::VERTEX,
@@ -195,35 +291,127 @@ impl Raytracer {
             vertex_uniform_bind_group,
             frame_data_buffer,
             image_bind_group,
+            luminance_buffer,
+            noise_buffer,
+            noise_readback: NoiseReadback::new(device),
             camera_buffer,
             sampling_parameter_buffer,
             hw_sky_state_buffer,
             parameter_bind_group,
             scene_group,
             vertex_buffer,
-            pipeline,
+            #[cfg(debug_assertions)]
+            pipeline_layout,
+            pipelines,
+            #[cfg(debug_assertions)]
+            shader_reload: shader_reload::ShaderReload::new()
+                .inspect_err(|err| eprintln!("Watching the raytracer shader failed: {err}"))
+                .ok(),
+            shader_error: None,
+            target_format,
+            upscale_pipeline,
+            upscale_bind_group_layout,
+            upscale_sampler,
+            offscreen_target: None,
             latest_render_params: *render_params,
             render_progress,
             frame_number,
+            restarted_at: frame_number,
+            estimated_noise: 1.0,
+            paused: false,
+            reseed: 0,
+            sky_error: sky_error.map(|err| err.to_string()),
+            scene_error: None,
+            timings: Mutex::default(),
+            frame_stopwatch: None,
         })
     }
 
+    /// Renders at the scaled resolution of `render_params`. Below the viewport resolution or with
+    /// denoising the frame is drawn into an offscreen texture with `encoder` and stretched in `render_frame`.
     pub fn prepare_frame(
         &mut self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
         render_params: &RenderParams,
         scene: Option<&Scene>,
         viewport_size: (u32, u32),
-    ) {
-        self.set_render_params(queue, scene.is_some(), render_params, viewport_size)
-            .expect("Render params should be valid");
+    ) -> Result<(), RenderParamsValidationError> {
+        let stopwatch = Stopwatch::start();
+        let previous_frame = self.frame_stopwatch.replace(stopwatch);
+        let frame = previous_frame.map(|previous| previous.elapsed());
+        let result = self.prepare(device, queue, encoder, render_params, scene, viewport_size);
+
+        let timings = self.timings.get_mut().unwrap();
+        timings.frame = frame.unwrap_or_default();
+        timings.prepare = stopwatch.elapsed();
+        result
+    }
+
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        render_params: &RenderParams,
+        scene: Option<&Scene>,
+        viewport_size: (u32, u32),
+    ) -> Result<(), RenderParamsValidationError> {
+        #[cfg(debug_assertions)]
+        self.reload_shader(device);
 
-        if let Some(scene) = scene {
-            self.scene_group.update(&device, &queue, scene);
+        let paused = self.paused;
+        let render_size = render_params.sampling.scaled_size(viewport_size);
+        if !paused {
+            self.set_render_params(queue, render_params, render_size)?;
+        }
+        let (crop_min, crop_max) = self.latest_render_params.crop.pixel_bounds(render_size);
+
+        if let Some(scene) = scene.filter(|_| !paused) {
+            let stopwatch = Stopwatch::start();
+            let updated = self.scene_group.update(&device, &queue, scene);
+            self.timings.get_mut().unwrap().scene_update = stopwatch.elapsed();
+            if apply_scene_update(updated, &mut self.render_progress, &mut self.scene_error) {
+                self.restarted();
+            }
         }
 
-        let gpu_sampling_params = self.render_progress.next_frame(&self.latest_render_params.sampling);
+        let sampling_params = self.latest_render_params.sampling;
+        let progress = if paused {
+            self.render_progress.paused_frame(&sampling_params)
+        } else {
+            self.render_progress
+                .next_frame(&sampling_params, pixel_count(crop_min, crop_max))
+        };
+        self.timings.get_mut().unwrap().samples = progress.num_samples_per_pixel;
+
+        // Exposure and bloom don't restart accumulation, so they are taken from the current parameters.
+        let (background_mode, background_top, background_bottom) = self.latest_render_params.background.to_gpu();
+        let (bloom_threshold, bloom_intensity) = BloomParams::to_gpu(render_params.bloom);
+        let gpu_sampling_params = GpuSamplingParams {
+            debug_flags: self.latest_render_params.debug.flags(),
+            num_emissive_lights: self.scene_group.light_count(),
+            exposure: render_params.exposure.exposure,
+            auto_exposure_key: render_params.exposure.auto_exposure_key(),
+            camera_exposure: render_params.camera.exposure,
+            white_balance: render_params.camera.white_balance_gain(),
+            max_ray_distance: render_params.gpu_max_ray_distance(),
+            bilinear_filtering: self.latest_render_params.sampling.bilinear_filtering as _,
+            exact_fresnel: self.latest_render_params.sampling.exact_fresnel as _,
+            aa_pattern: self.latest_render_params.sampling.aa_pattern.to_gpu(),
+            crop_min: [crop_min.0, crop_min.1],
+            crop_max: [crop_max.0, crop_max.1],
+            output_channel: self.latest_render_params.output_channel.to_gpu(),
+            variance_threshold: sampling_params.gpu_variance_threshold(),
+            background_top,
+            background_mode,
+            background_bottom,
+            bloom_threshold,
+            bloom_intensity,
+            seed: progress.seed.wrapping_add(self.reseed),
+            ..progress
+        };
 
         queue.write_buffer(
             self.sampling_parameter_buffer.handle(),
@@ -232,14 +420,136 @@ impl Raytracer {
         );
 
         let frame_number = self.frame_number;
-        let frame_data = [viewport_size.0, viewport_size.1, frame_number];
+        // Targets without an sRGB format store what they are given, so the resolve pass encodes it.
+        let encode_srgb = u32::from(!self.target_format.is_srgb());
+        let frame_data = [render_size.0, render_size.1, frame_number, encode_srgb];
         queue.write_buffer(self.frame_data_buffer.handle(), 0, bytemuck::cast_slice(&frame_data));
 
+        // This frame accumulates luminance into the slot of its parity, which was filled two frames ago.
+        let luminance_slot = (frame_number % 2) as wgpu::BufferAddress;
+        queue.write_buffer(
+            self.luminance_buffer.handle(),
+            luminance_slot * std::mem::size_of::<GpuLuminanceStats>() as wgpu::BufferAddress,
+            bytemuck::bytes_of(&GpuLuminanceStats::default()),
+        );
+        // Noise is slotted the same way, the other slot holds the noise of the previous frame.
+        let noise_size = std::mem::size_of::<GpuNoiseStats>() as wgpu::BufferAddress;
+        queue.write_buffer(
+            self.noise_buffer.handle(),
+            luminance_slot * noise_size,
+            bytemuck::bytes_of(&GpuNoiseStats::default()),
+        );
+        let previous_noise_slot = (1 - luminance_slot) * noise_size;
+        let readback = self.noise_readback.update(
+            device,
+            encoder,
+            self.noise_buffer.handle(),
+            previous_noise_slot,
+            frame_number - 1,
+        );
+        if let Some((_, stats)) = readback.filter(|(frame, _)| *frame >= self.restarted_at) {
+            if let Some(noise) = stats.mean_error() {
+                self.estimated_noise = noise;
+            }
+            if let Some(active_fraction) = stats.active_fraction() {
+                self.render_progress.set_active_fraction(active_fraction);
+            }
+        }
+
         self.frame_number += 1;
+
+        if render_size == viewport_size && !render_params.denoise && render_params.bloom.is_none() {
+            self.offscreen_target = None;
+            return Ok(());
+        }
+
+        if !self
+            .offscreen_target
+            .as_ref()
+            .is_some_and(|target| target.size == render_size)
+        {
+            self.offscreen_target = Some(self.create_offscreen_target(device, render_size));
+        }
+
+        if let Some(target) = &self.offscreen_target {
+            self.draw_offscreen(encoder, &target.view, &self.pipelines.main, "raytracer offscreen pass");
+            if render_params.bloom.is_some() {
+                self.draw_bloom(encoder, &target.view, render_size);
+            }
+            // Separate passes, so that every neighbour has been sampled before the filter reads it.
+            if render_params.denoise {
+                self.draw_offscreen(encoder, &target.view, &self.pipelines.denoise, "denoise pass");
+            } else if render_params.bloom.is_some() {
+                self.draw_offscreen(encoder, &target.view, &self.pipelines.resolve, "resolve pass");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_offscreen(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        pipeline: &wgpu::RenderPipeline,
+        label: &str,
+    ) {
+        let mut render_pass = Self::begin_offscreen_pass(encoder, view, label);
+        self.draw(&mut render_pass, pipeline);
+    }
+
+    /// Draws the bloom passes over the top left of `view`, at half the render resolution. Their results stay in
+    /// the bloom buffer, what they draw is overwritten by the pass that tonemaps the image.
+    fn draw_bloom(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, render_size: (u32, u32)) {
+        let bloom_size = (render_size.0.div_ceil(2), render_size.1.div_ceil(2));
+        let labels = [
+            "bloom prefilter pass",
+            "bloom horizontal blur pass",
+            "bloom vertical blur pass",
+        ];
+        for (pipeline, label) in self.pipelines.bloom.iter().zip(labels) {
+            let mut render_pass = Self::begin_offscreen_pass(encoder, view, label);
+            render_pass.set_viewport(0.0, 0.0, bloom_size.0 as f32, bloom_size.1 as f32, 0.0, 1.0);
+            self.draw(&mut render_pass, pipeline);
+        }
+    }
+
+    fn begin_offscreen_pass<'e>(
+        encoder: &'e mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        label: &str,
+    ) -> wgpu::RenderPass<'e> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            label: Some(label),
+        })
     }
 
     pub fn render_frame(&self, render_pass: &mut wgpu::RenderPass) {
-        render_pass.set_pipeline(&self.pipeline);
+        let stopwatch = Stopwatch::start();
+        match &self.offscreen_target {
+            Some(target) => {
+                render_pass.set_pipeline(&self.upscale_pipeline);
+                render_pass.set_bind_group(0, &target.bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            },
+            None => self.draw(render_pass, &self.pipelines.main),
+        }
+        self.timings.lock().unwrap().paint = stopwatch.elapsed();
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass, pipeline: &wgpu::RenderPipeline) {
+        render_pass.set_pipeline(pipeline);
         render_pass.set_bind_group(0, &self.vertex_uniform_bind_group, &[]);
         render_pass.set_bind_group(1, &self.image_bind_group, &[]);
         render_pass.set_bind_group(2, &self.parameter_bind_group, &[]);
@@ -253,11 +563,10 @@ impl Raytracer {
     pub fn set_render_params(
         &mut self,
         queue: &wgpu::Queue,
-        render_force: bool,
         render_params: &RenderParams,
         viewport_size: (u32, u32),
     ) -> Result<(), RenderParamsValidationError> {
-        if !render_force && *render_params == self.latest_render_params {
+        if self.is_current(render_params) {
             return Ok(());
         }
 
@@ -269,9 +578,12 @@ impl Raytracer {
             ));
         }
 
-        {
-            let sky_state = render_params.sky.to_sky_state()?;
+        // The sky buffer always holds the state of the latest parameters, and fitting the sky model
+        // is too slow to repeat for every camera move.
+        if render_params.sky != self.latest_render_params.sky {
+            let (sky_state, sky_error) = render_params.sky.to_sky_state();
             queue.write_buffer(self.hw_sky_state_buffer.handle(), 0, bytemuck::bytes_of(&sky_state));
+            self.sky_error = sky_error.map(|err| err.to_string());
         }
 
         {
@@ -281,31 +593,396 @@ impl Raytracer {
 
         self.latest_render_params = *render_params;
 
-        self.render_progress.reset();
+        self.restart();
 
         Ok(())
     }
 
+    fn create_offscreen_target(&self, device: &wgpu::Device, size: (u32, u32)) -> OffscreenTarget {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.target_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+            label: Some("offscreen texture"),
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.upscale_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.upscale_sampler),
+                },
+            ],
+            label: Some("upscale bind group"),
+        });
+
+        OffscreenTarget { size, view, bind_group }
+    }
+
     pub fn progress(&self) -> f32 {
-        self.render_progress.accumulated_samples() as f32
-            / self.latest_render_params.sampling.max_samples_per_pixel as f32
+        self.render_progress.progress(&self.latest_render_params.sampling)
+    }
+
+    /// Returns `true` if rendering with `render_params` continues the current accumulation.
+    /// Exposure, white balance, denoising and bloom are applied after accumulation, so changing them doesn't
+    /// restart the render.
+    pub fn is_current(&self, render_params: &RenderParams) -> bool {
+        RenderParams {
+            camera: Camera {
+                exposure: self.latest_render_params.camera.exposure,
+                white_balance: self.latest_render_params.camera.white_balance,
+                ..render_params.camera
+            },
+            exposure: self.latest_render_params.exposure,
+            denoise: self.latest_render_params.denoise,
+            bloom: self.latest_render_params.bloom,
+            ..*render_params
+        } == self.latest_render_params
+    }
+
+    /// Discards the accumulated samples and starts over with new random numbers.
+    pub fn reset(&mut self) {
+        self.restart();
+        self.reseed = self.reseed.wrapping_add(1);
+    }
+
+    fn restart(&mut self) {
+        self.render_progress.reset();
+        self.restarted();
+    }
+
+    /// Forgets what was measured about the samples before the render started over.
+    fn restarted(&mut self) {
+        self.restarted_at = self.frame_number;
+        self.estimated_noise = 1.0;
+    }
+
+    /// While paused, changes to the parameters and the scene are not applied, they restart the render once it is
+    /// resumed. The caller has to hold the scene back until then.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Returns `true` once the render stopped adding samples because of the sample count or time limit.
+    pub fn is_converged(&self) -> bool {
+        self.render_progress.is_converged()
+    }
+
+    /// Mean relative standard error of the luminance of the pixels, the fraction the average of the samples is
+    /// expected to be off by. It lags a few frames behind, and is 1 until the first estimate is read back.
+    pub fn estimated_noise(&self) -> f32 {
+        self.estimated_noise
+    }
+
+    /// Set while the sky parameters are rejected by the sky model and a plain gradient is drawn instead.
+    pub fn sky_error(&self) -> Option<&str> {
+        self.sky_error.as_deref()
+    }
+
+    pub fn scene_error(&self) -> Option<&str> {
+        self.scene_error.as_deref()
+    }
+
+    /// Always `None` in release builds, which only have the shader they were built with.
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
+
+    /// Recreates the pipelines once the shader file changed on disk. A shader that doesn't compile
+    /// is reported instead and the render continues with the previous one.
+    #[cfg(debug_assertions)]
+    fn reload_shader(&mut self, device: &wgpu::Device) {
+        let Some(shader_reload) = &self.shader_reload else {
+            return;
+        };
+        let Some(source) = shader_reload.take_source() else {
+            return;
+        };
+        match source {
+            Ok(source) => {
+                // Naga accepts shaders the pipelines can still reject, e.g. with bindings that don't match the layout.
+                device.push_error_scope(wgpu::ErrorFilter::Validation);
+                let pipelines = ShaderPipelines::new(device, &self.pipeline_layout, self.target_format, &source);
+                match pop_error_scope(device) {
+                    Some(err) => self.shader_error = Some(err.to_string()),
+                    None => {
+                        self.pipelines = pipelines;
+                        self.shader_error = None;
+                        self.reset();
+                    },
+                }
+            },
+            Err(err) => self.shader_error = Some(err),
+        }
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        RenderStats {
+            accumulated_samples: self.render_progress.mean_samples().round() as u32,
+            max_samples: self.latest_render_params.sampling.max_samples_per_pixel,
+            progress: self.progress(),
+            elapsed: self.render_progress.elapsed(),
+            samples_per_second: self.render_progress.samples_per_second(),
+            pixel_count: self.render_progress.pixel_count(),
+            noise: self.estimated_noise(),
+        }
+    }
+
+    pub fn frame_timings(&self) -> FrameTimings {
+        *self.timings.lock().unwrap()
+    }
+}
+
+/// Offscreen texture a frame is rendered into when it needs more than a single pass over the viewport.
+struct OffscreenTarget {
+    size: (u32, u32),
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Snapshot of the progress of the current render, per pixel.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderStats {
+    /// Average over the pixels, converged pixels stop taking samples when sampling adaptively.
+    pub accumulated_samples: u32,
+    pub max_samples: u32,
+    pub progress: f32,
+    pub elapsed: Duration,
+    pub samples_per_second: f64,
+    /// Pixels inside the crop rectangle, the only ones receiving samples.
+    pub pixel_count: u64,
+    /// See [`Raytracer::estimated_noise`].
+    pub noise: f32,
+}
+
+/// Copies the noise of a frame to a buffer the CPU can read, without waiting on the GPU. A copy is recorded in one
+/// frame, the buffer is mapped once that frame has been submitted, and it is read by a later frame when mapped.
+struct NoiseReadback {
+    buffer: wgpu::Buffer,
+    state: ReadbackState,
+    /// Set by the mapping callback, `Some(true)` once the buffer can be read.
+    mapped: Arc<Mutex<Option<bool>>>,
+    /// Frame whose noise was copied into `buffer`.
+    copied_frame: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadbackState {
+    Idle,
+    Copied,
+    Mapping,
+}
+
+impl NoiseReadback {
+    const SIZE: wgpu::BufferAddress = std::mem::size_of::<GpuNoiseStats>() as wgpu::BufferAddress;
+
+    fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+            label: Some("noise readback buffer"),
+        });
+
+        Self {
+            buffer,
+            state: ReadbackState::Idle,
+            mapped: Arc::new(Mutex::new(None)),
+            copied_frame: 0,
+        }
+    }
+
+    /// Advances the readback by one step, returns the noise of an earlier frame and its number once it was read.
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        frame: u32,
+    ) -> Option<(u32, GpuNoiseStats)> {
+        match self.state {
+            ReadbackState::Idle => {
+                encoder.copy_buffer_to_buffer(source, offset, &self.buffer, 0, Self::SIZE);
+                self.copied_frame = frame;
+                self.state = ReadbackState::Copied;
+                None
+            },
+            ReadbackState::Copied => {
+                let mapped = Arc::clone(&self.mapped);
+                self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                    *mapped.lock().unwrap() = Some(result.is_ok());
+                });
+                self.state = ReadbackState::Mapping;
+                None
+            },
+            ReadbackState::Mapping => {
+                device.poll(wgpu::Maintain::Poll);
+                let mapped = self.mapped.lock().unwrap().take()?;
+                self.state = ReadbackState::Idle;
+
+                if !mapped {
+                    eprintln!("Reading back the render noise failed");
+                    return None;
+                }
+                let stats: GpuNoiseStats = *bytemuck::from_bytes(&self.buffer.slice(..).get_mapped_range());
+                self.buffer.unmap();
+                Some((self.copied_frame, stats))
+            },
+        }
+    }
+}
+
+const RAYTRACER_SHADER: &str = include_str!("raytracer_shader.wgsl");
+
+/// Pipelines drawing with the entry points of the raytracer shader.
+/// Debug builds recreate them from the file on disk when it changes, see `Raytracer::reload_shader`.
+struct ShaderPipelines {
+    main: wgpu::RenderPipeline,
+    /// Filters the image accumulated by the main pipeline in a separate pass.
+    denoise: wgpu::RenderPipeline,
+    resolve: wgpu::RenderPipeline,
+    /// Prefilter, horizontal and vertical blur, see [`Raytracer::draw_bloom`].
+    bloom: [wgpu::RenderPipeline; 3],
+}
+
+impl ShaderPipelines {
+    fn new(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        target_format: wgpu::TextureFormat,
+        source: &str,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+            label: Some("raytracer_shader.wgsl"),
+        });
+
+        let create_pipeline = |fragment_entry_point: &str, label: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                layout: Some(pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vsMain"),
+                    buffers: &[Vertex::desc()],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(fragment_entry_point),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent::REPLACE,
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    cull_mode: Some(wgpu::Face::Back),
+                    // Requires Features::DEPTH_CLAMPING
+                    conservative: false,
+                    unclipped_depth: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                label: Some(label),
+                // If the pipeline will be used with a multiview render pass, this
+                // indicates how many array layers the attachments will have.
+                multiview: None,
+                cache: None,
+            })
+        };
+        Self {
+            main: create_pipeline("fsMain", "raytracer pipeline"),
+            denoise: create_pipeline("fsDenoise", "denoise pipeline"),
+            resolve: create_pipeline("fsResolve", "resolve pipeline"),
+            bloom: [
+                create_pipeline("fsBloomPrefilter", "bloom prefilter pipeline"),
+                create_pipeline("fsBloomBlurHorizontal", "bloom horizontal blur pipeline"),
+                create_pipeline("fsBloomBlurVertical", "bloom vertical blur pipeline"),
+            ],
+        }
     }
 }
 
 #[derive(Error, Debug)]
 pub enum RenderParamsValidationError {
+    #[error("num_samples_per_pixel must be greater than zero")]
+    SampleCountZero,
     #[error("max_samples_per_pixel ({0}) is not a multiple of num_samples_per_pixel ({1})")]
     MaxSampleCountNotMultiple(u32, u32),
+    #[error("time_limit_secs must be a finite non-negative number")]
+    TimeLimitOutOfRange(f64),
+    #[error("max_ray_distance must be a finite non-negative number")]
+    MaxRayDistanceOutOfRange(f32),
+    #[error("key_value must be between 0.01..=1")]
+    KeyValueOutOfRange(f32),
+    #[error("bloom threshold must be between 0..=10 and intensity between 0..=1")]
+    BloomOutOfRange(BloomParams),
     #[error("viewport_size elements cannot be zero: ({0}, {1})")]
     ViewportSize(u32, u32),
-    #[error("vfov must be between 0..=90 degrees")]
+    #[error("vfov must be between 0.1..=90 degrees")]
     VfovOutOfRange(f64),
     #[error("aperture must be between 0..=1")]
     ApertureOutOfRange(f64),
     #[error("focus_distance must be greater than zero")]
     FocusDistanceOutOfRange(f64),
-    #[error(transparent)]
-    HwSkyModelValidationError(#[from] hw_skymodel::rgb::Error),
+    #[error("orthographic scale must be a finite positive number")]
+    ScaleOutOfRange(f64),
+    #[error("bokeh_blades must be 0 or between 3..=12")]
+    BokehBladesOutOfRange(u32),
+    #[error("crop must lie within 0..=1 and be larger than zero")]
+    CropOutOfRange(CropRect),
+    #[error("resolution_scale must be between 0.25..=1")]
+    ResolutionScaleOutOfRange(f32),
+    #[error("sun_angular_radius must be between 0.05..=10 degrees")]
+    SunAngularRadiusOutOfRange(f64),
+    #[error("{0} buffer needs {1} bytes, the device allows at most {2}")]
+    BufferTooLarge(&'static str, u64, u64),
+}
+
+/// Error scopes of native backends are resolved by the time they are popped, so the future is polled once
+/// instead of awaited.
+#[cfg(debug_assertions)]
+fn pop_error_scope(device: &wgpu::Device) -> Option<wgpu::Error> {
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    match std::pin::pin!(device.pop_error_scope()).poll(&mut context) {
+        std::task::Poll::Ready(error) => error,
+        std::task::Poll::Pending => None,
+    }
+}
+
+/// Storage buffers are bound whole, so they are limited by the binding size as well as the buffer size.
+fn check_buffer_size(label: &'static str, size: u64, limits: &wgpu::Limits) -> Result<(), RenderParamsValidationError> {
+    let limit = u64::from(limits.max_storage_buffer_binding_size).min(limits.max_buffer_size);
+    if size > limit {
+        return Err(RenderParamsValidationError::BufferTooLarge(label, size, limit));
+    }
+    Ok(())
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -313,10 +990,36 @@ pub struct RenderParams {
     pub camera: Camera,
     pub sky: SkyParams,
     pub sampling: SamplingParams,
+    pub debug: DebugParams,
+    pub exposure: ExposureParams,
+    /// Edge-aware filter over the accumulated image, for a cleaner preview at low sample counts.
+    #[serde(default)]
+    pub denoise: bool,
+    #[serde(default)]
+    pub crop: CropRect,
+    #[serde(default)]
+    pub background: Background,
+    #[serde(default)]
+    pub output_channel: OutputChannel,
+    /// Glow around the highlights, none for physically accurate output.
+    #[serde(default)]
+    pub bloom: Option<BloomParams>,
+    /// Distance from the ray origin beyond which hits count as misses, zero means no limit.
+    #[serde(default)]
+    pub max_ray_distance: f32,
 }
 
 impl RenderParams {
-    fn validate(&self) -> Result<(), RenderParamsValidationError> {
+    /// Zero lets the rays go as far as the shader allows.
+    fn gpu_max_ray_distance(&self) -> f32 {
+        if self.max_ray_distance > 0.0 { self.max_ray_distance } else { f32::MAX }
+    }
+
+    pub fn validate(&self) -> Result<(), RenderParamsValidationError> {
+        if self.sampling.num_samples_per_pixel == 0 {
+            return Err(RenderParamsValidationError::SampleCountZero);
+        }
+
         if self.sampling.max_samples_per_pixel % self.sampling.num_samples_per_pixel != 0 {
             return Err(RenderParamsValidationError::MaxSampleCountNotMultiple(
                 self.sampling.max_samples_per_pixel,
@@ -324,13 +1027,49 @@ impl RenderParams {
             ));
         }
 
-        if !(Angle::degrees(0.0)..=Angle::degrees(90.0)).contains(&self.camera.vfov) {
+        if !self.sampling.time_limit_secs.is_finite() || self.sampling.time_limit_secs < 0.0 {
+            return Err(RenderParamsValidationError::TimeLimitOutOfRange(
+                self.sampling.time_limit_secs,
+            ));
+        }
+
+        if !self.max_ray_distance.is_finite() || self.max_ray_distance < 0.0 {
+            return Err(RenderParamsValidationError::MaxRayDistanceOutOfRange(
+                self.max_ray_distance,
+            ));
+        }
+
+        if !SamplingParams::RESOLUTION_SCALE_RANGE.contains(&self.sampling.resolution_scale) {
+            return Err(RenderParamsValidationError::ResolutionScaleOutOfRange(
+                self.sampling.resolution_scale,
+            ));
+        }
+
+        if !self.crop.is_valid() {
+            return Err(RenderParamsValidationError::CropOutOfRange(self.crop));
+        }
+
+        if !ExposureParams::KEY_VALUE_RANGE.contains(&self.exposure.key_value) {
+            return Err(RenderParamsValidationError::KeyValueOutOfRange(self.exposure.key_value));
+        }
+
+        if let Some(bloom) = self.bloom.filter(|bloom| !bloom.is_valid()) {
+            return Err(RenderParamsValidationError::BloomOutOfRange(bloom));
+        }
+
+        if !SkyParams::SUN_ANGULAR_RADIUS_RANGE.contains(&self.sky.sun_angular_radius.as_degrees()) {
+            return Err(RenderParamsValidationError::SunAngularRadiusOutOfRange(
+                self.sky.sun_angular_radius.as_degrees(),
+            ));
+        }
+
+        if !Camera::VFOV_RANGE.contains(&self.camera.vfov.as_degrees()) {
             return Err(RenderParamsValidationError::VfovOutOfRange(
                 self.camera.vfov.as_degrees(),
             ));
         }
 
-        if !(0.0..=1.0).contains(&self.camera.aperture) {
+        if !Camera::APERTURE_RANGE.contains(&self.camera.aperture) {
             return Err(RenderParamsValidationError::ApertureOutOfRange(self.camera.aperture));
         }
 
@@ -340,24 +1079,115 @@ impl RenderParams {
             ));
         }
 
+        if self.camera.bokeh_blades != 0 && !Camera::BOKEH_BLADES_RANGE.contains(&self.camera.bokeh_blades) {
+            return Err(RenderParamsValidationError::BokehBladesOutOfRange(
+                self.camera.bokeh_blades,
+            ));
+        }
+
+        if let CameraProjection::Orthographic { scale } = self.camera.projection {
+            if !scale.is_finite() || scale <= 0.0 {
+                return Err(RenderParamsValidationError::ScaleOutOfRange(scale));
+            }
+        }
+
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Camera {
     pub eye_pos: Vector3,
     pub eye_dir: Vector3,
     pub up: Vector3,
-    /// Angle must be between 0..=90 degrees.
+    /// Angle must be between 0.1..=90 degrees, a zero angle would collapse the image.
     pub vfov: Angle,
     /// Aperture must be between 0..=1.
     pub aperture: f64,
     /// Focus distance must be a positive number.
     pub focus_distance: f64,
+    pub projection: CameraProjection,
+    /// Number of aperture blades shaping out-of-focus highlights, 0 gives a circular aperture.
+    #[serde(default)]
+    pub bokeh_blades: u32,
+    /// Brightness adjustment in stops on top of the render exposure, it scales the radiance by `2^exposure`.
+    #[serde(default)]
+    pub exposure: f32,
+    /// Color temperature in Kelvin of the light that appears white, see [`Self::white_balance_gain`].
+    #[serde(default = "Camera::default_white_balance")]
+    pub white_balance: f32,
+}
+
+/// Looks down the negative z axis from the origin, with parameters that pass validation.
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye_pos: Vector3::zeros(),
+            eye_dir: -Vector3::z(),
+            up: Vector3::y(),
+            vfov: Angle::degrees(30.0),
+            aperture: 0.0,
+            focus_distance: 1.0,
+            projection: CameraProjection::default(),
+            bokeh_blades: 0,
+            exposure: 0.0,
+            white_balance: Self::NEUTRAL_WHITE_BALANCE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CameraProjection {
+    #[default]
+    Perspective,
+    /// Parallel rays, `scale` is the height of the view in world units.
+    /// Vfov, aperture and focus distance have no effect.
+    Orthographic { scale: f64 },
+}
+
+impl CameraProjection {
+    pub const DEFAULT_SCALE: f64 = 10.0;
+    /// Smallest scale that can be entered in the camera node.
+    pub const MIN_SCALE: f64 = 0.01;
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Perspective => "Perspective",
+            Self::Orthographic { .. } => "Orthographic",
+        }
+    }
 }
 
 impl Camera {
+    /// Valid vfov range in degrees, zero is left out because it collapses the image to a point.
+    pub const VFOV_RANGE: RangeInclusive<f64> = 0.1..=90.0;
+    pub const APERTURE_RANGE: RangeInclusive<f64> = 0.0..=1.0;
+    /// Smallest focus distance that can be entered in the camera node.
+    pub const MIN_FOCUS_DISTANCE: f64 = 0.01;
+    /// Valid blade counts of a polygonal aperture.
+    pub const BOKEH_BLADES_RANGE: RangeInclusive<u32> = 3..=12;
+    pub const EXPOSURE_RANGE: RangeInclusive<f64> = -20.0..=20.0;
+    /// Valid white balance range in Kelvin, covered by the fit in [`blackbody_color`].
+    pub const WHITE_BALANCE_RANGE: RangeInclusive<f64> = 1000.0..=40000.0;
+    /// White balance that leaves the colors unchanged.
+    pub const NEUTRAL_WHITE_BALANCE: f32 = 6500.0;
+
+    pub fn default_white_balance() -> f32 {
+        Self::NEUTRAL_WHITE_BALANCE
+    }
+
+    /// Per channel gain that turns light of the white balance temperature white, while keeping the luminance.
+    /// Like on a photo camera, a lower temperature cools the image down and a higher one warms it up.
+    pub fn white_balance_gain(&self) -> [f32; 3] {
+        let light = blackbody_color(self.white_balance);
+        let neutral = blackbody_color(Self::NEUTRAL_WHITE_BALANCE);
+        let gain: [f32; 3] = std::array::from_fn(|i| neutral[i] / light[i].max(1e-3));
+        let luminance = 0.2126 * gain[0] + 0.7152 * gain[1] + 0.0722 * gain[2];
+        gain.map(|channel| channel / luminance)
+    }
+
+    /// Values connected from other nodes are clamped to the valid ranges,
+    /// so a camera built from a node always passes validation.
     pub fn from_node(node: &CameraNode) -> Self {
         let orientation = node.orientation();
 
@@ -365,13 +1195,64 @@ impl Camera {
             eye_pos: node.position.get(),
             eye_dir: orientation.forward,
             up: orientation.up,
-            vfov: node.vfov.get(),
-            aperture: node.aperture.get(),
-            focus_distance: node.focus_distance.get(),
+            vfov: node.vfov.get().clamp(
+                Angle::degrees(*Self::VFOV_RANGE.start()),
+                Angle::degrees(*Self::VFOV_RANGE.end()),
+            ),
+            aperture: node
+                .aperture
+                .get()
+                .clamp(*Self::APERTURE_RANGE.start(), *Self::APERTURE_RANGE.end()),
+            focus_distance: node.focus_distance.get().max(Self::MIN_FOCUS_DISTANCE),
+            projection: match node.projection {
+                CameraProjection::Orthographic { scale } => CameraProjection::Orthographic {
+                    scale: scale.max(CameraProjection::MIN_SCALE),
+                },
+                projection => projection,
+            },
+            bokeh_blades: match node.bokeh_blades.get() {
+                0 => 0,
+                blades => blades.clamp(*Self::BOKEH_BLADES_RANGE.start(), *Self::BOKEH_BLADES_RANGE.end()),
+            },
+            exposure: node
+                .exposure
+                .get()
+                .clamp(*Self::EXPOSURE_RANGE.start(), *Self::EXPOSURE_RANGE.end()) as f32,
+            white_balance: node
+                .white_balance
+                .get()
+                .clamp(*Self::WHITE_BALANCE_RANGE.start(), *Self::WHITE_BALANCE_RANGE.end())
+                as f32,
         }
     }
 }
 
+/// Linear color of a black body at `kelvin`, from Tanner Helland's fit of the sRGB colors of the blackbody
+/// curve between 1000 K and 40000 K. The brightest channel is close to 1.
+fn blackbody_color(kelvin: f32) -> [f32; 3] {
+    let t = kelvin / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let green = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12217 * (t - 60.0).powf(-0.07551485)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+
+    // The fit gives gamma encoded values.
+    [red, green, blue].map(|channel| (channel.clamp(0.0, 255.0) / 255.0).powf(2.2))
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SkyParams {
     // Azimuth must be between 0..=360 degrees
@@ -382,6 +1263,19 @@ pub struct SkyParams {
     pub turbidity: f32,
     // Albedo elements must be between 0..=1
     pub albedo: [f32; 3],
+    // Draw the sun disk and sample it directly for sharp shadows
+    #[serde(default)]
+    pub sun_enabled: bool,
+    // Angular radius must be between 0.05..=10 degrees
+    #[serde(default = "SkyParams::default_sun_angular_radius")]
+    pub sun_angular_radius: Angle,
+    // Turns the whole sky around the vertical axis. The sky model is symmetric around the sun,
+    // so the sun turns along with it.
+    #[serde(default)]
+    pub env_rotation: Angle,
+    // The zenith is measured from this axis
+    #[serde(default)]
+    pub up_axis: UpAxis,
 }
 
 impl Default for SkyParams {
@@ -391,35 +1285,62 @@ impl Default for SkyParams {
             zenith: Angle::degrees(85.0),
             turbidity: 4.0,
             albedo: [1.0; 3],
+            sun_enabled: false,
+            sun_angular_radius: Self::default_sun_angular_radius(),
+            env_rotation: Angle::default(),
+            up_axis: UpAxis::default(),
         }
     }
 }
 
 impl SkyParams {
-    fn to_sky_state(self: &SkyParams) -> Result<GpuSkyState, hw_skymodel::rgb::Error> {
-        let azimuth = self.azimuth.as_radians() as f32;
-        let zenith = self.zenith.as_radians() as f32;
-        let sun_direction = [
-            zenith.sin() * azimuth.cos(),
-            zenith.cos(),
-            zenith.sin() * azimuth.sin(),
-            0.0,
-        ];
+    pub const SUN_ANGULAR_RADIUS_RANGE: RangeInclusive<f64> = 0.05..=10.0;
+
+    /// The real sun is about half a degree across.
+    pub fn default_sun_angular_radius() -> Angle {
+        Angle::degrees(0.27)
+    }
 
-        let state = hw_skymodel::rgb::SkyState::new(&hw_skymodel::rgb::SkyParams {
+    /// Parameters the sky model rejects, e.g. a turbidity out of range, give the gradient fallback state
+    /// along with the error, so the render goes on while they are fixed.
+    fn to_sky_state(self: &SkyParams) -> (GpuSkyState, Option<hw_skymodel::rgb::Error>) {
+        let azimuth = self.azimuth.as_radians() + self.env_rotation.as_radians();
+        let zenith = self.zenith.as_radians();
+        let sun_direction = Vector3::new(zenith.sin() * azimuth.cos(), zenith.cos(), zenith.sin() * azimuth.sin());
+        let sun_direction = from_vector3_to_vector3f32(&self.up_axis.from_y_up(sun_direction)).into();
+        let up = from_vector3_to_vector3f32(&self.up_axis.vector()).into();
+        let zenith = zenith as f32;
+
+        let state = match hw_skymodel::rgb::SkyState::new(&hw_skymodel::rgb::SkyParams {
             elevation: FRAC_PI_2 - zenith,
             turbidity: self.turbidity,
             albedo: self.albedo,
-        })?;
+        }) {
+            Ok(state) => state,
+            Err(err) => {
+                let fallback = GpuSkyState {
+                    up,
+                    ..GpuSkyState::FALLBACK
+                };
+                return (fallback, Some(err));
+            },
+        };
 
         let (params_data, radiance_data) = state.raw();
 
-        Ok(GpuSkyState {
+        let sky_state = GpuSkyState {
             params: params_data,
             radiances: radiance_data,
             _padding: [0, 2],
             sun_direction,
-        })
+            sun_cos_angular_radius: self.sun_angular_radius.as_radians().cos() as f32,
+            sun_enabled: self.sun_enabled as _,
+            fallback: 0,
+            _padding2: [0; 2],
+            up,
+            _padding3: 0,
+        };
+        (sky_state, None)
     }
 }
 
@@ -428,6 +1349,36 @@ pub struct SamplingParams {
     pub max_samples_per_pixel: u32,
     pub num_samples_per_pixel: u32,
     pub num_bounces: u32,
+    /// Bounces off and through glass are counted apart from `num_bounces`, so light going through several panes
+    /// or nested glass doesn't run out of bounces before it reaches a light.
+    #[serde(default = "SamplingParams::default_num_transmission_bounces")]
+    pub num_transmission_bounces: u32,
+    /// Wall-clock budget in seconds after which accumulation stops, zero means no limit.
+    #[serde(default)]
+    pub time_limit_secs: f64,
+    /// Mixed into the per-pixel random numbers, the same seed gives the same image.
+    #[serde(default)]
+    pub seed: u32,
+    /// Blend the four nearest texels instead of taking the closest one.
+    #[serde(default)]
+    pub bilinear_filtering: bool,
+    /// Fraction of the viewport resolution along each side that gets rendered.
+    #[serde(default = "SamplingParams::default_resolution_scale")]
+    pub resolution_scale: f32,
+    /// Stop sampling the pixels whose noise drops below `variance_threshold`, so the remaining samples go to
+    /// the noisy ones.
+    #[serde(default)]
+    pub adaptive: bool,
+    /// Relative standard error of the luminance below which a pixel counts as converged.
+    #[serde(default = "SamplingParams::default_variance_threshold")]
+    pub variance_threshold: f32,
+    /// Glass reflects by the full Fresnel equations instead of Schlick's approximation, which is a little slower
+    /// but more accurate at grazing angles.
+    #[serde(default)]
+    pub exact_fresnel: bool,
+    /// How the samples of a pixel are spread over its footprint and the lens.
+    #[serde(default)]
+    pub aa_pattern: AaPattern,
 }
 
 impl Default for SamplingParams {
@@ -436,67 +1387,508 @@ impl Default for SamplingParams {
             max_samples_per_pixel: 256,
             num_samples_per_pixel: 1,
             num_bounces: 8,
+            num_transmission_bounces: Self::default_num_transmission_bounces(),
+            time_limit_secs: 0.0,
+            seed: 0,
+            bilinear_filtering: true,
+            resolution_scale: Self::default_resolution_scale(),
+            adaptive: false,
+            variance_threshold: Self::default_variance_threshold(),
+            exact_fresnel: false,
+            aa_pattern: AaPattern::default(),
+        }
+    }
+}
+
+impl SamplingParams {
+    pub const RESOLUTION_SCALE_RANGE: RangeInclusive<f32> = 0.25..=1.0;
+    pub const VARIANCE_THRESHOLD_RANGE: RangeInclusive<f32> = 0.001..=0.5;
+    pub const MAX_SAMPLES_RANGE: RangeInclusive<u32> = 1..=65536;
+    pub const NUM_SAMPLES_RANGE: RangeInclusive<u32> = 1..=256;
+    pub const NUM_BOUNCES_RANGE: RangeInclusive<u32> = 1..=64;
+
+    pub fn default_num_transmission_bounces() -> u32 {
+        16
+    }
+
+    pub fn default_resolution_scale() -> f32 {
+        1.0
+    }
+
+    pub fn default_variance_threshold() -> f32 {
+        0.02
+    }
+
+    /// Zero turns adaptive sampling off in the shader.
+    fn gpu_variance_threshold(&self) -> f32 {
+        if self.adaptive { self.variance_threshold } else { 0.0 }
+    }
+
+    /// The multiple of `num_samples_per_pixel` nearest to `max_samples_per_pixel`, but at least one frame's worth,
+    /// so the pair passes [`RenderParams::validate`].
+    pub fn fit_max_samples(max_samples_per_pixel: u32, num_samples_per_pixel: u32) -> u32 {
+        let num = num_samples_per_pixel.max(1);
+        let frames = max_samples_per_pixel.saturating_add(num / 2) / num;
+        frames.max(1).saturating_mul(num)
+    }
+
+    /// Size of the image that gets rendered for a viewport of `viewport_size`, at least one pixel.
+    pub fn scaled_size(&self, viewport_size: (u32, u32)) -> (u32, u32) {
+        let scale = |side: u32| ((side as f32 * self.resolution_scale).round() as u32).max(1);
+        (scale(viewport_size.0), scale(viewport_size.1))
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        (self.time_limit_secs > 0.0).then(|| Duration::from_secs_f64(self.time_limit_secs))
+    }
+}
+
+/// Diagnostic switches that replace parts of the shading with false colors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DebugParams {
+    /// Tint surfaces whose normal faces away from the camera, which reveals inverted normals.
+    pub highlight_backfaces: bool,
+}
+
+impl DebugParams {
+    const HIGHLIGHT_BACKFACES: u32 = 1;
+
+    fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.highlight_backfaces {
+            flags |= Self::HIGHLIGHT_BACKFACES;
+        }
+        flags
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExposureParams {
+    /// Manual exposure in stops, ignored when `auto` is set.
+    pub exposure: f32,
+    /// Derive the exposure from the log-average luminance of the image.
+    pub auto: bool,
+    /// Brightness the average luminance is mapped to in auto mode.
+    pub key_value: f32,
+}
+
+impl Default for ExposureParams {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            auto: false,
+            key_value: 0.18,
+        }
+    }
+}
+
+impl ExposureParams {
+    pub const KEY_VALUE_RANGE: RangeInclusive<f32> = 0.01..=1.0;
+
+    /// Zero disables auto exposure in the shader.
+    fn auto_exposure_key(&self) -> f32 {
+        if self.auto { self.key_value } else { 0.0 }
+    }
+}
+
+/// Blurred highlights added to the exposed image before tonemapping, so that bright lights glow.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BloomParams {
+    /// Exposed luminance above which pixels start to glow.
+    pub threshold: f32,
+    /// Fraction of the highlights added back to the image.
+    pub intensity: f32,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.2,
+        }
+    }
+}
+
+impl BloomParams {
+    pub const THRESHOLD_RANGE: RangeInclusive<f32> = 0.0..=10.0;
+    pub const INTENSITY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+    fn is_valid(&self) -> bool {
+        Self::THRESHOLD_RANGE.contains(&self.threshold) && Self::INTENSITY_RANGE.contains(&self.intensity)
+    }
+
+    /// Zero intensity turns bloom off in the shader.
+    fn to_gpu(bloom: Option<Self>) -> (f32, f32) {
+        bloom.map_or((0.0, 0.0), |bloom| (bloom.threshold, bloom.intensity))
+    }
+}
+
+/// Normalized region of the image that receives samples, the rest keeps what was accumulated before.
+/// `min` is the top left corner, both corners are between 0..=1.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Default for CropRect {
+    fn default() -> Self {
+        Self {
+            min: [0.0, 0.0],
+            max: [1.0, 1.0],
+        }
+    }
+}
+
+impl CropRect {
+    pub fn is_valid(&self) -> bool {
+        let range = 0.0..=1.0;
+        (0..2).all(|axis| {
+            range.contains(&self.min[axis]) && range.contains(&self.max[axis]) && self.min[axis] < self.max[axis]
+        })
+    }
+
+    /// Pixel bounds in an image of `size`, the end is exclusive. Every valid crop covers at least one pixel.
+    pub fn pixel_bounds(&self, size: (u32, u32)) -> ((u32, u32), (u32, u32)) {
+        let bounds = |min: f32, max: f32, side: u32| {
+            let side = side.max(1);
+            let start = ((min * side as f32).floor() as u32).min(side - 1);
+            let end = ((max * side as f32).ceil() as u32).clamp(start + 1, side);
+            (start, end)
+        };
+        let (x0, x1) = bounds(self.min[0], self.max[0], size.0);
+        let (y0, y1) = bounds(self.min[1], self.max[1], size.1);
+        ((x0, y0), (x1, y1))
+    }
+}
+
+/// What camera rays see when they miss the scene. Bounced rays always light the scene with the sky.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+    #[default]
+    Sky,
+    Solid {
+        color: [f32; 3],
+    },
+    /// Blends from `bottom` to `top` with the vertical direction of the ray.
+    Gradient {
+        top: [f32; 3],
+        bottom: [f32; 3],
+    },
+}
+
+impl Background {
+    /// Mode, top and bottom colors as they are laid out in the sampling parameters of the shader.
+    fn to_gpu(self) -> (u32, [f32; 3], [f32; 3]) {
+        match self {
+            Self::Sky => (0, [0.0; 3], [0.0; 3]),
+            Self::Solid { color } => (1, color, color),
+            Self::Gradient { top, bottom } => (2, top, bottom),
+        }
+    }
+}
+
+/// What the render shows. Depth, normal and albedo are taken from a single ray through each pixel center,
+/// these are the guide buffers of a denoiser and help finding problems in a scene.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputChannel {
+    /// The shaded image.
+    #[default]
+    Beauty,
+    /// Distance to the first hit, `d` is shown as `1 / (1 + d / 10)` so close surfaces are bright.
+    Depth,
+    /// Shading normal of the first hit in world space, mapped from -1..=1 to 0..=1.
+    Normal,
+    /// Base color of the first hit without any lighting.
+    Albedo,
+    /// Mean number of bounces of the rays through each pixel, from blue for none to red at `num_bounces`.
+    /// Red areas are cut short by the bounce limit, e.g. inside nested dielectrics.
+    BounceHeatmap,
+}
+
+impl OutputChannel {
+    pub const ALL: [Self; 5] = [
+        Self::Beauty,
+        Self::Depth,
+        Self::Normal,
+        Self::Albedo,
+        Self::BounceHeatmap,
+    ];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Beauty => "Beauty",
+            Self::Depth => "Depth",
+            Self::Normal => "Normal",
+            Self::Albedo => "Albedo",
+            Self::BounceHeatmap => "Bounce heatmap",
+        }
+    }
+
+    fn to_gpu(self) -> u32 {
+        match self {
+            Self::Beauty => 0,
+            Self::Depth => 1,
+            Self::Normal => 2,
+            Self::Albedo => 3,
+            Self::BounceHeatmap => 4,
         }
     }
 }
 
+/// Distribution of the sample positions within a pixel and on the lens. The patterns other than `Random` cover
+/// both more evenly, which mostly shows in the first few frames of a progressive render.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AaPattern {
+    /// Independent uniform random positions.
+    #[default]
+    Random,
+    /// Every run of 16 samples takes one position in each cell of a 4×4 grid, jittered within the cell.
+    Stratified,
+    /// The R4 quasi-random sequence, which covers the pixel and the lens evenly at any sample count. Each pixel
+    /// rotates it by a random offset, so neighbouring pixels don't share their positions.
+    #[serde(alias = "BlueNoise")]
+    QuasiRandom,
+}
+
+impl AaPattern {
+    pub const ALL: [Self; 3] = [Self::Random, Self::Stratified, Self::QuasiRandom];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Random => "Random",
+            Self::Stratified => "Stratified",
+            Self::QuasiRandom => "Quasi-random",
+        }
+    }
+
+    fn to_gpu(self) -> u32 {
+        match self {
+            Self::Random => 0,
+            Self::Stratified => 1,
+            Self::QuasiRandom => 2,
+        }
+    }
+}
+
+/// Takes the outcome of uploading a new scene, returns `true` if the render started over. Samples of the previous
+/// scene would leave ghosts of moved spheres behind, while a scene that failed to upload keeps the old one.
+fn apply_scene_update(
+    updated: Result<(), RenderParamsValidationError>,
+    progress: &mut RenderProgress,
+    scene_error: &mut Option<String>,
+) -> bool {
+    match updated {
+        Ok(()) => {
+            *scene_error = None;
+            progress.reset();
+            true
+        },
+        Err(err) => {
+            *scene_error = Some(err.to_string());
+            false
+        },
+    }
+}
+
+fn pixel_count(min: (u32, u32), max: (u32, u32)) -> u64 {
+    (max.0 - min.0) as u64 * (max.1 - min.1) as u64
+}
+
 struct RenderProgress {
     accumulated_samples_per_pixel: u32,
+    /// Pixels sampled by the current render, only those in the crop rectangle.
+    pixel_count: u64,
+    /// Samples taken by all pixels together. Adaptive sampling skips converged pixels, so this can be less than
+    /// `accumulated_samples_per_pixel` for every pixel.
+    sample_count: u64,
+    /// Fraction of the pixels that haven't converged yet, as last read back from the GPU.
+    active_fraction: f32,
+    started_at: Option<Instant>,
+    /// Time of the latest frame that added samples, so the elapsed time stops once the render completes.
+    sampled_at: Option<Instant>,
+    converged: bool,
+    /// Samples per pixel taken by the next frame, at most `num_samples_per_pixel`. Kept across resets so
+    /// a restarted render, e.g. while dragging the camera, starts with the budget that was already found.
+    samples_per_frame: u32,
+    /// Start of the previous frame, the time between two frames includes waiting on the GPU work.
+    frame_at: Option<Instant>,
+    paused_at: Option<Instant>,
 }
 
 impl RenderProgress {
+    /// Frames taking longer than this cut the samples of the next frame, so input isn't stalled.
+    const TARGET_FRAME_TIME: Duration = Duration::from_millis(33);
+
     pub fn new() -> Self {
         Self {
             accumulated_samples_per_pixel: 0,
+            pixel_count: 0,
+            sample_count: 0,
+            active_fraction: 1.0,
+            started_at: None,
+            sampled_at: None,
+            converged: false,
+            samples_per_frame: 1,
+            frame_at: None,
+            paused_at: None,
         }
     }
 
-    pub fn next_frame(&mut self, sampling_params: &SamplingParams) -> GpuSamplingParams {
+    /// Frames that take fewer samples are made up for by more frames. The shader seeds every sample by its index
+    /// since the reset, so without adaptive sampling the image doesn't depend on how the samples were split.
+    pub fn next_frame(&mut self, sampling_params: &SamplingParams, pixel_count: u64) -> GpuSamplingParams {
+        self.pixel_count = pixel_count;
+        let now = Instant::now();
+        if let Some(paused_at) = self.paused_at.take() {
+            let pause = now - paused_at;
+            self.started_at = self.started_at.map(|started_at| started_at + pause);
+            self.sampled_at = self.sampled_at.map(|sampled_at| sampled_at + pause);
+        }
+        // Once converged, frames are only drawn when the UI needs them and don't say anything about the GPU.
+        if let Some(frame_at) = self.frame_at.replace(now).filter(|_| !self.converged) {
+            self.pace(now - frame_at);
+        }
+        let max_samples_per_frame = sampling_params.num_samples_per_pixel.max(1);
+        self.samples_per_frame = self.samples_per_frame.clamp(1, max_samples_per_frame);
+
         let current_accumulated_samples = self.accumulated_samples_per_pixel;
-        let next_accumulated_samples = sampling_params.num_samples_per_pixel + current_accumulated_samples;
+        let max_samples = sampling_params.max_samples_per_pixel;
+        let remaining_samples = max_samples.saturating_sub(current_accumulated_samples);
+        let num_samples_per_pixel = self.samples_per_frame.min(remaining_samples);
+        let next_accumulated_samples = num_samples_per_pixel + current_accumulated_samples;
 
         // Initial state: no samples have been accumulated yet. This is the first frame
         // after a reset. The image buffer's previous samples should be cleared by
         // setting clear_accumulated_samples to 1.
         if current_accumulated_samples == 0 {
             self.accumulated_samples_per_pixel = next_accumulated_samples;
+            self.sample_count = num_samples_per_pixel as u64 * pixel_count;
+            self.started_at = Some(now);
+            self.sampled_at = self.started_at;
             GpuSamplingParams {
-                num_samples_per_pixel: sampling_params.num_samples_per_pixel,
+                num_samples_per_pixel,
                 num_bounces: sampling_params.num_bounces,
+                num_transmission_bounces: sampling_params.num_transmission_bounces,
+                seed: sampling_params.seed,
                 accumulated_samples_per_pixel: next_accumulated_samples,
                 clear_accumulated_samples: 1,
+                ..Default::default()
             }
         }
         // Progressive render: accumulating samples in the image buffer over multiple
-        // frames.
-        else if next_accumulated_samples <= sampling_params.max_samples_per_pixel {
+        // frames, on the pixels that haven't converged yet.
+        else if num_samples_per_pixel > 0 && self.active_fraction > 0.0 && !self.is_out_of_time(sampling_params) {
             self.accumulated_samples_per_pixel = next_accumulated_samples;
+            let active_pixels = (pixel_count as f64 * self.active_fraction as f64).round() as u64;
+            self.sample_count += num_samples_per_pixel as u64 * active_pixels;
+            self.sampled_at = Some(now);
             GpuSamplingParams {
-                num_samples_per_pixel: sampling_params.num_samples_per_pixel,
+                num_samples_per_pixel,
                 num_bounces: sampling_params.num_bounces,
+                num_transmission_bounces: sampling_params.num_transmission_bounces,
+                seed: sampling_params.seed,
                 accumulated_samples_per_pixel: next_accumulated_samples,
                 clear_accumulated_samples: 0,
+                ..Default::default()
             }
         }
-        // Completed render: we have accumulated max_samples_per_pixel samples. Stop rendering
-        // by setting num_samples_per_pixel to zero.
+        // Completed render: we have accumulated max_samples_per_pixel samples, every pixel
+        // converged or we ran out of the time budget. Stop rendering by setting num_samples_per_pixel to zero.
         else {
-            GpuSamplingParams {
-                num_samples_per_pixel: 0,
-                num_bounces: sampling_params.num_bounces,
-                accumulated_samples_per_pixel: current_accumulated_samples,
-                clear_accumulated_samples: 0,
-            }
+            self.converged = true;
+            self.idle_frame(sampling_params)
+        }
+    }
+
+    /// Shows the accumulated samples without adding any. The time spent paused doesn't count towards
+    /// the elapsed time or the time limit once [`Self::next_frame`] resumes the render.
+    pub fn paused_frame(&mut self, sampling_params: &SamplingParams) -> GpuSamplingParams {
+        self.paused_at.get_or_insert_with(Instant::now);
+        self.frame_at = None;
+        self.idle_frame(sampling_params)
+    }
+
+    fn idle_frame(&self, sampling_params: &SamplingParams) -> GpuSamplingParams {
+        GpuSamplingParams {
+            num_samples_per_pixel: 0,
+            num_bounces: sampling_params.num_bounces,
+            num_transmission_bounces: sampling_params.num_transmission_bounces,
+            seed: sampling_params.seed,
+            accumulated_samples_per_pixel: self.accumulated_samples_per_pixel,
+            clear_accumulated_samples: 0,
+            ..Default::default()
         }
     }
 
     pub fn reset(&mut self) {
         self.accumulated_samples_per_pixel = 0;
+        self.sample_count = 0;
+        self.active_fraction = 1.0;
+        self.started_at = None;
+        self.sampled_at = None;
+        self.converged = false;
+        // The UI may have been idle since the previous frame, that time says nothing about the GPU.
+        self.frame_at = None;
+        self.paused_at = None;
+    }
+
+    /// Halves the samples of the next frame after a slow frame and grows them slowly otherwise.
+    fn pace(&mut self, frame_time: Duration) {
+        self.samples_per_frame = if frame_time > Self::TARGET_FRAME_TIME {
+            self.samples_per_frame / 2
+        } else {
+            self.samples_per_frame + self.samples_per_frame.div_ceil(4)
+        };
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.converged
+    }
+
+    /// Wall time spent accumulating samples since the last reset.
+    pub fn elapsed(&self) -> Duration {
+        match (self.started_at, self.sampled_at) {
+            (Some(started_at), Some(sampled_at)) => sampled_at - started_at,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Average number of samples per pixel added each second.
+    pub fn samples_per_second(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed > 0.0 { self.mean_samples() / elapsed } else { 0.0 }
+    }
+
+    /// Samples per pixel averaged over the pixels, the most sampled ones have [`Self::accumulated_samples`].
+    pub fn mean_samples(&self) -> f64 {
+        self.sample_count as f64 / self.pixel_count.max(1) as f64
+    }
+
+    /// Converged pixels count as done, the others as far as their samples got towards the maximum.
+    pub fn progress(&self, sampling_params: &SamplingParams) -> f32 {
+        let sampled = self.accumulated_samples_per_pixel as f32 / sampling_params.max_samples_per_pixel as f32;
+        1.0 - self.active_fraction * (1.0 - sampled)
+    }
+
+    pub fn set_active_fraction(&mut self, active_fraction: f32) {
+        self.active_fraction = active_fraction;
+    }
+
+    fn is_out_of_time(&self, sampling_params: &SamplingParams) -> bool {
+        match (sampling_params.time_limit(), self.started_at) {
+            (Some(limit), Some(started_at)) => started_at.elapsed() >= limit,
+            _ => false,
+        }
     }
 
     pub fn accumulated_samples(&self) -> u32 {
         self.accumulated_samples_per_pixel
     }
+
+    pub fn pixel_count(&self) -> u64 {
+        self.pixel_count
+    }
 }
 
 #[repr(C)]
@@ -513,22 +1905,33 @@ pub struct GpuCamera {
     v: Vector3f32,
     lens_radius: f32,
     lower_left_corner: Vector3f32,
-    _padding5: f32,
+    is_ortho: u32,
+    bokeh_blades: u32,
+    _padding5: [u32; 3],
 }
 
 impl GpuCamera {
+    /// For both projections `lower_left_corner`, `horizontal` and `vertical` span the image plane.
+    /// A perspective camera places it at the focus distance and aims rays at it from the lens,
+    /// an orthographic camera places it at the eye and shoots parallel rays from it.
     pub fn new(camera: &Camera, viewport_size: (u32, u32)) -> Self {
-        let lens_radius = 0.5 * camera.aperture;
         let aspect = viewport_size.0 as f64 / viewport_size.1 as f64;
-        let theta = camera.vfov.as_radians();
-        let half_height = camera.focus_distance * (0.5 * theta).tan();
-        let half_width = aspect * half_height;
 
         let w = camera.eye_dir.normalize();
         let v = camera.up.normalize();
         let u = w.cross(&v);
 
-        let lower_left_corner = camera.eye_pos + camera.focus_distance * w - half_width * u - half_height * v;
+        let (lens_radius, half_height, plane_center) = match camera.projection {
+            CameraProjection::Perspective => {
+                let theta = camera.vfov.as_radians();
+                let half_height = camera.focus_distance * (0.5 * theta).tan();
+                (0.5 * camera.aperture, half_height, camera.eye_pos + camera.focus_distance * w)
+            },
+            CameraProjection::Orthographic { scale } => (0.0, 0.5 * scale, camera.eye_pos),
+        };
+        let half_width = aspect * half_height;
+
+        let lower_left_corner = plane_center - half_width * u - half_height * v;
         let horizontal = 2.0 * half_width * u;
         let vertical = 2.0 * half_height * v;
 
@@ -544,7 +1947,9 @@ impl GpuCamera {
             v: from_vector3_to_vector3f32(&v),
             lens_radius: lens_radius as _,
             lower_left_corner: from_vector3_to_vector3f32(&lower_left_corner),
-            _padding5: 0.0,
+            is_ortho: matches!(camera.projection, CameraProjection::Orthographic { .. }) as u32,
+            bokeh_blades: camera.bokeh_blades,
+            _padding5: [0; 3],
         }
     }
 }
@@ -552,19 +1957,94 @@ impl GpuCamera {
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct GpuSkyState {
-    params: [f32; 27],       // 0 byte offset, 108 byte size
-    radiances: [f32; 3],     // 108 byte offset, 12 byte size
-    _padding: [u32; 2],      // 120 byte offset, 8 byte size
-    sun_direction: [f32; 4], // 128 byte offset, 16 byte size
+    params: [f32; 27],           // 0 byte offset, 108 byte size
+    radiances: [f32; 3],         // 108 byte offset, 12 byte size
+    _padding: [u32; 2],          // 120 byte offset, 8 byte size
+    sun_direction: [f32; 3],     // 128 byte offset, 12 byte size
+    sun_cos_angular_radius: f32, // 140 byte offset, 4 byte size
+    sun_enabled: u32,            // 144 byte offset, 4 byte size
+    fallback: u32,               // 148 byte offset, 4 byte size
+    _padding2: [u32; 2],         // 152 byte offset, 8 byte size
+    up: [f32; 3],                // 160 byte offset, 12 byte size
+    _padding3: u32,              // 172 byte offset, 4 byte size
+}
+
+impl GpuSkyState {
+    /// Two-color gradient drawn in place of the sky model, without a sun.
+    const FALLBACK: Self = Self {
+        params: [0.0; 27],
+        radiances: [0.0; 3],
+        _padding: [0; 2],
+        sun_direction: [0.0, 1.0, 0.0],
+        sun_cos_angular_radius: 1.0,
+        sun_enabled: 0,
+        fallback: 1,
+        _padding2: [0; 2],
+        up: [0.0, 1.0, 0.0],
+        _padding3: 0,
+    };
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
 struct GpuSamplingParams {
     num_samples_per_pixel: u32,
     num_bounces: u32,
     accumulated_samples_per_pixel: u32,
     clear_accumulated_samples: u32,
+    debug_flags: u32,
+    num_emissive_lights: u32,
+    exposure: f32,
+    auto_exposure_key: f32,
+    seed: u32,
+    bilinear_filtering: u32,
+    crop_min: [u32; 2],
+    crop_max: [u32; 2],
+    output_channel: u32,
+    variance_threshold: f32,
+    background_top: [f32; 3],
+    background_mode: u32,
+    background_bottom: [f32; 3],
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    camera_exposure: f32,
+    max_ray_distance: f32,
+    exact_fresnel: u32,
+    white_balance: [f32; 3],
+    aa_pattern: u32,
+    num_transmission_bounces: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuLuminanceStats {
+    log_sum: i32,
+    count: u32,
+}
+
+/// Relative errors of the pixels on the auto exposure grid summed up as fixed point, and how many pixels of the
+/// crop still take samples. See `accumulateNoise` and `accumulateConvergence` in the shader.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuNoiseStats {
+    error_sum: u32,
+    count: u32,
+    active_count: u32,
+    pixel_count: u32,
+}
+
+impl GpuNoiseStats {
+    /// Same as `NOISE_SCALE` in the shader.
+    const SCALE: f32 = 1024.0;
+
+    fn mean_error(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.error_sum as f32 / (Self::SCALE * self.count as f32))
+    }
+
+    fn active_fraction(&self) -> Option<f32> {
+        (self.pixel_count > 0).then(|| self.active_count as f32 / self.pixel_count as f32)
+    }
 }
 
 #[repr(C)]
@@ -672,3 +2152,300 @@ const VERTICES: &[Vertex] = &[
         tex_coords: [1.0, 0.0],
     },
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_sky_falls_back_to_gradient() {
+        let (sky_state, err) = SkyParams::default().to_sky_state();
+        assert_eq!((sky_state.fallback, err.is_none()), (0, true));
+
+        let sky = SkyParams {
+            turbidity: 20.0,
+            ..Default::default()
+        };
+        let (sky_state, err) = sky.to_sky_state();
+        assert_eq!((sky_state.fallback, sky_state.sun_enabled), (1, 0));
+        assert!(err.is_some());
+    }
+
+    #[test]
+    fn test_env_rotation_turns_the_sun() {
+        let rotated = SkyParams {
+            azimuth: Angle::degrees(30.0),
+            env_rotation: Angle::degrees(60.0),
+            ..Default::default()
+        };
+        let turned = SkyParams {
+            azimuth: Angle::degrees(90.0),
+            ..Default::default()
+        };
+
+        let (rotated, _) = rotated.to_sky_state();
+        let (turned, _) = turned.to_sky_state();
+        for (a, b) in rotated.sun_direction.iter().zip(turned.sun_direction) {
+            assert!((a - b).abs() < 1e-6, "{:?}", rotated.sun_direction);
+        }
+    }
+
+    #[test]
+    fn test_fit_max_samples_to_multiple() {
+        assert_eq!(SamplingParams::fit_max_samples(256, 1), 256);
+        assert_eq!(SamplingParams::fit_max_samples(100, 8), 104);
+        assert_eq!(SamplingParams::fit_max_samples(99, 8), 96);
+        assert_eq!(SamplingParams::fit_max_samples(2, 16), 16);
+        assert_eq!(SamplingParams::fit_max_samples(10, 0), 10);
+    }
+
+    #[test]
+    fn test_render_progress_stops_after_time_limit() {
+        let sampling = SamplingParams {
+            time_limit_secs: 1e-9,
+            ..Default::default()
+        };
+        let mut progress = RenderProgress::new();
+
+        assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 1);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 0);
+        assert_eq!(progress.accumulated_samples(), 1);
+
+        progress.reset();
+        assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 1);
+    }
+
+    #[test]
+    fn test_new_scene_restarts_converged_render() {
+        let sampling = SamplingParams {
+            max_samples_per_pixel: 2,
+            ..Default::default()
+        };
+        let mut progress = RenderProgress::new();
+        while progress.next_frame(&sampling, 1).num_samples_per_pixel > 0 {}
+        assert!(progress.is_converged());
+
+        // A scene that failed to upload leaves the render of the old one alone.
+        let mut scene_error = None;
+        let failed = Err(RenderParamsValidationError::SampleCountZero);
+        assert!(!apply_scene_update(failed, &mut progress, &mut scene_error));
+        assert!(scene_error.is_some());
+        assert!(progress.is_converged());
+
+        assert!(apply_scene_update(Ok(()), &mut progress, &mut scene_error));
+        assert_eq!(scene_error, None);
+        assert_eq!(progress.accumulated_samples(), 0);
+        assert!(!progress.is_converged());
+        assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 1);
+    }
+
+    #[test]
+    fn test_render_progress_without_time_limit() {
+        let sampling = SamplingParams::default();
+        let mut progress = RenderProgress::new();
+
+        for _ in 0..sampling.max_samples_per_pixel {
+            assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 1);
+        }
+        assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 0);
+    }
+
+    #[test]
+    fn test_slow_frames_take_fewer_samples() {
+        let sampling = SamplingParams {
+            max_samples_per_pixel: 64,
+            num_samples_per_pixel: 16,
+            ..Default::default()
+        };
+        let mut progress = RenderProgress::new();
+        progress.samples_per_frame = 16;
+        assert_eq!(progress.next_frame(&sampling, 1).num_samples_per_pixel, 16);
+
+        progress.pace(2 * RenderProgress::TARGET_FRAME_TIME);
+        assert_eq!(progress.samples_per_frame, 8);
+        progress.pace(Duration::ZERO);
+        assert_eq!(progress.samples_per_frame, 10);
+
+        // The render still stops at exactly the maximum sample count.
+        progress.samples_per_frame = 15;
+        let samples = std::iter::from_fn(|| {
+            progress.frame_at = None;
+            Some(progress.next_frame(&sampling, 1).num_samples_per_pixel)
+        });
+        assert_eq!(samples.take(5).collect::<Vec<_>>(), [15, 15, 15, 3, 0]);
+        assert!(progress.is_converged());
+    }
+
+    #[test]
+    fn test_adaptive_progress_counts_converged_pixels() {
+        let sampling = SamplingParams {
+            max_samples_per_pixel: 4,
+            adaptive: true,
+            ..Default::default()
+        };
+        let mut progress = RenderProgress::new();
+        progress.next_frame(&sampling, 100);
+        progress.next_frame(&sampling, 100);
+        assert_eq!(progress.mean_samples(), 2.0);
+        assert_eq!(progress.progress(&sampling), 0.5);
+
+        progress.set_active_fraction(0.25);
+        progress.next_frame(&sampling, 100);
+        assert_eq!(progress.mean_samples(), 2.25);
+        assert_eq!(progress.progress(&sampling), 0.9375);
+
+        progress.set_active_fraction(0.0);
+        assert_eq!(progress.next_frame(&sampling, 100).num_samples_per_pixel, 0);
+        assert!(progress.is_converged());
+        assert_eq!(progress.progress(&sampling), 1.0);
+    }
+
+    #[test]
+    fn test_paused_render_keeps_samples() {
+        let sampling = SamplingParams::default();
+        let mut progress = RenderProgress::new();
+        progress.next_frame(&sampling, 1);
+        progress.next_frame(&sampling, 1);
+
+        let paused = progress.paused_frame(&sampling);
+        assert_eq!(paused.num_samples_per_pixel, 0);
+        assert_eq!(paused.accumulated_samples_per_pixel, 2);
+        std::thread::sleep(Duration::from_millis(5));
+        progress.paused_frame(&sampling);
+
+        let resumed = progress.next_frame(&sampling, 1);
+        assert_eq!(resumed.accumulated_samples_per_pixel, 3);
+        assert_eq!(resumed.clear_accumulated_samples, 0);
+        assert!(progress.elapsed() < Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_scaled_size() {
+        let sampling = SamplingParams {
+            resolution_scale: 0.25,
+            ..Default::default()
+        };
+
+        assert_eq!(sampling.scaled_size((1920, 1080)), (480, 270));
+        assert_eq!(sampling.scaled_size((2, 1)), (1, 1));
+        assert_eq!(SamplingParams::default().scaled_size((1920, 1080)), (1920, 1080));
+    }
+
+    #[test]
+    fn test_crop_pixel_bounds() {
+        assert_eq!(CropRect::default().pixel_bounds((640, 480)), ((0, 0), (640, 480)));
+
+        let crop = CropRect {
+            min: [0.25, 0.5],
+            max: [0.5, 0.75],
+        };
+        assert!(crop.is_valid());
+        assert_eq!(crop.pixel_bounds((640, 480)), ((160, 240), (320, 360)));
+
+        // A sliver thinner than a pixel still gets sampled.
+        let crop = CropRect {
+            min: [1.0 - 1e-6, 0.0],
+            max: [1.0, 1e-6],
+        };
+        assert_eq!(crop.pixel_bounds((640, 480)), ((639, 0), (640, 1)));
+
+        let crop = CropRect {
+            min: [0.5, 0.0],
+            max: [0.5, 1.0],
+        };
+        assert!(!crop.is_valid());
+    }
+
+    #[test]
+    fn test_camera_bokeh_blades_from_node() {
+        let mut node = CameraNode::default();
+        assert_eq!(Camera::from_node(&node).bokeh_blades, 0);
+
+        // Too few blades for a polygon still give a valid aperture.
+        node.bokeh_blades = crate::types::NodePin::new(2);
+        assert_eq!(Camera::from_node(&node).bokeh_blades, 3);
+
+        node.bokeh_blades = crate::types::NodePin::new(100);
+        assert_eq!(Camera::from_node(&node).bokeh_blades, *Camera::BOKEH_BLADES_RANGE.end());
+    }
+
+    #[test]
+    fn test_white_balance_gain() {
+        let neutral = Camera::default().white_balance_gain();
+        assert!(neutral.iter().all(|&gain| (gain - 1.0).abs() < 1e-5));
+
+        // Balancing for warm light makes the image bluer, and the other way around.
+        let warm = Camera {
+            white_balance: 3200.0,
+            ..Default::default()
+        };
+        let [red, _, blue] = warm.white_balance_gain();
+        assert!(blue > red);
+        let cool = Camera {
+            white_balance: 10000.0,
+            ..Default::default()
+        };
+        let [red, _, blue] = cool.white_balance_gain();
+        assert!(red > blue);
+    }
+
+    #[test]
+    fn test_camera_exposure_from_node() {
+        let mut node = CameraNode::default();
+        assert_eq!(Camera::from_node(&node).exposure, 0.0);
+
+        node.exposure = crate::types::NodePin::new(1.5);
+        assert_eq!(Camera::from_node(&node).exposure, 1.5);
+
+        node.exposure = crate::types::NodePin::new(f64::INFINITY);
+        assert_eq!(Camera::from_node(&node).exposure as f64, *Camera::EXPOSURE_RANGE.end());
+    }
+
+    #[test]
+    fn test_zero_vfov_is_rejected() {
+        let mut render_params = RenderParams {
+            camera: Camera::default(),
+            sky: SkyParams::default(),
+            sampling: SamplingParams::default(),
+            debug: DebugParams::default(),
+            exposure: ExposureParams::default(),
+            denoise: false,
+            crop: CropRect::default(),
+            background: Background::default(),
+            output_channel: OutputChannel::default(),
+            bloom: None,
+            max_ray_distance: 0.0,
+        };
+        assert!(render_params.validate().is_ok());
+
+        render_params.camera.vfov = Angle::degrees(0.0);
+        assert!(matches!(
+            render_params.validate(),
+            Err(RenderParamsValidationError::VfovOutOfRange(0.0))
+        ));
+    }
+
+    #[test]
+    fn test_bloom_out_of_range_is_rejected() {
+        let bloom = BloomParams {
+            intensity: 2.0,
+            ..Default::default()
+        };
+        assert!(!bloom.is_valid());
+        assert!(BloomParams::default().is_valid());
+    }
+
+    #[test]
+    fn test_every_final_pass_encodes_for_display() {
+        // The main pass draws straight to the viewport when the frame needs no offscreen target, so it has to
+        // tonemap and encode like the denoise and resolve passes.
+        for entry_point in ["fsMain", "fsDenoise", "fsResolve"] {
+            let start = RAYTRACER_SHADER.find(&format!("fn {entry_point}(")).unwrap();
+            let body = &RAYTRACER_SHADER[start..];
+            let body = &body[..body.find("\n}\n").unwrap()];
+            assert!(body.contains("return displayColor("), "{entry_point}");
+            assert!(!body.contains("uncharted2("), "{entry_point}");
+        }
+    }
+}
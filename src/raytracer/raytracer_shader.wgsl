@@ -7,10 +7,73 @@ const FRAC_PI_2 = 1.5707964f;
 const MIN_T = 0.001f;
 const MAX_T = 1000f;
 
+const BVH_STACK_SIZE = 32u;
+
 const CHANNEL_R = 0u;
 const CHANNEL_G = 1u;
 const CHANNEL_B = 2u;
 
+// Set on the BVH indices of CSG operations, the other bits are the index of the operation.
+const CSG_PRIMITIVE = 0x80000000u;
+const CSG_INTERSECTION = 1u;
+const CSG_DIFFERENCE = 2u;
+
+const MATERIAL_MIX = 7u;
+// Mixes of mixes are followed this many levels deep.
+const MAX_MIX_DEPTH = 4u;
+
+const LIGHT_POINT = 1u;
+const LIGHT_DIRECTIONAL = 2u;
+const LIGHT_SPOT = 3u;
+
+const BACKGROUND_SKY = 0u;
+const BACKGROUND_SOLID = 1u;
+const BACKGROUND_GRADIENT = 2u;
+
+const WRAP_CLAMP = 1u;
+const WRAP_MIRROR = 2u;
+
+const DEBUG_HIGHLIGHT_BACKFACES = 1u;
+
+const OUTPUT_BEAUTY = 0u;
+const OUTPUT_DEPTH = 1u;
+const OUTPUT_NORMAL = 2u;
+const OUTPUT_ALBEDO = 3u;
+const OUTPUT_BOUNCES = 4u;
+
+const AA_STRATIFIED = 1u;
+const AA_QUASI_RANDOM = 2u;
+// Cells along each side of the grid stratified samples are spread over.
+const AA_STRATA = 4u;
+// Steps of the R4 quasi-random sequence, the powers of 1/g for the root g of x^5 = x + 1, in 0.32 fixed point.
+// Integer steps wrap around exactly at any sample index, where a float product would lose the fraction.
+// https://extremelearning.com.au/unreasonable-effectiveness-of-quasirandom-sequences/
+const R4_ALPHA = vec4(3679390609u, 3152041523u, 2700274806u, 2313257605u);
+
+// Auto exposure samples every LUMINANCE_STRIDE-th pixel in both directions and stores log2 luminance as fixed point.
+const LUMINANCE_STRIDE = 4u;
+const LUMINANCE_SCALE = 64f;
+const MIN_LOG_LUMINANCE = -12f;
+const MAX_LOG_LUMINANCE = 12f;
+const EXPOSURE_BIAS = 0.246f;   // determined experimentally for the scene
+// The noise estimate sums the relative error of the same pixels as auto exposure, as fixed point. Errors are
+// clamped so a few fireflies can't dominate it, and measured against a floor so black pixels don't divide by zero.
+const NOISE_SCALE = 1024f;
+const MAX_RELATIVE_ERROR = 1f;
+const NOISE_LUMINANCE_FLOOR = 0.01f;
+// Adaptive sampling trusts the noise of a pixel only after it has taken this many samples.
+const MIN_ADAPTIVE_SAMPLES = 16f;
+const BACKFACE_TINT = vec3(1f, 0f, 1f);
+
+// Luminance difference, relative to the center pixel, at which neighbours stop contributing to the denoised color
+// after a single sample. The tolerance shrinks with the noise, by the square root of the sample count.
+const DENOISE_SIGMA = 2f;
+// Irradiance of the sun disk relative to the sky radiance, independent of its angular size.
+const SUN_IRRADIANCE = 40f;   // determined experimentally for the scene
+// Gradient sky without a sun, for parameters the sky model rejects.
+const FALLBACK_SKY_HORIZON = vec3(1f, 1f, 1f);
+const FALLBACK_SKY_ZENITH = vec3(0.5f, 0.7f, 1f);
+
 @group(0) @binding(0) var<uniform> vertexUniforms: VertexUniforms;
 
 @vertex
@@ -36,8 +99,18 @@ struct VertexOutput {
     @location(0) texCoords: vec2<f32>,
 }
 
+// Image width and height, frame number, and 1 in w if the target expects sRGB encoded colors.
 @group(1) @binding(0) var<uniform> frameData: vec4<u32>;
-@group(1) @binding(1) var<storage, read_write> imageBuffer: array<array<f32, 3>>;
+// Sum of the samples in rgb and their count in w. Pixels outside the crop keep the samples of earlier renders.
+@group(1) @binding(1) var<storage, read_write> imageBuffer: array<vec4<f32>>;
+// Indexed by frame parity: the current frame accumulates into one slot while reading the other one.
+@group(1) @binding(2) var<storage, read_write> luminanceStats: array<LuminanceStats, 2>;
+// Sum of the squared luminance of the samples in `imageBuffer`, for their variance.
+@group(1) @binding(3) var<storage, read_write> squareBuffer: array<f32>;
+// Indexed by frame parity like `luminanceStats`, read back by the CPU.
+@group(1) @binding(4) var<storage, read_write> noiseStats: array<NoiseStats, 2>;
+// Exposed highlights at half the image resolution, in two halves the bloom passes blur back and forth between.
+@group(1) @binding(5) var<storage, read_write> bloomBuffer: array<vec4<f32>>;
 
 @group(2) @binding(0) var<uniform> camera: Camera;
 @group(2) @binding(1) var<uniform> samplingParams: SamplingParams;
@@ -47,6 +120,10 @@ struct VertexOutput {
 @group(3) @binding(1) var<storage, read> materials: array<Material>;
 @group(3) @binding(2) var<storage, read> textures: array<array<f32, 3>>;
 @group(3) @binding(3) var<storage, read> lights: array<u32>;
+@group(3) @binding(4) var<storage, read> bvhNodes: array<BvhNode>;
+@group(3) @binding(5) var<storage, read> bvhIndices: array<u32>;
+@group(3) @binding(6) var<storage, read> analyticLights: array<Light>;
+@group(3) @binding(7) var<storage, read> csgOps: array<CsgOp>;
 
 @fragment
 fn fsMain(in: VertexOutput) -> @location(0) vec4<f32> {
@@ -61,31 +138,332 @@ fn fsMain(in: VertexOutput) -> @location(0) vec4<f32> {
     let y = u32(v * f32(imageHeight));
     let idx = imageWidth * y + x;
 
-    var rngState = initRng(vec2(x, y), vec2(imageWidth, imageHeight), frameNumber);
-    var pixel = vec3(imageBuffer[idx][0u], imageBuffer[idx][1u], imageBuffer[idx][2u]);
-    {
+    // Seeding with the sample count instead of the frame number makes renders reproducible. The beauty pass
+    // reseeds for every sample, see `samplePixel`.
+    let sampleIndex = samplingParams.accumulatedSamplesPerPixel;
+    var rngState = initRng(vec2(x, y), vec2(imageWidth, imageHeight), sampleIndex, samplingParams.seed);
+    if samplingParams.outputChannel == OUTPUT_BOUNCES {
+        // The bounce counts are summed in the square buffer and the ray count in `w`,
+        // the image holds their mean as a heatmap.
+        var pixel = imageBuffer[idx];
+        var bounceSum = squareBuffer[idx];
         if samplingParams.clearAccumulatedSamples == 1u {
-            pixel = vec3(0f);
+            pixel = vec4(0f);
+            bounceSum = 0f;
+        }
+        if samplingParams.numSamplesPerPixel > 0u {
+            bounceSum += f32(bounceCount(x, y, &rngState));
+            pixel.w += 1f;
+            squareBuffer[idx] = bounceSum;
         }
 
-        let rgb = samplePixel(x, y, &rngState);
-        pixel += rgb;
+        let meanBounces = bounceSum / max(pixel.w, 1f);
+        let heat = bounceHeatmap(meanBounces / f32(max(samplingParams.numBounces, 1u)));
+        imageBuffer[idx] = vec4(heat, pixel.w);
+        return vec4(heat, 1f);
+    }
+    if samplingParams.outputChannel != OUTPUT_BEAUTY {
+        // Already in the displayable range, so neither exposure nor tonemapping apply.
+        let aov = outputChannelColor(x, y, &rngState);
+        imageBuffer[idx] = vec4(aov, 1f);
+        return vec4(aov, 1f);
     }
-    imageBuffer[idx] = array<f32, 3>(pixel.r, pixel.g, pixel.b);
 
-    let invN = 1f / f32(samplingParams.accumulatedSamplesPerPixel);
+    var pixel = imageBuffer[idx];
+    let insideCrop = all(vec2(x, y) >= samplingParams.cropMin) && all(vec2(x, y) < samplingParams.cropMax);
+    if insideCrop {
+        var squareSum = squareBuffer[idx];
+        if samplingParams.clearAccumulatedSamples == 1u {
+            pixel = vec4(0f);
+            squareSum = 0f;
+        }
 
-    return vec4(
-        uncharted2(invN * pixel),
-        1f
-    );
+        let converged = samplingParams.varianceThreshold > 0f && pixel.w >= MIN_ADAPTIVE_SAMPLES
+            && relativeError(pixel, squareSum) < samplingParams.varianceThreshold;
+        if !converged {
+            let numSamples = f32(samplingParams.numSamplesPerPixel);
+            let samples = samplePixel(x, y);
+            pixel += vec4(samples.rgb, numSamples);
+            imageBuffer[idx] = pixel;
+
+            squareSum += samples.w;
+            squareBuffer[idx] = squareSum;
+        }
+
+        accumulateConvergence(converged, frameNumber % 2u);
+        if x % LUMINANCE_STRIDE == 0u && y % LUMINANCE_STRIDE == 0u {
+            accumulateNoise(pixel, squareSum, frameNumber % 2u);
+        }
+    }
+
+    let color = accumulatedColor(pixel);
+
+    if samplingParams.autoExposureKey > 0f && x % LUMINANCE_STRIDE == 0u && y % LUMINANCE_STRIDE == 0u {
+        accumulateLuminance(color, frameNumber % 2u);
+    }
+
+    return displayColor(exposureScale(frameNumber) * color);
+}
+
+// Edge-aware blur of the image accumulated by `fsMain` in the previous pass. A single À-Trous iteration
+// with a 5x5 B3 spline kernel, neighbours are weighted down by their luminance difference to the center.
+@fragment
+fn fsDenoise(in: VertexOutput) -> @location(0) vec4<f32> {
+    let imageWidth = frameData.x;
+    let imageHeight = frameData.y;
+    let frameNumber = frameData.z;
+
+    let x = i32(in.texCoords.x * f32(imageWidth));
+    let y = i32(in.texCoords.y * f32(imageHeight));
+
+    let centerPixel = imageBuffer[u32(y) * imageWidth + u32(x)];
+    if samplingParams.outputChannel != OUTPUT_BEAUTY {
+        return vec4(centerPixel.rgb, 1f);
+    }
+
+    let center = accumulatedColor(centerPixel);
+    let centerLuminance = luminance(center);
+    let sigma = DENOISE_SIGMA * inverseSqrt(max(centerPixel.w, 1f)) * (centerLuminance + 0.01f);
+
+    var colorSum = vec3(0f);
+    var weightSum = 0f;
+    for (var j = -2; j <= 2; j += 1) {
+        for (var i = -2; i <= 2; i += 1) {
+            let px = x + i;
+            let py = y + j;
+            if px < 0 || py < 0 || px >= i32(imageWidth) || py >= i32(imageHeight) {
+                continue;
+            }
+
+            let color = accumulatedColor(imageBuffer[u32(py) * imageWidth + u32(px)]);
+            let edgeWeight = exp(-abs(luminance(color) - centerLuminance) / sigma);
+            let weight = atrousWeight(i) * atrousWeight(j) * edgeWeight;
+            colorSum += weight * color;
+            weightSum += weight;
+        }
+    }
+
+    return displayColor(exposureScale(frameNumber) * colorSum / weightSum + bloom(u32(x), u32(y)));
+}
+
+// Tonemaps the image accumulated by `fsMain` in the previous pass with the bloom added, when it isn't denoised.
+@fragment
+fn fsResolve(in: VertexOutput) -> @location(0) vec4<f32> {
+    let imageWidth = frameData.x;
+    let imageHeight = frameData.y;
+    let frameNumber = frameData.z;
+
+    let x = u32(in.texCoords.x * f32(imageWidth));
+    let y = u32(in.texCoords.y * f32(imageHeight));
+
+    let pixel = imageBuffer[y * imageWidth + x];
+    if samplingParams.outputChannel != OUTPUT_BEAUTY {
+        return vec4(pixel.rgb, 1f);
+    }
+
+    return displayColor(exposureScale(frameNumber) * accumulatedColor(pixel) + bloom(x, y));
+}
+
+// Tonemaps an exposed color, and encodes it as sRGB unless the target does, whichever pass draws the image last.
+fn displayColor(exposed: vec3<f32>) -> vec4<f32> {
+    let color = uncharted2(exposed);
+    if frameData.w == 0u {
+        return vec4(color, 1f);
+    }
+    return vec4(linearToSrgb(saturate(color)), 1f);
+}
+
+// Lighting is computed in linear space, the displayed image is sRGB.
+fn linearToSrgb(color: vec3<f32>) -> vec3<f32> {
+    let low = 12.92f * color;
+    let high = 1.055f * pow(color, vec3(1f / 2.4f)) - 0.055f;
+    return select(high, low, color <= vec3(0.0031308f));
+}
+
+// First bloom pass: averages 2x2 pixels of the exposed image and keeps the part brighter than the threshold.
+// Like the blur passes, it is drawn over the bloom resolution and its color output is discarded.
+@fragment
+fn fsBloomPrefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let imageSize = frameData.xy;
+    let p = vec2<u32>(in.texCoords * vec2<f32>(bloomSize()));
+
+    var color = vec3(0f);
+    for (var j = 0u; j < 2u; j += 1u) {
+        for (var i = 0u; i < 2u; i += 1u) {
+            let q = min(2u * p + vec2(i, j), imageSize - 1u);
+            color += accumulatedColor(imageBuffer[q.y * imageSize.x + q.x]);
+        }
+    }
+    color *= 0.25f * exposureScale(frameData.z);
+
+    let l = luminance(color);
+    let bright = color * max(l - samplingParams.bloomThreshold, 0f) / max(l, 1e-6f);
+    bloomBuffer[bloomIndex(0u, p)] = vec4(bright, 1f);
+    return vec4(0f);
+}
+
+@fragment
+fn fsBloomBlurHorizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    let p = vec2<u32>(in.texCoords * vec2<f32>(bloomSize()));
+    bloomBuffer[bloomIndex(1u, p)] = vec4(bloomBlur(p, vec2(1, 0), 0u), 1f);
+    return vec4(0f);
+}
+
+@fragment
+fn fsBloomBlurVertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    let p = vec2<u32>(in.texCoords * vec2<f32>(bloomSize()));
+    bloomBuffer[bloomIndex(0u, p)] = vec4(bloomBlur(p, vec2(0, 1), 1u), 1f);
+    return vec4(0f);
+}
+
+fn bloomSize() -> vec2<u32> {
+    return (frameData.xy + 1u) / 2u;
+}
+
+fn bloomIndex(slot: u32, p: vec2<u32>) -> u32 {
+    let index = slot * (arrayLength(&bloomBuffer) / 2u) + p.y * bloomSize().x + p.x;
+    return min(index, arrayLength(&bloomBuffer) - 1u);
+}
+
+fn bloomBlur(p: vec2<u32>, direction: vec2<i32>, slot: u32) -> vec3<f32> {
+    let maxP = vec2<i32>(bloomSize()) - 1;
+    var color = bloomWeight(0) * bloomBuffer[bloomIndex(slot, p)].rgb;
+    for (var i = 1; i < 5; i += 1) {
+        let before = vec2<u32>(clamp(vec2<i32>(p) - i * direction, vec2(0), maxP));
+        let after = vec2<u32>(clamp(vec2<i32>(p) + i * direction, vec2(0), maxP));
+        let neighbours = bloomBuffer[bloomIndex(slot, before)].rgb + bloomBuffer[bloomIndex(slot, after)].rgb;
+        color += bloomWeight(i) * neighbours;
+    }
+    return color;
+}
+
+// Gaussian weights for offsets -4..=4 summing up to one.
+fn bloomWeight(offset: i32) -> f32 {
+    switch abs(offset) {
+        case 0: {
+            return 0.227027f;
+        }
+
+        case 1: {
+            return 0.1945946f;
+        }
+
+        case 2: {
+            return 0.1216216f;
+        }
+
+        case 3: {
+            return 0.054054f;
+        }
+
+        default: {
+            return 0.016216f;
+        }
+    }
+}
+
+// Blurred highlights at an image pixel, interpolated between the pixels of the bloom buffer. Zero intensity
+// means the bloom passes didn't run.
+fn bloom(x: u32, y: u32) -> vec3<f32> {
+    if samplingParams.bloomIntensity <= 0f {
+        return vec3(0f);
+    }
+
+    let position = (vec2(f32(x), f32(y)) + 0.5f) * 0.5f - 0.5f;
+    let p = vec2<i32>(floor(position));
+    let t = position - floor(position);
+    let top = mix(bloomTexel(p), bloomTexel(p + vec2(1, 0)), t.x);
+    let bottom = mix(bloomTexel(p + vec2(0, 1)), bloomTexel(p + vec2(1, 1)), t.x);
+    return samplingParams.bloomIntensity * mix(top, bottom, t.y);
+}
+
+fn bloomTexel(p: vec2<i32>) -> vec3<f32> {
+    let clamped = vec2<u32>(clamp(p, vec2(0), vec2<i32>(bloomSize()) - 1));
+    return bloomBuffer[bloomIndex(0u, clamped)].rgb;
+}
+
+// Average of the samples of an image buffer pixel, black before the first sample.
+fn accumulatedColor(pixel: vec4<f32>) -> vec3<f32> {
+    return pixel.rgb / max(pixel.w, 1f);
+}
+
+// B3 spline coefficients 1/16, 1/4, 3/8, 1/4, 1/16 for offsets -2..=2.
+fn atrousWeight(offset: i32) -> f32 {
+    switch abs(offset) {
+        case 0: {
+            return 0.375f;
+        }
+
+        case 1: {
+            return 0.25f;
+        }
+
+        default: {
+            return 0.0625f;
+        }
+    }
+}
+
+// Per channel, so that it includes the white balance.
+fn exposureScale(frameNumber: u32) -> vec3<f32> {
+    let cameraScale = exp2(samplingParams.cameraExposure) * samplingParams.whiteBalance;
+    if samplingParams.autoExposureKey > 0f {
+        return cameraScale * autoExposure(samplingParams.autoExposureKey, (frameNumber + 1u) % 2u);
+    }
+    return cameraScale * exp2(samplingParams.exposure);
+}
+
+fn accumulateLuminance(color: vec3<f32>, slot: u32) {
+    let logLuminance = clamp(log2(max(luminance(color), 1e-6f)), MIN_LOG_LUMINANCE, MAX_LOG_LUMINANCE);
+    atomicAdd(&luminanceStats[slot].logSum, i32(round(logLuminance * LUMINANCE_SCALE)));
+    atomicAdd(&luminanceStats[slot].count, 1u);
+}
+
+// Counts every pixel of the crop and those that still take samples, so that the progress doesn't hinge on a grid.
+fn accumulateConvergence(converged: bool, slot: u32) {
+    atomicAdd(&noiseStats[slot].pixelCount, 1u);
+    if !converged {
+        atomicAdd(&noiseStats[slot].activeCount, 1u);
+    }
+}
+
+// Adds the relative error of a pixel.
+fn accumulateNoise(pixel: vec4<f32>, squareSum: f32, slot: u32) {
+    if pixel.w < 2f {
+        return;
+    }
+
+    atomicAdd(&noiseStats[slot].errorSum, u32(round(relativeError(pixel, squareSum) * NOISE_SCALE)));
+    atomicAdd(&noiseStats[slot].count, 1u);
+}
+
+// Standard error of the mean luminance of a pixel, relative to that luminance.
+fn relativeError(pixel: vec4<f32>, squareSum: f32) -> f32 {
+    let count = max(pixel.w, 1f);
+    let mean = luminance(pixel.rgb) / count;
+    let variance = max(squareSum / count - mean * mean, 0f);
+    return min(sqrt(variance / count) / (mean + NOISE_LUMINANCE_FLOOR), MAX_RELATIVE_ERROR);
+}
+
+// Scales the image so that its log-average luminance enters the tonemapping curve at `key`.
+fn autoExposure(key: f32, slot: u32) -> f32 {
+    let count = atomicLoad(&luminanceStats[slot].count);
+    if count == 0u {
+        return 1f;
+    }
+
+    let logAverage = f32(atomicLoad(&luminanceStats[slot].logSum)) / (LUMINANCE_SCALE * f32(count));
+    return key / (EXPOSURE_BIAS * exp2(logAverage));
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126f, 0.7152f, 0.0722f));
 }
 
 fn uncharted2(x: vec3<f32>) -> vec3<f32> {
     // Based on uncharted2 tonemapping function
     // https://dmnsgn.github.io/glsl-tone-map/
-    let exposureBias = 0.246;   // determined experimentally for the scene
-    let curr = uncharted2Tonemap(exposureBias * x);
+    let curr = uncharted2Tonemap(EXPOSURE_BIAS * x);
 
     let w = 11.2;
     let whiteScale = 1f / uncharted2Tonemap(vec3(w));
@@ -103,23 +481,180 @@ fn uncharted2Tonemap(x: vec3<f32>) -> vec3<f32> {
     return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
 }
 
-fn samplePixel(x: u32, y: u32, rngState: ptr<function, u32>) -> vec3<f32> {
+// Sum of the samples in rgb and of their squared luminance in w. Each sample is seeded by its index since the
+// start of the render, so the image doesn't depend on how many samples the frames took.
+fn samplePixel(x: u32, y: u32) -> vec4<f32> {
     let imageWidth = frameData.x;
     let imageHeight = frameData.y;
     let invWidth = 1f / f32(imageWidth);
     let invHeight = 1f / f32(imageHeight);
 
     let numSamples = samplingParams.numSamplesPerPixel;
+    // The accumulated count already includes the samples of this frame.
+    let firstSample = samplingParams.accumulatedSamplesPerPixel - numSamples;
     var color = vec3(0f);
+    var squareSum = 0f;
     for (var i = 0u; i < numSamples; i += 1u) {
-        let u = (f32(x) + rngNextFloat(rngState)) * invWidth;
-        let v = (f32(y) + rngNextFloat(rngState)) * invHeight;
+        var rngState = initRng(vec2(x, y), vec2(imageWidth, imageHeight), firstSample + i, samplingParams.seed);
+        let aa = aaSample(x, y, firstSample + i, &rngState);
+        let u = (f32(x) + aa.x) * invWidth;
+        let v = (f32(y) + aa.y) * invHeight;
+
+        let primaryRay = cameraMakeRay(camera, aa.zw, u, 1f - v);
+        let sampleColor = rayColor(primaryRay, &rngState);
+        color += sampleColor;
+        squareSum += luminance(sampleColor) * luminance(sampleColor);
+    }
 
-        let primaryRay = cameraMakeRay(camera, rngState, u, 1f - v);
-        color += rayColor(primaryRay, rngState);
+    return vec4(color, squareSum);
+}
+
+// Position within the pixel in `xy` and on the lens in `zw` of a sample, each in [0, 1).
+fn aaSample(x: u32, y: u32, sampleIndex: u32, rngState: ptr<function, u32>) -> vec4<f32> {
+    let jitter = vec4(rngNextFloat(rngState), rngNextFloat(rngState), rngNextFloat(rngState), rngNextFloat(rngState));
+    switch samplingParams.aaPattern {
+        case AA_STRATIFIED: {
+            // Each run of AA_STRATA^2 samples takes every cell of the pixel and of the lens once,
+            // in an order shuffled per pixel. The lens cells are shuffled again for every run, so that the
+            // cell on the lens doesn't follow from the cell in the pixel.
+            let scramble = jenkinsHash((x + y * frameData.x) ^ jenkinsHash(samplingParams.seed));
+            let run = sampleIndex / (AA_STRATA * AA_STRATA);
+            let pixelCell = stratum(sampleIndex ^ scramble);
+            let lensCell = stratum(sampleIndex ^ jenkinsHash(scramble ^ jenkinsHash(run)));
+            return (vec4(pixelCell, lensCell) + jitter) / f32(AA_STRATA);
+        }
+        case AA_QUASI_RANDOM: {
+            // Rotated by a random offset per pixel (Cranley-Patterson rotation), so neighbouring pixels don't
+            // share their positions. The top 24 bits are exact in a float.
+            let h0 = jenkinsHash((x + y * frameData.x) ^ jenkinsHash(samplingParams.seed));
+            let h1 = jenkinsHash(h0);
+            let h2 = jenkinsHash(h1);
+            let offset = vec4(h0, h1, h2, jenkinsHash(h2));
+            let point = offset + sampleIndex * R4_ALPHA;
+            return vec4<f32>(point >> vec4(8u)) / 16777216f;
+        }
+        default: {
+            return jitter;
+        }
     }
+}
 
-    return color;
+fn stratum(index: u32) -> vec2<f32> {
+    let cell = index % (AA_STRATA * AA_STRATA);
+    return vec2(f32(cell % AA_STRATA), f32(cell / AA_STRATA));
+}
+
+// Depth, normal or albedo of the first hit of a single ray through the pixel center, black where it misses.
+fn outputChannelColor(x: u32, y: u32, rngState: ptr<function, u32>) -> vec3<f32> {
+    let u = (f32(x) + 0.5f) / f32(frameData.x);
+    let v = (f32(y) + 0.5f) / f32(frameData.y);
+    let ray = cameraMakeRay(camera, rngNextVec2(rngState), u, 1f - v);
+
+    var hit = Intersection();
+    if !intersection(ray, &hit) {
+        return vec3(0f);
+    }
+
+    hit.materialIdx = mixedMaterialIdx(hit, rngState);
+    let material = materials[hit.materialIdx];
+    switch samplingParams.outputChannel {
+        case OUTPUT_DEPTH: {
+            let depth = hit.t * length(ray.direction);
+            return vec3(1f / (1f + 0.1f * depth));
+        }
+
+        case OUTPUT_NORMAL: {
+            if (material.id == 0u || material.id == 1u) && material.desc2.offset != 0xffffffffu {
+                hit.n = applyNormalMap(hit, material.desc2);
+            }
+            if (material.id == 0u || material.id == 1u) && material.desc4.offset != 0xffffffffu {
+                hit.n = applyHeightMap(hit, material.desc4);
+            }
+            return 0.5f * hit.n + 0.5f;
+        }
+
+        default: {
+            var albedo = vec3(1f);
+            if diffuseAlbedo(hit, material, &albedo) {
+                return albedo;
+            }
+
+            switch material.id {
+                case 1u: {
+                    return materialTint(material) * textureLookup(material.desc1, hit.u, hit.v);
+                }
+
+                case 4u, 5u, 6u: {
+                    return min(textureLookup(material.desc1, hit.u, hit.v), vec3(1f));
+                }
+
+                // Clear glass lets the color behind it through.
+                default: {
+                    return albedo;
+                }
+            }
+        }
+    }
+}
+
+// Bounces a jittered ray through the pixel takes until it escapes, reaches a light or hits the bounce limit.
+fn bounceCount(x: u32, y: u32, rngState: ptr<function, u32>) -> u32 {
+    let u = (f32(x) + rngNextFloat(rngState)) / f32(frameData.x);
+    let v = (f32(y) + rngNextFloat(rngState)) / f32(frameData.y);
+    var ray = cameraMakeRay(camera, rngNextVec2(rngState), u, 1f - v);
+
+    var diffuseBounces = 0u;
+    var transmissionBounces = 0u;
+    var bounce = 0u;
+    for (; withinBounceLimits(diffuseBounces, transmissionBounces); bounce += 1u) {
+        var hit = Intersection();
+        if !intersection(ray, &hit) {
+            return bounce;
+        }
+
+        hit.materialIdx = mixedMaterialIdx(hit, rngState);
+        let material = materials[hit.materialIdx];
+        if material.id == 4u {
+            return bounce;
+        }
+        countBounce(material, &diffuseBounces, &transmissionBounces);
+        ray = scatterRay(ray, hit, material, rngState).ray;
+    }
+
+    return bounce;
+}
+
+// Glass has a budget of its own, so that light passing through nested or internally reflecting glass doesn't
+// run out of bounces and turn dark.
+fn withinBounceLimits(diffuseBounces: u32, transmissionBounces: u32) -> bool {
+    return diffuseBounces < samplingParams.numBounces
+        && transmissionBounces < samplingParams.numTransmissionBounces;
+}
+
+// Picks one of the materials of a mix, the second with the probability of the red channel of the mask.
+// Choosing anew at every hit blends the scattering of the two on average.
+fn mixedMaterialIdx(hit: Intersection, rngState: ptr<function, u32>) -> u32 {
+    var materialIdx = hit.materialIdx;
+    for (var depth = 0u; depth < MAX_MIX_DEPTH && materials[materialIdx].id == MATERIAL_MIX; depth += 1u) {
+        let mixMaterial = materials[materialIdx];
+        let mask = textureLookup(mixMaterial.desc1, hit.u, hit.v).r;
+        materialIdx = select(u32(mixMaterial.x), u32(mixMaterial.y), rngNextFloat(rngState) < mask);
+    }
+    return materialIdx;
+}
+
+fn countBounce(material: Material, diffuseBounces: ptr<function, u32>, transmissionBounces: ptr<function, u32>) {
+    if material.id == 2u {
+        *transmissionBounces += 1u;
+    } else {
+        *diffuseBounces += 1u;
+    }
+}
+
+// Jet colormap, dark blue for `t` = 0 through green and yellow to dark red for `t` = 1.
+fn bounceHeatmap(t: f32) -> vec3<f32> {
+    let x = 4f * clamp(t, 0f, 1f);
+    return clamp(vec3(1.5f) - abs(vec3(x - 3f, x - 2f, x - 1f)), vec3(0f), vec3(1f));
 }
 
 fn rayColor(primaryRay: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
@@ -128,28 +663,87 @@ fn rayColor(primaryRay: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
     var color = vec3(0f);
     var throughput = vec3(1f);
 
-    for (var bounce = 0u; bounce < samplingParams.numBounces; bounce += 1u) {
+    // Previous diffuse hit, needed to weight emission found by BSDF sampling against light sampling.
+    var previousHit = Intersection();
+    var previousDiffuse = false;
+
+    var diffuseBounces = 0u;
+    var transmissionBounces = 0u;
+    for (var bounce = 0u; withinBounceLimits(diffuseBounces, transmissionBounces); bounce += 1u) {
         var intersection = Intersection();
 
         if intersection(ray, &intersection) {
+            if bounce == 0u
+                && (samplingParams.debugFlags & DEBUG_HIGHLIGHT_BACKFACES) != 0u
+                && dot(ray.direction, intersection.n) > 0f {
+                // The camera sees the inside of the surface.
+                color += BACKFACE_TINT;
+                break;
+            }
+
+            intersection.materialIdx = mixedMaterialIdx(intersection, rngState);
             let material = materials[intersection.materialIdx];
+            countBounce(material, &diffuseBounces, &transmissionBounces);
 
             if material.id == 4u {
+                // One-sided lights leave their back faces dark.
+                if material.x == 0f && dot(ray.direction, intersection.n) > 0f {
+                    break;
+                }
                 let emissionTexture = material.desc1;
                 let emissionColor = textureLookup(emissionTexture, intersection.u, intersection.v);
-                color += throughput * emissionColor;
+                var weight = 1f;
+                let sphere = spheres[intersection.sphereIdx];
+                // Only spheres with an emissive material of their own are lights, a mix that picked an emissive
+                // material is never sampled and keeps all of its weight.
+                let isLight = materials[sphere.materialIdx].id == 4u;
+                if previousDiffuse && isLight {
+                    let wi = normalize(ray.direction);
+                    let lightPdf = pdfEmissiveLight(previousHit.p, sphere);
+                    let bsdfPdf = max(0f, dot(previousHit.n, wi)) * FRAC_1_PI;
+                    weight = misWeight(bsdfPdf, lightPdf);
+                }
+                color += throughput * weight * emissionColor;
                 break;
             }
 
+            if (material.id == 0u || material.id == 1u) && material.desc2.offset != 0xffffffffu {
+                intersection.n = applyNormalMap(intersection, material.desc2);
+            }
+            if (material.id == 0u || material.id == 1u) && material.desc4.offset != 0xffffffffu {
+                intersection.n = applyHeightMap(intersection, material.desc4);
+            }
+
+            var albedo = vec3(0f);
+            previousDiffuse = diffuseAlbedo(intersection, material, &albedo);
+            previousHit = intersection;
+            if previousDiffuse {
+                // Analytic lights are infinitely small and can never be hit by a scattered ray,
+                // so sampling them explicitly does not count any light twice.
+                let incoming = sampleAnalyticLights(intersection) + sampleEmissiveLight(intersection, rngState)
+                    + sampleSun(intersection, rngState);
+                color += throughput * albedo * incoming;
+            }
+
             var scatter = scatterRay(ray, intersection, material, rngState);
             ray = scatter.ray;
             throughput *= scatter.throughput;
         } else {
             // The ray missed. Output background color.
             let v = normalize(ray.direction);
+            if bounce == 0u && samplingParams.backgroundMode != BACKGROUND_SKY {
+                color += throughput * backgroundColor(v);
+                break;
+            }
+
+            if skyState.fallback != 0u {
+                color += throughput * fallbackSky(v);
+                break;
+            }
+
             let s = skyState.sunDirection;
 
-            let theta = acos(v.y);
+            let theta = acos(clamp(dot(v, skyState.up), -1f, 1f));
             let gamma = acos(clamp(dot(v, s), -1f, 1f));
 
             color += throughput * vec3(
@@ -158,6 +752,11 @@ fn rayColor(primaryRay: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
                 radiance(theta, gamma, CHANNEL_B)
             );
 
+            // Diffuse surfaces sample the sun directly, so only rays that did not bounce off one see the disk.
+            if skyState.sunEnabled != 0u && !previousDiffuse && dot(v, s) >= skyState.sunCosAngularRadius {
+                color += throughput * sunColor() / sunSolidAngle();
+            }
+
             break;
         }
     }
@@ -165,19 +764,64 @@ fn rayColor(primaryRay: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
     return color;
 }
 
+// Drawn while the sky model rejects its parameters, so the scene stays lit until they are fixed.
+fn fallbackSky(direction: vec3<f32>) -> vec3<f32> {
+    let t = 0.5f * (dot(direction, skyState.up) + 1f);
+    return mix(FALLBACK_SKY_HORIZON, FALLBACK_SKY_ZENITH, t);
+}
+
+fn backgroundColor(direction: vec3<f32>) -> vec3<f32> {
+    if samplingParams.backgroundMode == BACKGROUND_SOLID {
+        return samplingParams.backgroundTop;
+    }
+    let t = 0.5f * (dot(direction, skyState.up) + 1f);
+    return mix(samplingParams.backgroundBottom, samplingParams.backgroundTop, t);
+}
+
 fn intersection(ray: Ray, intersection: ptr<function, Intersection>) -> bool {
-    var closestT = MAX_T;
+    // Hits beyond the max ray distance count as misses.
+    let maxT = min(MAX_T, samplingParams.maxRayDistance / length(ray.direction));
+    var closestT = maxT;
     var closestIntersection = Intersection();
 
-    for (var idx = 0u; idx < arrayLength(&spheres); idx = idx + 1u) {
-        var testIntersect = Intersection();
-        if rayIntersectSphere(ray, idx, MIN_T, closestT, &testIntersect) {
-            closestT = testIntersect.t;
-            closestIntersection = testIntersect;
+    let invDirection = 1f / ray.direction;
+    var stack: array<u32, BVH_STACK_SIZE>;
+    var stackSize = 1u;
+    stack[0] = 0u;
+
+    while stackSize > 0u {
+        stackSize -= 1u;
+        let node = bvhNodes[stack[stackSize]];
+
+        if !rayIntersectAabb(ray, invDirection, node.boundsMin, node.boundsMax, closestT) {
+            continue;
+        }
+
+        if node.count > 0u {
+            // Leaf node: test every primitive it references.
+            for (var i = 0u; i < node.count; i += 1u) {
+                let idx = bvhIndices[node.leftOrFirst + i];
+                var testIntersect = Intersection();
+                var found = false;
+                if (idx & CSG_PRIMITIVE) != 0u {
+                    found = rayIntersectCsg(ray, idx & ~CSG_PRIMITIVE, MIN_T, closestT, &testIntersect);
+                } else {
+                    found = rayIntersectSphere(ray, idx, MIN_T, closestT, &testIntersect);
+                }
+                if found {
+                    closestT = testIntersect.t;
+                    closestIntersection = testIntersect;
+                }
+            }
+        } else if node.leftOrFirst != 0u && stackSize + 2u <= BVH_STACK_SIZE {
+            // Inner node: children are stored next to each other.
+            stack[stackSize] = node.leftOrFirst + 1u;
+            stack[stackSize + 1u] = node.leftOrFirst;
+            stackSize += 2u;
         }
     }
 
-    if closestT < MAX_T {
+    if closestT < maxT {
         *intersection = closestIntersection;
         return true;
     }
@@ -185,110 +829,314 @@ fn intersection(ray: Ray, intersection: ptr<function, Intersection>) -> bool {
     return false;
 }
 
-fn scatterRay(wo: Ray, hit: Intersection, material: Material, rngState: ptr<function, u32>) -> Scatter {
-    switch material.id {
-        case 0u: {
-            let texture = material.desc1;
-            return scatterMixtureDensity(hit, texture, rngState);
+// Returns the light reflected by a white diffuse surface, to be multiplied with the albedo.
+fn sampleAnalyticLights(hit: Intersection) -> vec3<f32> {
+    var radiance = vec3(0f);
+    let lightGroupBit = 1u << spheres[hit.sphereIdx].lightGroup;
+    let numLights = arrayLength(&analyticLights);
+    for (var i = 0u; i < numLights; i += 1u) {
+        let light = analyticLights[i];
+        if (light.groupMask & lightGroupBit) == 0u {
+            continue;
         }
 
-        case 1u: {
-            let texture = material.desc1;
-            let fuzz = material.x;
-            return scatterMetal(wo, hit, texture, fuzz, rngState);
+        var wi = vec3(0f);
+        var distance = MAX_T;
+        var falloff = 1f;
+        if light.kind == LIGHT_POINT {
+            let toLight = light.positionOrDirection - hit.p;
+            distance = length(toLight);
+            wi = toLight / distance;
+            falloff = 1f / max(EPSILON, distance * distance);
+        } else if light.kind == LIGHT_DIRECTIONAL {
+            wi = -light.positionOrDirection;
+        } else if light.kind == LIGHT_SPOT {
+            let toLight = light.positionOrDirection - hit.p;
+            distance = length(toLight);
+            wi = toLight / distance;
+            falloff = spotCone(light, dot(-wi, light.spotDirection)) / max(EPSILON, distance * distance);
+        } else {
+            continue;
         }
 
-        case 2u: {
-            let refractionIndex = material.x;
-            return scatterDielectric(wo, hit, refractionIndex, rngState);
+        let cosine = dot(hit.n, wi);
+        if cosine <= 0f {
+            continue;
         }
 
-        case 3u: {
-            let texture1 = material.desc1;
-            let texture2 = material.desc2;
-            return scatterCheckerboard(hit, texture1, texture2, rngState);
+        var shadowHit = Intersection();
+        if intersection(Ray(hit.p, wi), &shadowHit) && shadowHit.t < distance {
+            continue;
         }
 
-        default: {
-            return scatterMissingMaterial(hit, rngState);
-        }
+        radiance += FRAC_1_PI * cosine * falloff * light.radiance;
+    }
+
+    return radiance;
+}
+
+// Smoothstep from the outer to the inner cone angle, given the cosine of the angle to the cone axis.
+fn spotCone(light: Light, cosAngle: f32) -> f32 {
+    let t = clamp((cosAngle - light.cosOuter) / max(light.cosInner - light.cosOuter, EPSILON), 0f, 1f);
+    return t * t * (3f - 2f * t);
+}
+
+// Samples a direction toward one randomly selected emissive sphere. The result is weighted with
+// the balance heuristic, the remaining contribution is found by BSDF sampling in `rayColor`.
+fn sampleEmissiveLight(hit: Intersection, rngState: ptr<function, u32>) -> vec3<f32> {
+    let numLights = samplingParams.numEmissiveLights;
+    if numLights == 0u {
+        return vec3(0f);
+    }
+
+    let sphereIdx = lights[rngNextInt(rngState) % numLights];
+    let sphere = spheres[sphereIdx];
+
+    var lightPdf = 0f;
+    let wi = sampleSphereCone(hit.p, sphere, rngState, &lightPdf);
+    let cosine = dot(hit.n, wi);
+    if lightPdf <= 0f || cosine <= 0f {
+        return vec3(0f);
+    }
+
+    var lightHit = Intersection();
+    if !intersection(Ray(hit.p, wi), &lightHit) || lightHit.sphereIdx != sphereIdx {
+        return vec3(0f);
+    }
+
+    // The back of a one-sided light is dark, as when a path hits it.
+    let material = materials[sphere.materialIdx];
+    if material.x == 0f && dot(wi, lightHit.n) > 0f {
+        return vec3(0f);
     }
+
+    let emission = textureLookup(material.desc1, lightHit.u, lightHit.v);
+
+    lightPdf /= f32(numLights);
+    let bsdfPdf = cosine * FRAC_1_PI;
+    return emission * FRAC_1_PI * cosine * misWeight(lightPdf, bsdfPdf) / lightPdf;
 }
 
-fn scatterMixtureDensity(hit: Intersection, albedo: TextureDescriptor, rngState: ptr<function, u32>) -> Scatter {
-    let scatterDirection = sampleMixtureDensity(hit, rngState);
-    let materialValue = evalLambertian(hit, albedo, scatterDirection);
-    let materialPdf = pdfLambertian(hit, scatterDirection);
-    let lightPdf = pdfLight(hit, scatterDirection);
-    let throughput = materialValue / max(EPSILON, (0.5f * materialPdf + 0.5f * lightPdf));
-    return Scatter(Ray(hit.p, scatterDirection), throughput);
+// Probability density, per unit solid angle, of sampling `wi` toward the sphere from `p`
+// when the light is selected uniformly among all emissive spheres.
+fn pdfEmissiveLight(p: vec3<f32>, sphere: Sphere) -> f32 {
+    let numLights = samplingParams.numEmissiveLights;
+    if numLights == 0u {
+        return 0f;
+    }
+
+    let coneArea = sphereConeSolidAngle(p, sphere);
+    if coneArea <= 0f {
+        return 0f;
+    }
+
+    return 1f / (coneArea * f32(numLights));
 }
 
-fn sampleMixtureDensity(hit: Intersection, rngState: ptr<function, u32>) -> vec3<f32> {
-    if rngNextFloat(rngState) < 0.5f {
-        return sampleLambertian(hit, rngState);
-    } else {
-        return sampleLight(hit, rngState);
+// Solid angle of the cone the sphere subtends from `p`, zero when `p` is inside the sphere. A transformed
+// sphere is sampled through its bounding sphere, directions that miss it just don't find the light.
+fn sphereConeSolidAngle(p: vec3<f32>, sphere: Sphere) -> f32 {
+    let toCenter = sphere.boundingSphere.xyz - p;
+    let distanceSqr = dot(toCenter, toCenter);
+    let radiusSqr = sphere.boundingSphere.w * sphere.boundingSphere.w;
+    if distanceSqr <= radiusSqr {
+        return 0f;
     }
+
+    let cosThetaMax = sqrt(1f - radiusSqr / distanceSqr);
+    return 2f * PI * (1f - cosThetaMax);
 }
 
-fn evalLambertian(hit: Intersection, texture: TextureDescriptor, wi: vec3<f32>) -> vec3<f32> {
-    return textureLookup(texture, hit.u, hit.v) * FRAC_1_PI * max(EPSILON, dot(hit.n, wi));
+// Uniformly samples a direction inside the cone the sphere subtends from `p`.
+fn sampleSphereCone(p: vec3<f32>, sphere: Sphere, rngState: ptr<function, u32>, pdf: ptr<function, f32>) -> vec3<f32> {
+    let coneArea = sphereConeSolidAngle(p, sphere);
+    if coneArea <= 0f {
+        *pdf = 0f;
+        return vec3(0f);
+    }
+
+    let cosThetaMax = 1f - coneArea / (2f * PI);
+    *pdf = 1f / coneArea;
+    return sampleCone(normalize(sphere.boundingSphere.xyz - p), cosThetaMax, rngState);
 }
 
-fn sampleLambertian(hit: Intersection, rngState: ptr<function, u32>) -> vec3<f32> {
-    let v = rngNextInCosineWeightedHemisphere(rngState);
-    let onb = pixarOnb(hit.n);
-    return onb * v;
+// Irradiance of the sun tinted by the sky around it, so it reddens toward the horizon.
+fn sunColor() -> vec3<f32> {
+    let s = skyState.sunDirection;
+    let theta = acos(clamp(dot(s, skyState.up), -1f, 1f));
+    let tint = vec3(
+        radiance(theta, 0f, CHANNEL_R),
+        radiance(theta, 0f, CHANNEL_G),
+        radiance(theta, 0f, CHANNEL_B)
+    );
+    return SUN_IRRADIANCE * tint / max(EPSILON, max(tint.r, max(tint.g, tint.b)));
 }
 
-fn pdfLambertian(hit: Intersection, wi: vec3<f32>) -> f32 {
-    return max(EPSILON, dot(hit.n, wi) * FRAC_1_PI);
+fn sunSolidAngle() -> f32 {
+    return 2f * PI * (1f - skyState.sunCosAngularRadius);
 }
 
-fn sampleLight(hit: Intersection, rngState: ptr<function, u32>) -> vec3<f32> {
-    // Select a random light using a uniform distribution.
-    let numLights = arrayLength(&lights);   // TODO: what about when there are no lights?
-    let lightIdx = rngNextUintInRange(rngState, 0u, numLights - 1u);
-    let sphereIdx = lights[lightIdx];
-    let sphere = spheres[sphereIdx];
+// Samples a direction toward the sun disk and traces a shadow ray. The disk is uniformly bright,
+// so the pdf of the sampled direction cancels the solid angle in its radiance.
+fn sampleSun(hit: Intersection, rngState: ptr<function, u32>) -> vec3<f32> {
+    if skyState.sunEnabled == 0u {
+        return vec3(0f);
+    }
+
+    let wi = sampleCone(skyState.sunDirection, skyState.sunCosAngularRadius, rngState);
+    let cosine = dot(hit.n, wi);
+    if cosine <= 0f {
+        return vec3(0f);
+    }
 
-    return sampleHemisphere(hit, sphere, rngState);
+    var shadowHit = Intersection();
+    if intersection(Ray(hit.p, wi), &shadowHit) {
+        return vec3(0f);
+    }
+
+    return sunColor() * FRAC_1_PI * cosine;
+}
+
+// Uniformly samples a direction inside the cone around `axis` with the given half angle cosine.
+fn sampleCone(axis: vec3<f32>, cosThetaMax: f32, rngState: ptr<function, u32>) -> vec3<f32> {
+    let cosTheta = 1f - rngNextFloat(rngState) * (1f - cosThetaMax);
+    let sinTheta = sqrt(max(0f, 1f - cosTheta * cosTheta));
+    let phi = 2f * PI * rngNextFloat(rngState);
+
+    let onb = pixarOnb(axis);
+    return onb * vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
 }
 
-fn sampleHemisphere(hit: Intersection, sphere: Sphere, rngState: ptr<function, u32>) -> vec3<f32> {
-    let v = rngNextInUnitHemisphere(rngState);
+fn misWeight(pdf: f32, otherPdf: f32) -> f32 {
+    // Balance heuristic
+    return pdf / max(EPSILON, pdf + otherPdf);
+}
 
-    // Sample the hemisphere facing the intersection point.
-    let dir = normalize(hit.p - sphere.centerAndPad.xyz);
-    let onb = pixarOnb(dir);
+fn diffuseAlbedo(hit: Intersection, material: Material, albedo: ptr<function, vec3<f32>>) -> bool {
+    switch material.id {
+        case 0u: {
+            *albedo = materialTint(material) * textureLookup(material.desc1, hit.u, hit.v);
+            return true;
+        }
 
-    let pointOnSphere = sphere.centerAndPad.xyz + onb * sphere.radius * v;
-    let toPointOnSphere = pointOnSphere - hit.p;
+        case 3u: {
+            *albedo = textureLookup(checkerboardTexture(hit, material), hit.u, hit.v);
+            return true;
+        }
 
-    return normalize(toPointOnSphere);
+        default: {
+            return false;
+        }
+    }
 }
 
-fn pdfLight(hit: Intersection, wi: vec3<f32>) -> f32 {
-    let ray = Ray(hit.p, wi);
-    var lightHit = Intersection();
-    var pdf = 0f;
+// Normal maps store tangent space normals, red along the direction of increasing u and green along v.
+fn applyNormalMap(hit: Intersection, normalMap: TextureDescriptor) -> vec3<f32> {
+    let n = hit.n;
+    let t = sphereTangent(n);
+    let b = cross(n, t);
+    let ts = 2f * textureLookup(normalMap, hit.u, hit.v) - 1f;
+    return normalize(ts.x * t + ts.y * b + ts.z * n);
+}
 
-    if intersection(ray, &lightHit) {
-        let sphereIdx = lightHit.sphereIdx;
-        let sphere = spheres[sphereIdx];
-        let numSpheres = arrayLength(&spheres);
-        let toLight = lightHit.p - hit.p;
-        let lengthSqr = dot(toLight, toLight);
-        let cosine = abs(dot(wi, lightHit.n));
-        let areaHalfSphere = 2f * PI * sphere.radius * sphere.radius;
+// Bump mapping with forward differences of the red channel one texel along u and v. A height step of 1
+// between neighbouring texels tilts the normal by 45 degrees.
+fn applyHeightMap(hit: Intersection, heightMap: TextureDescriptor) -> vec3<f32> {
+    let n = hit.n;
+    let t = sphereTangent(n);
+    let b = cross(n, t);
+    let du = 1f / (f32(heightMap.width) * heightMap.scaleU);
+    let dv = 1f / (f32(heightMap.height) * heightMap.scaleV);
+    let h = textureLookup(heightMap, hit.u, hit.v).r;
+    let dhdu = textureLookup(heightMap, hit.u + du, hit.v).r - h;
+    let dhdv = textureLookup(heightMap, hit.u, hit.v + dv).r - h;
+    return normalize(n - dhdu * t - dhdv * b);
+}
 
-        // lengthSqr / cosine is the inverse of the geometric factor, as defined in
-        // "MULTIPLE IMPORTANCE SAMPLING 101".
-        pdf = lengthSqr / max(EPSILON, cosine * areaHalfSphere * f32(numSpheres));
+// Direction of increasing u on the sphere. It is undefined at the poles, where any perpendicular
+// direction is returned instead.
+fn sphereTangent(n: vec3<f32>) -> vec3<f32> {
+    let tangentLength = length(vec2(n.x, n.z));
+    if tangentLength < EPSILON {
+        return vec3(1f, 0f, 0f);
     }
 
-    return pdf;
+    return vec3(n.z, 0f, -n.x) / tangentLength;
+}
+
+fn scatterRay(wo: Ray, hit: Intersection, material: Material, rngState: ptr<function, u32>) -> Scatter {
+    switch material.id {
+        case 0u: {
+            let texture = material.desc1;
+            var scatter = scatterLambertian(hit, texture, rngState);
+            scatter.throughput *= materialTint(material);
+            return scatter;
+        }
+
+        case 1u: {
+            let texture = material.desc1;
+            let fuzz = roughnessScale(hit, material.desc3) * material.x * vec2(1f + material.y, 1f - material.y);
+            var scatter = scatterMetal(wo, hit, texture, fuzz, rngState);
+            scatter.throughput *= materialTint(material);
+            return scatter;
+        }
+
+        case 2u: {
+            let refractionIndex = material.x;
+            let roughness = roughnessScale(hit, material.desc3) * material.y;
+            var scatter = scatterDielectric(wo, hit, refractionIndex, roughness, rngState);
+            // Hit from the inside, so the whole way here was through the glass.
+            if dot(wo.direction, hit.n) > 0f {
+                let distance = hit.t * length(wo.direction);
+                scatter.throughput *= pow(max(materialTint(material), vec3(EPSILON)), vec3(distance));
+            }
+            return scatter;
+        }
+
+        case 3u: {
+            return scatterCheckerboard(hit, material, rngState);
+        }
+
+        case 5u: {
+            let texture = material.desc1;
+            let density = material.x;
+            return scatterIsotropic(wo, hit, texture, density, rngState);
+        }
+
+        case 6u: {
+            let texture = material.desc1;
+            let radius = material.x;
+            return scatterSubsurface(wo, hit, texture, radius, rngState);
+        }
+
+        default: {
+            return scatterMissingMaterial(hit, rngState);
+        }
+    }
+}
+
+fn materialTint(material: Material) -> vec3<f32> {
+    return vec3(material.tint[0], material.tint[1], material.tint[2]);
+}
+
+// Red channel of the roughness map at the hit point, 1 without a map.
+fn roughnessScale(hit: Intersection, roughnessMap: TextureDescriptor) -> f32 {
+    if roughnessMap.offset == 0xffffffffu {
+        return 1f;
+    }
+    return textureLookup(roughnessMap, hit.u, hit.v).r;
+}
+
+fn scatterLambertian(hit: Intersection, albedo: TextureDescriptor, rngState: ptr<function, u32>) -> Scatter {
+    // Cosine-weighted sampling cancels out the cosine term and the 1/pi of the BRDF.
+    let scatterDirection = sampleLambertian(hit, rngState);
+    return Scatter(Ray(hit.p, scatterDirection), textureLookup(albedo, hit.u, hit.v));
+}
+
+fn sampleLambertian(hit: Intersection, rngState: ptr<function, u32>) -> vec3<f32> {
+    let v = rngNextInCosineWeightedHemisphere(rngState);
+    let onb = pixarOnb(hit.n);
+    return onb * v;
 }
 
 fn pixarOnb(n: vec3<f32>) -> mat3x3<f32> {
@@ -302,42 +1150,75 @@ fn pixarOnb(n: vec3<f32>) -> mat3x3<f32> {
     return mat3x3<f32>(u, v, n);
 }
 
-fn scatterMetal(wo: Ray, hit: Intersection, texture: TextureDescriptor, fuzz: f32, rngState: ptr<function, u32>) -> Scatter {
-    let scatterDirection = reflect(wo.direction, hit.n) + fuzz * rngNextVec3InUnitSphere(rngState);
+// Fuzz is given separately along U and V, stretching the highlight into brushed metal streaks.
+fn scatterMetal(wo: Ray, hit: Intersection, texture: TextureDescriptor, fuzz: vec2<f32>, rngState: ptr<function, u32>) -> Scatter {
+    let t = sphereTangent(hit.n);
+    let b = cross(hit.n, t);
+    let r = rngNextVec3InUnitSphere(rngState);
+    let offset = fuzz.x * r.x * t + fuzz.y * r.y * b + 0.5 * (fuzz.x + fuzz.y) * r.z * hit.n;
+    let scatterDirection = reflect(wo.direction, hit.n) + offset;
     let albedo = textureLookup(texture, hit.u, hit.v);
     return Scatter(Ray(hit.p, scatterDirection), albedo);
 }
 
-fn scatterDielectric(rayIn: Ray, hit: Intersection, refractionIndex: f32, rngState: ptr<function, u32>) -> Scatter {
+fn scatterDielectric(
+    rayIn: Ray,
+    hit: Intersection,
+    refractionIndex: f32,
+    roughness: f32,
+    rngState: ptr<function, u32>
+) -> Scatter {
     let wo = rayIn.direction;
+    // Rough glass refracts and reflects around a microfacet normal instead of the surface normal.
+    let n = sampleGgxNormal(hit.n, roughness, rngState);
     var outwardNormal = vec3(0f);
     var niOverNt = 0f;
     var cosine = 0f;
-    if dot(wo, hit.n) > 0f {
-        outwardNormal = -hit.n;
+    if dot(wo, n) > 0f {
+        outwardNormal = -n;
         niOverNt = refractionIndex;
-        cosine = refractionIndex * dot(normalize(wo), hit.n);
+        cosine = refractionIndex * dot(normalize(wo), n);
     } else {
-        outwardNormal = hit.n;
+        outwardNormal = n;
         niOverNt = 1f / refractionIndex;
-        cosine = dot(normalize(-wo), hit.n);
+        cosine = dot(normalize(-wo), n);
     };
 
     var refractedDirection = vec3(0f);
     if refract(wo, outwardNormal, niOverNt, &refractedDirection) {
-        let reflectionProb = schlick(cosine, refractionIndex);
+        var reflectionProb = schlick(cosine, refractionIndex);
+        if samplingParams.exactFresnel == 1u {
+            reflectionProb = fresnelDielectric(abs(dot(normalize(wo), n)), niOverNt);
+        }
         var wi = refractedDirection;
         if rngNextFloat(rngState) < reflectionProb {
-            reflect(wo, hit.n);
+            wi = reflect(wo, n);
         }
 
         return Scatter(Ray(hit.p, wi), vec3(1f));
     }
 
-    let wi = reflect(wo, hit.n);
+    let wi = reflect(wo, n);
     return Scatter(Ray(hit.p, wi), vec3(1f));
 }
 
+fn sampleGgxNormal(n: vec3<f32>, roughness: f32, rngState: ptr<function, u32>) -> vec3<f32> {
+    if roughness <= 0f {
+        return n;
+    }
+
+    let alpha = roughness * roughness;
+    let u1 = rngNextFloat(rngState);
+    let u2 = rngNextFloat(rngState);
+    let tanTheta2 = alpha * alpha * u1 / max(EPSILON, 1f - u1);
+    let cosTheta = 1f / sqrt(1f + tanTheta2);
+    let sinTheta = sqrt(max(0f, 1f - cosTheta * cosTheta));
+    let phi = 2f * PI * u2;
+
+    let onb = pixarOnb(n);
+    return onb * vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+}
+
 fn refract(v: vec3<f32>, n: vec3<f32>, niOverNt: f32, refractDirection: ptr<function, vec3<f32>>) -> bool {
     // ni * sin(i) = nt * sin(t)
     // sin(t) = sin(i) * (ni / nt)
@@ -358,13 +1239,92 @@ fn schlick(cosine: f32, refractionIndex: f32) -> f32 {
     return r0 + pow((1f - r0) * (1f - cosine), 5f);
 }
 
-fn scatterCheckerboard(hit: Intersection, texture1: TextureDescriptor, texture2: TextureDescriptor, rngState: ptr<function, u32>) -> Scatter {
-    let sines = sin(5f * hit.p.x) * sin(5f * hit.p.y) * sin(5f * hit.p.z);
+// Reflectance of unpolarized light at a smooth interface, the average of the s and p polarizations.
+// `eta` is the index of refraction of the incident side over that of the transmitted side.
+fn fresnelDielectric(cosI: f32, eta: f32) -> f32 {
+    let sinT2 = eta * eta * (1f - cosI * cosI);
+    if sinT2 >= 1f {
+        return 1f;
+    }
+
+    let cosT = sqrt(1f - sinT2);
+    let rs = (eta * cosI - cosT) / (eta * cosI + cosT);
+    let rp = (eta * cosT - cosI) / (eta * cosT + cosI);
+    return 0.5f * (rs * rs + rp * rp);
+}
+
+fn scatterCheckerboard(hit: Intersection, material: Material, rngState: ptr<function, u32>) -> Scatter {
+    return scatterLambertian(hit, checkerboardTexture(hit, material), rngState);
+}
+
+// The tile at the hit point, `desc1` or `desc2`. `x` is the tiling frequency, `y` is 1 for tiles laid out
+// in texture coordinates instead of solid world space cells.
+fn checkerboardTexture(hit: Intersection, material: Material) -> TextureDescriptor {
+    let scale = material.x;
+    var sines: f32;
+    if material.y == 1f {
+        sines = sin(scale * PI * hit.u) * sin(scale * PI * hit.v);
+    } else {
+        sines = sin(scale * hit.p.x) * sin(scale * hit.p.y) * sin(scale * hit.p.z);
+    }
+
     if sines < 0f {
-        return scatterMixtureDensity(hit, texture1, rngState);
+        return material.desc1;
+    }
+    return material.desc2;
+}
+
+// The sphere is a volume of constant density. A ray either scatters somewhere inside it or passes
+// through unchanged. Objects inside the volume do not stop the sampled distance.
+fn scatterIsotropic(wo: Ray, hit: Intersection, albedo: TextureDescriptor, density: f32, rngState: ptr<function, u32>) -> Scatter {
+    let direction = normalize(wo.direction);
+
+    var start = hit.p;
+    var inside = 0f;
+    if dot(direction, hit.n) < 0f {
+        // Entering the volume, it is crossed up to where the ray leaves the sphere.
+        var exit = Intersection();
+        if rayIntersectSphere(Ray(hit.p, direction), hit.sphereIdx, MIN_T, MAX_T, &exit) {
+            inside = exit.t;
+        }
     } else {
-        return scatterMixtureDensity(hit, texture2, rngState);
+        // The ray already started inside, e.g. after scattering.
+        start = wo.origin;
+        inside = distance(wo.origin, hit.p);
+    }
+
+    let scatterDistance = -log(1f - rngNextFloat(rngState)) / max(density, EPSILON);
+    if scatterDistance < inside {
+        // Isotropic phase function: every direction is equally likely.
+        let scatterDirection = normalize(rngNextVec3InUnitSphere(rngState));
+        let p = start + scatterDistance * direction;
+        return Scatter(Ray(p, scatterDirection), textureLookup(albedo, hit.u, hit.v));
     }
+
+    return Scatter(Ray(start + inside * direction, direction), vec3(1f));
+}
+
+// Random walk beneath the surface. Light enters diffusely, scatters on average every `radius` units and
+// leaves diffusely wherever the walk reaches the surface again. Thin parts glow when lit from behind,
+// while a small radius compared to the sphere looks like a soft Lambertian surface.
+fn scatterSubsurface(wo: Ray, hit: Intersection, albedo: TextureDescriptor, radius: f32, rngState: ptr<function, u32>) -> Scatter {
+    let direction = normalize(wo.direction);
+
+    if dot(direction, hit.n) < 0f {
+        let inward = pixarOnb(-hit.n) * rngNextInCosineWeightedHemisphere(rngState);
+        return Scatter(Ray(hit.p, inward), vec3(1f));
+    }
+
+    // The ray started inside, either where it entered or at the previous scattering event.
+    let inside = distance(wo.origin, hit.p);
+    let scatterDistance = -log(1f - rngNextFloat(rngState)) * max(radius, EPSILON);
+    if scatterDistance < inside {
+        let scatterDirection = normalize(rngNextVec3InUnitSphere(rngState));
+        let p = wo.origin + scatterDistance * direction;
+        return Scatter(Ray(p, scatterDirection), textureLookup(albedo, hit.u, hit.v));
+    }
+
+    return Scatter(Ray(hit.p, sampleLambertian(hit, rngState)), vec3(1f));
 }
 
 fn scatterMissingMaterial(hit: Intersection, rngState: ptr<function, u32>) -> Scatter {
@@ -407,6 +1367,10 @@ struct SkyState {
     params: array<f32, 27>,
     radiances: array<f32, 3>,
     sunDirection: vec3<f32>,
+    sunCosAngularRadius: f32,
+    sunEnabled: u32,
+    fallback: u32,
+    up: vec3<f32>,
 };
 
 struct SamplingParams {
@@ -414,12 +1378,65 @@ struct SamplingParams {
     numBounces: u32,
     accumulatedSamplesPerPixel: u32,
     clearAccumulatedSamples: u32,
+    debugFlags: u32,
+    numEmissiveLights: u32,
+    exposure: f32,
+    autoExposureKey: f32,
+    seed: u32,
+    bilinearFiltering: u32,
+    // Pixel bounds of the crop rectangle, the end is exclusive.
+    cropMin: vec2<u32>,
+    cropMax: vec2<u32>,
+    outputChannel: u32,
+    // Relative error below which a pixel stops taking samples, zero samples every pixel.
+    varianceThreshold: f32,
+    // Background seen by camera rays that miss the scene, the sky is used when the mode is zero.
+    backgroundTop: vec3<f32>,
+    backgroundMode: u32,
+    backgroundBottom: vec3<f32>,
+    // Zero intensity turns bloom off.
+    bloomThreshold: f32,
+    bloomIntensity: f32,
+    // Stops added by the camera, in manual and auto exposure alike.
+    cameraExposure: f32,
+    // Distance from the ray origin beyond which hits are ignored.
+    maxRayDistance: f32,
+    // Glass uses the full Fresnel equations instead of Schlick's approximation when 1.
+    exactFresnel: u32,
+    // Per channel gain of the camera white balance.
+    whiteBalance: vec3<f32>,
+    // Random, stratified or quasi-random positions of the samples in the pixel and on the lens.
+    aaPattern: u32,
+    // Bounces off and through glass, which don't count towards `numBounces`.
+    numTransmissionBounces: u32,
+}
+
+struct LuminanceStats {
+    logSum: atomic<i32>,
+    count: atomic<u32>,
+}
+
+struct NoiseStats {
+    errorSum: atomic<u32>,
+    count: atomic<u32>,
+    activeCount: atomic<u32>,
+    pixelCount: atomic<u32>,
 }
 
 struct Sphere {
     centerAndPad: vec4<f32>,
     radius: f32,
     materialIdx: u32,
+    lightGroup: u32,
+    // Closes the clipped side with a flat cap when 1.
+    clipCapped: u32,
+    // Plane normal in xyz and its offset from the center in w, the sphere is cut away in front of the plane.
+    // A zero normal keeps the whole sphere.
+    clip: vec4<f32>,
+    // Rows of the affine map from world space into the space the sphere is given in.
+    inverseTransform: array<vec4<f32>, 3>,
+    // Center in xyz and radius in w of a sphere around the transformed one, in world space.
+    boundingSphere: vec4<f32>,
 }
 
 struct Material {
@@ -427,26 +1444,100 @@ struct Material {
     desc1: TextureDescriptor,
     desc2: TextureDescriptor,
     x: f32,
+    y: f32,
+    // Roughness map of metals and dielectrics.
+    desc3: TextureDescriptor,
+    // Height map of lambertians and metals.
+    desc4: TextureDescriptor,
+    // Multiplies the albedo of lambertians and metals, the absorption color of dielectrics.
+    tint: array<f32, 3>,
+    // Added to U of spheres, turns the textures around them.
+    uvOffset: f32,
+}
+
+struct Light {
+    positionOrDirection: vec3<f32>,
+    kind: u32,
+    radiance: vec3<f32>,
+    cosOuter: f32,
+    spotDirection: vec3<f32>,
+    cosInner: f32,
+    // Bit of every light group the light reaches.
+    groupMask: u32,
+}
+
+struct CsgOp {
+    operation: u32,
+    a: u32,
+    b: u32,
+}
+
+struct BvhNode {
+    boundsMin: vec3<f32>,
+    leftOrFirst: u32,
+    boundsMax: vec3<f32>,
+    count: u32,
 }
 
 struct TextureDescriptor {
     width: u32,
     height: u32,
     offset: u32,
+    scaleU: f32,
+    scaleV: f32,
+    wrap: u32,
 }
 
-fn textureLookup(desc: TextureDescriptor, arg_u: f32, arg_v: f32) -> vec3<f32> {
-    let u = clamp(arg_u, 0f, 1f);
-    let v = 1f - clamp(arg_v, 0f, 1f);
+fn wrapCoordinate(x: f32, wrap: u32) -> f32 {
+    if wrap == WRAP_CLAMP {
+        return clamp(x, 0f, 1f);
+    } else if wrap == WRAP_MIRROR {
+        // Triangle wave going 0 -> 1 -> 0 over two repeats.
+        return 1f - abs(1f - 2f * fract(0.5f * x));
+    }
+    return fract(x);
+}
 
-    let j = u32(u * f32(desc.width));
-    let i = u32(v * f32(desc.height));
-    let idx = i * desc.width + j;
+// Index of a texel next to the texture edge, repeating textures continue on the opposite side.
+fn wrapTexel(idx: i32, size: u32, wrap: u32) -> u32 {
+    let n = i32(size);
+    if wrap == WRAP_CLAMP || wrap == WRAP_MIRROR {
+        return u32(clamp(idx, 0, n - 1));
+    }
+    return u32(((idx % n) + n) % n);
+}
 
-    let elem = textures[desc.offset + idx];
+fn textureTexel(desc: TextureDescriptor, i: u32, j: u32) -> vec3<f32> {
+    let elem = textures[desc.offset + i * desc.width + j];
     return vec3(elem[0u], elem[1u], elem[2u]);
 }
 
+fn textureLookup(desc: TextureDescriptor, arg_u: f32, arg_v: f32) -> vec3<f32> {
+    let u = wrapCoordinate(arg_u * desc.scaleU, desc.wrap);
+    let v = 1f - wrapCoordinate(arg_v * desc.scaleV, desc.wrap);
+
+    if samplingParams.bilinearFiltering == 0u {
+        let j = min(u32(u * f32(desc.width)), desc.width - 1u);
+        let i = min(u32(v * f32(desc.height)), desc.height - 1u);
+        return textureTexel(desc, i, j);
+    }
+
+    // Texel centers sit at half-integer coordinates.
+    let x = u * f32(desc.width) - 0.5f;
+    let y = v * f32(desc.height) - 0.5f;
+    let x0 = floor(x);
+    let y0 = floor(y);
+
+    let j0 = wrapTexel(i32(x0), desc.width, desc.wrap);
+    let j1 = wrapTexel(i32(x0) + 1, desc.width, desc.wrap);
+    let i0 = wrapTexel(i32(y0), desc.height, desc.wrap);
+    let i1 = wrapTexel(i32(y0) + 1, desc.height, desc.wrap);
+
+    let top = mix(textureTexel(desc, i0, j0), textureTexel(desc, i0, j1), x - x0);
+    let bottom = mix(textureTexel(desc, i1, j0), textureTexel(desc, i1, j1), x - x0);
+    return mix(top, bottom, y - y0);
+}
+
 struct Ray {
     origin: vec3<f32>,
     direction: vec3<f32>
@@ -467,29 +1558,147 @@ struct Intersection {
     sphereIdx: u32,
 }
 
-fn rayIntersectSphere(ray: Ray, sphereIdx: u32, tmin: f32, tmax: f32, hit: ptr<function, Intersection>) -> bool {
+fn rayIntersectSphere(worldRay: Ray, sphereIdx: u32, tmin: f32, tmax: f32, hit: ptr<function, Intersection>) -> bool {
     let sphere = spheres[sphereIdx];
+    let ray = toObjectSpace(worldRay, sphere);
     let oc = ray.origin - sphere.centerAndPad.xyz;
     let a = dot(ray.direction, ray.direction);
     let b = dot(oc, ray.direction);
     let c = dot(oc, oc) - sphere.radius * sphere.radius;
     let discriminant = b * b - a * c;
 
-    if discriminant > 0f {
-        var t = (-b - sqrt(b * b - a * c)) / a;
-        if t < tmax && t > tmin {
-            *hit = sphereIntersection(ray, sphere, sphereIdx, t);
-            return true;
+    if discriminant <= 0f {
+        return false;
+    }
+
+    var closest = tmax;
+    var found = false;
+    var t = (-b - sqrt(discriminant)) / a;
+    if t < closest && t > tmin && !sphereIsClipped(sphere, rayPointAtParameter(ray, t)) {
+        *hit = sphereIntersection(ray, sphere, sphereIdx, t);
+        closest = t;
+        found = true;
+    }
+
+    t = (-b + sqrt(discriminant)) / a;
+    if t < closest && t > tmin && !sphereIsClipped(sphere, rayPointAtParameter(ray, t)) {
+        *hit = sphereIntersection(ray, sphere, sphereIdx, t);
+        closest = t;
+        found = true;
+    }
+
+    if sphere.clipCapped == 1u {
+        // The cap is the disk the clip plane cuts out of the sphere.
+        let clipNormal = sphere.clip.xyz;
+        let denominator = dot(ray.direction, clipNormal);
+        if denominator != 0f {
+            t = (sphere.clip.w - dot(oc, clipNormal)) / denominator;
+            let p = rayPointAtParameter(ray, t);
+            let fromCenter = p - sphere.centerAndPad.xyz;
+            if t < closest && t > tmin && dot(fromCenter, fromCenter) <= sphere.radius * sphere.radius {
+                *hit = sphereIntersection(ray, sphere, sphereIdx, t);
+                // Like the rest of the sphere, a negative radius turns the cap inside out.
+                (*hit).n = sign(sphere.radius) * clipNormal;
+                found = true;
+            }
         }
+    }
 
-        t = (-b + sqrt(b * b - a * c)) / a;
-        if t < tmax && t > tmin {
-            *hit = sphereIntersection(ray, sphere, sphereIdx, t);
-            return true;
+    if found {
+        *hit = hitToWorld(*hit, worldRay, sphere);
+    }
+    return found;
+}
+
+// The ray in the space the sphere is given in. The direction isn't normalized, so `t` stays the same.
+fn toObjectSpace(ray: Ray, sphere: Sphere) -> Ray {
+    let m = sphere.inverseTransform;
+    let origin = vec3(dot(m[0].xyz, ray.origin), dot(m[1].xyz, ray.origin), dot(m[2].xyz, ray.origin));
+    let direction = vec3(dot(m[0].xyz, ray.direction), dot(m[1].xyz, ray.direction), dot(m[2].xyz, ray.direction));
+    return Ray(origin + vec3(m[0].w, m[1].w, m[2].w), direction);
+}
+
+// Moves a hit found with `toObjectSpace` back into world space, normals go through the transposed inverse.
+fn hitToWorld(hit: Intersection, worldRay: Ray, sphere: Sphere) -> Intersection {
+    let m = sphere.inverseTransform;
+    var worldHit = hit;
+    worldHit.p = rayPointAtParameter(worldRay, hit.t);
+    worldHit.n = normalize(hit.n.x * m[0].xyz + hit.n.y * m[1].xyz + hit.n.z * m[2].xyz);
+    return worldHit;
+}
+
+// Entry and exit of the ray through the whole sphere, an empty interval if it misses.
+fn sphereInterval(worldRay: Ray, sphere: Sphere) -> vec2<f32> {
+    let ray = toObjectSpace(worldRay, sphere);
+    let oc = ray.origin - sphere.centerAndPad.xyz;
+    let a = dot(ray.direction, ray.direction);
+    let b = dot(oc, ray.direction);
+    let c = dot(oc, oc) - sphere.radius * sphere.radius;
+    let discriminant = b * b - a * c;
+    if discriminant <= 0f {
+        return vec2(MAX_T, -MAX_T);
+    }
+
+    let root = sqrt(discriminant);
+    return vec2((-b - root) / a, (-b + root) / a);
+}
+
+// Every surface of the two spheres is a candidate, which is kept depending on whether it lies inside the other
+// sphere. The surface of the second sphere bounding a difference faces into that sphere.
+fn rayIntersectCsg(ray: Ray, csgIdx: u32, tmin: f32, tmax: f32, hit: ptr<function, Intersection>) -> bool {
+    let op = csgOps[csgIdx];
+    var sphereIdx = array(op.a, op.b);
+    var intervals = array(sphereInterval(ray, spheres[op.a]), sphereInterval(ray, spheres[op.b]));
+
+    var closest = tmax;
+    var found = false;
+    for (var side = 0u; side < 2u; side += 1u) {
+        let other = intervals[1u - side];
+        for (var end = 0u; end < 2u; end += 1u) {
+            let t = intervals[side][end];
+            if t <= tmin || t >= closest {
+                continue;
+            }
+
+            let insideOther = other.x < t && t < other.y;
+            var keep = !insideOther;
+            if op.operation == CSG_INTERSECTION {
+                keep = insideOther;
+            } else if op.operation == CSG_DIFFERENCE {
+                keep = (side == 0u) != insideOther;
+            }
+
+            if keep {
+                let sphere = spheres[sphereIdx[side]];
+                let objectHit = sphereIntersection(toObjectSpace(ray, sphere), sphere, sphereIdx[side], t);
+                *hit = hitToWorld(objectHit, ray, sphere);
+                if op.operation == CSG_DIFFERENCE && side == 1u {
+                    (*hit).n = -(*hit).n;
+                }
+                closest = t;
+                found = true;
+            }
         }
     }
 
-    return false;
+    return found;
+}
+
+fn sphereIsClipped(sphere: Sphere, p: vec3<f32>) -> bool {
+    return dot(p - sphere.centerAndPad.xyz, sphere.clip.xyz) > sphere.clip.w;
+}
+
+fn rayIntersectAabb(ray: Ray, invDirection: vec3<f32>, boundsMin: vec3<f32>, boundsMax: vec3<f32>, tmax: f32) -> bool {
+    // Slab test
+    let t0 = (boundsMin - ray.origin) * invDirection;
+    let t1 = (boundsMax - ray.origin) * invDirection;
+    let tsmaller = min(t0, t1);
+    let tbigger = max(t0, t1);
+
+    let tnear = max(max(tsmaller.x, tsmaller.y), tsmaller.z);
+    let tfar = min(min(tbigger.x, tbigger.y), tbigger.z);
+
+    return tnear <= tfar && tfar > MIN_T && tnear < tmax;
 }
 
 fn sphereIntersection(ray: Ray, sphere: Sphere, sphereIdx: u32, t: f32) -> Intersection {
@@ -497,7 +1706,7 @@ fn sphereIntersection(ray: Ray, sphere: Sphere, sphereIdx: u32, t: f32) -> Inter
     let n = (1f / sphere.radius) * (p - sphere.centerAndPad.xyz);
     let theta = acos(-n.y);
     let phi = atan2(-n.z, n.x) + PI;
-    let u = 0.5 * FRAC_1_PI * phi;
+    let u = fract(0.5 * FRAC_1_PI * phi + materials[sphere.materialIdx].uvOffset);
     let v = FRAC_1_PI * theta;
 
     // TODO: passing sphereIdx in here just to pass it to Intersection
@@ -516,11 +1725,26 @@ struct Camera {
     v: vec3<f32>,
     lensRadius: f32,
     lowerLeftCorner: vec3<f32>,
+    isOrtho: u32,
+    bokehBlades: u32,
 }
 
-fn cameraMakeRay(camera: Camera, rngState: ptr<function, u32>, u: f32, v: f32) -> Ray {
-    let randomPointInLens = camera.lensRadius * rngNextVec3InUnitDisk(rngState);
-    let lensOffset = randomPointInLens.x * camera.u + randomPointInLens.y * camera.v;
+// `lensSample` picks the point on the lens, uniform in [0, 1) gives a uniform point.
+fn cameraMakeRay(camera: Camera, lensSample: vec2<f32>, u: f32, v: f32) -> Ray {
+    if camera.isOrtho == 1u {
+        // Parallel rays leave the image plane, which is centered on the eye.
+        let origin = camera.lowerLeftCorner + u * camera.horizontal + v * camera.vertical;
+        let direction = cross(camera.v, camera.u);
+        return Ray(origin, direction);
+    }
+
+    var pointInLens: vec2<f32>;
+    if camera.bokehBlades == 0u {
+        pointInLens = camera.lensRadius * unitDiskPoint(lensSample);
+    } else {
+        pointInLens = camera.lensRadius * unitPolygonPoint(lensSample, camera.bokehBlades);
+    }
+    let lensOffset = pointInLens.x * camera.u + pointInLens.y * camera.v;
 
     let origin = camera.eye + lensOffset;
     let direction = camera.lowerLeftCorner + u * camera.horizontal + v * camera.vertical - origin;
@@ -541,32 +1765,34 @@ fn rngNextInCosineWeightedHemisphere(state: ptr<function, u32>) -> vec3<f32> {
     return vec3<f32>(x, y, z);
 }
 
-fn rngNextInUnitHemisphere(state: ptr<function, u32>) -> vec3<f32> {
-    let r1 = rngNextFloat(state);
-    let r2 = rngNextFloat(state);
-
-    let phi = 2f * PI * r1;
-    let sinTheta = sqrt(1f - r2 * r2);
-
-    let x = cos(phi) * sinTheta;
-    let y = sin(phi) * sinTheta;
-    let z = r2;
-
-    return vec3(x, y, z);
-}
-
-fn rngNextVec3InUnitDisk(state: ptr<function, u32>) -> vec3<f32> {
+fn unitDiskPoint(uv: vec2<f32>) -> vec2<f32> {
     // Generate numbers uniformly in a disk:
     // https://stats.stackexchange.com/a/481559
 
     // r^2 is distributed as U(0, 1).
-    let r = sqrt(rngNextFloat(state));
-    let alpha = 2f * PI * rngNextFloat(state);
+    let r = sqrt(uv.x);
+    let alpha = 2f * PI * uv.y;
 
-    let x = r * cos(alpha);
-    let y = r * sin(alpha);
+    return r * vec2(cos(alpha), sin(alpha));
+}
+
+// Uniform point in the regular polygon with `corners` corners on the unit circle, the first one points up.
+fn unitPolygonPoint(uv: vec2<f32>, corners: u32) -> vec2<f32> {
+    let sectorAngle = 2f * PI / f32(corners);
+    // The fraction of the sector index left over is uniform again and places the point along the sector.
+    let scaled = uv.x * f32(corners);
+    let sector = min(u32(scaled), corners - 1u);
+    let angle = FRAC_PI_2 + f32(sector) * sectorAngle;
+    let corner1 = vec2(cos(angle), sin(angle));
+    let corner2 = vec2(cos(angle + sectorAngle), sin(angle + sectorAngle));
+
+    // Uniform in the triangle spanned by the center and the two corners of the sector.
+    let r = sqrt(uv.y);
+    return r * mix(corner1, corner2, fract(scaled));
+}
 
-    return vec3(x, y, 0f);
+fn rngNextVec2(state: ptr<function, u32>) -> vec2<f32> {
+    return vec2(rngNextFloat(state), rngNextFloat(state));
 }
 
 fn rngNextVec3InUnitSphere(state: ptr<function, u32>) -> vec3<f32> {
@@ -582,19 +1808,14 @@ fn rngNextVec3InUnitSphere(state: ptr<function, u32>) -> vec3<f32> {
     return vec3(x, y, z);
 }
 
-fn rngNextUintInRange(state: ptr<function, u32>, min: u32, max: u32) -> u32 {
-    let x = rngNextInt(state);
-    return min + (x) % (max - min);
-}
-
 fn rngNextFloat(state: ptr<function, u32>) -> f32 {
     let x = rngNextInt(state);
     return f32(x) / f32(0xffffffffu);
 }
 
-fn initRng(pixel: vec2<u32>, resolution: vec2<u32>, frame: u32) -> u32 {
+fn initRng(pixel: vec2<u32>, resolution: vec2<u32>, frame: u32, userSeed: u32) -> u32 {
     // Adapted from https://github.com/boksajak/referencePT
-    let seed = dot(pixel, vec2<u32>(1u, resolution.x)) ^ jenkinsHash(frame);
+    let seed = dot(pixel, vec2<u32>(1u, resolution.x)) ^ jenkinsHash(frame ^ jenkinsHash(userSeed));
     return jenkinsHash(seed);
 }
 
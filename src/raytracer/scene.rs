@@ -1,13 +1,20 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
 
 use eframe::wgpu;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use super::Texture;
 use super::gpu_buffer::StorageBuffer;
-use crate::node::material::MaterialNode;
+use super::{RenderParamsValidationError, Texture, TextureError, WrapMode, check_buffer_size};
+use crate::node::light::LightNode;
+use crate::node::material::{MaterialNode, MixMaterialNode};
 use crate::node::primitive::SphereNode;
-use crate::types::{Vector3, Vector3f32, Vector4f32};
+use crate::types::{Angle, Matrix4f32, Vector3, Vector3f32, Vector4f32, from_vector3_to_vector3f32, linear_rgb};
 
 pub type TextureId = usize;
 
@@ -16,6 +23,16 @@ pub struct TextureData {
     pub texture: Texture,
     pub key: Option<Cow<'static, str>>,
     pub scale: f32,
+    /// How many times the texture repeats along U and V.
+    #[serde(default = "default_uv_scale")]
+    pub scale_u: f32,
+    #[serde(default = "default_uv_scale")]
+    pub scale_v: f32,
+    #[serde(default)]
+    pub wrap: WrapMode,
+    /// The texels were decoded from sRGB, see [`Self::decode_srgb`].
+    #[serde(default)]
+    pub srgb: bool,
 }
 
 impl TextureData {
@@ -24,22 +41,212 @@ impl TextureData {
             texture,
             key: None,
             scale: 1.0,
+            scale_u: 1.0,
+            scale_v: 1.0,
+            wrap: WrapMode::default(),
+            srgb: false,
         }
     }
 
+    pub fn with_uv_scale(self, scale_u: f32, scale_v: f32) -> Self {
+        Self {
+            scale_u,
+            scale_v,
+            ..self
+        }
+    }
+
+    pub fn with_wrap(self, wrap: WrapMode) -> Self {
+        Self { wrap, ..self }
+    }
+
+    /// Color textures are stored in sRGB and decoded once here, normal, roughness and height maps are
+    /// used as stored.
+    pub fn decode_srgb(self) -> Self {
+        if self.srgb {
+            return self;
+        }
+        Self {
+            texture: self.texture.decode_srgb(self.scale),
+            srgb: true,
+            ..self
+        }
+    }
+
+    /// Falls back to [`Self::missing`] if the file can't be loaded.
     pub fn load_scaled(path: impl Into<Cow<'static, str>>, scale: f32) -> Self {
         let path = path.into();
-        let texture = Texture::new_from_scaled_image(&path, scale).expect("Failed to load texture from file");
-        Self {
-            texture,
+        Self::try_load_scaled(path.clone(), scale).unwrap_or_else(|err| {
+            eprintln!("Failed to load texture `{path}`: {err}");
+            Self::missing()
+        })
+    }
+
+    /// Solid magenta, stands in for a texture whose file couldn't be loaded so the problem shows in the render.
+    pub fn missing() -> Self {
+        Self::new(Texture::new_from_color(Vector3f32::new(1.0, 0.0, 1.0)))
+    }
+
+    pub fn try_load_scaled(path: impl Into<Cow<'static, str>>, scale: f32) -> Result<Self, TextureError> {
+        let path = path.into();
+        let texture = Texture::new_from_scaled_file(&path, scale)?;
+        Ok(Self {
             key: Some(path),
             scale,
-        }
+            ..Self::new(texture)
+        })
     }
 
     pub fn load(path: impl Into<Cow<'static, str>>) -> Self {
         Self::load_scaled(path, 1.0)
     }
+
+    /// Generated noise, keyed by its parameters so unchanged noise can be reused.
+    pub fn noise(resolution: u32, cells: u32, seed: u32) -> Self {
+        Self {
+            key: Some(Self::noise_key(resolution, cells, seed).into()),
+            ..Self::new(Texture::new_from_noise(resolution, cells, seed))
+        }
+    }
+
+    pub fn noise_key(resolution: u32, cells: u32, seed: u32) -> String {
+        format!("noise:{resolution}:{cells}:{seed}")
+    }
+}
+
+fn default_uv_scale() -> f32 {
+    1.0
+}
+
+enum CachedTexture {
+    Loading,
+    Loaded(Texture),
+    Failed { error: String, at: Instant },
+}
+
+struct CacheEntry {
+    texture: CachedTexture,
+    last_used: Instant,
+}
+
+impl CacheEntry {
+    fn new(texture: CachedTexture) -> Self {
+        Self {
+            texture,
+            last_used: Instant::now(),
+        }
+    }
+}
+
+/// Most images kept at once. Every scale of a file is an image of its own, dragging the scale of a texture
+/// would keep all of them otherwise.
+const MAX_CACHED_TEXTURES: usize = 32;
+/// A file that failed to load is tried again once it is asked for after this long, e.g. after it was fixed.
+const FAILED_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+pub enum TextureLoad {
+    Loaded(TextureData),
+    Loading,
+    Failed,
+}
+
+/// Images loaded from files, keyed by path and scale and shared by every scene. Files are loaded on the
+/// rayon pool, so building a scene never waits for one.
+#[derive(Clone, Default)]
+pub struct TextureCache {
+    entries: Arc<Mutex<HashMap<(String, u32), CacheEntry>>>,
+    /// Bumped whenever a load finishes, scenes waiting for a texture compare it to know when to rebuild.
+    generation: Arc<AtomicU64>,
+}
+
+static SHARED_TEXTURE_CACHE: LazyLock<TextureCache> = LazyLock::new(TextureCache::default);
+
+impl TextureCache {
+    pub fn shared() -> &'static Self {
+        &SHARED_TEXTURE_CACHE
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    #[cfg(test)]
+    fn is_loading(&self) -> bool {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .any(|entry| matches!(entry.texture, CachedTexture::Loading))
+    }
+
+    /// Why the file at `path` failed to load, if it did.
+    pub fn error(&self, path: &str, scale: f32) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        match entries
+            .get(&(path.to_owned(), scale.to_bits()))
+            .map(|entry| &entry.texture)
+        {
+            Some(CachedTexture::Failed { error, .. }) => Some(error.clone()),
+            _ => None,
+        }
+    }
+
+    /// The texture if it is loaded already, otherwise starts loading it in the background.
+    /// A file that failed to load is tried again after [`FAILED_RETRY_INTERVAL`].
+    pub fn load_scaled(&self, path: &str, scale: f32) -> TextureLoad {
+        let key = (path.to_owned(), scale.to_bits());
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&key) {
+            entry.last_used = Instant::now();
+            match &entry.texture {
+                CachedTexture::Loaded(texture) => {
+                    return TextureLoad::Loaded(TextureData {
+                        key: Some(Cow::Owned(path.to_owned())),
+                        scale,
+                        ..TextureData::new(texture.clone())
+                    });
+                },
+                CachedTexture::Loading => return TextureLoad::Loading,
+                CachedTexture::Failed { at, .. } if at.elapsed() < FAILED_RETRY_INTERVAL => return TextureLoad::Failed,
+                CachedTexture::Failed { .. } => {},
+            }
+        }
+
+        Self::evict(&mut entries);
+        entries.insert(key.clone(), CacheEntry::new(CachedTexture::Loading));
+        let cache = self.clone();
+        rayon::spawn(move || {
+            let texture = match Texture::new_from_scaled_file(&key.0, scale) {
+                Ok(texture) => CachedTexture::Loaded(texture),
+                Err(err) => {
+                    eprintln!("Failed to load texture `{}`: {err}", key.0);
+                    CachedTexture::Failed {
+                        error: err.to_string(),
+                        at: Instant::now(),
+                    }
+                },
+            };
+            let mut entries = cache.entries.lock().unwrap();
+            entries.insert(key, CacheEntry::new(texture));
+            cache.generation.fetch_add(1, Ordering::Release);
+        });
+        TextureLoad::Loading
+    }
+
+    /// Drops the least recently used images until there is room for one more. Images still loading are kept,
+    /// their load inserts them again anyway.
+    fn evict(entries: &mut HashMap<(String, u32), CacheEntry>) {
+        while entries.len() >= MAX_CACHED_TEXTURES {
+            let oldest = entries
+                .iter()
+                .filter(|(_, entry)| !matches!(entry.texture, CachedTexture::Loading))
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => entries.remove(&key),
+                None => break,
+            };
+        }
+    }
 }
 
 impl From<Texture> for TextureData {
@@ -53,12 +260,30 @@ pub struct Scene {
     pub spheres: Vec<Sphere>,
     pub materials: Vec<Material>,
     pub textures: Vec<TextureData>,
+    /// Analytic lights, sampled explicitly in addition to the emissive spheres.
+    #[serde(default)]
+    pub lights: Vec<GpuLight>,
+    /// Boolean combinations of two spheres, the spheres they use are not drawn on their own.
+    #[serde(default)]
+    pub csg_ops: Vec<CsgOp>,
 }
 
 impl Scene {
     pub fn stub() -> Self {
         let textures = vec![Texture::new_from_color(Vector3f32::new(0.0, 0.0, 0.0)).into()];
-        let materials = vec![Material::Lambertian { albedo: 0 }, Material::Emissive { emit: 0 }];
+        let materials = vec![
+            Material::Lambertian {
+                albedo: 0,
+                normal_map: None,
+                height_map: None,
+                tint: Material::DEFAULT_TINT,
+                uv_offset: 0.0,
+            },
+            Material::Emissive {
+                emit: 0,
+                two_sided: true,
+            },
+        ];
         let spheres = vec![
             Sphere::new(Vector3::new(0.0, 0.0, 0.0), 0.0, 0),
             Sphere::new(Vector3::new(0.0, 0.0, 0.0), 0.0, 1),
@@ -68,6 +293,8 @@ impl Scene {
             spheres,
             materials,
             textures,
+            lights: Vec::new(),
+            csg_ops: Vec::new(),
         }
     }
 
@@ -86,16 +313,65 @@ impl Scene {
         ];
 
         let materials = vec![
-            Material::Checkerboard { even: 0, odd: 1 },
-            Material::Lambertian { albedo: 2 },
-            Material::Metal { albedo: 3, fuzz: 0.4 },
-            Material::Dielectric { refraction_index: 1.5 },
-            Material::Lambertian { albedo: 4 },
-            Material::Emissive { emit: 5 },
-            Material::Lambertian { albedo: 6 },
-            Material::Emissive { emit: 7 },
-            Material::Emissive { emit: 8 },
-            Material::Emissive { emit: 9 },
+            Material::Checkerboard {
+                even: 0,
+                odd: 1,
+                scale: CheckerboardSpace::DEFAULT_SCALE,
+                space: CheckerboardSpace::Solid,
+            },
+            Material::Lambertian {
+                albedo: 2,
+                normal_map: None,
+                height_map: None,
+                tint: Material::DEFAULT_TINT,
+                uv_offset: 0.0,
+            },
+            Material::Metal {
+                albedo: 3,
+                fuzz: 0.4,
+                normal_map: None,
+                anisotropy: 0.0,
+                roughness_map: None,
+                height_map: None,
+                tint: Material::DEFAULT_TINT,
+                uv_offset: 0.0,
+            },
+            Material::Dielectric {
+                refraction_index: 1.5,
+                roughness: 0.0,
+                roughness_map: None,
+                absorption: Material::DEFAULT_TINT,
+            },
+            Material::Lambertian {
+                albedo: 4,
+                normal_map: None,
+                height_map: None,
+                tint: Material::DEFAULT_TINT,
+                uv_offset: 0.0,
+            },
+            Material::Emissive {
+                emit: 5,
+                two_sided: true,
+            },
+            Material::Lambertian {
+                albedo: 6,
+                normal_map: None,
+                height_map: None,
+                tint: Material::DEFAULT_TINT,
+                uv_offset: 0.0,
+            },
+            Material::Emissive {
+                emit: 7,
+                two_sided: true,
+            },
+            Material::Emissive {
+                emit: 8,
+                two_sided: true,
+            },
+            Material::Emissive {
+                emit: 9,
+                two_sided: true,
+            },
         ];
 
         let spheres = vec![
@@ -118,10 +394,195 @@ impl Scene {
             spheres,
             materials,
             textures,
+            lights: Vec::new(),
+            csg_ops: Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> SceneStats {
+        SceneStats {
+            spheres: self.spheres.len(),
+            materials: self.materials.len(),
+            textures: self.textures.len(),
+            lights: self.lights.len(),
+            texture_bytes: self
+                .textures
+                .iter()
+                .map(|data| std::mem::size_of_val(data.texture.as_slice()))
+                .sum(),
+        }
+    }
+
+    /// Problems that don't stop the scene from rendering, but likely make it look wrong or crash the shader.
+    pub fn validate(&self) -> Vec<SceneWarning> {
+        let mut warnings = Vec::new();
+
+        for (idx, sphere) in self.spheres.iter().enumerate() {
+            if sphere.radius == 0.0 {
+                warnings.push(SceneWarning::ZeroRadius(idx));
+            }
+            if sphere.material_idx as usize >= self.materials.len() {
+                warnings.push(SceneWarning::MissingMaterial {
+                    sphere: idx,
+                    material: sphere.material_idx as usize,
+                });
+            }
+        }
+
+        for (idx, material) in self.materials.iter().enumerate() {
+            for texture in material.texture_ids() {
+                if texture >= self.textures.len() {
+                    warnings.push(SceneWarning::MissingTexture { material: idx, texture });
+                }
+            }
+            if let &Material::Mix { first, second, .. } = material {
+                for material in [first, second] {
+                    if material >= self.materials.len() {
+                        warnings.push(SceneWarning::MissingMixedMaterial { mix: idx, material });
+                    }
+                }
+            }
+        }
+
+        for (idx, op) in self.csg_ops.iter().enumerate() {
+            for sphere in [op.a, op.b] {
+                if sphere as usize >= self.spheres.len() {
+                    warnings.push(SceneWarning::MissingCsgSphere {
+                        op: idx,
+                        sphere: sphere as usize,
+                    });
+                }
+            }
+        }
+
+        warnings
+    }
+
+    /// What the BVH is built over: the spheres that are not part of a CSG operation and the operations.
+    /// Operations referencing missing spheres are left out.
+    pub fn bvh_primitives(&self) -> Vec<BvhPrimitive> {
+        let mut operands = vec![false; self.spheres.len()];
+        let mut primitives = Vec::new();
+        for (idx, op) in self.csg_ops.iter().enumerate() {
+            let (Some(a), Some(b)) = (self.spheres.get(op.a as usize), self.spheres.get(op.b as usize)) else {
+                continue;
+            };
+            operands[op.a as usize] = true;
+            operands[op.b as usize] = true;
+            primitives.push(BvhPrimitive {
+                id: CsgOp::PRIMITIVE_BIT | idx as u32,
+                bounds: op.operation().bounds(&a.bounds(), &b.bounds()),
+            });
+        }
+
+        let spheres = self.spheres.iter().enumerate().filter(|&(idx, _)| !operands[idx]);
+        let spheres = spheres.map(|(idx, sphere)| BvhPrimitive {
+            id: idx as u32,
+            bounds: sphere.bounds(),
+        });
+        spheres.chain(primitives).collect()
+    }
+}
+
+/// Sizes of a [`Scene`], `texture_bytes` is the size of the packed texture buffer on the GPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SceneStats {
+    pub spheres: usize,
+    pub materials: usize,
+    pub textures: usize,
+    pub lights: usize,
+    pub texture_bytes: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum SceneWarning {
+    #[error("sphere {0} has a radius of 0")]
+    ZeroRadius(usize),
+    #[error("sphere {sphere} references missing material {material}")]
+    MissingMaterial { sphere: usize, material: usize },
+    #[error("material {material} references missing texture {texture}")]
+    MissingTexture { material: usize, texture: TextureId },
+    #[error("CSG operation {op} references missing sphere {sphere}")]
+    MissingCsgSphere { op: usize, sphere: usize },
+    #[error("mix material {mix} references missing material {material}")]
+    MissingMixedMaterial { mix: usize, material: usize },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CsgOperation {
+    #[default]
+    Union,
+    Intersection,
+    /// The first sphere with the second one cut out of it.
+    Difference,
+}
+
+impl CsgOperation {
+    pub const ALL: [Self; 3] = [Self::Union, Self::Intersection, Self::Difference];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Union => "Union",
+            Self::Intersection => "Intersection",
+            Self::Difference => "Difference",
+        }
+    }
+
+    const fn to_gpu(self) -> u32 {
+        match self {
+            Self::Union => 0,
+            Self::Intersection => 1,
+            Self::Difference => 2,
+        }
+    }
+
+    const fn from_gpu(operation: u32) -> Self {
+        match operation {
+            1 => Self::Intersection,
+            2 => Self::Difference,
+            _ => Self::Union,
+        }
+    }
+
+    /// Box around the result, given the boxes around the two spheres.
+    fn bounds(&self, a: &Aabb, b: &Aabb) -> Aabb {
+        match self {
+            Self::Union => a.union(b),
+            Self::Intersection => Aabb {
+                min: a.min.sup(&b.min),
+                max: a.max.inf(&b.max),
+            },
+            Self::Difference => *a,
         }
     }
 }
 
+/// Boolean combination of the spheres at indices `a` and `b`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct CsgOp {
+    operation: u32,
+    pub a: u32,
+    pub b: u32,
+}
+
+impl CsgOp {
+    /// Set on the BVH indices of CSG operations, the other bits are the index of the operation.
+    pub const PRIMITIVE_BIT: u32 = 1 << 31;
+
+    pub fn new(operation: CsgOperation, a: u32, b: u32) -> Self {
+        Self {
+            operation: operation.to_gpu(),
+            a,
+            b,
+        }
+    }
+
+    pub fn operation(&self) -> CsgOperation {
+        CsgOperation::from_gpu(self.operation)
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::NoUninit, Serialize, Deserialize)]
 pub struct Sphere {
@@ -131,7 +592,24 @@ pub struct Sphere {
     center: Vector4f32, // 0 byte offset
     radius: f32,        // 16 byte offset
     material_idx: u32,  // 20 byte offset
-    _padding: [u32; 2], // 24 byte offset, 8 bytes size
+    /// Only lights of this group or of group 0 reach the sphere, see [`GpuLight::with_group`].
+    #[serde(default)]
+    light_group: u32, // 24 byte offset
+    /// Non-zero closes the clipped side with a flat cap, otherwise the sphere is left open there.
+    #[serde(default)]
+    clip_capped: u32, // 28 byte offset
+    /// Unit plane normal in xyz and offset from the center in w, only the part of the sphere behind the plane
+    /// is kept. A zero normal keeps the whole sphere.
+    #[serde(default = "Sphere::no_clip")]
+    clip: Vector4f32, // 32 byte offset, 16 bytes size
+    /// Rows of the affine map from world space into the space `center`, `radius` and `clip` are given in,
+    /// rays are intersected with the sphere there. See [`Self::with_transform`].
+    #[serde(default = "Sphere::identity_rows")]
+    inverse_transform: [Vector4f32; 3], // 48 byte offset, 48 bytes size
+    /// Center in xyz and radius in w of a sphere in world space around the transformed one, emissive spheres
+    /// are sampled as lights through it.
+    #[serde(default)]
+    bounding_sphere: Vector4f32, // 96 byte offset, 16 bytes size
 }
 
 impl Sphere {
@@ -140,56 +618,631 @@ impl Sphere {
             center: Vector4f32::new(center.x as _, center.y as _, center.z as _, 0.0),
             radius: radius as _,
             material_idx,
-            _padding: [0; 2],
+            light_group: 0,
+            clip_capped: 0,
+            clip: Self::no_clip(),
+            inverse_transform: Self::identity_rows(),
+            bounding_sphere: Vector4f32::new(center.x as _, center.y as _, center.z as _, radius.abs() as _),
+        }
+    }
+
+    fn no_clip() -> Vector4f32 {
+        Vector4f32::zeros()
+    }
+
+    fn identity_rows() -> [Vector4f32; 3] {
+        [Vector4f32::x(), Vector4f32::y(), Vector4f32::z()]
+    }
+
+    /// Moves the sphere by the affine `transform`, on top of its center and any earlier transform. With a
+    /// non-uniform scale it becomes an ellipsoid. A transform that can't be inverted, e.g. one scaling by zero,
+    /// leaves the sphere as it is.
+    pub fn with_transform(self, transform: &Matrix4f32) -> Self {
+        let transform = transform * self.transform();
+        let Some(inverse) = transform.try_inverse() else {
+            return self;
+        };
+
+        let center = (transform * self.center.xyz().push(1.0)).xyz();
+        let radius = self.radius.abs() * transform.fixed_view::<3, 3>(0, 0).into_owned().singular_values().max();
+        Self {
+            inverse_transform: [0, 1, 2].map(|i| inverse.row(i).transpose()),
+            bounding_sphere: Vector4f32::new(center.x, center.y, center.z, radius),
+            ..self
         }
     }
 
+    /// Map from world space into the space the sphere is given in.
+    pub fn inverse_transform(&self) -> Matrix4f32 {
+        let mut inverse = Matrix4f32::identity();
+        for (i, row) in self.inverse_transform.iter().enumerate() {
+            inverse.set_row(i, &row.transpose());
+        }
+        inverse
+    }
+
+    /// Map from the space the sphere is given in into world space.
+    pub fn transform(&self) -> Matrix4f32 {
+        self.inverse_transform()
+            .try_inverse()
+            .unwrap_or_else(Matrix4f32::identity)
+    }
+
+    /// Center of the transformed sphere in world space.
+    pub fn world_center(&self) -> Vector3f32 {
+        (self.transform() * self.center.xyz().push(1.0)).xyz()
+    }
+
+    /// Center and radius of a sphere in world space that contains the transformed one.
+    pub fn bounding_sphere(&self) -> (Vector3f32, f32) {
+        (self.bounding_sphere.xyz(), self.bounding_sphere.w)
+    }
+
+    pub fn with_light_group(self, light_group: u32) -> Self {
+        Self { light_group, ..self }
+    }
+
+    /// Cuts the sphere by the plane with `normal` at `offset` from the center, keeping the side the normal
+    /// points away from. A zero normal keeps the whole sphere and drops the offset, which the shader would still
+    /// compare against.
+    pub fn with_clip(self, normal: Vector3f32, offset: f32, capped: bool) -> Self {
+        let Some(normal) = normal.try_normalize(f32::EPSILON) else {
+            return Self {
+                clip: Self::no_clip(),
+                clip_capped: 0,
+                ..self
+            };
+        };
+        Self {
+            clip: Vector4f32::new(normal.x, normal.y, normal.z, offset),
+            clip_capped: capped as _,
+            ..self
+        }
+    }
+
+    /// Plane normal and offset, `None` for a whole sphere.
+    pub fn clip(&self) -> Option<(Vector3f32, f32)> {
+        let normal = self.clip.xyz();
+        (normal != Vector3f32::zeros()).then_some((normal, self.clip.w))
+    }
+
+    pub fn is_clip_capped(&self) -> bool {
+        self.clip_capped != 0
+    }
+
     pub fn from_node(sphere_node: &SphereNode, material_idx: u32) -> Self {
         let center = sphere_node.center.get();
+        let sphere = Self {
+            light_group: GpuLight::light_group(sphere_node.light_group.get()),
+            ..Self::new(center, sphere_node.radius.get(), material_idx)
+        };
+        let clip_normal = from_vector3_to_vector3f32(&sphere_node.clip_normal.get());
+        sphere.with_clip(clip_normal, sphere_node.clip_offset.get() as _, !sphere_node.clip_open)
+    }
+
+    pub fn center(&self) -> Vector3f32 {
+        self.center.xyz()
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn material_idx(&self) -> u32 {
+        self.material_idx
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        // Along each axis the transformed sphere reaches as far as the radius times the length of that
+        // row of the linear part of the transform.
+        let transform = self.transform();
+        let linear = transform.fixed_view::<3, 3>(0, 0);
+        let center = self.world_center();
+        let radius = Vector3f32::from_fn(|i, _| self.radius.abs() * linear.row(i).norm());
+        Aabb {
+            min: center - radius,
+            max: center + radius,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize)]
+pub struct GpuLight {
+    /// Position of a point or spot light, travel direction of a directional light.
+    position_or_direction: [f32; 3], // 0 byte offset
+    /// Zero marks a missing light, so a zero-filled buffer of an empty scene contributes nothing.
+    kind: u32, // 12 byte offset
+    radiance: [f32; 3], // 16 byte offset
+    /// Cosine of the outer cone angle of a spot light.
+    cos_outer: f32, // 28 byte offset
+    /// Normalized cone axis of a spot light.
+    spot_direction: [f32; 3], // 32 byte offset
+    /// Cosine of the inner cone angle of a spot light.
+    cos_inner: f32, // 44 byte offset
+    /// Bit of every light group the light reaches.
+    #[serde(default = "GpuLight::all_groups")]
+    group_mask: u32, // 48 byte offset
+    #[serde(skip)]
+    _padding: [u32; 3], // 52 byte offset, 12 bytes size
+}
+
+impl GpuLight {
+    const POINT: u32 = 1;
+    const DIRECTIONAL: u32 = 2;
+    const SPOT: u32 = 3;
+
+    /// Valid values of the light group pins, groups above 0 link lights to spheres.
+    pub const LIGHT_GROUP_RANGE: RangeInclusive<f64> = 0.0..=31.0;
+
+    fn all_groups() -> u32 {
+        u32::MAX
+    }
+
+    /// Light group of a pin value, rounded and clamped to [`Self::LIGHT_GROUP_RANGE`].
+    pub fn light_group(value: f64) -> u32 {
+        value
+            .round()
+            .clamp(*Self::LIGHT_GROUP_RANGE.start(), *Self::LIGHT_GROUP_RANGE.end()) as _
+    }
+
+    /// A light in group 0 reaches every sphere, one in another group only the spheres of that group.
+    pub fn with_group(self, group: u32) -> Self {
+        let group_mask = match group {
+            0 => Self::all_groups(),
+            group => 1 << group,
+        };
+        Self { group_mask, ..self }
+    }
+
+    pub fn point(position: Vector3, radiance: Vector3) -> Self {
         Self {
-            center: Vector4f32::new(center.x as _, center.y as _, center.z as _, 0.0),
-            radius: sphere_node.radius.get() as f32,
-            material_idx,
-            _padding: [0; 2],
+            position_or_direction: from_vector3_to_vector3f32(&position).into(),
+            kind: Self::POINT,
+            radiance: from_vector3_to_vector3f32(&radiance).into(),
+            cos_outer: 0.0,
+            spot_direction: [0.0; 3],
+            cos_inner: 0.0,
+            group_mask: Self::all_groups(),
+            _padding: [0; 3],
+        }
+    }
+
+    /// Direction the light travels in, a zero vector from a pin shines straight down instead of giving NaNs.
+    fn unit_direction(direction: Vector3) -> [f32; 3] {
+        let direction = direction.try_normalize(f64::EPSILON).unwrap_or(-Vector3::y());
+        from_vector3_to_vector3f32(&direction).into()
+    }
+
+    pub fn directional(direction: Vector3, radiance: Vector3) -> Self {
+        Self {
+            position_or_direction: Self::unit_direction(direction),
+            kind: Self::DIRECTIONAL,
+            radiance: from_vector3_to_vector3f32(&radiance).into(),
+            cos_outer: 0.0,
+            spot_direction: [0.0; 3],
+            cos_inner: 0.0,
+            group_mask: Self::all_groups(),
+            _padding: [0; 3],
+        }
+    }
+
+    /// The intensity fades out between `inner_angle` and `outer_angle` away from the cone axis.
+    pub fn spot(
+        position: Vector3,
+        direction: Vector3,
+        radiance: Vector3,
+        inner_angle: Angle,
+        outer_angle: Angle,
+    ) -> Self {
+        Self {
+            position_or_direction: from_vector3_to_vector3f32(&position).into(),
+            kind: Self::SPOT,
+            radiance: from_vector3_to_vector3f32(&radiance).into(),
+            cos_outer: outer_angle.as_radians().cos() as _,
+            spot_direction: Self::unit_direction(direction),
+            cos_inner: inner_angle.as_radians().cos() as _,
+            group_mask: Self::all_groups(),
+            _padding: [0; 3],
+        }
+    }
+
+    pub fn from_node(light_node: &LightNode) -> Self {
+        let light = match light_node {
+            LightNode::Point(point_node) => {
+                let color = linear_rgb(point_node.color.get()).cast::<f64>();
+                Self::point(point_node.position.get(), point_node.intensity.get() * color)
+            },
+            LightNode::Directional(directional_node) => {
+                let color = linear_rgb(directional_node.color.get()).cast::<f64>();
+                Self::directional(directional_node.direction.get(), color)
+            },
+            LightNode::Spot(spot_node) => {
+                let color = linear_rgb(spot_node.color.get()).cast::<f64>();
+                let (inner_angle, outer_angle) = spot_node.cone_angles();
+                Self::spot(
+                    spot_node.position.get(),
+                    spot_node.direction.get(),
+                    spot_node.intensity.get() * color,
+                    inner_angle,
+                    outer_angle,
+                )
+            },
+        };
+        light.with_group(Self::light_group(light_node.light_group().get()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3f32,
+    pub max: Vector3f32,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3f32::repeat(f32::MAX),
+            max: Vector3f32::repeat(-f32::MAX),
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.inf(&other.min),
+            max: self.max.sup(&other.max),
+        }
+    }
+
+    pub fn grow(&mut self, point: &Vector3f32) {
+        self.min = self.min.inf(point);
+        self.max = self.max.sup(point);
+    }
+
+    pub fn centroid(&self) -> Vector3f32 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        (self.max - self.min).imax()
+    }
+}
+
+/// Something the BVH holds, `id` is what ends up in its index buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BvhPrimitive {
+    pub id: u32,
+    pub bounds: Aabb,
+}
+
+/// Bounding volume hierarchy over the scene primitives, see [`Scene::bvh_primitives`].
+///
+/// Nodes are stored in a flat array with the root at index 0. Children of an inner node are
+/// allocated next to each other, so only the index of the left child is stored. A node with
+/// `count == 0` and `left_or_first == 0` is an empty leaf, which only happens for an empty scene.
+#[derive(Clone, Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<u32>,
+    primitives: Vec<BvhPrimitive>,
+}
+
+impl Bvh {
+    const MAX_LEAF_SIZE: usize = 2;
+
+    pub fn build(primitives: &[BvhPrimitive]) -> Self {
+        let primitive_bounds = primitives.iter().map(|primitive| primitive.bounds).collect::<Vec<_>>();
+        let mut indices = (0..primitives.len() as u32).collect::<Vec<_>>();
+        let mut nodes = vec![BvhNode::leaf(&Aabb::empty(), 0, 0)];
+
+        if !indices.is_empty() {
+            let count = indices.len();
+            Self::subdivide(0, 0, count, &primitive_bounds, &mut indices, &mut nodes);
+        }
+
+        for idx in &mut indices {
+            *idx = primitives[*idx as usize].id;
+        }
+
+        Self {
+            nodes,
+            indices,
+            primitives: primitives.to_vec(),
+        }
+    }
+
+    fn subdivide(
+        node_idx: usize,
+        first: usize,
+        count: usize,
+        primitive_bounds: &[Aabb],
+        indices: &mut [u32],
+        nodes: &mut Vec<BvhNode>,
+    ) {
+        let range = first..first + count;
+        let node_bounds = indices[range.clone()]
+            .iter()
+            .fold(Aabb::empty(), |bounds, &idx| bounds.union(&primitive_bounds[idx as usize]));
+
+        if count <= Self::MAX_LEAF_SIZE {
+            nodes[node_idx] = BvhNode::leaf(&node_bounds, first as u32, count as u32);
+            return;
+        }
+
+        let mut centroid_bounds = Aabb::empty();
+        for &idx in &indices[range.clone()] {
+            centroid_bounds.grow(&primitive_bounds[idx as usize].centroid());
+        }
+        let axis = centroid_bounds.longest_axis();
+
+        // Median split: partition primitives around the middle one along the longest axis.
+        let left_count = count / 2;
+        indices[range].select_nth_unstable_by(left_count, |&lhs, &rhs| {
+            let lhs = primitive_bounds[lhs as usize].centroid()[axis];
+            let rhs = primitive_bounds[rhs as usize].centroid()[axis];
+            lhs.total_cmp(&rhs)
+        });
+
+        let left_idx = nodes.len();
+        nodes.push(BvhNode::leaf(&Aabb::empty(), 0, 0));
+        nodes.push(BvhNode::leaf(&Aabb::empty(), 0, 0));
+        nodes[node_idx] = BvhNode::inner(&node_bounds, left_idx as u32);
+
+        Self::subdivide(left_idx, first, left_count, primitive_bounds, indices, nodes);
+        Self::subdivide(
+            left_idx + 1,
+            first + left_count,
+            count - left_count,
+            primitive_bounds,
+            indices,
+            nodes,
+        );
+    }
+
+    /// Checks whether the hierarchy was built for the same primitives with the same bounds,
+    /// so it can be reused when only materials or other values changed.
+    pub fn is_built_for(&self, primitives: &[BvhPrimitive]) -> bool {
+        self.primitives == primitives
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BvhNode {
+    min: [f32; 3],
+    /// Index of the left child for inner nodes, index of the first primitive for leaves.
+    left_or_first: u32,
+    max: [f32; 3],
+    /// Number of primitives in a leaf, zero for inner nodes.
+    count: u32,
+}
+
+impl BvhNode {
+    fn leaf(bounds: &Aabb, first: u32, count: u32) -> Self {
+        Self {
+            min: bounds.min.into(),
+            left_or_first: first,
+            max: bounds.max.into(),
+            count,
+        }
+    }
+
+    fn inner(bounds: &Aabb, left: u32) -> Self {
+        Self {
+            min: bounds.min.into(),
+            left_or_first: left,
+            max: bounds.max.into(),
+            count: 0,
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Material {
-    Lambertian { albedo: TextureId },
-    Metal { albedo: TextureId, fuzz: f32 },
-    Dielectric { refraction_index: f32 },
-    Checkerboard { even: TextureId, odd: TextureId },
-    Emissive { emit: TextureId },
+    Lambertian {
+        albedo: TextureId,
+        #[serde(default)]
+        normal_map: Option<TextureId>,
+        /// Grayscale texture bumping the normal along its gradient.
+        #[serde(default)]
+        height_map: Option<TextureId>,
+        /// Color the albedo texture is multiplied with.
+        #[serde(default = "Material::default_tint")]
+        tint: [f32; 3],
+        /// Added to U on spheres, a whole turn around the sphere is 1.
+        #[serde(default)]
+        uv_offset: f32,
+    },
+    Metal {
+        albedo: TextureId,
+        fuzz: f32,
+        #[serde(default)]
+        normal_map: Option<TextureId>,
+        /// Fuzz is scaled by `1 + anisotropy` along U and `1 - anisotropy` along V.
+        #[serde(default)]
+        anisotropy: f32,
+        /// Grayscale texture the fuzz is multiplied with.
+        #[serde(default)]
+        roughness_map: Option<TextureId>,
+        /// Grayscale texture bumping the normal along its gradient.
+        #[serde(default)]
+        height_map: Option<TextureId>,
+        /// Color the albedo texture is multiplied with.
+        #[serde(default = "Material::default_tint")]
+        tint: [f32; 3],
+        /// Added to U on spheres, a whole turn around the sphere is 1.
+        #[serde(default)]
+        uv_offset: f32,
+    },
+    Dielectric {
+        refraction_index: f32,
+        /// Spread of the microfacet normals, 0 is clear glass.
+        #[serde(default)]
+        roughness: f32,
+        /// Grayscale texture the roughness is multiplied with.
+        #[serde(default)]
+        roughness_map: Option<TextureId>,
+        /// Color after traveling a distance of 1 inside, following the Beer-Lambert law.
+        #[serde(default = "Material::default_tint")]
+        absorption: [f32; 3],
+    },
+    Checkerboard {
+        even: TextureId,
+        odd: TextureId,
+        /// Tiling frequency, see [`CheckerboardSpace`].
+        #[serde(default = "CheckerboardSpace::default_scale")]
+        scale: f32,
+        #[serde(default)]
+        space: CheckerboardSpace,
+    },
+    Emissive {
+        emit: TextureId,
+        /// Back faces emit too, off for panel-like lights that only shine to the front.
+        #[serde(default = "Material::default_two_sided")]
+        two_sided: bool,
+    },
+    Isotropic {
+        albedo: TextureId,
+        density: f32,
+    },
+    Subsurface {
+        albedo: TextureId,
+        /// Mean distance between scattering events beneath the surface.
+        radius: f32,
+    },
+    /// One of two materials at each hit, `second` with the probability of the red channel of `mask`.
+    /// Averaged over the samples their scattering is blended.
+    Mix {
+        first: usize,
+        second: usize,
+        mask: TextureId,
+    },
+}
+
+/// Coordinates the checkerboard tiles are laid out in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum CheckerboardSpace {
+    /// Tiles fill the world as 3D cells, the size of a cell is `PI / scale`.
+    #[default]
+    Solid,
+    /// Tiles follow the texture coordinates, `scale` tiles along each axis.
+    Uv,
+}
+
+impl CheckerboardSpace {
+    pub const DEFAULT_SCALE: f32 = 5.0;
+
+    pub fn default_scale() -> f32 {
+        Self::DEFAULT_SCALE
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Solid => "Solid",
+            Self::Uv => "UV",
+        }
+    }
 }
 
 impl Material {
+    pub const DEFAULT_TINT: [f32; 3] = [1.0; 3];
+
+    pub fn default_tint() -> [f32; 3] {
+        Self::DEFAULT_TINT
+    }
+
+    pub fn default_two_sided() -> bool {
+        true
+    }
+
+    /// Every texture the material reads from.
+    pub fn texture_ids(&self) -> Vec<TextureId> {
+        match *self {
+            Self::Lambertian {
+                albedo,
+                normal_map,
+                height_map,
+                ..
+            } => [Some(albedo), normal_map, height_map].into_iter().flatten().collect(),
+            Self::Metal {
+                albedo,
+                normal_map,
+                roughness_map,
+                height_map,
+                ..
+            } => [Some(albedo), normal_map, roughness_map, height_map]
+                .into_iter()
+                .flatten()
+                .collect(),
+            Self::Dielectric { roughness_map, .. } => roughness_map.into_iter().collect(),
+            Self::Checkerboard { even, odd, .. } => vec![even, odd],
+            Self::Emissive { emit, .. } => vec![emit],
+            Self::Isotropic { albedo, .. } => vec![albedo],
+            Self::Subsurface { albedo, .. } => vec![albedo],
+            Self::Mix { mask, .. } => vec![mask],
+        }
+    }
+
+    /// Emissive material of a sphere flagged as a light, standing in for a separate material node.
+    pub fn from_sphere_light(sphere_node: &SphereNode, textures: &mut Vec<TextureData>) -> Self {
+        let emit = sphere_node.light_emit();
+        let texture = Texture::new_from_color(from_vector3_to_vector3f32(&emit));
+        textures.push(TextureData::new(texture));
+        Self::Emissive {
+            emit: textures.len() - 1,
+            two_sided: Self::default_two_sided(),
+        }
+    }
+
     pub fn from_node(
         material_node: &MaterialNode,
         texture_id: Option<TextureId>,
+        normal_texture_id: Option<TextureId>,
+        roughness_texture_id: Option<TextureId>,
+        height_texture_id: Option<TextureId>,
         textures: &mut Vec<TextureData>,
     ) -> Self {
         match material_node {
-            MaterialNode::Metal(metal_node) => Self::Metal {
-                albedo: texture_id.unwrap_or_else(|| {
-                    let color = metal_node.albedo.get().to_normalized_gamma_f32();
-                    let texture = Texture::new_from_color(Vector3f32::new(color[0], color[1], color[2]));
-                    textures.push(TextureData::new(texture));
-                    textures.len() - 1
-                }),
-                fuzz: metal_node.fuzz.get() as _,
+            MaterialNode::Metal(metal_node) => {
+                // Without a texture the color becomes the texture, otherwise it tints the texture.
+                let [r, g, b]: [f32; 3] = linear_rgb(metal_node.albedo.get()).into();
+                Self::Metal {
+                    albedo: texture_id.unwrap_or_else(|| {
+                        let texture = Texture::new_from_color(Vector3f32::new(r, g, b));
+                        textures.push(TextureData::new(texture));
+                        textures.len() - 1
+                    }),
+                    fuzz: metal_node.fuzz.get() as _,
+                    normal_map: normal_texture_id,
+                    anisotropy: metal_node.anisotropy.get().clamp(-1.0, 1.0) as _,
+                    roughness_map: roughness_texture_id,
+                    height_map: height_texture_id,
+                    tint: texture_id.map_or(Self::DEFAULT_TINT, |_| [r, g, b]),
+                    uv_offset: uv_offset(metal_node.texture_rotation.get()),
+                }
             },
-            MaterialNode::Dielectric(dielectric_node) => Self::Dielectric {
-                refraction_index: dielectric_node.ior.get() as _,
+            MaterialNode::Dielectric(dielectric_node) => {
+                let [r, g, b]: [f32; 3] = linear_rgb(dielectric_node.absorption.get()).into();
+                Self::Dielectric {
+                    refraction_index: dielectric_node.ior.get() as _,
+                    roughness: dielectric_node.roughness.get() as _,
+                    roughness_map: roughness_texture_id,
+                    absorption: [r, g, b],
+                }
             },
-            MaterialNode::Lambertian(lambertian_node) => Self::Lambertian {
-                albedo: texture_id.unwrap_or_else(|| {
-                    let color = lambertian_node.albedo.get().to_normalized_gamma_f32();
-                    let texture = Texture::new_from_color(Vector3f32::new(color[0], color[1], color[2]));
-                    textures.push(TextureData::new(texture));
-                    textures.len() - 1
-                }),
+            MaterialNode::Lambertian(lambertian_node) => {
+                let [r, g, b]: [f32; 3] = linear_rgb(lambertian_node.albedo.get()).into();
+                Self::Lambertian {
+                    albedo: texture_id.unwrap_or_else(|| {
+                        let texture = Texture::new_from_color(Vector3f32::new(r, g, b));
+                        textures.push(TextureData::new(texture));
+                        textures.len() - 1
+                    }),
+                    normal_map: normal_texture_id,
+                    height_map: height_texture_id,
+                    tint: texture_id.map_or(Self::DEFAULT_TINT, |_| [r, g, b]),
+                    uv_offset: uv_offset(lambertian_node.texture_rotation.get()),
+                }
             },
             MaterialNode::Emissive(emissive_node) => Self::Emissive {
                 emit: texture_id.unwrap_or_else(|| {
@@ -198,21 +1251,59 @@ impl Material {
                     textures.push(TextureData::new(texture));
                     textures.len() - 1
                 }),
+                two_sided: emissive_node.two_sided,
             },
             MaterialNode::Checkerboard(checkerboard_node) => Self::Checkerboard {
                 even: {
-                    let color = checkerboard_node.even.get().to_normalized_gamma_f32();
-                    let texture = Texture::new_from_color(Vector3f32::new(color[0], color[1], color[2]));
+                    let texture = Texture::new_from_color(linear_rgb(checkerboard_node.even.get()));
                     textures.push(TextureData::new(texture));
                     textures.len() - 1
                 },
                 odd: {
-                    let color = checkerboard_node.odd.get().to_normalized_gamma_f32();
-                    let texture = Texture::new_from_color(Vector3f32::new(color[0], color[1], color[2]));
+                    let texture = Texture::new_from_color(linear_rgb(checkerboard_node.odd.get()));
                     textures.push(TextureData::new(texture));
                     textures.len() - 1
                 },
+                scale: checkerboard_node.scale.get().max(0.0) as _,
+                space: checkerboard_node.space,
             },
+            MaterialNode::Isotropic(isotropic_node) => Self::Isotropic {
+                albedo: {
+                    let texture = Texture::new_from_color(linear_rgb(isotropic_node.albedo.get()));
+                    textures.push(TextureData::new(texture));
+                    textures.len() - 1
+                },
+                density: isotropic_node.density.get().max(0.0) as _,
+            },
+            MaterialNode::Subsurface(subsurface_node) => Self::Subsurface {
+                albedo: {
+                    let texture = Texture::new_from_color(linear_rgb(subsurface_node.albedo.get()));
+                    textures.push(TextureData::new(texture));
+                    textures.len() - 1
+                },
+                radius: subsurface_node.radius.get().max(0.0) as _,
+            },
+            // A mix needs the materials of its inputs, see `from_mix_node`. Without them it is the default material.
+            MaterialNode::Mix(_) => Self::from_node(&MaterialNode::default(), None, None, None, None, textures),
+        }
+    }
+
+    /// Without a mask texture the factor of the node is used all over the surface.
+    pub fn from_mix_node(
+        mix_node: &MixMaterialNode,
+        first: usize,
+        second: usize,
+        mask_texture_id: Option<TextureId>,
+        textures: &mut Vec<TextureData>,
+    ) -> Self {
+        Self::Mix {
+            first,
+            second,
+            mask: mask_texture_id.unwrap_or_else(|| {
+                let factor = mix_node.factor.get().clamp(0.0, 1.0) as f32;
+                textures.push(TextureData::new(Texture::new_from_color(Vector3f32::repeat(factor))));
+                textures.len() - 1
+            }),
         }
     }
 }
@@ -222,16 +1313,26 @@ pub struct GroupData {
     material_buffer: StorageBuffer,
     texture_buffer: StorageBuffer,
     light_buffer: StorageBuffer,
+    bvh_node_buffer: StorageBuffer,
+    bvh_index_buffer: StorageBuffer,
+    analytic_light_buffer: StorageBuffer,
+    csg_buffer: StorageBuffer,
+    /// Number of emissive spheres in `light_buffer`, which is never empty on the GPU side.
+    light_count: u32,
     layout: wgpu::BindGroupLayout,
 }
 
 pub struct SceneBuffersGroup {
     data: GroupData,
     bind_group: wgpu::BindGroup,
+    bvh: Bvh,
 }
 
 impl GroupData {
-    pub fn from_scene(scene: &Scene, device: &wgpu::Device) -> Self {
+    /// Fails without creating any buffer if one of them would exceed the limits of `device`.
+    pub fn from_scene(scene: &Scene, bvh: &Bvh, device: &wgpu::Device) -> Result<Self, RenderParamsValidationError> {
+        Self::check_sizes(scene, bvh, &device.limits())?;
+
         let sphere_buffer = StorageBuffer::new_from_bytes(
             device,
             bytemuck::cast_slice(scene.spheres.as_slice()),
@@ -241,29 +1342,13 @@ impl GroupData {
 
         let mut global_texture_data = Vec::new();
         let mut texture_descriptors = Vec::new();
-        let mut material_data = Vec::with_capacity(scene.materials.len());
 
         for texture in &scene.textures {
-            texture_descriptors.push(append_to_global_texture_data(
-                &texture.texture,
-                &mut global_texture_data,
-            ));
-        }
-
-        for material in &scene.materials {
-            let gpu_material = match material {
-                Material::Lambertian { albedo } => GpuMaterial::lambertian(texture_descriptors[*albedo]),
-                Material::Metal { albedo, fuzz } => GpuMaterial::metal(texture_descriptors[*albedo], *fuzz),
-                Material::Dielectric { refraction_index } => GpuMaterial::dielectric(*refraction_index),
-                Material::Checkerboard { odd, even } => {
-                    GpuMaterial::checkerboard(texture_descriptors[*odd], texture_descriptors[*even])
-                },
-                Material::Emissive { emit } => GpuMaterial::emissive(texture_descriptors[*emit]),
-            };
-
-            material_data.push(gpu_material);
+            texture_descriptors.push(append_to_global_texture_data(texture, &mut global_texture_data));
         }
 
+        let material_data = gpu_materials(scene, &texture_descriptors);
+
         let material_buffer = StorageBuffer::new_from_bytes(
             device,
             bytemuck::cast_slice(material_data.as_slice()),
@@ -278,13 +1363,7 @@ impl GroupData {
             Some("textures buffer"),
         );
 
-        let light_indices: Vec<u32> = scene
-            .spheres
-            .iter()
-            .enumerate()
-            .filter(|(_, s)| matches!(scene.materials[s.material_idx as usize], Material::Emissive { .. }))
-            .map(|(idx, _)| idx as u32)
-            .collect();
+        let light_indices = light_indices(scene);
 
         let light_buffer = StorageBuffer::new_from_bytes(
             device,
@@ -293,23 +1372,84 @@ impl GroupData {
             Some("lights buffer"),
         );
 
+        let bvh_node_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(bvh.nodes.as_slice()),
+            4,
+            Some("bvh nodes buffer"),
+        );
+
+        let bvh_index_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(bvh.indices.as_slice()),
+            5,
+            Some("bvh indices buffer"),
+        );
+
+        let analytic_light_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(scene.lights.as_slice()),
+            6,
+            Some("analytic lights buffer"),
+        );
+
+        let csg_buffer = StorageBuffer::new_from_bytes(
+            device,
+            bytemuck::cast_slice(scene.csg_ops.as_slice()),
+            7,
+            Some("csg buffer"),
+        );
+
         let scene_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 sphere_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
                 material_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
                 texture_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
                 light_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
+                bvh_node_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
+                bvh_index_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
+                analytic_light_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
+                csg_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
             ],
             label: Some("scene layout"),
         });
 
-        Self {
+        Ok(Self {
             sphere_buffer,
             material_buffer,
             texture_buffer,
             light_buffer,
+            bvh_node_buffer,
+            bvh_index_buffer,
+            analytic_light_buffer,
+            csg_buffer,
+            light_count: light_indices.len() as u32,
             layout: scene_bind_group_layout,
+        })
+    }
+
+    /// Wgpu panics on buffers larger than the device allows, large textures are the usual culprit
+    /// since all of them are packed into a single buffer.
+    fn check_sizes(scene: &Scene, bvh: &Bvh, limits: &wgpu::Limits) -> Result<(), RenderParamsValidationError> {
+        let texture_size = scene
+            .textures
+            .iter()
+            .map(|texture| size_of_val(texture.texture.as_slice()))
+            .sum();
+        let sizes = [
+            ("spheres", size_of_val(scene.spheres.as_slice())),
+            ("materials", scene.materials.len() * size_of::<GpuMaterial>()),
+            ("textures", texture_size),
+            ("lights", light_indices(scene).len() * size_of::<u32>()),
+            ("bvh nodes", size_of_val(bvh.nodes.as_slice())),
+            ("bvh indices", size_of_val(bvh.indices.as_slice())),
+            ("analytic lights", size_of_val(scene.lights.as_slice())),
+            ("csg", size_of_val(scene.csg_ops.as_slice())),
+        ];
+        for (label, size) in sizes {
+            check_buffer_size(label, size as u64, limits)?;
         }
+        Ok(())
     }
 
     pub fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
@@ -320,6 +1460,10 @@ impl GroupData {
                 self.material_buffer.binding(),
                 self.texture_buffer.binding(),
                 self.light_buffer.binding(),
+                self.bvh_node_buffer.binding(),
+                self.bvh_index_buffer.binding(),
+                self.analytic_light_buffer.binding(),
+                self.csg_buffer.binding(),
             ],
             label: Some("scene bind group"),
         })
@@ -327,23 +1471,38 @@ impl GroupData {
 }
 
 impl SceneBuffersGroup {
-    pub fn new(scene: &Scene, device: &wgpu::Device) -> Self {
-        let data = GroupData::from_scene(scene, device);
+    pub fn new(scene: &Scene, device: &wgpu::Device) -> Result<Self, RenderParamsValidationError> {
+        let bvh = Bvh::build(&scene.bvh_primitives());
+        let data = GroupData::from_scene(scene, &bvh, device)?;
         let scene_bind_group = data.create_bind_group(device);
 
-        Self {
+        Ok(Self {
             data,
             bind_group: scene_bind_group,
-        }
+            bvh,
+        })
     }
 
-    pub fn update(&mut self, device: &wgpu::Device, _queue: &wgpu::Queue, scene: &Scene) {
+    /// Keeps the previous scene on the GPU if `scene` doesn't fit.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        scene: &Scene,
+    ) -> Result<(), RenderParamsValidationError> {
         // if new_spheres.len() > self.data.sphere_count {
         //     self.data.sphere_count = new_spheres.len();
         // let new_size = (self.capacity * std::mem::size_of::<T>()) as u64;
 
+        // Rebuilding the hierarchy is the expensive part, so it is skipped
+        // when only material or texture values have changed.
+        let primitives = scene.bvh_primitives();
+        if !self.bvh.is_built_for(&primitives) {
+            self.bvh = Bvh::build(&primitives);
+        }
+
         // self.data.need_recreate
-        self.data = GroupData::from_scene(scene, device);
+        self.data = GroupData::from_scene(scene, &self.bvh, device)?;
         // self.data.sphere_buffer =
         //     StorageBuffer::new_from_bytes(device, bytemuck::cast_slice(new_spheres), 0, Some("scene buffer"));
 
@@ -351,6 +1510,11 @@ impl SceneBuffersGroup {
         // } else {
         //     queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(new_data));
         // }
+        Ok(())
+    }
+
+    pub fn light_count(&self) -> u32 {
+        self.data.light_count
     }
 
     pub fn bind_group(&self) -> &wgpu::BindGroup {
@@ -369,63 +1533,271 @@ struct GpuMaterial {
     desc1: TextureDescriptor,
     desc2: TextureDescriptor,
     x: f32,
+    y: f32,
+    /// Roughness map of metals and dielectrics, empty for the other materials.
+    desc3: TextureDescriptor,
+    /// Height map of lambertians and metals, empty for the other materials.
+    desc4: TextureDescriptor,
+    /// Multiplies the albedo of lambertians and metals, the absorption color of dielectrics.
+    tint: [f32; 3],
+    /// Added to U of spheres, in turns.
+    uv_offset: f32,
 }
 
 impl GpuMaterial {
-    pub fn lambertian(albedo: TextureDescriptor) -> Self {
+    pub fn lambertian(albedo: TextureDescriptor, normal_map: TextureDescriptor) -> Self {
         Self {
             id: 0,
             desc1: albedo,
-            desc2: TextureDescriptor::empty(),
+            desc2: normal_map,
             x: 0.0,
+            y: 0.0,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
         }
     }
 
-    pub fn metal(albedo: TextureDescriptor, fuzz: f32) -> Self {
+    pub fn metal(albedo: TextureDescriptor, fuzz: f32, anisotropy: f32, normal_map: TextureDescriptor) -> Self {
         Self {
             id: 1,
             desc1: albedo,
-            desc2: TextureDescriptor::empty(),
+            desc2: normal_map,
             x: fuzz,
+            y: anisotropy,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
         }
     }
 
-    pub fn dielectric(refraction_index: f32) -> Self {
+    pub fn dielectric(refraction_index: f32, roughness: f32) -> Self {
         Self {
             id: 2,
             desc1: TextureDescriptor::empty(),
             desc2: TextureDescriptor::empty(),
             x: refraction_index,
+            y: roughness,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
         }
     }
 
-    pub fn checkerboard(even: TextureDescriptor, odd: TextureDescriptor) -> Self {
+    pub fn checkerboard(even: TextureDescriptor, odd: TextureDescriptor, scale: f32, space: CheckerboardSpace) -> Self {
         Self {
             id: 3,
             desc1: even,
             desc2: odd,
-            x: 0.0,
+            x: scale,
+            y: match space {
+                CheckerboardSpace::Solid => 0.0,
+                CheckerboardSpace::Uv => 1.0,
+            },
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
         }
     }
 
-    pub fn emissive(emit: TextureDescriptor) -> Self {
+    pub fn emissive(emit: TextureDescriptor, two_sided: bool) -> Self {
         Self {
             id: 4,
             desc1: emit,
             desc2: TextureDescriptor::empty(),
-            x: 0.0,
+            x: if two_sided { 1.0 } else { 0.0 },
+            y: 0.0,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
         }
     }
+
+    pub fn isotropic(albedo: TextureDescriptor, density: f32) -> Self {
+        Self {
+            id: 5,
+            desc1: albedo,
+            desc2: TextureDescriptor::empty(),
+            x: density,
+            y: 0.0,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
+        }
+    }
+
+    pub fn subsurface(albedo: TextureDescriptor, radius: f32) -> Self {
+        Self {
+            id: 6,
+            desc1: albedo,
+            desc2: TextureDescriptor::empty(),
+            x: radius,
+            y: 0.0,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
+        }
+    }
+
+    /// Spheres turn their textures by the offset of their own material, the offsets of the mixed materials are
+    /// left out.
+    pub fn mix(first: u32, second: u32, mask: TextureDescriptor) -> Self {
+        Self {
+            id: 7,
+            desc1: mask,
+            desc2: TextureDescriptor::empty(),
+            x: first as f32,
+            y: second as f32,
+            desc3: TextureDescriptor::empty(),
+            desc4: TextureDescriptor::empty(),
+            tint: Material::DEFAULT_TINT,
+            uv_offset: 0.0,
+        }
+    }
+
+    /// The red channel of `roughness_map` scales the fuzz or roughness, an empty descriptor leaves it as is.
+    pub fn with_roughness_map(self, roughness_map: TextureDescriptor) -> Self {
+        Self {
+            desc3: roughness_map,
+            ..self
+        }
+    }
+
+    /// The normal is tilted along the gradient of the red channel of `height_map`, an empty descriptor keeps it smooth.
+    pub fn with_height_map(self, height_map: TextureDescriptor) -> Self {
+        Self {
+            desc4: height_map,
+            ..self
+        }
+    }
+
+    pub fn with_tint(self, tint: [f32; 3]) -> Self {
+        Self { tint, ..self }
+    }
+
+    pub fn with_uv_offset(self, uv_offset: f32) -> Self {
+        Self { uv_offset, ..self }
+    }
+}
+
+/// Texture rotation as an offset of U, which goes around a sphere once.
+fn uv_offset(rotation: Angle) -> f32 {
+    (rotation.as_degrees() / 360.0).rem_euclid(1.0) as f32
+}
+
+/// Converts the materials for the GPU, a texture that doesn't exist is replaced by the empty texture.
+fn gpu_materials(scene: &Scene, texture_descriptors: &[TextureDescriptor]) -> Vec<GpuMaterial> {
+    let mut material_data = Vec::with_capacity(scene.materials.len());
+
+    for (material_idx, material) in scene.materials.iter().enumerate() {
+        let descriptor = |texture: TextureId| {
+            texture_descriptors.get(texture).copied().unwrap_or_else(|| {
+                let warning = SceneWarning::MissingTexture {
+                    material: material_idx,
+                    texture,
+                };
+                eprintln!("{warning}");
+                TextureDescriptor::empty()
+            })
+        };
+        let optional_descriptor = |id: &Option<TextureId>| id.map_or_else(TextureDescriptor::empty, &descriptor);
+
+        let gpu_material = match material {
+            Material::Lambertian {
+                albedo,
+                normal_map,
+                height_map,
+                tint,
+                uv_offset,
+            } => GpuMaterial::lambertian(descriptor(*albedo), optional_descriptor(normal_map))
+                .with_height_map(optional_descriptor(height_map))
+                .with_tint(*tint)
+                .with_uv_offset(*uv_offset),
+            Material::Metal {
+                albedo,
+                fuzz,
+                normal_map,
+                anisotropy,
+                roughness_map,
+                height_map,
+                tint,
+                uv_offset,
+            } => {
+                let normal_map = optional_descriptor(normal_map);
+                GpuMaterial::metal(descriptor(*albedo), *fuzz, *anisotropy, normal_map)
+                    .with_roughness_map(optional_descriptor(roughness_map))
+                    .with_height_map(optional_descriptor(height_map))
+                    .with_tint(*tint)
+                    .with_uv_offset(*uv_offset)
+            },
+            Material::Dielectric {
+                refraction_index,
+                roughness,
+                roughness_map,
+                absorption,
+            } => GpuMaterial::dielectric(*refraction_index, *roughness)
+                .with_roughness_map(optional_descriptor(roughness_map))
+                .with_tint(*absorption),
+            Material::Checkerboard {
+                odd,
+                even,
+                scale,
+                space,
+            } => GpuMaterial::checkerboard(descriptor(*odd), descriptor(*even), *scale, *space),
+            Material::Emissive { emit, two_sided } => GpuMaterial::emissive(descriptor(*emit), *two_sided),
+            Material::Isotropic { albedo, density } => GpuMaterial::isotropic(descriptor(*albedo), *density),
+            Material::Subsurface { albedo, radius } => GpuMaterial::subsurface(descriptor(*albedo), *radius),
+            Material::Mix { first, second, mask } => GpuMaterial::mix(*first as u32, *second as u32, descriptor(*mask)),
+        };
+
+        material_data.push(gpu_material);
+    }
+
+    material_data
+}
+
+/// Indices of the emissive spheres, a sphere without a material is never a light.
+fn light_indices(scene: &Scene) -> Vec<u32> {
+    let mut indices = Vec::new();
+
+    for (idx, sphere) in scene.spheres.iter().enumerate() {
+        match scene.materials.get(sphere.material_idx as usize) {
+            Some(Material::Emissive { .. }) => indices.push(idx as u32),
+            Some(_) => {},
+            None => {
+                let warning = SceneWarning::MissingMaterial {
+                    sphere: idx,
+                    material: sphere.material_idx as usize,
+                };
+                eprintln!("{warning}");
+            },
+        }
+    }
+
+    indices
 }
 
-fn append_to_global_texture_data(texture: &Texture, global_texture_data: &mut Vec<[f32; 3]>) -> TextureDescriptor {
-    let dimensions = texture.dimensions();
+fn append_to_global_texture_data(
+    texture_data: &TextureData,
+    global_texture_data: &mut Vec<[f32; 3]>,
+) -> TextureDescriptor {
+    let dimensions = texture_data.texture.dimensions();
     let offset = global_texture_data.len() as u32;
-    global_texture_data.extend_from_slice(texture.as_slice());
+    global_texture_data.extend_from_slice(texture_data.texture.as_slice());
     TextureDescriptor {
         width: dimensions.0,
         height: dimensions.1,
         offset,
+        scale_u: texture_data.scale_u,
+        scale_v: texture_data.scale_v,
+        wrap: texture_data.wrap.gpu_value(),
     }
 }
 
@@ -435,6 +1807,9 @@ struct TextureDescriptor {
     width: u32,
     height: u32,
     offset: u32,
+    scale_u: f32,
+    scale_v: f32,
+    wrap: u32,
 }
 
 impl TextureDescriptor {
@@ -443,6 +1818,380 @@ impl TextureDescriptor {
             width: 0,
             height: 0,
             offset: 0xffffffff,
+            scale_u: 1.0,
+            scale_v: 1.0,
+            wrap: WrapMode::default().gpu_value(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::light::PointLightNode;
+    use crate::node::material::LambertianNode;
+    use crate::types::Color;
+
+    fn node_bounds(node: &BvhNode) -> Aabb {
+        Aabb {
+            min: node.min.into(),
+            max: node.max.into(),
+        }
+    }
+
+    fn contains(outer: &Aabb, inner: &Aabb) -> bool {
+        (0..3).all(|axis| outer.min[axis] <= inner.min[axis] && inner.max[axis] <= outer.max[axis])
+    }
+
+    fn grid_spheres(size: usize) -> Vec<Sphere> {
+        (0..size * size * size)
+            .map(|idx| {
+                let x = (idx % size) as f64;
+                let y = (idx / size % size) as f64;
+                let z = (idx / (size * size)) as f64;
+                Sphere::new(Vector3::new(3.0 * x, 3.0 * y, 3.0 * z), 1.0, 0)
+            })
+            .collect()
+    }
+
+    fn sphere_primitives(spheres: &[Sphere]) -> Vec<BvhPrimitive> {
+        let scene = Scene {
+            spheres: spheres.to_vec(),
+            ..Default::default()
+        };
+        scene.bvh_primitives()
+    }
+
+    #[test]
+    fn test_bvh_empty_scene() {
+        let bvh = Bvh::build(&[]);
+        assert_eq!(bvh.nodes.len(), 1);
+        assert_eq!(bvh.nodes[0].count, 0);
+        assert_eq!(bvh.nodes[0].left_or_first, 0);
+        assert!(bvh.indices.is_empty());
+    }
+
+    #[test]
+    fn test_bvh_references_every_primitive_once() {
+        let spheres = grid_spheres(5);
+        let bvh = Bvh::build(&sphere_primitives(&spheres));
+
+        let mut seen = vec![0; spheres.len()];
+        for node in bvh.nodes.iter().filter(|node| node.count > 0) {
+            assert!(node.count as usize <= Bvh::MAX_LEAF_SIZE);
+            let first = node.left_or_first as usize;
+            for &idx in &bvh.indices[first..first + node.count as usize] {
+                seen[idx as usize] += 1;
+            }
+        }
+
+        assert!(seen.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_bvh_nodes_enclose_children() {
+        let spheres = grid_spheres(4);
+        let bvh = Bvh::build(&sphere_primitives(&spheres));
+
+        for node in &bvh.nodes {
+            let bounds = node_bounds(node);
+            if node.count == 0 {
+                let left = node.left_or_first as usize;
+                assert!(contains(&bounds, &node_bounds(&bvh.nodes[left])));
+                assert!(contains(&bounds, &node_bounds(&bvh.nodes[left + 1])));
+            } else {
+                let first = node.left_or_first as usize;
+                for &idx in &bvh.indices[first..first + node.count as usize] {
+                    assert!(contains(&bounds, &spheres[idx as usize].bounds()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bvh_reused_for_same_geometry() {
+        let spheres = grid_spheres(2);
+        let bvh = Bvh::build(&sphere_primitives(&spheres));
+
+        let recolored = spheres
+            .iter()
+            .map(|sphere| Sphere {
+                material_idx: 1,
+                ..*sphere
+            })
+            .collect::<Vec<_>>();
+        assert!(bvh.is_built_for(&sphere_primitives(&recolored)));
+
+        let moved = grid_spheres(3);
+        assert!(!bvh.is_built_for(&sphere_primitives(&moved)));
+    }
+
+    #[test]
+    fn test_csg_operands_are_not_drawn_alone() {
+        let mut scene = Scene {
+            spheres: grid_spheres(2),
+            ..Default::default()
+        };
+        scene.csg_ops = vec![
+            CsgOp::new(CsgOperation::Intersection, 1, 3),
+            CsgOp::new(CsgOperation::Union, 0, 8),
+        ];
+
+        let primitives = scene.bvh_primitives();
+        let ids = primitives.iter().map(|primitive| primitive.id).collect::<Vec<_>>();
+        assert_eq!(ids, [0, 2, 4, 5, 6, 7, CsgOp::PRIMITIVE_BIT]);
+        let bounds = primitives.last().unwrap().bounds;
+        assert!(bounds.min.y > bounds.max.y, "the spheres don't overlap");
+        let missing = SceneWarning::MissingCsgSphere { op: 1, sphere: 8 };
+        assert!(scene.validate().contains(&missing));
+    }
+
+    #[test]
+    fn test_zero_clip_normal_keeps_the_sphere() {
+        let sphere = Sphere::new(Vector3::zeros(), 1.0, 0).with_clip(Vector3f32::zeros(), -0.5, true);
+        assert_eq!(sphere.clip, Sphere::no_clip());
+        assert!(sphere.clip().is_none() && !sphere.is_clip_capped());
+    }
+
+    #[test]
+    fn test_old_texture_data_defaults_sampling() {
+        let json = r#"{"texture":{"dimensions":[1,1],"data":[[0.5,0.5,0.5]]},"key":"moon.jpeg","scale":2.0}"#;
+        let data: TextureData = serde_json::from_str(json).unwrap();
+
+        assert_eq!((data.scale_u, data.scale_v), (1.0, 1.0));
+        assert_eq!(data.wrap, WrapMode::Repeat);
+    }
+
+    #[test]
+    fn test_missing_file_loads_placeholder() {
+        let data = TextureData::load("noded_test_missing_file_loads_placeholder.png");
+        assert_eq!(data.texture.as_slice(), TextureData::missing().texture.as_slice());
+        assert_eq!(data.texture.dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn test_texture_cache_loads_in_background() {
+        let path = std::env::temp_dir().join("noded_test_texture_cache_loads_in_background.png");
+        let image = image::RgbImage::from_pixel(2, 1, image::Rgb([255, 0, 0]));
+        image.save(&path).unwrap();
+        let path = path.to_str().unwrap();
+
+        let cache = TextureCache::default();
+        assert!(matches!(cache.load_scaled(path, 1.0), TextureLoad::Loading));
+        assert!(matches!(cache.load_scaled("missing.png", 1.0), TextureLoad::Loading));
+        while cache.is_loading() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
         }
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(cache.generation(), 2);
+
+        let TextureLoad::Loaded(data) = cache.load_scaled(path, 1.0) else {
+            panic!("texture was not loaded");
+        };
+        assert_eq!(data.texture.dimensions(), (2, 1));
+        assert_eq!(data.key.as_deref(), Some(path));
+        assert!(matches!(cache.load_scaled(path, 2.0), TextureLoad::Loading));
+        assert!(matches!(cache.load_scaled("missing.png", 1.0), TextureLoad::Failed));
+        assert!(cache.error("missing.png", 1.0).is_some());
+        assert!(cache.error(path, 1.0).is_none());
+
+        // A failure is tried again once it is old enough.
+        let missing = ("missing.png".to_owned(), 1f32.to_bits());
+        cache.entries.lock().unwrap().get_mut(&missing).unwrap().texture = CachedTexture::Failed {
+            error: String::new(),
+            at: Instant::now() - FAILED_RETRY_INTERVAL,
+        };
+        assert!(matches!(cache.load_scaled("missing.png", 1.0), TextureLoad::Loading));
+
+        // The least recently used image makes room for new ones.
+        for scale in 0..MAX_CACHED_TEXTURES {
+            cache.load_scaled("missing.png", 3.0 + scale as f32);
+        }
+        let entries = cache.entries.lock().unwrap();
+        assert!(!entries.contains_key(&(path.to_owned(), 1f32.to_bits())));
+    }
+
+    #[test]
+    fn test_validate_stub_scene() {
+        let scene = Scene::stub();
+        assert_eq!(
+            scene.validate(),
+            [SceneWarning::ZeroRadius(0), SceneWarning::ZeroRadius(1)]
+        );
+        assert_eq!(scene.stats().texture_bytes, 12);
+
+        let broken = Scene {
+            materials: vec![Material::Emissive {
+                emit: 3,
+                two_sided: true,
+            }],
+            spheres: vec![Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, 1)],
+            ..Scene::stub()
+        };
+        assert_eq!(
+            broken.validate(),
+            [
+                SceneWarning::MissingMaterial { sphere: 0, material: 1 },
+                SceneWarning::MissingTexture {
+                    material: 0,
+                    texture: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_texture_uses_empty_descriptor() {
+        let scene = Scene {
+            materials: vec![Material::Lambertian {
+                albedo: 999,
+                normal_map: Some(998),
+                height_map: None,
+                tint: Material::DEFAULT_TINT,
+                uv_offset: 0.0,
+            }],
+            ..Scene::stub()
+        };
+        let mut global_texture_data = Vec::new();
+        let texture_descriptors = scene
+            .textures
+            .iter()
+            .map(|texture| append_to_global_texture_data(texture, &mut global_texture_data))
+            .collect::<Vec<_>>();
+
+        let materials = gpu_materials(&scene, &texture_descriptors);
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials[0].desc1.offset, TextureDescriptor::empty().offset);
+        assert_eq!(materials[0].desc2.offset, TextureDescriptor::empty().offset);
+        assert!(light_indices(&scene).is_empty());
+    }
+
+    #[test]
+    fn test_old_metal_is_isotropic() {
+        let json = r#"{"Metal":{"albedo":0,"fuzz":0.4}}"#;
+        let material: Material = serde_json::from_str(json).unwrap();
+
+        let Material::Metal { fuzz, anisotropy, .. } = material else {
+            panic!("Expected a metal material");
+        };
+        assert_eq!((fuzz, anisotropy), (0.4, 0.0));
+    }
+
+    #[test]
+    fn test_albedo_tints_texture() {
+        let node = MaterialNode::Lambertian(LambertianNode::default());
+        let mut textures = Vec::new();
+
+        let Material::Lambertian { tint, .. } = Material::from_node(&node, None, None, None, None, &mut textures)
+        else {
+            panic!("Expected a lambertian material");
+        };
+        assert_eq!(tint, Material::DEFAULT_TINT);
+        assert_eq!(textures.len(), 1);
+
+        let Material::Lambertian { albedo, tint, .. } =
+            Material::from_node(&node, Some(0), None, None, None, &mut textures)
+        else {
+            panic!("Expected a lambertian material");
+        };
+        assert_eq!(albedo, 0);
+        assert_ne!(tint, Material::DEFAULT_TINT);
+        assert_eq!(textures.len(), 1);
+    }
+
+    #[test]
+    fn test_mid_gray_is_uploaded_linear() {
+        // sRGB 128 is about 0.2159 in linear space, the sRGB value itself is about 0.502.
+        let gray = Color::from_gray(128);
+        let is_linear_gray = |value: &f32| (value - 0.2159).abs() < 1e-3;
+
+        let mut lambertian_node = LambertianNode::default();
+        lambertian_node.albedo.set(gray);
+        let node = MaterialNode::Lambertian(lambertian_node);
+        let mut textures = Vec::new();
+        Material::from_node(&node, None, None, None, None, &mut textures);
+        let [albedo] = textures[0].texture.as_slice() else {
+            panic!("Expected a single texel");
+        };
+        assert!(albedo.iter().all(is_linear_gray));
+
+        let mut point_node = PointLightNode::default();
+        point_node.color.set(gray);
+        point_node.intensity.set(1.0);
+        let light = GpuLight::from_node(&LightNode::Point(point_node));
+        assert!(light.radiance.iter().all(is_linear_gray));
+
+        let texture = Texture::new_from_color(Vector3f32::repeat(128.0 / 255.0)).decode_srgb(1.0);
+        assert!(texture.as_slice()[0].iter().all(is_linear_gray));
+    }
+
+    #[test]
+    fn test_zero_light_direction_points_down() {
+        let light = GpuLight::directional(Vector3::zeros(), Vector3::repeat(1.0));
+        assert_eq!(light.position_or_direction, [0.0, -1.0, 0.0]);
+
+        let angle = Angle::degrees(30.0);
+        let light = GpuLight::spot(Vector3::zeros(), Vector3::zeros(), Vector3::repeat(1.0), angle, angle);
+        assert_eq!(light.spot_direction, [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_light_group_mask() {
+        let light = GpuLight::point(Vector3::zeros(), Vector3::repeat(1.0));
+        assert_eq!(light.group_mask, u32::MAX);
+        assert_eq!(light.with_group(3).group_mask, 0b1000);
+        assert_eq!(GpuLight::light_group(40.0), 31);
+        assert_eq!(GpuLight::light_group(-1.0), 0);
+    }
+
+    #[test]
+    fn test_texture_rotation_wraps_uv_offset() {
+        assert_eq!(uv_offset(Angle::degrees(90.0)), 0.25);
+        assert_eq!(uv_offset(Angle::degrees(-90.0)), 0.75);
+        assert_eq!(uv_offset(Angle::degrees(720.0)), 0.0);
+    }
+
+    #[test]
+    fn test_old_checkerboard_keeps_solid_tiles() {
+        let json = r#"{"Checkerboard":{"even":0,"odd":1}}"#;
+        let material: Material = serde_json::from_str(json).unwrap();
+
+        let Material::Checkerboard { scale, space, .. } = material else {
+            panic!("Expected a checkerboard material");
+        };
+        assert_eq!(scale, CheckerboardSpace::DEFAULT_SCALE);
+        assert_eq!(space, CheckerboardSpace::Solid);
+    }
+
+    #[test]
+    fn test_transformed_sphere_bounds() {
+        let transform = Matrix4f32::new_translation(&Vector3f32::new(0.0, 2.0, 0.0))
+            * Matrix4f32::new_nonuniform_scaling(&Vector3f32::new(2.0, 1.0, 1.0));
+        let sphere = Sphere::new(Vector3::new(1.0, 0.0, 0.0), 1.0, 0).with_transform(&transform);
+
+        assert_eq!(sphere.world_center(), Vector3f32::new(2.0, 2.0, 0.0));
+        let bounds = sphere.bounds();
+        assert_eq!(bounds.min, Vector3f32::new(0.0, 1.0, -1.0));
+        assert_eq!(bounds.max, Vector3f32::new(4.0, 3.0, 1.0));
+        let (center, radius) = sphere.bounding_sphere();
+        assert_eq!(center, Vector3f32::new(2.0, 2.0, 0.0));
+        assert!((radius - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_oversized_textures_are_rejected() {
+        let mut scene = Scene::stub();
+        scene.textures.push(TextureData::new(Texture::new_from_noise(64, 4, 0)));
+        let bvh = Bvh::build(&scene.bvh_primitives());
+        let limits = wgpu::Limits {
+            max_storage_buffer_binding_size: 16 << 10,
+            ..wgpu::Limits::default()
+        };
+
+        assert!(GroupData::check_sizes(&Scene::stub(), &bvh, &limits).is_ok());
+        assert!(matches!(
+            GroupData::check_sizes(&scene, &bvh, &limits),
+            Err(RenderParamsValidationError::BufferTooLarge("textures", _, limit)) if limit == 16 << 10
+        ));
     }
 }
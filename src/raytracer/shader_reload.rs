@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use eframe::wgpu::naga;
+use notify::{RecursiveMode, Watcher};
+
+/// The shader the debug build was made from, edits to it are picked up while the app runs.
+const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/raytracer/raytracer_shader.wgsl");
+
+/// Watches the raytracer shader on disk.
+pub struct ShaderReload {
+    _watcher: notify::RecommendedWatcher,
+    changed: Arc<AtomicBool>,
+}
+
+impl ShaderReload {
+    pub fn new() -> notify::Result<Self> {
+        let path = Path::new(SHADER_PATH);
+        let shader_name = path.file_name();
+        let changed = Arc::new(AtomicBool::new(false));
+
+        let flag = Arc::clone(&changed);
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let touches_shader = |event: &notify::Event| {
+                (event.kind.is_modify() || event.kind.is_create())
+                    && event.paths.iter().any(|changed| changed.file_name() == shader_name)
+            };
+            if event.is_ok_and(|event| touches_shader(&event)) {
+                flag.store(true, Ordering::Relaxed);
+            }
+        })?;
+        // Editors often save by replacing the file, a watch on the file itself would stop seeing it.
+        let directory = path.parent().expect("the shader is inside of the source directory");
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changed,
+        })
+    }
+
+    /// Source of the shader if it changed since the last call, or why it can't be used.
+    pub fn take_source(&self) -> Option<Result<String, String>> {
+        if !self.changed.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+
+        let source = std::fs::read_to_string(SHADER_PATH).map_err(|err| format!("failed to read {SHADER_PATH}: {err}"));
+        Some(source.and_then(|source| validate(&source).map(|()| source)))
+    }
+}
+
+/// Wgpu panics on shaders that don't compile, so they are checked before any pipeline is made from them.
+fn validate(source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|err| err.emit_to_string(source))?;
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .map_err(|err| err.emit_to_string(source))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracer::RAYTRACER_SHADER;
+
+    #[test]
+    fn test_validate_reports_compile_errors() {
+        assert_eq!(validate(RAYTRACER_SHADER), Ok(()));
+        assert!(validate("fn broken( {").is_err());
+        assert!(validate("fn mismatch() -> f32 { return vec3<f32>(1.0); }").is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::{Arc, Mutex};
+
+use eframe::wgpu;
+use thiserror::Error;
+
+use super::scene::Scene;
+use super::{Raytracer, RenderParams, RenderParamsValidationError};
+
+#[derive(Error, Debug)]
+pub enum StillRenderError {
+    #[error(transparent)]
+    InvalidParams(#[from] RenderParamsValidationError),
+    #[error("failed to read back the image: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+}
+
+/// Renders an image offscreen with its own [`Raytracer`] and reads it back, e.g. a frame of an animation.
+/// Each [`Self::step`] submits a single frame of samples, so the UI keeps running while the image accumulates.
+pub struct StillRender {
+    raytracer: Raytracer,
+    render_params: RenderParams,
+    size: (u32, u32),
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    /// Rows in `buffer` are padded to the alignment of texture copies.
+    padded_bytes_per_row: u32,
+    /// Set by the mapping callback once the finished image was copied into `buffer`.
+    mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    copied: bool,
+}
+
+impl StillRender {
+    /// Not sRGB, so the shader encodes the colors and the texture holds what an image file stores.
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(
+        device: &wgpu::Device,
+        scene: &Scene,
+        render_params: &RenderParams,
+        size: (u32, u32),
+    ) -> Result<Self, RenderParamsValidationError> {
+        if size.0 == 0 || size.1 == 0 {
+            return Err(RenderParamsValidationError::ViewportSize(size.0, size.1));
+        }
+        let raytracer = Raytracer::new(device, Self::FORMAT, scene, render_params, size, size.0 * size.1)?;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+            label: Some("still texture"),
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let padded_bytes_per_row = (size.0 * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            size: u64::from(padded_bytes_per_row) * u64::from(size.1),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+            label: Some("still readback buffer"),
+        });
+
+        Ok(Self {
+            raytracer,
+            render_params: *render_params,
+            size,
+            texture,
+            view,
+            buffer,
+            padded_bytes_per_row,
+            mapped: Arc::new(Mutex::new(None)),
+            copied: false,
+        })
+    }
+
+    /// Adds the samples of the next frame, and copies the image for reading once it has all of them.
+    /// Returns the image once it was read back, without waiting on the GPU until then.
+    pub fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Option<image::RgbImage>, StillRenderError> {
+        if self.copied {
+            device.poll(wgpu::Maintain::Poll);
+            let Some(mapped) = self.mapped.lock().unwrap().take() else {
+                return Ok(None);
+            };
+            mapped?;
+            return Ok(Some(self.read_image()));
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("still encoder"),
+        });
+        self.raytracer
+            .prepare_frame(device, queue, &mut encoder, &self.render_params, None, self.size)?;
+        {
+            let mut render_pass = Raytracer::begin_offscreen_pass(&mut encoder, &self.view, "still pass");
+            self.raytracer.render_frame(&mut render_pass);
+        }
+
+        // The frame that finds the render complete takes no samples and only draws the accumulated image.
+        let complete = self.raytracer.is_converged();
+        if complete {
+            encoder.copy_texture_to_buffer(
+                self.texture.as_image_copy(),
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &self.buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(self.padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                self.texture.size(),
+            );
+        }
+        queue.submit([encoder.finish()]);
+
+        if complete {
+            let mapped = Arc::clone(&self.mapped);
+            self.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                *mapped.lock().unwrap() = Some(result);
+            });
+            self.copied = true;
+        }
+        Ok(None)
+    }
+
+    fn read_image(&self) -> image::RgbImage {
+        let rgb = {
+            let data = self.buffer.slice(..).get_mapped_range();
+            data.chunks_exact(self.padded_bytes_per_row as usize)
+                .flat_map(|row| row[..self.size.0 as usize * 4].chunks_exact(4))
+                .flat_map(|texel| [texel[0], texel[1], texel[2]])
+                .collect()
+        };
+        self.buffer.unmap();
+        image::RgbImage::from_raw(self.size.0, self.size.1, rgb).expect("one pixel per texel")
+    }
+}
@@ -15,6 +15,38 @@ impl Texture {
         Self::new_from_scaled_image(path, 1.0)
     }
 
+    /// Loads `.hdr` and `.exr` files through [`Self::new_from_scaled_hdr`], everything else as an LDR image.
+    pub fn new_from_scaled_file(path: &str, scale: f32) -> Result<Self, TextureError> {
+        if Self::is_hdr_path(path) {
+            Self::new_from_scaled_hdr(path, scale)
+        } else {
+            Self::new_from_scaled_image(path, scale)
+        }
+    }
+
+    pub fn is_hdr_path(path: &str) -> bool {
+        std::path::Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr") || extension.eq_ignore_ascii_case("exr"))
+    }
+
+    pub fn new_from_hdr(path: &str) -> Result<Self, TextureError> {
+        Self::new_from_scaled_hdr(path, 1.0)
+    }
+
+    /// Keeps values above 1.0, so the texture can be used for bright emitters.
+    pub fn new_from_scaled_hdr(path: &str, scale: f32) -> Result<Self, TextureError> {
+        let pixels = image::open(path)?.into_rgb32f();
+        let dimensions = pixels.dimensions();
+        let data = pixels
+            .pixels()
+            .map(|p| -> [f32; 3] { [scale * p[0], scale * p[1], scale * p[2]] })
+            .collect();
+
+        Ok(Self { dimensions, data })
+    }
+
     pub fn new_from_scaled_image(path: &str, scale: f32) -> Result<Self, TextureError> {
         use std::fs::*;
         use std::io::BufReader;
@@ -44,6 +76,48 @@ impl Texture {
         Self { dimensions, data }
     }
 
+    /// Grayscale fractal Perlin noise of `resolution`×`resolution` texels. The lowest octave has
+    /// `cells` noise cells along each side and every octave repeats with the texture, so it tiles.
+    pub fn new_from_noise(resolution: u32, cells: u32, seed: u32) -> Self {
+        const OCTAVES: u32 = 4;
+
+        let resolution = resolution.max(1);
+        let cells = cells.max(1);
+        let data = (0..resolution * resolution)
+            .map(|idx| {
+                let u = (idx % resolution) as f32 / resolution as f32;
+                let v = (idx / resolution) as f32 / resolution as f32;
+
+                let (mut value, mut amplitude, mut total) = (0.0, 1.0, 0.0);
+                for octave in 0..OCTAVES {
+                    let period = cells << octave;
+                    value += amplitude * perlin(u * period as f32, v * period as f32, period, seed);
+                    total += amplitude;
+                    amplitude *= 0.5;
+                }
+
+                let value = (0.5 + 0.5 * value / total).clamp(0.0, 1.0);
+                [value; 3]
+            })
+            .collect();
+
+        Self {
+            dimensions: (resolution, resolution),
+            data,
+        }
+    }
+
+    /// Decodes texels stored as sRGB, e.g. loaded from an LDR image, into linear values. `scale` is the
+    /// multiplier the texels were loaded with, it is kept.
+    pub fn decode_srgb(mut self, scale: f32) -> Self {
+        if scale > 0.0 {
+            for value in self.data.iter_mut().flatten() {
+                *value = scale * srgb_to_linear(*value / scale);
+            }
+        }
+        self
+    }
+
     pub fn as_slice(&self) -> &[[f32; 3]] {
         self.data.as_slice()
     }
@@ -53,6 +127,81 @@ impl Texture {
     }
 }
 
+/// How texture coordinates outside of `0..=1` are mapped back onto the texture.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    /// Edge texels stretch out, which avoids seams where a texture isn't tileable.
+    Clamp,
+    /// Every other repeat is flipped.
+    Mirror,
+}
+
+impl WrapMode {
+    pub const ALL: [Self; 3] = [Self::Repeat, Self::Clamp, Self::Mirror];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Repeat => "Repeat",
+            Self::Clamp => "Clamp",
+            Self::Mirror => "Mirror",
+        }
+    }
+
+    /// Value of the mode in the shader's texture descriptor.
+    pub const fn gpu_value(&self) -> u32 {
+        match self {
+            Self::Repeat => 0,
+            Self::Clamp => 1,
+            Self::Mirror => 2,
+        }
+    }
+}
+
+fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Gradient noise in about `-1..=1` that repeats every `period` cells along both axes.
+fn perlin(x: f32, y: f32, period: u32, seed: u32) -> f32 {
+    let gradient = |ix: u32, iy: u32, dx: f32, dy: f32| {
+        let angle = hash(ix % period, iy % period, seed) as f32 / u32::MAX as f32 * std::f32::consts::TAU;
+        dx * angle.cos() + dy * angle.sin()
+    };
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+
+    let (x0, y0) = (x.floor(), y.floor());
+    let (dx, dy) = (x - x0, y - y0);
+    let (ix, iy) = (x0 as u32, y0 as u32);
+
+    let top = lerp(gradient(ix, iy, dx, dy), gradient(ix + 1, iy, dx - 1.0, dy), fade(dx));
+    let bottom = lerp(
+        gradient(ix, iy + 1, dx, dy - 1.0),
+        gradient(ix + 1, iy + 1, dx - 1.0, dy - 1.0),
+        fade(dx),
+    );
+    // Gradient noise peaks at sqrt(0.5), scale it up to fill the range.
+    lerp(top, bottom, fade(dy)) * std::f32::consts::SQRT_2
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn hash(x: u32, y: u32, seed: u32) -> u32 {
+    let mut hash = seed ^ x.wrapping_mul(0x27d4_eb2d) ^ y.wrapping_mul(0x1656_67b1);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297a_2d39);
+    hash ^ (hash >> 15)
+}
+
 #[derive(Error, Debug)]
 pub enum TextureError {
     #[error(transparent)]
@@ -60,3 +209,42 @@ pub enum TextureError {
     #[error(transparent)]
     ImageLoadError(#[from] image::ImageError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_tiles() {
+        let period = 4;
+        for step in 0..16 {
+            let t = step as f32 / 4.0;
+            assert!((perlin(t, 0.3, period, 7) - perlin(t + period as f32, 0.3, period, 7)).abs() < 1e-5);
+            assert!((perlin(0.3, t, period, 7) - perlin(0.3, t + period as f32, period, 7)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_hdr_keeps_bright_values() {
+        let path = std::env::temp_dir().join("noded_test_hdr_keeps_bright_values.hdr");
+        let image = image::Rgb32FImage::from_pixel(2, 2, image::Rgb([4.0, 0.5, 16.0]));
+        image.save(&path).unwrap();
+
+        let texture = Texture::new_from_hdr(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(texture.dimensions(), (2, 2));
+        let [r, g, b] = texture.as_slice()[0];
+        assert!((r - 4.0).abs() < 0.1 && (g - 0.5).abs() < 0.1 && (b - 16.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_noise_texture() {
+        let texture = Texture::new_from_noise(32, 4, 1);
+        assert_eq!(texture.dimensions(), (32, 32));
+        let mut values = texture.as_slice().iter().flatten();
+        assert!(values.all(|value| (0.0..=1.0).contains(value)));
+        assert_eq!(texture.as_slice(), Texture::new_from_noise(32, 4, 1).as_slice());
+        assert_ne!(texture.as_slice(), Texture::new_from_noise(32, 4, 2).as_slice());
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+#[cfg(feature = "frame-timings")]
+use std::time::Instant;
+
+/// CPU time of the parts of the latest raytracer frame. Only measured in builds with the `frame-timings`
+/// feature, the durations stay zero otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    /// Time between the starts of the latest two frames. The queue holds the CPU back once the GPU falls behind,
+    /// so while rendering it follows the GPU time of a frame.
+    pub frame: Duration,
+    pub prepare: Duration,
+    pub paint: Duration,
+    /// Latest upload of a changed scene, part of `prepare` of the frame it happened in.
+    pub scene_update: Duration,
+    /// Samples per pixel taken by the latest frame.
+    pub samples: u32,
+}
+
+impl FrameTimings {
+    pub const ENABLED: bool = cfg!(feature = "frame-timings");
+}
+
+/// Measures the time since it was started, always zero without the `frame-timings` feature.
+#[derive(Clone, Copy, Debug)]
+pub struct Stopwatch {
+    #[cfg(feature = "frame-timings")]
+    started_at: Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            #[cfg(feature = "frame-timings")]
+            started_at: Instant::now(),
+        }
+    }
+
+    #[cfg(feature = "frame-timings")]
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    #[cfg(not(feature = "frame-timings"))]
+    pub fn elapsed(&self) -> Duration {
+        Duration::ZERO
+    }
+}
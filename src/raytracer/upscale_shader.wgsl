@@ -0,0 +1,21 @@
+// Stretches a render made at a reduced resolution over the whole viewport.
+
+@group(0) @binding(0) var image: texture_2d<f32>;
+@group(0) @binding(1) var imageSampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clipPosition: vec4<f32>,
+    @location(0) texCoords: vec2<f32>,
+}
+
+@vertex
+fn vsMain(@builtin(vertex_index) vertexIdx: u32) -> VertexOutput {
+    // A single triangle covering the viewport, texture coordinates start in the top left corner.
+    let uv = vec2(f32((vertexIdx << 1u) & 2u), f32(vertexIdx & 2u));
+    return VertexOutput(vec4(uv.x * 2f - 1f, 1f - uv.y * 2f, 0f, 1f), uv);
+}
+
+@fragment
+fn fsMain(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(image, imageSampler, in.texCoords);
+}
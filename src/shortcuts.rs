@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+
+use egui::{InputState, Key, KeyboardShortcut, ModifierNames, Modifiers, Ui};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Action {
+    ToggleSettings,
+    SwitchEditMode,
+    ToggleNodes,
+    ToggleShortcuts,
+    ToggleCommandPalette,
+    Undo,
+    Redo,
+    Copy,
+    Paste,
+    DeleteSelection,
+    CameraForward,
+    CameraBackward,
+    CameraLeft,
+    CameraRight,
+    CameraDown,
+    CameraUp,
+    ToggleRenderPause,
+    RestartRender,
+}
+
+impl Action {
+    pub const ALL: [Action; 18] = [
+        Action::ToggleSettings,
+        Action::SwitchEditMode,
+        Action::ToggleNodes,
+        Action::ToggleShortcuts,
+        Action::ToggleCommandPalette,
+        Action::Undo,
+        Action::Redo,
+        Action::Copy,
+        Action::Paste,
+        Action::DeleteSelection,
+        Action::CameraForward,
+        Action::CameraBackward,
+        Action::CameraLeft,
+        Action::CameraRight,
+        Action::CameraDown,
+        Action::CameraUp,
+        Action::ToggleRenderPause,
+        Action::RestartRender,
+    ];
+
+    pub const fn description(&self) -> &'static str {
+        match self {
+            Self::ToggleSettings => "Show/hide settings panel",
+            Self::SwitchEditMode => "Switch between editing and view mode",
+            Self::ToggleNodes => "Show/hide nodes",
+            Self::ToggleShortcuts => "Show/hide this shortcut reference",
+            Self::ToggleCommandPalette => "Open/close command palette",
+            Self::Undo => "Undo graph edit",
+            Self::Redo => "Redo graph edit",
+            Self::Copy => "Copy selected nodes",
+            Self::Paste => "Paste copied nodes",
+            Self::DeleteSelection => "Delete selected nodes",
+            Self::CameraForward => "Move camera forward",
+            Self::CameraBackward => "Move camera backward",
+            Self::CameraLeft => "Move camera left",
+            Self::CameraRight => "Move camera right",
+            Self::CameraDown => "Move camera down",
+            Self::CameraUp => "Move camera up",
+            Self::ToggleRenderPause => "Pause/resume rendering",
+            Self::RestartRender => "Restart rendering",
+        }
+    }
+
+    pub const fn default_key(&self) -> Key {
+        match self {
+            Self::ToggleSettings => Key::N,
+            Self::SwitchEditMode => Key::Tab,
+            Self::ToggleNodes => Key::H,
+            Self::ToggleShortcuts => Key::F1,
+            Self::ToggleCommandPalette => Key::P,
+            Self::Undo => Key::Z,
+            Self::Redo => Key::Y,
+            Self::Copy => Key::C,
+            Self::Paste => Key::V,
+            Self::DeleteSelection => Key::Delete,
+            Self::CameraForward => Key::W,
+            Self::CameraBackward => Key::S,
+            Self::CameraLeft => Key::A,
+            Self::CameraRight => Key::D,
+            Self::CameraDown => Key::Q,
+            Self::CameraUp => Key::E,
+            Self::ToggleRenderPause => Key::Space,
+            Self::RestartRender => Key::R,
+        }
+    }
+
+    /// Modifiers are fixed per action, only the key can be rebound.
+    pub const fn modifiers(&self) -> Modifiers {
+        match self {
+            Self::ToggleCommandPalette | Self::Undo | Self::Redo | Self::Copy | Self::Paste => Modifiers::COMMAND,
+            _ => Modifiers::NONE,
+        }
+    }
+}
+
+/// User overrides of the default key for each [`Action`].
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct KeyBindings {
+    overrides: BTreeMap<Action, Key>,
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> Key {
+        self.overrides
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_key())
+    }
+
+    pub fn shortcut(&self, action: Action) -> KeyboardShortcut {
+        KeyboardShortcut::new(action.modifiers(), self.key(action))
+    }
+
+    /// Binds `key` to `action`. An action that had the same shortcut gets the previous key of `action` instead,
+    /// so that a key press never triggers two actions.
+    pub fn set(&mut self, action: Action, key: Key) {
+        let shortcut = KeyboardShortcut::new(action.modifiers(), key);
+        let conflict = Action::ALL
+            .into_iter()
+            .find(|&other| other != action && self.shortcut(other) == shortcut);
+        let previous = self.key(action);
+
+        self.bind(action, key);
+        if let Some(other) = conflict {
+            self.bind(other, previous);
+        }
+    }
+
+    fn bind(&mut self, action: Action, key: Key) {
+        if key == action.default_key() {
+            self.overrides.remove(&action);
+        } else {
+            self.overrides.insert(action, key);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.overrides.clear();
+    }
+
+    pub fn pressed(&self, action: Action, input_state: &InputState) -> bool {
+        input_state.modifiers.matches_logically(action.modifiers()) && input_state.key_pressed(self.key(action))
+    }
+
+    /// Whether the key of the action is held down, unlike [`Self::pressed`] this stays true between key repeats.
+    pub fn down(&self, action: Action, input_state: &InputState) -> bool {
+        input_state.modifiers.matches_logically(action.modifiers()) && input_state.key_down(self.key(action))
+    }
+}
+
+/// Window listing every shortcut with a button to rebind it.
+#[derive(Debug, Default)]
+pub struct ShortcutsWindow {
+    rebinding: Option<Action>,
+}
+
+impl ShortcutsWindow {
+    pub fn is_rebinding(&self) -> bool {
+        self.rebinding.is_some()
+    }
+
+    /// Keys are only captured for rebinding while the window is open, closing it cancels the rebinding.
+    pub fn show(&mut self, ctx: &egui::Context, open: &mut bool, bindings: &mut KeyBindings) {
+        if !*open {
+            self.rebinding = None;
+            return;
+        }
+
+        if let Some(action) = self.rebinding {
+            let pressed = ctx.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    egui::Event::Key { key, pressed: true, .. } => Some(*key),
+                    _ => None,
+                })
+            });
+
+            match pressed {
+                Some(Key::Escape) => self.rebinding = None,
+                Some(key) => {
+                    bindings.set(action, key);
+                    self.rebinding = None;
+                },
+                None => {},
+            }
+        }
+
+        egui::Window::new("Shortcuts")
+            .open(open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| self.show_table(ui, bindings));
+        if !*open {
+            self.rebinding = None;
+        }
+    }
+
+    fn show_table(&mut self, ui: &mut Ui, bindings: &mut KeyBindings) {
+        egui::Grid::new("shortcuts_grid").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.description());
+
+                let text = if self.rebinding == Some(action) {
+                    "Press a key...".to_owned()
+                } else {
+                    bindings
+                        .shortcut(action)
+                        .format(&ModifierNames::NAMES, cfg!(target_os = "macos"))
+                };
+
+                if ui.button(text).clicked() {
+                    self.rebinding = Some(action);
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Reset to defaults").clicked() {
+                bindings.reset();
+                self.rebinding = None;
+            }
+            ui.label("Press Escape to cancel rebinding");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebinding_round_trip() {
+        let mut bindings = KeyBindings::default();
+        bindings.set(Action::RestartRender, Key::F5);
+        assert_eq!(bindings.key(Action::RestartRender), Key::F5);
+
+        bindings.set(Action::RestartRender, Key::R);
+        assert!(bindings.overrides.is_empty());
+
+        bindings.set(Action::ToggleNodes, Key::F6);
+        bindings.reset();
+        assert_eq!(bindings.key(Action::ToggleNodes), Action::ToggleNodes.default_key());
+        assert!(bindings.overrides.is_empty());
+    }
+
+    #[test]
+    fn test_conflicting_keys_are_swapped() {
+        let mut bindings = KeyBindings::default();
+        bindings.set(Action::RestartRender, Key::W);
+        assert_eq!(bindings.key(Action::RestartRender), Key::W);
+        assert_eq!(bindings.key(Action::CameraForward), Key::R);
+
+        // The same key with other modifiers is another shortcut.
+        bindings.set(Action::ToggleSettings, Key::C);
+        assert_eq!(bindings.key(Action::Copy), Key::C);
+
+        bindings.set(Action::Copy, Key::Z);
+        assert_eq!(bindings.key(Action::Undo), Key::C);
+
+        for (i, &action) in Action::ALL.iter().enumerate() {
+            for &other in &Action::ALL[i + 1..] {
+                assert_ne!(
+                    bindings.shortcut(action),
+                    bindings.shortcut(other),
+                    "{action:?} {other:?}"
+                );
+            }
+        }
+    }
+}
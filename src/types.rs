@@ -1,4 +1,6 @@
-pub use self::angle::Angle;
+use serde::{Deserialize, Serialize};
+
+pub use self::angle::{Angle, AngleUnit};
 pub use self::pin::NodePin;
 pub use self::ray::Ray;
 
@@ -22,6 +24,44 @@ pub struct Basis {
     pub w: Vector3,
 }
 
+/// World axis pointing up. Camera yaw and pitch and the sun position are given as if y pointed up and are turned
+/// onto this axis, so scenes from tools that put z up don't lie on their side.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, egui_probe::EguiProbe)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    pub fn vector(self) -> Vector3 {
+        self.from_y_up(Vector3::y())
+    }
+
+    /// Turns `v` given with y up into the world, a quarter turn around the x axis for Z-up.
+    pub fn from_y_up(self, v: Vector3) -> Vector3 {
+        match self {
+            Self::Y => v,
+            Self::Z => Vector3::new(v.x, -v.z, v.y),
+        }
+    }
+
+    /// Inverse of [`Self::from_y_up`].
+    pub fn to_y_up(self, v: Vector3) -> Vector3 {
+        match self {
+            Self::Y => v,
+            Self::Z => Vector3::new(v.x, v.z, -v.y),
+        }
+    }
+}
+
 pub fn from_vector3_to_vector3f32(v: &Vector3) -> Vector3f32 {
     Vector3f32::new(v.x as _, v.y as _, v.z as _)
 }
+
+/// Colors are picked in sRGB, lighting is computed on the linear values. The render is encoded back to
+/// sRGB only when it is shown or saved.
+pub fn linear_rgb(color: Color) -> Vector3f32 {
+    let rgba = egui::Rgba::from(color);
+    Vector3f32::new(rgba.r(), rgba.g(), rgba.b())
+}
@@ -1,25 +1,84 @@
+use std::cmp::Ordering;
 use std::ops;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+/// Unit an angle is shown and edited in, it does not change the angle itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AngleUnit {
+    #[default]
+    Degrees,
+    Radians,
+}
+
+impl AngleUnit {
+    pub const ALL: [Self; 2] = [Self::Degrees, Self::Radians];
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Degrees => "Degrees",
+            Self::Radians => "Radians",
+        }
+    }
+
+    pub const fn suffix(&self) -> &'static str {
+        match self {
+            Self::Degrees => "°",
+            Self::Radians => " rad",
+        }
+    }
+
+    /// Angle of `value` measured in this unit, which it keeps for display.
+    pub fn angle(self, value: f64) -> Angle {
+        let angle = match self {
+            Self::Degrees => Angle::degrees(value),
+            Self::Radians => Angle::radians(value),
+        };
+        angle.with_unit(self)
+    }
+
+    /// `angle` measured in this unit.
+    pub fn value(self, angle: Angle) -> f64 {
+        match self {
+            Self::Degrees => angle.as_degrees(),
+            Self::Radians => angle.as_radians(),
+        }
+    }
+}
+
+/// Two angles are equal when they have the same size, whatever unit they are shown in.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub struct Angle {
     degrees: f64,
+    #[serde(default)]
+    unit: AngleUnit,
 }
 
 impl Angle {
     #[allow(dead_code)]
     pub fn degrees(degrees: f64) -> Self {
-        Self { degrees }
+        Self {
+            degrees,
+            unit: AngleUnit::Degrees,
+        }
     }
 
     #[allow(dead_code)]
     pub fn radians(radians: f64) -> Self {
         Self {
             degrees: radians.to_degrees(),
+            unit: AngleUnit::Degrees,
         }
     }
 
+    pub fn unit(&self) -> AngleUnit {
+        self.unit
+    }
+
+    pub fn with_unit(self, unit: AngleUnit) -> Self {
+        Self { unit, ..self }
+    }
+
     #[allow(dead_code)]
     pub fn as_degrees(&self) -> f64 {
         self.degrees
@@ -38,7 +97,19 @@ impl Angle {
 
 impl From<f64> for Angle {
     fn from(value: f64) -> Self {
-        Self { degrees: value }
+        Self::degrees(value)
+    }
+}
+
+impl PartialEq for Angle {
+    fn eq(&self, other: &Self) -> bool {
+        self.degrees == other.degrees
+    }
+}
+
+impl PartialOrd for Angle {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.degrees.partial_cmp(&other.degrees)
     }
 }
 
@@ -60,6 +131,7 @@ impl ops::Add for Angle {
     fn add(self, rhs: Self) -> Self {
         Self {
             degrees: self.degrees + rhs.degrees,
+            ..self
         }
     }
 }
@@ -95,6 +167,14 @@ mod tests {
         assert_eq!(result.as_degrees(), DEGREES_180);
     }
 
+    #[test]
+    fn test_angle_unit_keeps_size() {
+        let angle = AngleUnit::Radians.angle(FRAC_PI_2);
+        assert_eq!(angle.unit(), AngleUnit::Radians);
+        assert_eq!(angle, Angle::degrees(DEGREES_90));
+        assert_eq!(AngleUnit::Degrees.value(angle), DEGREES_90);
+    }
+
     #[test]
     fn test_angle_clamp_max() {
         let angle = Angle::degrees(DEGREES_90);
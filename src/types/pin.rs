@@ -2,17 +2,48 @@ use std::ops;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+use super::{Angle, AngleUnit};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct NodePin<T> {
     initial: T,
     value: Option<T>,
+    /// Value the pin was made with. Pins of files saved before it was recorded don't know it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<T>,
 }
 
-impl<T> NodePin<T> {
+impl<T: Default + Clone> Default for NodePin<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Clone> NodePin<T> {
     pub fn new(initial: T) -> Self {
-        Self { initial, value: None }
+        Self {
+            default: Some(initial.clone()),
+            initial,
+            value: None,
+        }
     }
 
+    /// Puts the value a fresh node has back in place of the one edited in the node.
+    pub fn reset_to_default(&mut self) {
+        if let Some(default) = &self.default {
+            self.initial = default.clone();
+        }
+    }
+}
+
+impl<T: PartialEq> NodePin<T> {
+    /// Returns `true` if the edited value differs from the value a fresh node has.
+    pub fn can_reset_to_default(&self) -> bool {
+        self.default.as_ref().is_some_and(|default| *default != self.initial)
+    }
+}
+
+impl<T> NodePin<T> {
     pub fn set(&mut self, value: T) {
         self.value = Some(value);
     }
@@ -42,8 +73,48 @@ impl ops::Deref for NodePin<f64> {
     }
 }
 
+impl NodePin<Angle> {
+    /// Shows the pin in `unit`, also once a connected value is reset.
+    pub fn set_unit(&mut self, unit: AngleUnit) {
+        self.initial = self.initial.with_unit(unit);
+        self.default = self.default.map(|default| default.with_unit(unit));
+        if let Some(value) = &mut self.value {
+            *value = value.with_unit(unit);
+        }
+    }
+}
+
 impl<T: Copy> NodePin<T> {
     pub fn get(&self) -> T {
         self.value.unwrap_or(self.initial)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_to_default_keeps_the_remote_value() {
+        let mut pin = NodePin::new(1.5);
+        *pin.as_mut() = 2.0;
+        assert!(pin.can_reset_to_default());
+        pin.reset_to_default();
+        assert_eq!(pin.get(), 1.5);
+        assert!(!pin.can_reset_to_default());
+
+        pin.set(3.0);
+        pin.reset_to_default();
+        assert_eq!(pin.get(), 3.0);
+        pin.reset();
+        assert_eq!(pin.get(), 1.5);
+    }
+
+    #[test]
+    fn test_pins_of_older_files_have_no_default() {
+        let mut pin: NodePin<f64> = serde_json::from_str(r#"{"initial": 2.0, "value": null}"#).unwrap();
+        assert!(!pin.can_reset_to_default());
+        pin.reset_to_default();
+        assert_eq!(pin.get(), 2.0);
+    }
+}